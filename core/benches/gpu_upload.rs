@@ -0,0 +1,69 @@
+#![feature(test)]
+
+extern crate test;
+
+use ovis_core::{
+    resource_bytes, test_resource, EntityId, Gpu, IdMappedResourceStorage, Resource,
+    ResourceStorage, VersionedIndexId,
+};
+use std::sync::Arc;
+use test::Bencher;
+
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct Transform([f32; 16]);
+
+test_resource!(Transform, EntityComponent, IdMappedResourceStorage<EntityId, Transform>, {
+    fn gpu_bytes(resources: &[Self]) -> Option<&[u8]> {
+        return Some(resource_bytes(resources));
+    }
+});
+
+const ENTITY_COUNT: usize = 100_000;
+
+fn gpu() -> Option<Arc<Gpu>> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    return pollster::block_on(Gpu::new(&instance, 0)).ok().map(Arc::new);
+}
+
+// Changes the transforms of the given entities each frame and uploads the storage. Changing all
+// of them uploads the whole storage like before the uploads were incremental.
+fn bench_upload(b: &mut Bencher, changed_entities: Vec<usize>) {
+    let Some(gpu) = gpu() else {
+        return;
+    };
+    let resource_id = Transform::id();
+    let gpus = std::slice::from_ref(&gpu);
+    let mut storage = IdMappedResourceStorage::<EntityId, Transform>::new(gpus, resource_id);
+    for i in 0..ENTITY_COUNT {
+        storage.insert(EntityId::from_index(i), Transform([0.0; 16]));
+    }
+    storage.update_gpu_buffers();
+
+    b.iter(|| {
+        storage.end_frame();
+        for &i in &changed_entities {
+            storage.get_mut(EntityId::from_index(i)).unwrap().0[12] += 1.0;
+        }
+        storage.update_gpu_buffers();
+        gpu.queue().submit([]);
+        gpu.device().poll(wgpu::Maintain::Wait);
+    });
+}
+
+#[bench]
+fn full_upload(b: &mut Bencher) {
+    bench_upload(b, (0..ENTITY_COUNT).collect());
+}
+
+// One percent of the entities move, e.g., the ones that were spawned together.
+#[bench]
+fn incremental_upload_of_mostly_static_storage(b: &mut Bencher) {
+    bench_upload(b, (0..ENTITY_COUNT / 100).collect());
+}
+
+// One percent of the entities move, but they are spread over the whole storage.
+#[bench]
+fn incremental_upload_of_scattered_changes(b: &mut Bencher) {
+    bench_upload(b, (0..ENTITY_COUNT).step_by(100).collect());
+}
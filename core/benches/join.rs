@@ -0,0 +1,82 @@
+#![feature(test)]
+
+extern crate test;
+
+use ovis_core::{
+    test_resource, EntityId, IdMappedResourceStorage, ResourceId, VersionedIndexId,
+};
+use test::{black_box, Bencher};
+
+struct Position([f32; 3]);
+
+struct Velocity([f32; 3]);
+
+test_resource!(Position, EntityComponent, IdMappedResourceStorage<EntityId, Position>);
+
+test_resource!(Velocity, EntityComponent, IdMappedResourceStorage<EntityId, Velocity>);
+
+const ENTITY_COUNT: usize = 100_000;
+
+// All entities have a position, every tenth one also moves.
+fn storages() -> (
+    IdMappedResourceStorage<EntityId, Position>,
+    IdMappedResourceStorage<EntityId, Velocity>,
+) {
+    let mut positions = IdMappedResourceStorage::new(&[], ResourceId::from_index(0));
+    let mut velocities = IdMappedResourceStorage::new(&[], ResourceId::from_index(1));
+    for i in 0..ENTITY_COUNT {
+        positions.insert(EntityId::from_index(i), Position([0.0; 3]));
+        if i % 10 == 0 {
+            velocities.insert(EntityId::from_index(i), Velocity([1.0; 3]));
+        }
+    }
+    return (positions, velocities);
+}
+
+#[bench]
+fn nested_get(b: &mut Bencher) {
+    let (positions, velocities) = storages();
+    b.iter(|| {
+        let mut sum = 0.0;
+        for (entity_id, position) in positions.iter() {
+            if let Some(velocity) = velocities.get(entity_id) {
+                sum += position.0[0] + velocity.0[0];
+            }
+        }
+        black_box(sum);
+    });
+}
+
+#[bench]
+fn join(b: &mut Bencher) {
+    let (positions, velocities) = storages();
+    b.iter(|| {
+        let mut sum = 0.0;
+        for (_, position, velocity) in positions.join(&velocities) {
+            sum += position.0[0] + velocity.0[0];
+        }
+        black_box(sum);
+    });
+}
+
+#[bench]
+fn nested_get_mut(b: &mut Bencher) {
+    let (mut positions, velocities) = storages();
+    b.iter(|| {
+        for (entity_id, velocity) in velocities.iter() {
+            if let Some(position) = positions.get_mut(entity_id) {
+                position.0[0] += velocity.0[0];
+            }
+        }
+    });
+}
+
+#[bench]
+fn join_mut(b: &mut Bencher) {
+    let (mut positions, velocities) = storages();
+    b.iter(|| {
+        for (_, velocity, position) in velocities.join_mut(&mut positions) {
+            position.0[0] += velocity.0[0];
+        }
+    });
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+use crate::{EntityId, Error, Result, SourceLocation};
+
+// A compact binary encoding of scenes, see `SceneState::to_bytes`. Every component is stored as
+// the index of the label of its resource followed by the component encoded with bincode, the
+// labels are stored once. Loading deserializes the components directly from their bytes, see
+// `EntityComponentResourceStorage::insert_serialized`. Bincode is not self-describing, so
+// components whose deserialization depends on the input, e.g., untagged enums, can only be loaded
+// from JSON.
+const MAGIC: &[u8; 4] = b"OVIS";
+const VERSION: u8 = 3;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct BinaryScene {
+    labels: Vec<String>,
+    entities: Vec<BinaryEntity>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BinaryEntity {
+    id: EntityId,
+    components: Vec<BinaryComponent>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BinaryComponent {
+    // The index of the label of the resource in the labels of the scene.
+    label: u32,
+    bytes: Vec<u8>,
+}
+
+// Lengths and integers are stored as varints and the whole input has to be consumed.
+fn options() -> impl Options {
+    return bincode::DefaultOptions::new().reject_trailing_bytes();
+}
+
+impl BinaryScene {
+    pub(crate) fn entity_count(&self) -> usize {
+        return self.entities.len();
+    }
+
+    // Returns the id of every entity with the label and the bytes of its components.
+    pub(crate) fn entities(&self) -> impl Iterator<Item = (EntityId, Vec<(&str, &[u8])>)> {
+        return self.entities.iter().map(|entity| {
+            let components = entity.components.iter().map(|component| {
+                return (self.labels[component.label as usize].as_str(), &component.bytes[..]);
+            });
+            return (entity.id, components.collect());
+        });
+    }
+}
+
+// Collects the entities of a scene and encodes them, see `SceneState::to_bytes`.
+pub(crate) struct SceneEncoder {
+    scene: BinaryScene,
+    label_indices: HashMap<String, u32>,
+}
+
+impl SceneEncoder {
+    pub(crate) fn new() -> Self {
+        return Self {
+            scene: BinaryScene {
+                labels: Vec::new(),
+                entities: Vec::new(),
+            },
+            label_indices: HashMap::new(),
+        };
+    }
+
+    // Adds an entity, the following components belong to it.
+    pub(crate) fn add_entity(&mut self, id: EntityId) {
+        self.scene.entities.push(BinaryEntity {
+            id,
+            components: Vec::new(),
+        });
+    }
+
+    pub(crate) fn add_component(
+        &mut self,
+        label: &str,
+        component: &dyn erased_serde::Serialize,
+    ) -> Result<()> {
+        let bytes = options().serialize(component).map_err(|error| {
+            Error::new(format!("failed to serialize {label}: {error}"), SourceLocation::here())
+        })?;
+        let label = match self.label_indices.get(label) {
+            Some(&index) => index,
+            None => {
+                let index = self.scene.labels.len() as u32;
+                self.scene.labels.push(label.to_string());
+                self.label_indices.insert(label.to_string(), index);
+                index
+            }
+        };
+        let entity = self.scene.entities.last_mut().expect("components belong to an entity");
+        entity.components.push(BinaryComponent { label, bytes });
+        return Ok(());
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        options()
+            .serialize_into(&mut bytes, &self.scene)
+            .expect("encoding a scene into memory cannot fail");
+        return bytes;
+    }
+}
+
+pub(crate) fn decode_scene(bytes: &[u8]) -> Result<BinaryScene> {
+    let Some(bytes) = bytes.strip_prefix(MAGIC) else {
+        return Err(decode_error("not a binary scene"));
+    };
+    let Some((&version, bytes)) = bytes.split_first() else {
+        return Err(decode_error("missing version"));
+    };
+    if version != VERSION {
+        return Err(decode_error(&format!("unsupported version {version}")));
+    }
+
+    // The limit keeps corrupted lengths from allocating more than the size of the input.
+    let scene: BinaryScene = options()
+        .with_limit(bytes.len() as u64)
+        .deserialize(bytes)
+        .map_err(|error| decode_error(&error.to_string()))?;
+    let label_count = scene.labels.len();
+    let mut components = scene.entities.iter().flat_map(|entity| &entity.components);
+    if components.any(|component| component.label as usize >= label_count) {
+        return Err(decode_error("unknown label reference"));
+    }
+    return Ok(scene);
+}
+
+// Passes a deserializer for the bytes of a component to `deserialize`, which has to consume all
+// of them.
+pub(crate) fn deserialize_component(
+    bytes: &[u8],
+    deserialize: impl FnOnce(&mut dyn erased_serde::Deserializer) -> Result<()>,
+) -> Result<()> {
+    let mut remaining = bytes;
+    let mut deserializer = bincode::Deserializer::with_reader(
+        &mut remaining,
+        options().with_limit(bytes.len() as u64),
+    );
+    deserialize(&mut <dyn erased_serde::Deserializer>::erase(&mut deserializer))?;
+    if !remaining.is_empty() {
+        return Err(decode_error("trailing bytes after a component"));
+    }
+    return Ok(());
+}
+
+fn decode_error(message: &str) -> Error {
+    return Error::new(format!("invalid binary scene: {message}"), SourceLocation::here());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::VersionedIndexId;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    fn deserialize_position(bytes: &[u8]) -> Result<Position> {
+        let mut position = None;
+        deserialize_component(bytes, |deserializer| {
+            position = Some(erased_serde::deserialize(deserializer).map_err(|error| {
+                Error::new(error.to_string(), SourceLocation::here())
+            })?);
+            return Ok(());
+        })?;
+        return Ok(position.unwrap());
+    }
+
+    #[test]
+    fn components_survive_the_round_trip() {
+        let mut encoder = SceneEncoder::new();
+        for index in 0..3 {
+            encoder.add_entity(EntityId::from_index(index));
+            let position = Position { x: index as f32, y: -1.0 };
+            encoder.add_component("Position", &position).unwrap();
+        }
+        let bytes = encoder.finish();
+
+        // Repeated labels are stored once.
+        let label_count = bytes.windows(8).filter(|window| window == b"Position").count();
+        assert_eq!(label_count, 1);
+
+        let scene = decode_scene(&bytes).unwrap();
+        assert_eq!(scene.entity_count(), 3);
+        for (index, (id, components)) in scene.entities().enumerate() {
+            assert_eq!(id, EntityId::from_index(index));
+            assert_eq!(components.len(), 1);
+            assert_eq!(components[0].0, "Position");
+            let position = deserialize_position(components[0].1).unwrap();
+            assert_eq!(position, Position { x: index as f32, y: -1.0 });
+        }
+
+        assert!(decode_scene(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode_scene(b"JSON").is_err());
+    }
+
+    #[test]
+    fn components_have_to_consume_their_bytes() {
+        let bytes = options().serialize(&(Position { x: 1.0, y: 2.0 }, 3.0f32)).unwrap();
+        let error = deserialize_position(&bytes).unwrap_err();
+        assert!(error.message().contains("trailing bytes"), "{error}");
+
+        assert!(deserialize_position(&bytes[..4]).is_err());
+    }
+
+    #[test]
+    fn unknown_label_references_are_rejected() {
+        let scene = BinaryScene {
+            labels: vec!["Position".to_string()],
+            entities: vec![BinaryEntity {
+                id: EntityId::from_index(0),
+                components: vec![BinaryComponent { label: 1, bytes: vec![] }],
+            }],
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        options().serialize_into(&mut bytes, &scene).unwrap();
+
+        let error = decode_scene(&bytes).err().unwrap();
+        assert!(error.message().contains("unknown label reference"), "{error}");
+    }
+}
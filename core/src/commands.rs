@@ -0,0 +1,113 @@
+use std::{
+    any::{Any, TypeId},
+    sync::{Mutex, RwLock},
+};
+
+use crate::{
+    EntityDescriptor, EntityId, Error, IdMappedResourceSliceStorage, IdMappedResourceStorage,
+    IdStorage, Resource, ResourceId, Result, SourceLocation, SparseResourceStorage,
+};
+
+// A structural change of the scene that a job requested, see `Commands`.
+pub(crate) enum Command {
+    // The id of the entity is reserved when the command is recorded.
+    Spawn(EntityId, EntityDescriptor),
+    Despawn(EntityId),
+    Insert(EntityId, ResourceId, Box<dyn Any + Send>),
+    Remove(EntityId, ResourceId),
+}
+
+// Records structural changes of the scene, i.e., spawning and despawning entities and adding and
+// removing their components. Jobs cannot lock the storages of arbitrary components without
+// conflicting with the jobs that access them, so the commands are applied by the scheduler after
+// all jobs of the frame finished and the changes are visible in the next frame. The commands of a
+// frame are applied in the order they were recorded by a worker and dropped if the frame fails.
+pub struct Commands<'a> {
+    queue: &'a Mutex<Vec<Command>>,
+    entities: &'a RwLock<IdStorage<EntityId>>,
+}
+
+impl<'a> Commands<'a> {
+    pub(crate) fn new(
+        queue: &'a Mutex<Vec<Command>>,
+        entities: &'a RwLock<IdStorage<EntityId>>,
+    ) -> Self {
+        return Self { queue, entities };
+    }
+
+    // Reserves the id of the entity right away, so it can be referenced by other components in
+    // the same frame. The components are added after the frame. Locks the entities for writing,
+    // so the job must not hold a lock on them.
+    pub fn spawn(&self, entity: EntityDescriptor) -> EntityId {
+        let entity_id = self.entities.write().unwrap().reserve();
+        self.push(Command::Spawn(entity_id, entity));
+        return entity_id;
+    }
+
+    pub fn despawn(&self, entity_id: EntityId) {
+        self.push(Command::Despawn(entity_id));
+    }
+
+    // Adds the component to the entity or replaces its current one. Commands for entities that do
+    // not exist anymore when the commands are applied are ignored. Fails if the resource is not an
+    // entity component or is stored as a slice of resources, see `insert_slice`.
+    pub fn insert<C: Resource>(&self, entity_id: EntityId, component: C) -> Result<()> {
+        check_entity_component::<C>(Some(false))?;
+        self.push(Command::Insert(entity_id, C::id(), Box::new(component)));
+        return Ok(());
+    }
+
+    // Adds a component that is stored as a slice of resources, see `EntityDescriptor::with_slice`.
+    pub fn insert_slice<C: Resource>(&self, entity_id: EntityId, components: Vec<C>) -> Result<()> {
+        check_entity_component::<C>(Some(true))?;
+        self.push(Command::Insert(entity_id, C::id(), Box::new(components)));
+        return Ok(());
+    }
+
+    pub fn remove<C: Resource>(&self, entity_id: EntityId) -> Result<()> {
+        check_entity_component::<C>(None)?;
+        self.push(Command::Remove(entity_id, C::id()));
+        return Ok(());
+    }
+
+    fn push(&self, command: Command) {
+        // Every worker has its own queue, so the lock is never contended.
+        self.queue.lock().unwrap().push(command);
+    }
+}
+
+// Fails if the resource is not an entity component or, if `slice` is given, whether it is stored as
+// a slice of resources does not match. The storage type tells, so the storage does not have to be
+// locked while the job that records the command may hold a lock on it.
+fn check_entity_component<C: Resource>(slice: Option<bool>) -> Result<()> {
+    let storage = TypeId::of::<C::Storage>();
+    let is_slice = if storage == TypeId::of::<IdMappedResourceSliceStorage<EntityId, C>>() {
+        true
+    } else if storage == TypeId::of::<IdMappedResourceStorage<EntityId, C>>()
+        || storage == TypeId::of::<SparseResourceStorage<EntityId, C>>()
+    {
+        false
+    } else {
+        return Err(Error::new(
+            format!("{} is not an entity component", C::label()),
+            SourceLocation::here(),
+        ));
+    };
+    return match slice {
+        Some(true) if !is_slice => Err(Error::new(
+            format!(
+                "{} is not stored as a slice of resources, use insert",
+                C::label()
+            ),
+            SourceLocation::here(),
+        )),
+        Some(false) if is_slice => Err(Error::new(
+            format!(
+                "{} is stored as a slice of resources, use insert_slice",
+                C::label()
+            ),
+            SourceLocation::here(),
+        )),
+        _ => Ok(()),
+    };
+}
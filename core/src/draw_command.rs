@@ -0,0 +1,114 @@
+use crate::{Resource, SceneState};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy)]
+struct IndexBuffer<'a> {
+    slice: wgpu::BufferSlice<'a>,
+    format: wgpu::IndexFormat,
+    base_vertex: i32,
+}
+
+// A draw call that a render job fills and records into its render pass. Without an index buffer,
+// the elements are the vertex indices that are passed to the vertex shader. With one, they are
+// the range of indices in the index buffer and the vertex shader gets the indices read from it,
+// offset by the base vertex.
+//
+// The instance index in the shader can be used to look up per-instance data in the storage
+// buffers of the resources, e.g., drawing one instance per `Transform` with
+// `with_instance_per_component`. Keep in mind that only the `SparseResourceStorage` packs its
+// resources densely, an `IdMappedResourceStorage` is looked up via its reverse array, which has
+// empty entries for the entities without the component.
+#[derive(Debug, Clone)]
+pub struct DrawCommand<'a> {
+    elements: Range<u32>,
+    instances: Range<u32>,
+    index_buffer: Option<IndexBuffer<'a>>,
+}
+
+impl<'a> DrawCommand<'a> {
+    // Draws a single instance of the vertices.
+    pub fn new(vertices: Range<u32>) -> Self {
+        return Self {
+            elements: vertices,
+            instances: 0..1,
+            index_buffer: None,
+        };
+    }
+
+    // Draws a single instance of the given indices of the index buffer. The range is relative to
+    // the start of the slice.
+    pub fn indexed(
+        index_buffer: wgpu::BufferSlice<'a>,
+        format: wgpu::IndexFormat,
+        indices: Range<u32>,
+    ) -> Self {
+        return Self {
+            elements: indices,
+            instances: 0..1,
+            index_buffer: Some(IndexBuffer {
+                slice: index_buffer,
+                format,
+                base_vertex: 0,
+            }),
+        };
+    }
+
+    // The value that is added to the indices read from the index buffer. Has no effect on draws
+    // without an index buffer.
+    pub fn with_base_vertex(mut self, base_vertex: i32) -> Self {
+        if let Some(index_buffer) = &mut self.index_buffer {
+            index_buffer.base_vertex = base_vertex;
+        }
+        return self;
+    }
+
+    pub fn with_instances(mut self, instances: Range<u32>) -> Self {
+        self.instances = instances;
+        return self;
+    }
+
+    pub fn with_instance_count(self, instance_count: u32) -> Self {
+        return self.with_instances(0..instance_count);
+    }
+
+    // Draws one instance per slot of the component `C`, see `SceneState::component_slot_bound`.
+    // For an `IdMappedResourceStorage`, the instance index is the index of the entity and the
+    // shader has to skip the instances whose entry in the reverse array is empty, e.g., by
+    // producing degenerate triangles like the sprite shader. The bound is taken when the command
+    // is created, so it tracks the entities spawned and despawned in previous frames.
+    pub fn with_instance_per_component<C: Resource>(self, s: &SceneState) -> Self {
+        return self.with_instance_count(s.component_slot_bound(C::id()) as u32);
+    }
+
+    pub fn elements(&self) -> Range<u32> {
+        return self.elements.clone();
+    }
+
+    pub fn instances(&self) -> Range<u32> {
+        return self.instances.clone();
+    }
+
+    pub fn instance_count(&self) -> u32 {
+        return self.instances.len() as u32;
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        return self.index_buffer.is_some();
+    }
+
+    // Binds the index buffer if there is one and issues the draw call. The pipeline and bind
+    // groups have to be set by the job beforehand.
+    pub fn record(&self, render_pass: &mut wgpu::RenderPass<'a>) {
+        match &self.index_buffer {
+            Some(index_buffer) => {
+                render_pass.set_index_buffer(index_buffer.slice, index_buffer.format);
+                render_pass.draw_indexed(
+                    self.elements(),
+                    index_buffer.base_vertex,
+                    self.instances(),
+                );
+            }
+            None => render_pass.draw(self.elements(), self.instances()),
+        }
+    }
+}
@@ -1,3 +1,7 @@
+use std::sync::Arc;
+
+use crate::{Error, Result, SourceLocation};
+
 pub struct Gpu {
     index: usize,
     adapter: wgpu::Adapter,
@@ -10,27 +14,112 @@ pub struct Gpu {
 }
 
 impl Gpu {
-    pub async fn new(wgpu_instance: &wgpu::Instance, index: usize) -> Self {
-        let adapter = wgpu_instance
+    // Fails if the instance has no suitable adapter or the adapter does not provide a device with
+    // the features and limits the framework needs.
+    pub async fn new(wgpu_instance: &wgpu::Instance, index: usize) -> Result<Self> {
+        let Some(adapter) = wgpu_instance
             .request_adapter(&wgpu::RequestAdapterOptionsBase {
                 power_preference: wgpu::PowerPreference::default(),
                 force_fallback_adapter: false,
                 compatible_surface: None,
             })
             .await
-            .unwrap();
+        else {
+            return Err(no_adapter_error());
+        };
+        return Self::from_adapter(adapter, index).await;
+    }
 
+    // Creates a GPU for every adapter of the instance, e.g., to render viewports on different
+    // GPUs. Adapters that are exposed by multiple backends are only used once, with the backend
+    // that is enumerated first. Software renderers that run on the CPU are only used if the
+    // machine has no other adapter, e.g., on servers without a GPU. Discrete GPUs come first, so
+    // the first GPU is usually the fastest one. The index of a GPU is its position in the returned
+    // list, which is stable as long as the adapters of the machine do not change. Adapters that do
+    // not provide a suitable device are skipped, it only fails if none does.
+    pub async fn all(wgpu_instance: &wgpu::Instance) -> Result<Vec<Arc<Self>>> {
+        let mut adapters = Vec::<wgpu::Adapter>::new();
+        for adapter in wgpu_instance.enumerate_adapters(wgpu::Backends::all()) {
+            let info = adapter.get_info();
+            let is_duplicate = adapters.iter().any(|known| {
+                let known = known.get_info();
+                return known.vendor == info.vendor
+                    && known.device == info.device
+                    && known.name == info.name;
+            });
+            if !is_duplicate {
+                adapters.push(adapter);
+            }
+        }
+        if adapters.is_empty() {
+            return Err(no_adapter_error());
+        }
+        let is_software = |adapter: &wgpu::Adapter| {
+            return adapter.get_info().device_type == wgpu::DeviceType::Cpu;
+        };
+        if !adapters.iter().all(is_software) {
+            adapters.retain(|adapter| !is_software(adapter));
+        }
+        adapters.sort_by_key(|adapter| match adapter.get_info().device_type {
+            wgpu::DeviceType::DiscreteGpu => 0,
+            wgpu::DeviceType::IntegratedGpu => 1,
+            wgpu::DeviceType::VirtualGpu => 2,
+            wgpu::DeviceType::Other => 3,
+            wgpu::DeviceType::Cpu => 4,
+        });
+
+        let mut gpus = Vec::with_capacity(adapters.len());
+        let mut last_error = None;
+        for adapter in adapters {
+            match Self::from_adapter(adapter, gpus.len()).await {
+                Ok(gpu) => gpus.push(Arc::new(gpu)),
+                Err(error) => last_error = Some(error),
+            }
+        }
+        return match last_error {
+            Some(error) if gpus.is_empty() => Err(error),
+            _ => Ok(gpus),
+        };
+    }
+
+    async fn from_adapter(adapter: wgpu::Adapter, index: usize) -> Result<Self> {
+        // Timestamp queries are optional, they time the jobs on the GPU while profiling. So are
+        // the polygon modes other than fill, see `PipelineConfig::supported_by`, and the sample
+        // counts that are not guaranteed for every format, see `supports_sample_count`.
+        let features = adapter.features()
+            & (wgpu::Features::TIMESTAMP_QUERY
+                | wgpu::Features::POLYGON_MODE_LINE
+                | wgpu::Features::POLYGON_MODE_POINT
+                | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        // Every resource storage binds its buffers to the resource bind groups, so use as many
+        // storage buffers and bind groups as the adapter supports.
+        let limits = wgpu::Limits {
+            max_storage_buffers_per_shader_stage: adapter
+                .limits()
+                .max_storage_buffers_per_shader_stage,
+            max_bind_groups: adapter.limits().max_bind_groups,
+            ..wgpu::Limits::default()
+        };
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
+                    features,
+                    limits: limits.clone(),
                     label: None,
                 },
                 None,
             )
             .await
-            .unwrap();
+            .map_err(|error| {
+                let adapter_name = adapter.get_info().name;
+                return Error::new(
+                    format!(
+                        "failed to request a device from the adapter {adapter_name}: {error} \
+                         (requested features: {features:?}, requested limits: {limits:?})"
+                    ),
+                    SourceLocation::here(),
+                );
+            })?;
 
         let shader_module = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
 
@@ -68,7 +157,7 @@ impl Gpu {
             }],
         });
 
-        return Gpu {
+        return Ok(Gpu {
             index,
             device,
             queue,
@@ -77,7 +166,7 @@ impl Gpu {
             entity_buffer,
             bind_group_layout,
             bind_group,
-        };
+        });
     }
 
     pub fn index(&self) -> usize {
@@ -107,4 +196,80 @@ impl Gpu {
     pub fn system_bind_group(&self) -> &wgpu::BindGroup {
         &self.bind_group
     }
+
+    // Whether the device supports timestamp queries, without them the jobs are not timed on the
+    // GPU while profiling.
+    pub fn supports_timestamps(&self) -> bool {
+        return self
+            .device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY);
+    }
+
+    // Whether textures of the format can be rendered to with the given number of samples per
+    // pixel and resolved afterwards. Without adapter specific format features, only the counts
+    // guaranteed by WebGPU are supported, i.e., 1 and 4 for most color formats.
+    pub fn supports_sample_count(&self, format: wgpu::TextureFormat, sample_count: u32) -> bool {
+        if sample_count == 1 {
+            return true;
+        }
+        let features = if self
+            .device
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        {
+            self.adapter.get_texture_format_features(format)
+        } else {
+            format.describe().guaranteed_format_features
+        };
+        return features.flags.sample_count_supported(sample_count)
+            && features
+                .flags
+                .contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE);
+    }
+}
+
+fn no_adapter_error() -> Error {
+    return Error::new(
+        "no graphics adapter found, make sure a GPU driver supporting Vulkan, Metal, DX12 or \
+         OpenGL is installed",
+        SourceLocation::here(),
+    );
+}
+
+// Creates a GPU for tests or returns None if the machine running the tests has no adapter, so the
+// GPU parts of the tests are skipped. Machines that are expected to have one set `OVIS_REQUIRE_GPU`
+// to make these tests fail instead. Other crates use it with the `test-support` feature.
+#[cfg(any(test, feature = "test-support"))]
+pub fn test_gpu() -> Option<std::sync::Arc<Gpu>> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let gpu = pollster::block_on(Gpu::new(&instance, 0));
+    if std::env::var_os("OVIS_REQUIRE_GPU").is_some() {
+        return Some(std::sync::Arc::new(gpu.unwrap()));
+    }
+    return gpu.ok().map(std::sync::Arc::new);
+}
+
+// Creates a GPU for every adapter of the machine running the tests, see `Gpu::all`.
+#[cfg(test)]
+pub(crate) fn test_gpus() -> Vec<std::sync::Arc<Gpu>> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    return pollster::block_on(Gpu::all(&instance)).unwrap_or_default();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn missing_adapters_are_reported() {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::empty(),
+            ..Default::default()
+        });
+        let error = pollster::block_on(Gpu::new(&instance, 0)).err().unwrap();
+        assert!(error.message().contains("no graphics adapter found"));
+        let error = pollster::block_on(Gpu::all(&instance)).err().unwrap();
+        assert!(error.message().contains("no graphics adapter found"));
+    }
 }
@@ -1,39 +1,59 @@
-use std::{mem::MaybeUninit, iter::{Enumerate, FilterMap}};
+use std::mem::MaybeUninit;
 
-use crate::{StandardVersionedIndexId, VersionedIndexId};
+use crate::{Error, Result, SourceLocation, StandardVersionedIndexId, VersionedIndexId};
 
+// Stores which ids are reserved. The slot of a reserved id stores the id itself, so it has the
+// index of the slot. The indices of the free slots are kept in a list, and a free slot stores its
+// position in that list and the version of the last id at its index instead. The position can be
+// the index of the slot as well, so free slots are recognized by checking the list at that
+// position. This way, `claim` can take any free slot out of the list in constant time.
 pub struct IdStorage<Id: VersionedIndexId = StandardVersionedIndexId> {
     ids: Vec<Id>,
-    free_list_head: usize,
-    free_list_size: usize,
+    free_indices: Vec<usize>,
 }
 
 impl<Id: VersionedIndexId> IdStorage<Id> {
-    const FREE_LIST_END: usize = Id::MAX_INDEX;
     pub const MAX_SIZE: usize = Id::MAX_INDEX;
 
     pub fn new() -> Self {
         Self {
             ids: vec![],
-            free_list_head: Self::FREE_LIST_END,
-            free_list_size: 0,
+            free_indices: vec![],
         }
     }
 
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            ids: Vec::with_capacity(capacity),
+            free_indices: vec![],
+        }
+    }
+
+    // Makes room for `additional` more ids without reallocating, e.g., before loading a scene.
+    // Freed slots are reused first, so only the ids that do not fit into them need new memory.
+    pub fn reserve_exact(&mut self, additional: usize) {
+        self.ids.reserve_exact(additional.saturating_sub(self.free_indices.len()));
+    }
+
     pub fn len(&self) -> usize {
-        return self.ids.len() - self.free_list_size;
+        return self.ids.len() - self.free_indices.len();
     }
 
-    pub fn reserve(&mut self) -> Id {
-        if self.free_list_head != Self::FREE_LIST_END {
-            let index = self.free_list_head;
-            let indexed_id = self.ids[index];
-            self.free_list_head = indexed_id.index();
+    pub fn capacity(&self) -> usize {
+        return self.ids.capacity();
+    }
 
-            let id = Id::from_index_and_version(index, indexed_id.version()).next_version_id();
-            self.ids[index] = id;
-            self.free_list_size -= 1;
+    // One more than the largest index that was ever reserved. All ids in the storage have an
+    // index smaller than this, e.g., it can be used as the instance count when drawing entities.
+    pub fn index_bound(&self) -> usize {
+        return self.ids.len();
+    }
 
+    pub fn reserve(&mut self) -> Id {
+        if let Some(index) = self.free_indices.pop() {
+            let version = self.ids[index].version();
+            let id = Id::from_index_and_version(index, version).next_version_id();
+            self.ids[index] = id;
             return id;
         } else {
             let id = Id::from_index(self.ids.len().into());
@@ -42,33 +62,114 @@ impl<Id: VersionedIndexId> IdStorage<Id> {
         }
     }
 
+    // Reserves exactly the given id, e.g., to restore the ids of a saved scene. Fails if its index
+    // is in use or if the id is not newer than the last one at its index, as ids that were freed
+    // may still be referred to. Indices below it that were never reserved are added to the free
+    // slots and are handed out with version 0 by `reserve`. The storage grows up to the index, so
+    // callers bound indices that are read from files, see `SceneState::load_json`.
+    pub fn claim(&mut self, id: Id) -> Result<()> {
+        let index = id.index();
+        if index >= Id::MAX_INDEX {
+            return Err(Error::new(
+                format!("id {id} exceeds the maximum index"),
+                SourceLocation::here(),
+            ));
+        }
+        if index >= self.ids.len() {
+            for free_index in self.ids.len()..index {
+                self.ids.push(Id::from_index_and_version(self.free_indices.len(), Id::MAX_VERSION));
+                self.free_indices.push(free_index);
+            }
+            self.ids.push(id);
+            return Ok(());
+        }
+        if let Some(current_id) = self.current_id_at(index) {
+            return Err(Error::new(
+                format!("id {id} conflicts with the reserved id {current_id}"),
+                SourceLocation::here(),
+            ));
+        }
+
+        // Slots that were never reserved store the maximum version, see above.
+        let freed_version = self.ids[index].version();
+        if id.version() == freed_version
+            || (freed_version != Id::MAX_VERSION && id.version() < freed_version)
+        {
+            return Err(Error::new(
+                format!("id {id} is not newer than the freed id with version {freed_version}"),
+                SourceLocation::here(),
+            ));
+        }
+
+        // The last free index takes the position of the claimed one.
+        let position = self.ids[index].index();
+        self.free_indices.swap_remove(position);
+        if let Some(&moved_index) = self.free_indices.get(position) {
+            let moved_version = self.ids[moved_index].version();
+            self.ids[moved_index] = Id::from_index_and_version(position, moved_version);
+        }
+        self.ids[index] = id;
+        return Ok(());
+    }
+
     pub fn free(&mut self, id: Id) {
         assert!(self.contains(id));
         let index = id.index();
-        self.ids[index] = Id::from_index_and_version(self.free_list_head, id.version());
-        self.free_list_head = id.index();
-        self.free_list_size += 1;
+        self.ids[index] = Id::from_index_and_version(self.free_indices.len(), id.version());
+        self.free_indices.push(index);
+    }
+
+    // Frees all ids. Like freeing them one by one, the slots keep the versions of their ids, so the
+    // ids that are reserved or claimed afterwards differ from the freed ones.
+    pub fn clear(&mut self) {
+        for index in 0..self.ids.len() {
+            if let Some(id) = self.current_id_at(index) {
+                self.free(id);
+            }
+        }
     }
 
     pub fn contains(&self, id: Id) -> bool {
-        return id.index() < self.ids.len() && self.ids[id.index()] == id;
+        return self.current_id_at(id.index()) == Some(id);
     }
+
+    // Returns the id that is currently reserved at the index or None if the index is free or was
+    // never reserved.
+    pub fn current_id_at(&self, index: usize) -> Option<Id> {
+        let id = *self.ids.get(index)?;
+        let is_free = id.index() != index || self.free_indices.get(index) == Some(&index);
+        return (!is_free).then_some(id);
+    }
+}
+
+pub struct IdStorageIterator<'a, Id: VersionedIndexId> {
+    storage: &'a IdStorage<Id>,
+    index: usize,
 }
 
-fn id_filter<Id: VersionedIndexId>(p: (usize, &Id)) -> Option<Id> {
-    if p.0 == p.1.index() {
-        return Some(*p.1);
-    } else {
+impl<'a, Id: VersionedIndexId> Iterator for IdStorageIterator<'a, Id> {
+    type Item = Id;
+
+    fn next(&mut self) -> Option<Id> {
+        while self.index < self.storage.ids.len() {
+            self.index += 1;
+            if let Some(id) = self.storage.current_id_at(self.index - 1) {
+                return Some(id);
+            }
+        }
         return None;
     }
 }
 
 impl<'a, Id: VersionedIndexId> IntoIterator for &'a IdStorage<Id> {
     type Item = Id;
-    type IntoIter = FilterMap<Enumerate<std::slice::Iter<'a, Id>>, fn((usize, &'a Id)) -> Option<Id>>;
+    type IntoIter = IdStorageIterator<'a, Id>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.ids.iter().enumerate().filter_map(id_filter)
+        return IdStorageIterator {
+            storage: self,
+            index: 0,
+        };
     }
 }
 
@@ -96,6 +197,113 @@ fn id_storage_works() {
     assert_eq!(storage.into_iter().collect::<Vec<_>>(), vec![second_id]);
 }
 
+#[test]
+fn current_id_at_returns_live_ids() {
+    type Id = StandardVersionedIndexId;
+    let mut storage = IdStorage::<Id>::new();
+    let first_id = storage.reserve();
+    let second_id = storage.reserve();
+    assert_eq!(storage.current_id_at(second_id.index()), Some(second_id));
+    assert_eq!(storage.current_id_at(2), None);
+
+    storage.free(first_id);
+    storage.free(second_id);
+    assert_eq!(storage.current_id_at(first_id.index()), None);
+    assert_eq!(storage.current_id_at(second_id.index()), None);
+
+    // The slot is reused with the next version.
+    let reused_id = storage.reserve();
+    assert_eq!(reused_id.index(), second_id.index());
+    assert_eq!(storage.current_id_at(reused_id.index()), Some(reused_id));
+    assert_ne!(reused_id, second_id);
+}
+
+#[test]
+fn reserved_capacity_is_not_reallocated() {
+    type Id = StandardVersionedIndexId;
+    const COUNT: usize = 10_000;
+
+    let mut storage = IdStorage::<Id>::with_capacity(COUNT);
+    let ids = storage.ids.as_ptr();
+    for _ in 0..COUNT {
+        storage.reserve();
+    }
+    assert_eq!(storage.ids.as_ptr(), ids);
+
+    // Freed slots are reused, so they count towards the reserved capacity.
+    for index in 0..COUNT / 2 {
+        storage.free(Id::from_index(index));
+    }
+    storage.reserve_exact(COUNT);
+    assert_eq!(storage.capacity(), COUNT + COUNT / 2);
+    let ids = storage.ids.as_ptr();
+    for _ in 0..COUNT {
+        storage.reserve();
+    }
+    assert_eq!(storage.ids.as_ptr(), ids);
+    assert_eq!(storage.len(), 3 * COUNT / 2);
+}
+
+#[test]
+fn specific_ids_can_be_reserved() {
+    type Id = StandardVersionedIndexId;
+    let mut storage = IdStorage::<Id>::new();
+    let specific_id = Id::from_index_and_version(3, 5);
+    storage.claim(specific_id).unwrap();
+    assert_eq!(storage.len(), 1);
+    assert_eq!(storage.index_bound(), 4);
+    assert!(storage.contains(specific_id));
+
+    let error = storage.claim(Id::from_index(3)).unwrap_err();
+    assert_eq!(error.message(), "id [3;0] conflicts with the reserved id [3;5]");
+
+    // A free slot in the middle of the free list can be claimed as well.
+    storage.claim(Id::from_index_and_version(1, 2)).unwrap();
+    let mut reserved = [storage.reserve(), storage.reserve()];
+    reserved.sort_by_key(|id| id.index());
+    assert_eq!(reserved, [Id::from_index(0), Id::from_index(2)]);
+    assert_eq!(storage.len(), 4);
+    assert_eq!(storage.reserve(), Id::from_index(4));
+}
+
+#[test]
+fn cleared_ids_are_not_handed_out_again() {
+    type Id = StandardVersionedIndexId;
+    let mut storage = IdStorage::<Id>::new();
+    let ids = [storage.reserve(), storage.reserve()];
+    storage.clear();
+    assert_eq!(storage.len(), 0);
+    assert!(!storage.contains(ids[0]) && !storage.contains(ids[1]));
+
+    let error = storage.claim(ids[1]).unwrap_err();
+    assert_eq!(error.message(), "id [1;0] is not newer than the freed id with version 0");
+    storage.claim(ids[1].next_version_id()).unwrap();
+    assert_eq!(storage.reserve(), ids[0].next_version_id());
+}
+
+#[test]
+fn any_free_slot_can_be_claimed() {
+    type Id = StandardVersionedIndexId;
+    let mut storage = IdStorage::<Id>::new();
+    let ids = (0..8).map(|_| storage.reserve()).collect::<Vec<_>>();
+    // The slots are freed in order, so every free slot stores its own index as its position.
+    for &id in &ids {
+        storage.free(id);
+    }
+    for index in [3, 0, 7, 5] {
+        storage.claim(ids[index].next_version_id()).unwrap();
+    }
+    assert_eq!(storage.len(), 4);
+    let claimed_ids = storage.into_iter().collect::<Vec<_>>();
+    assert_eq!(claimed_ids, [0, 3, 5, 7].map(|index| ids[index].next_version_id()));
+    assert_eq!(storage.current_id_at(1), None);
+
+    let mut reserved = (0..4).map(|_| storage.reserve().index()).collect::<Vec<_>>();
+    reserved.sort();
+    assert_eq!(reserved, [1, 2, 4, 6]);
+    assert_eq!(storage.reserve().index(), 8);
+}
+
 pub struct IdMap<Id: VersionedIndexId, T> {
     ids: IdStorage<Id>,
     values: Vec<MaybeUninit<T>>,
@@ -109,6 +317,13 @@ impl<Id: VersionedIndexId, T> IdMap<Id, T> {
         }
     }
 
+    pub fn with_capacity(capacity: usize) -> Self {
+        return Self {
+            ids: IdStorage::with_capacity(capacity),
+            values: Vec::with_capacity(capacity),
+        };
+    }
+
     pub fn len(&self) -> usize {
         return self.ids.len();
     }
@@ -116,11 +331,20 @@ impl<Id: VersionedIndexId, T> IdMap<Id, T> {
     pub fn insert(&mut self, value: T) -> (Id, &mut T) {
         let id = self.ids.reserve();
         if id.index() >= self.values.len() {
-            self.values.resize_with(self.ids.len(), || MaybeUninit::uninit());
+            self.values.resize_with(self.ids.index_bound(), || MaybeUninit::uninit());
         }
         return (id, self.values[id.index()].write(value));
     }
 
+    // Inserts the value with the given id, see `IdStorage::claim`.
+    pub fn insert_at(&mut self, id: Id, value: T) -> Result<&mut T> {
+        self.ids.claim(id)?;
+        if id.index() >= self.values.len() {
+            self.values.resize_with(self.ids.index_bound(), || MaybeUninit::uninit());
+        }
+        return Ok(self.values[id.index()].write(value));
+    }
+
     pub fn get(&self, id: Id) -> Option<&T> {
         if self.ids.contains(id) {
             unsafe {
@@ -173,12 +397,9 @@ impl<'a, Id: VersionedIndexId, T> Iterator for IdMapIntoIterator<'a, Id, T> {
     type Item = (Id, &'a T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(id) = self.id_iterator.next() {
-            unsafe {
-                return Some((id, self.values[id.index()].assume_init_ref()));
-            }
-        } else {
-            return None
+        let id = self.id_iterator.next()?;
+        unsafe {
+            return Some((id, self.values[id.index()].assume_init_ref()));
         }
     }
 }
@@ -192,12 +413,10 @@ impl<'a, Id: VersionedIndexId, T> Iterator for IdMapMutIntoIterator<'a, Id, T> {
     type Item = (Id, &'a mut T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(id) = self.id_iterator.next() {
-            unsafe {
-                return Some((id, self.values[id.index()].as_mut_ptr().as_mut().unwrap_unchecked())); // TODO: is this safe? :D
-            }
-        } else {
-            return None
+        let id = self.id_iterator.next()?;
+        unsafe {
+            let value = self.values[id.index()].as_mut_ptr().as_mut().unwrap_unchecked();
+            return Some((id, value)); // TODO: is this safe? :D
         }
     }
 }
@@ -262,6 +481,64 @@ mod test {
         assert!(map.get(id).is_none());
         assert_eq!(Rc::strong_count(&value), 1);
     }
+
+    #[test]
+    fn values_can_be_inserted_at_claimed_ids() {
+        type Id = StandardVersionedIndexId;
+        let value = Rc::new(0);
+        let mut map = IdMap::<Id, Rc<i32>>::new();
+        let ids = [(); 3].map(|_| map.insert(value.clone()).0);
+        map.remove(ids[1]);
+
+        let claimed_id = Id::from_index_and_version(1, 7);
+        map.insert_at(claimed_id, value.clone()).unwrap();
+        assert!(map.get(claimed_id).is_some());
+        assert!(map.get(ids[1]).is_none());
+        let error = map.insert_at(Id::from_index(1), value.clone()).unwrap_err();
+        assert_eq!(error.message(), "id [1;0] conflicts with the reserved id [1;7]");
+
+        // Claiming an index past the end leaves a gap that is filled by later inserts.
+        map.insert_at(Id::from_index(5), value.clone()).unwrap();
+        let (id, _) = map.insert(value.clone());
+        assert!((3..5).contains(&id.index()));
+        assert_eq!(map.len(), 5);
+        assert_eq!(Rc::strong_count(&value), 6);
+
+        drop(map);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn inserts_skip_claimed_indices() {
+        type Id = StandardVersionedIndexId;
+        let mut map = IdMap::<Id, usize>::new();
+        let ids = (0..5).map(|index| map.insert(index).0).collect::<Vec<_>>();
+        for &id in &ids {
+            map.remove(id);
+        }
+
+        let claimed_id = ids[2].next_version_id();
+        map.insert_at(claimed_id, 2).unwrap();
+        let mut indices = (0..4).map(|_| map.insert(0).0.index()).collect::<Vec<_>>();
+        indices.sort();
+        assert_eq!(indices, [0, 1, 3, 4]);
+        assert_eq!(map.insert(0).0.index(), 5);
+        assert_eq!(map.get(claimed_id), Some(&2));
+    }
+
+    #[test]
+    fn id_map_with_capacity_does_not_reallocate() {
+        type Id = StandardVersionedIndexId;
+        const COUNT: usize = 1000;
+
+        let mut map = IdMap::<Id, usize>::with_capacity(COUNT);
+        let values = map.values.as_ptr();
+        for i in 0..COUNT {
+            map.insert(i);
+        }
+        assert_eq!(map.values.as_ptr(), values);
+        assert_eq!(map.ids.capacity(), COUNT);
+    }
 }
 
 pub struct SimpleStorage<T> {
@@ -284,7 +561,7 @@ impl<T> SimpleStorage<T> {
         self.data = None;
     }
 
-    pub fn get(&mut self) -> Option<&T> {
+    pub fn get(&self) -> Option<&T> {
         self.data.as_ref()
     }
 
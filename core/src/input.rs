@@ -0,0 +1,216 @@
+use std::collections::HashSet;
+
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+
+// The keys jobs can query, see `Input`. Only the keys that games commonly bind are included, the
+// names do not depend on the version of winit.
+#[rustfmt::skip]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Digit0, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Up, Down, Left, Right,
+    Space, Enter, Escape, Tab, Backspace, Delete, Insert, Home, End, PageUp, PageDown,
+    LeftShift, RightShift, LeftControl, RightControl, LeftAlt, RightAlt,
+}
+
+impl Key {
+    // Returns None for keys that are not part of `Key`.
+    pub fn from_virtual_keycode(keycode: VirtualKeyCode) -> Option<Self> {
+        use VirtualKeyCode as V;
+        return Some(match keycode {
+            V::A => Key::A,
+            V::B => Key::B,
+            V::C => Key::C,
+            V::D => Key::D,
+            V::E => Key::E,
+            V::F => Key::F,
+            V::G => Key::G,
+            V::H => Key::H,
+            V::I => Key::I,
+            V::J => Key::J,
+            V::K => Key::K,
+            V::L => Key::L,
+            V::M => Key::M,
+            V::N => Key::N,
+            V::O => Key::O,
+            V::P => Key::P,
+            V::Q => Key::Q,
+            V::R => Key::R,
+            V::S => Key::S,
+            V::T => Key::T,
+            V::U => Key::U,
+            V::V => Key::V,
+            V::W => Key::W,
+            V::X => Key::X,
+            V::Y => Key::Y,
+            V::Z => Key::Z,
+            V::Key0 | V::Numpad0 => Key::Digit0,
+            V::Key1 | V::Numpad1 => Key::Digit1,
+            V::Key2 | V::Numpad2 => Key::Digit2,
+            V::Key3 | V::Numpad3 => Key::Digit3,
+            V::Key4 | V::Numpad4 => Key::Digit4,
+            V::Key5 | V::Numpad5 => Key::Digit5,
+            V::Key6 | V::Numpad6 => Key::Digit6,
+            V::Key7 | V::Numpad7 => Key::Digit7,
+            V::Key8 | V::Numpad8 => Key::Digit8,
+            V::Key9 | V::Numpad9 => Key::Digit9,
+            V::F1 => Key::F1,
+            V::F2 => Key::F2,
+            V::F3 => Key::F3,
+            V::F4 => Key::F4,
+            V::F5 => Key::F5,
+            V::F6 => Key::F6,
+            V::F7 => Key::F7,
+            V::F8 => Key::F8,
+            V::F9 => Key::F9,
+            V::F10 => Key::F10,
+            V::F11 => Key::F11,
+            V::F12 => Key::F12,
+            V::Up => Key::Up,
+            V::Down => Key::Down,
+            V::Left => Key::Left,
+            V::Right => Key::Right,
+            V::Space => Key::Space,
+            V::Return | V::NumpadEnter => Key::Enter,
+            V::Escape => Key::Escape,
+            V::Tab => Key::Tab,
+            V::Back => Key::Backspace,
+            V::Delete => Key::Delete,
+            V::Insert => Key::Insert,
+            V::Home => Key::Home,
+            V::End => Key::End,
+            V::PageUp => Key::PageUp,
+            V::PageDown => Key::PageDown,
+            V::LShift => Key::LeftShift,
+            V::RShift => Key::RightShift,
+            V::LControl => Key::LeftControl,
+            V::RControl => Key::RightControl,
+            V::LAlt => Key::LeftAlt,
+            V::RAlt => Key::RightAlt,
+            _ => return None,
+        });
+    }
+}
+
+// The state of the keyboard of a scene, see `SystemResources::input`. It is updated from the
+// events of the windows of the scene between frames, so it does not change while the jobs run.
+// The keys that were pressed or released since the last frame are tracked separately, so a key
+// that is tapped within a frame is still noticed.
+#[derive(Debug, Default)]
+pub struct Input {
+    pressed: HashSet<Key>,
+    just_pressed: HashSet<Key>,
+    just_released: HashSet<Key>,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn is_pressed(&self, key: Key) -> bool {
+        return self.pressed.contains(&key);
+    }
+
+    // Whether the key went down since the last frame. Repeated key events of a held key do not
+    // count.
+    pub fn just_pressed(&self, key: Key) -> bool {
+        return self.just_pressed.contains(&key);
+    }
+
+    pub fn just_released(&self, key: Key) -> bool {
+        return self.just_released.contains(&key);
+    }
+
+    pub fn pressed_keys(&self) -> impl Iterator<Item = Key> + '_ {
+        return self.pressed.iter().copied();
+    }
+
+    pub fn press(&mut self, key: Key) {
+        if self.pressed.insert(key) {
+            self.just_pressed.insert(key);
+        }
+    }
+
+    pub fn release(&mut self, key: Key) {
+        if self.pressed.remove(&key) {
+            self.just_released.insert(key);
+        }
+    }
+
+    // Releases all keys, e.g., when the window loses the focus and the release events would be
+    // missed.
+    pub fn release_all(&mut self) {
+        self.just_released.extend(self.pressed.drain());
+    }
+
+    // Applies a keyboard event of winit. Keys that are not part of `Key` are ignored.
+    pub fn handle_keyboard_input(&mut self, input: &KeyboardInput) {
+        let Some(key) = input.virtual_keycode.and_then(Key::from_virtual_keycode) else {
+            return;
+        };
+        match input.state {
+            ElementState::Pressed => self.press(key),
+            ElementState::Released => self.release(key),
+        }
+    }
+
+    // Forgets which keys were pressed or released in the frame that ended.
+    pub fn end_frame(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[allow(deprecated)]
+    fn event(keycode: VirtualKeyCode, state: ElementState) -> KeyboardInput {
+        return KeyboardInput {
+            scancode: 0,
+            state,
+            virtual_keycode: Some(keycode),
+            modifiers: Default::default(),
+        };
+    }
+
+    #[test]
+    fn key_edges_are_detected_once() {
+        let mut input = Input::new();
+        input.handle_keyboard_input(&event(VirtualKeyCode::W, ElementState::Pressed));
+        assert!(input.is_pressed(Key::W));
+        assert!(input.just_pressed(Key::W));
+        assert!(!input.just_released(Key::W));
+
+        // Holding the key repeats the event, but it is not pressed again.
+        input.end_frame();
+        input.handle_keyboard_input(&event(VirtualKeyCode::W, ElementState::Pressed));
+        assert!(input.is_pressed(Key::W));
+        assert!(!input.just_pressed(Key::W));
+
+        input.end_frame();
+        input.handle_keyboard_input(&event(VirtualKeyCode::W, ElementState::Released));
+        assert!(!input.is_pressed(Key::W));
+        assert!(input.just_released(Key::W));
+        input.end_frame();
+        assert!(!input.just_released(Key::W));
+
+        // A key that is tapped within a frame is both pressed and released.
+        input.handle_keyboard_input(&event(VirtualKeyCode::Space, ElementState::Pressed));
+        input.handle_keyboard_input(&event(VirtualKeyCode::Space, ElementState::Released));
+        assert!(input.just_pressed(Key::Space) && input.just_released(Key::Space));
+        assert!(!input.is_pressed(Key::Space));
+
+        input.handle_keyboard_input(&event(VirtualKeyCode::Numpad4, ElementState::Pressed));
+        input.handle_keyboard_input(&event(VirtualKeyCode::Mute, ElementState::Pressed));
+        assert_eq!(input.pressed_keys().collect::<Vec<_>>(), [Key::Digit4]);
+        input.end_frame();
+        input.release_all();
+        assert!(input.just_released(Key::Digit4));
+        assert_eq!(input.pressed_keys().count(), 0);
+    }
+}
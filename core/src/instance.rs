@@ -1,33 +1,85 @@
 use std::{sync::Arc, time::Instant};
 use winit::{
-    error::OsError,
+    dpi::{PhysicalPosition, PhysicalSize},
     event::*,
     event_loop::{ControlFlow, EventLoop},
     monitor::MonitorHandle,
-    window::{Window, WindowBuilder},
+    window::{Window, WindowBuilder, WindowId},
 };
 
-use crate::{Gpu, Scene};
+use crate::{
+    Error, Gpu, JobId, Resource, ResourceAccess, ResourceId, Scene, SourceLocation,
+    ViewportConfig, ViewportEvent, ViewportId,
+};
+
+// Resizes the viewports that present to the window.
+fn resize_window(scenes: &mut [Scene], window_id: WindowId, size: PhysicalSize<u32>) {
+    for scene in scenes {
+        if let Some(viewport_id) = scene.viewport_of_window(window_id) {
+            scene.resize_viewport(viewport_id, size);
+        }
+    }
+}
+
+// Moves the cursor within the viewports that present to the window.
+fn move_cursor(scenes: &[Scene], window_id: WindowId, position: PhysicalPosition<f64>) {
+    for scene in scenes {
+        let Some(viewport_id) = scene.viewport_of_window(window_id) else {
+            continue;
+        };
+        let state = scene.state();
+        let Some(size) = state.viewports().read().unwrap().get(viewport_id).map(|v| v.size()) else {
+            continue;
+        };
+        let position = [position.x as f32, position.y as f32];
+        state.mouse_mut().move_cursor(viewport_id, position, size);
+    }
+}
+
+// Emits the event for the viewports that present to the window.
+fn emit_viewport_event(
+    scenes: &[Scene],
+    window_id: WindowId,
+    event: impl Fn(ViewportId) -> ViewportEvent,
+) {
+    for scene in scenes {
+        if let Some(viewport_id) = scene.viewport_of_window(window_id) {
+            scene.state().emit_between_frames(event(viewport_id));
+        }
+    }
+}
+
+// The scenes that present to the window and receive its input.
+fn scenes_of_window(scenes: &[Scene], window_id: WindowId) -> impl Iterator<Item = &Scene> {
+    return scenes
+        .iter()
+        .filter(move |scene| scene.viewport_of_window(window_id).is_some());
+}
 
 pub struct Instance {
     wgpu_instance: wgpu::Instance,
     gpus: Vec<Arc<Gpu>>,
     event_loop: EventLoop<()>,
+    quit_on_escape: bool,
 }
 
 impl Instance {
-    pub async fn new() -> Self {
+    // Uses all GPUs of the machine, see `Gpu::all`. Fails if no GPU is available.
+    pub async fn new() -> crate::Result<Self> {
         let wgpu_instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
 
-        let gpus = vec![Arc::new(Gpu::new(&wgpu_instance, 0).await)];
+        let gpus = Gpu::all(&wgpu_instance).await?;
+        // Scenes only have storages for the resources that were registered before them.
+        ViewportEvent::register();
 
         let instance = Self {
             event_loop: EventLoop::new(),
             gpus,
             wgpu_instance,
+            quit_on_escape: true,
         };
 
-        return instance;
+        return Ok(instance);
     }
 
     pub fn wgpu(&self) -> &wgpu::Instance {
@@ -38,34 +90,94 @@ impl Instance {
         return &self.gpus;
     }
 
+    // Returns the registered jobs that access the resource, e.g., to find out which jobs are
+    // affected by changing a component.
+    pub fn jobs_accessing(&self, resource_id: ResourceId) -> Vec<(JobId, ResourceAccess)> {
+        return crate::jobs_accessing(resource_id);
+    }
+
     pub fn primary_monitor(&self) -> Option<MonitorHandle> {
         return self.event_loop.primary_monitor();
     }
 
+    // By default, pressing Escape quits like closing a window. Games that bind Escape, e.g., to
+    // open a menu, disable it.
+    pub fn set_quit_on_escape(&mut self, quit_on_escape: bool) {
+        self.quit_on_escape = quit_on_escape;
+    }
+
+    // Ticks the scenes until a window is closed. The event loop cannot return the errors of the
+    // ticks, so they are reported as tracing events, see the `tracing` feature, and the scenes
+    // keep running.
     pub fn run<S: IntoIterator<Item = Scene>>(self, scenes: S) {
         let mut scenes = scenes.into_iter().collect::<Vec<_>>();
         let mut last_update = Instant::now();
+        let quit_on_escape = self.quit_on_escape;
+        // Quitting is delayed by a frame, so the jobs can react to the close request.
+        let mut quitting = false;
 
         self.event_loop.run(move |event, _, control_flow| {
             *control_flow = ControlFlow::Wait;
 
-            println!("{:?}", event);
+            trace_debug!(?event, "window event");
 
             match event {
                 Event::WindowEvent {
                     ref event,
-                    window_id: _,
+                    window_id,
                 } => match event {
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
-                        ..
-                    } => *control_flow = ControlFlow::Exit,
+                    WindowEvent::CloseRequested => {
+                        emit_viewport_event(&scenes, window_id, |viewport_id| {
+                            ViewportEvent::CloseRequested { viewport_id }
+                        });
+                        quitting = true;
+                    }
+                    WindowEvent::KeyboardInput { input, .. } => {
+                        if quit_on_escape
+                            && input.state == ElementState::Pressed
+                            && input.virtual_keycode == Some(VirtualKeyCode::Escape)
+                        {
+                            quitting = true;
+                        }
+                        for scene in scenes_of_window(&scenes, window_id) {
+                            scene.state().input_mut().handle_keyboard_input(input);
+                        }
+                    }
+                    WindowEvent::Focused(focused) => {
+                        if !focused {
+                            for scene in scenes_of_window(&scenes, window_id) {
+                                scene.state().input_mut().release_all();
+                                scene.state().mouse_mut().release_all();
+                            }
+                        }
+                        emit_viewport_event(&scenes, window_id, |viewport_id| {
+                            ViewportEvent::Focused { viewport_id, focused: *focused }
+                        });
+                    }
+                    WindowEvent::CursorMoved { position, .. } => {
+                        move_cursor(&scenes, window_id, *position)
+                    }
+                    WindowEvent::CursorLeft { .. } => {
+                        for scene in &scenes {
+                            if let Some(viewport_id) = scene.viewport_of_window(window_id) {
+                                scene.state().mouse_mut().leave_viewport(viewport_id);
+                            }
+                        }
+                    }
+                    WindowEvent::MouseInput { state, button, .. } => {
+                        for scene in scenes_of_window(&scenes, window_id) {
+                            scene.state().mouse_mut().handle_mouse_input(*state, *button);
+                        }
+                    }
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        for scene in scenes_of_window(&scenes, window_id) {
+                            scene.state().mouse_mut().handle_mouse_wheel(*delta);
+                        }
+                    }
+                    WindowEvent::Resized(size) => resize_window(&mut scenes, window_id, *size),
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        resize_window(&mut scenes, window_id, **new_inner_size)
+                    }
                     _ => {}
                 },
                 Event::MainEventsCleared => {
@@ -75,9 +187,12 @@ impl Instance {
 
                     for scene in &mut scenes {
                         if let Err(error) = scene.tick(diff as f32) {
-                            println!("{error}")
+                            trace_error!(%error, "failed to tick a scene");
                         }
                     }
+                    if quitting {
+                        *control_flow = ControlFlow::Exit;
+                    }
                 }
                 _ => {}
             }
@@ -88,15 +203,33 @@ impl Instance {
         &self,
         scene: &mut Scene,
         window_builder: WindowBuilder,
-    ) -> Result<Window, OsError> {
-        match window_builder.build(&self.event_loop) {
-            Ok(window) => {
-                let surface = unsafe { self.wgpu_instance.create_surface(&window).unwrap() };
-                scene.add_viewport(self.gpus()[0].clone(), surface, window.inner_size());
+    ) -> crate::Result<Window> {
+        return self.build_window_with_config(scene, window_builder, ViewportConfig::default());
+    }
 
-                Ok(window)
-            }
-            result => result,
-        }
+    // Builds a window whose viewport is created with the given options, e.g., to disable vsync.
+    // Fails if the window cannot be built or the instance has no GPU with the index of the config.
+    pub fn build_window_with_config(
+        &self,
+        scene: &mut Scene,
+        window_builder: WindowBuilder,
+        viewport_config: ViewportConfig,
+    ) -> crate::Result<Window> {
+        let Some(gpu) = self.gpus().get(viewport_config.gpu_index) else {
+            return Err(Error::new(
+                format!("the instance has no GPU with index {}", viewport_config.gpu_index),
+                SourceLocation::here(),
+            ));
+        };
+        let window = window_builder.build(&self.event_loop).map_err(|error| {
+            Error::new(format!("failed to build the window: {error}"), SourceLocation::here())
+        })?;
+        let surface = unsafe { self.wgpu_instance.create_surface(&window) }.map_err(|error| {
+            Error::new(format!("failed to create the surface: {error}"), SourceLocation::here())
+        })?;
+        let viewport_id =
+            scene.add_viewport(gpu.clone(), surface, window.inner_size(), viewport_config);
+        scene.attach_window(window.id(), viewport_id);
+        return Ok(window);
     }
 }
@@ -1,29 +1,115 @@
-use crate::{Result, SceneState, StandardVersionedIndexId, SystemResources, IdMap, ResourceId};
+use crate::{
+    IdMap, ResourceId, Result, SceneState, ShaderId, StandardVersionedIndexId, SystemResources,
+};
 use lazy_static::lazy_static;
 use std::{collections::HashSet, sync::{RwLock, RwLockReadGuard}};
 
 // A `Job` corresponds to a `System` in the classical ECS terminology.
 // More concrete, a job is a function that operates on the state of of a scene (scene components,
 // entities and their components, events, ...).
-// There are two kind of jobs: setup and update jobs. Setup-jobs run once when the scene is
-// created. Those can be used to set the initial state of the scene. Update jobs run on every frame
-// of the scene.
+// There are three kind of jobs: setup, update and fixed update jobs. Setup-jobs run once when the
+// scene is created. Those can be used to set the initial state of the scene. Update jobs run on
+// every frame of the scene. Fixed update jobs run zero or more times per frame, such that they
+// advance the scene in steps of the same length, e.g., for physics that depend on the step size.
 
 pub type JobId = StandardVersionedIndexId;
 pub type JobFunction = fn(&SystemResources, &SceneState) -> Result<()>;
+// Decides whether a job runs in the current frame, see `Job::set_run_if`.
+pub type RunCondition = fn(&SystemResources, &SceneState) -> bool;
+
+// The time since the previous frame and the time since the scene started in seconds. Functions
+// annotated with the `job` macro receive them, and the `FrameId`, by declaring a parameter of
+// these types.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DeltaTime(pub f32);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GameTime(pub f32);
+
+// The id of the current frame, see `SystemResources::frame_id`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameId(pub u32);
 
 // The kind of job
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum JobKind {
     Setup,
     Update,
+    FixedUpdate,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+// A named group of jobs, see `register_stage`. Stages are ordered by their registration: all
+// jobs of a stage finish before any job of a later stage starts, e.g., to run the input jobs
+// before the game logic and the game logic before the rendering. Within a stage, jobs run
+// concurrently unless their dependencies or resource accesses order them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Stage(usize);
+
+// The way a job accesses a resource. Jobs that access the same resource never run concurrently if
+// one of them writes it, jobs that only read a resource can share it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ResourceAccess {
     Read(ResourceId),
-    // Write(ResourceId),
-    // ReadWrite(ResourceId),
+    Write(ResourceId),
+    ReadWrite(ResourceId),
+}
+
+impl ResourceAccess {
+    pub fn resource_id(&self) -> ResourceId {
+        match self {
+            ResourceAccess::Read(resource_id)
+            | ResourceAccess::Write(resource_id)
+            | ResourceAccess::ReadWrite(resource_id) => *resource_id,
+        }
+    }
+
+    pub fn writes(&self) -> bool {
+        return matches!(self, ResourceAccess::Write(_) | ResourceAccess::ReadWrite(_));
+    }
+}
+
+// The fixed-function state of the render pipelines of a job, see `Job::set_pipeline_config`.
+// The default renders filled triangle lists, culls the back faces and replaces the colors of the
+// targets. Strip topologies are drawn without a strip index format, i.e., only non-indexed strips
+// can be restarted by separate draws.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineConfig {
+    pub topology: wgpu::PrimitiveTopology,
+    pub polygon_mode: wgpu::PolygonMode,
+    pub cull_mode: Option<wgpu::Face>,
+    pub blend: Option<wgpu::BlendState>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        return Self {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            cull_mode: Some(wgpu::Face::Back),
+            blend: Some(wgpu::BlendState::REPLACE),
+        };
+    }
+}
+
+impl PipelineConfig {
+    // Returns the config with the polygon mode replaced by `Fill` if the device lacks the feature
+    // it requires, e.g., wireframes need `wgpu::Features::POLYGON_MODE_LINE`, which not every GPU
+    // supports.
+    pub fn supported_by(mut self, features: wgpu::Features) -> Self {
+        let required_feature = match self.polygon_mode {
+            wgpu::PolygonMode::Fill => wgpu::Features::empty(),
+            wgpu::PolygonMode::Line => wgpu::Features::POLYGON_MODE_LINE,
+            wgpu::PolygonMode::Point => wgpu::Features::POLYGON_MODE_POINT,
+        };
+        if !features.contains(required_feature) {
+            trace_debug!(
+                polygon_mode = ?self.polygon_mode,
+                "polygon mode is not supported, falling back to filled polygons"
+            );
+            self.polygon_mode = wgpu::PolygonMode::Fill;
+        }
+        return self;
+    }
 }
 
 pub struct Job {
@@ -31,6 +117,15 @@ pub struct Job {
     function: JobFunction,
     dependencies: HashSet<JobId>,
     resource_access: Vec<ResourceAccess>,
+    executed_per_viewport: bool,
+    exclusive: bool,
+    additional_color_targets: Vec<wgpu::TextureFormat>,
+    shader: Option<ShaderId>,
+    uses_pipeline: bool,
+    pipeline_config: PipelineConfig,
+    compute_shader: Option<ShaderId>,
+    run_if: Option<RunCondition>,
+    stage: Option<Stage>,
 }
 
 impl Job {
@@ -40,9 +135,117 @@ impl Job {
             function,
             dependencies: HashSet::new(),
             resource_access: resource_access.to_vec(),
+            executed_per_viewport: true,
+            exclusive: false,
+            additional_color_targets: vec![],
+            shader: None,
+            uses_pipeline: false,
+            pipeline_config: PipelineConfig::default(),
+            compute_shader: None,
+            run_if: None,
+            stage: None,
         };
     }
 
+    // Per-viewport jobs are executed once for every viewport of the scene, e.g., to render into
+    // it. Other jobs are executed once per frame.
+    pub fn executed_per_viewport(&self) -> bool {
+        self.executed_per_viewport
+    }
+
+    pub fn set_executed_per_viewport(&mut self, executed_per_viewport: bool) {
+        self.executed_per_viewport = executed_per_viewport;
+    }
+
+    // An exclusive job never runs concurrently to any other job. The scheduler waits for all
+    // running jobs to finish before starting it and does not start other jobs until it is done.
+    pub fn exclusive(&self) -> bool {
+        self.exclusive
+    }
+
+    pub fn set_exclusive(&mut self, exclusive: bool) {
+        self.exclusive = exclusive;
+    }
+
+    // The formats of the color targets a per-viewport job renders to in addition to the texture of
+    // the viewport, e.g., for deferred rendering. The viewport texture is bound to location 0 of
+    // the fragment shader, the additional targets to the following locations.
+    pub fn additional_color_targets(&self) -> &[wgpu::TextureFormat] {
+        &self.additional_color_targets
+    }
+
+    pub fn set_additional_color_targets(&mut self, formats: &[wgpu::TextureFormat]) {
+        self.additional_color_targets = formats.to_vec();
+    }
+
+    // The registered shader the pipelines of the job are created with, see `register_shader`. If
+    // None, the built-in shader of the GPU is used.
+    pub fn shader(&self) -> Option<ShaderId> {
+        self.shader
+    }
+
+    // Setting a shader implies that the job uses a pipeline.
+    pub fn set_shader(&mut self, shader: ShaderId) {
+        self.shader = Some(shader);
+        self.uses_pipeline = true;
+    }
+
+    // Only jobs that use a pipeline get one for every viewport, see `SystemResources::pipeline`.
+    // Jobs that do not render or record render passes with their own pipelines do not need one.
+    pub fn uses_pipeline(&self) -> bool {
+        return self.uses_pipeline;
+    }
+
+    pub fn set_uses_pipeline(&mut self, uses_pipeline: bool) {
+        self.uses_pipeline = uses_pipeline;
+    }
+
+    // The topology, polygon mode, culling and blending of the pipelines of the job. Jobs with the
+    // same config share their pipelines, see `Scheduler::pipeline_count`.
+    pub fn pipeline_config(&self) -> PipelineConfig {
+        return self.pipeline_config;
+    }
+
+    // Setting a pipeline config implies that the job uses a pipeline.
+    pub fn set_pipeline_config(&mut self, config: PipelineConfig) {
+        self.pipeline_config = config;
+        self.uses_pipeline = true;
+    }
+
+    // The registered shader the compute pipelines of the job are created with, one for every GPU
+    // from the compute entry point of the shader, see `SystemResources::dispatch_compute`. A
+    // compute job that is executed per viewport dispatches on the GPU of the viewport, other ones
+    // on the first GPU.
+    pub fn compute_shader(&self) -> Option<ShaderId> {
+        return self.compute_shader;
+    }
+
+    pub fn set_compute_shader(&mut self, shader: ShaderId) {
+        self.compute_shader = Some(shader);
+    }
+
+    // The condition that is checked for every frame, i.e., once per viewport for per viewport
+    // jobs. The conditions of all jobs are checked when the frame is scheduled, before any of its
+    // jobs runs, so they see the state the previous frame left behind. If it does not hold, the
+    // job is skipped and counts as finished, so the jobs that depend on it still run, e.g., to
+    // only run a job every n frames or while a flag is set.
+    pub fn run_if(&self) -> Option<RunCondition> {
+        return self.run_if;
+    }
+
+    pub fn set_run_if(&mut self, condition: RunCondition) {
+        self.run_if = Some(condition);
+    }
+
+    // Jobs without a stage are only ordered by their dependencies and resource accesses.
+    pub fn stage(&self) -> Option<Stage> {
+        return self.stage;
+    }
+
+    pub fn set_stage(&mut self, stage: Stage) {
+        self.stage = Some(stage);
+    }
+
     pub fn dependencies(&self) -> &HashSet<JobId> {
         return &self.dependencies;
     }
@@ -66,10 +269,29 @@ impl Job {
 
 lazy_static! {
     static ref REGISTERED_JOBS: RwLock<IdMap<JobId, Job>> = RwLock::new(IdMap::new());
+    static ref REGISTERED_STAGES: RwLock<Vec<String>> = RwLock::new(Vec::new());
+}
+
+// Returns the stage with the name. A new stage is ordered after all stages registered before it,
+// registering a name again returns the existing stage.
+pub fn register_stage(name: &str) -> Stage {
+    let mut stages = REGISTERED_STAGES.write().unwrap();
+    if let Some(index) = stages.iter().position(|stage| stage == name) {
+        return Stage(index);
+    }
+    stages.push(name.to_string());
+    return Stage(stages.len() - 1);
+}
+
+pub fn stage_name(stage: Stage) -> String {
+    return REGISTERED_STAGES.read().unwrap()[stage.0].clone();
 }
 
 pub fn register_job(kind: JobKind, function: JobFunction, resource_access: &[ResourceAccess]) -> JobId {
-    return REGISTERED_JOBS.write().unwrap().insert(Job::new(kind, function, resource_access)).0;
+    let job = Job::new(kind, function, resource_access);
+    let job_id = REGISTERED_JOBS.write().unwrap().insert(job).0;
+    trace_debug!(%job_id, "registered job");
+    return job_id;
 }
 
 pub fn add_job_dependency(job_id: JobId, dependency_id: JobId) {
@@ -84,7 +306,147 @@ pub fn add_job_dependency(job_id: JobId, dependency_id: JobId) {
     }
 }
 
+pub fn set_job_executed_per_viewport(job_id: JobId, executed_per_viewport: bool) {
+    if let Some(job) = REGISTERED_JOBS.write().unwrap().get_mut(job_id) {
+        job.set_executed_per_viewport(executed_per_viewport);
+    }
+}
+
+pub fn set_job_exclusive(job_id: JobId, exclusive: bool) {
+    if let Some(job) = REGISTERED_JOBS.write().unwrap().get_mut(job_id) {
+        job.set_exclusive(exclusive);
+    }
+}
+
+pub fn set_job_additional_color_targets(job_id: JobId, formats: &[wgpu::TextureFormat]) {
+    if let Some(job) = REGISTERED_JOBS.write().unwrap().get_mut(job_id) {
+        job.set_additional_color_targets(formats);
+    }
+}
+
+pub fn set_job_shader(job_id: JobId, shader: ShaderId) {
+    if let Some(job) = REGISTERED_JOBS.write().unwrap().get_mut(job_id) {
+        job.set_shader(shader);
+    }
+}
+
+pub fn set_job_uses_pipeline(job_id: JobId, uses_pipeline: bool) {
+    if let Some(job) = REGISTERED_JOBS.write().unwrap().get_mut(job_id) {
+        job.set_uses_pipeline(uses_pipeline);
+    }
+}
+
+pub fn set_job_pipeline_config(job_id: JobId, config: PipelineConfig) {
+    if let Some(job) = REGISTERED_JOBS.write().unwrap().get_mut(job_id) {
+        job.set_pipeline_config(config);
+    }
+}
+
+pub fn set_job_compute_shader(job_id: JobId, shader: ShaderId) {
+    if let Some(job) = REGISTERED_JOBS.write().unwrap().get_mut(job_id) {
+        job.set_compute_shader(shader);
+    }
+}
+
+pub fn set_job_stage(job_id: JobId, stage: Stage) {
+    if let Some(job) = REGISTERED_JOBS.write().unwrap().get_mut(job_id) {
+        job.set_stage(stage);
+    }
+}
+
+pub fn set_job_run_if(job_id: JobId, condition: RunCondition) {
+    if let Some(job) = REGISTERED_JOBS.write().unwrap().get_mut(job_id) {
+        job.set_run_if(condition);
+    }
+}
+
 pub fn jobs() -> RwLockReadGuard<'static, IdMap<JobId, Job>> {
     return REGISTERED_JOBS.read().unwrap();
 
 }
+
+// Returns the registered jobs that access the resource together with the kind of access.
+pub fn jobs_accessing(resource_id: ResourceId) -> Vec<(JobId, ResourceAccess)> {
+    return find_jobs_accessing(&jobs(), resource_id);
+}
+
+fn find_jobs_accessing(
+    jobs: &IdMap<JobId, Job>,
+    resource_id: ResourceId,
+) -> Vec<(JobId, ResourceAccess)> {
+    return jobs
+        .into_iter()
+        .flat_map(|(job_id, job)| {
+            job.resource_access()
+                .iter()
+                .filter(|access| access.resource_id() == resource_id)
+                .map(move |access| (job_id, *access))
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{SystemResources, VersionedIndexId};
+
+    fn job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        Ok(())
+    }
+
+    #[test]
+    fn find_jobs_accessing_resource() {
+        let position = ResourceId::from_index(0);
+        let velocity = ResourceId::from_index(1);
+        let mut jobs = IdMap::new();
+
+        let (integrate, _) = jobs.insert(Job::new(
+            JobKind::Update,
+            job,
+            &[ResourceAccess::Read(velocity), ResourceAccess::Write(position)],
+        ));
+        let (draw, _) = jobs.insert(Job::new(
+            JobKind::Update,
+            job,
+            &[ResourceAccess::Read(position)],
+        ));
+        let (accelerate, _) = jobs.insert(Job::new(
+            JobKind::Update,
+            job,
+            &[ResourceAccess::Write(velocity)],
+        ));
+        jobs.insert(Job::new(JobKind::Setup, job, &[]));
+
+        assert_eq!(
+            find_jobs_accessing(&jobs, position),
+            vec![
+                (integrate, ResourceAccess::Write(position)),
+                (draw, ResourceAccess::Read(position)),
+            ]
+        );
+        assert_eq!(
+            find_jobs_accessing(&jobs, velocity),
+            vec![
+                (integrate, ResourceAccess::Read(velocity)),
+                (accelerate, ResourceAccess::Write(velocity)),
+            ]
+        );
+        assert!(find_jobs_accessing(&jobs, ResourceId::from_index(2)).is_empty());
+    }
+
+    #[test]
+    fn unsupported_polygon_modes_fall_back_to_fill() {
+        let wireframe = PipelineConfig {
+            polygon_mode: wgpu::PolygonMode::Line,
+            cull_mode: None,
+            ..Default::default()
+        };
+        assert_eq!(wireframe.supported_by(wgpu::Features::POLYGON_MODE_LINE), wireframe);
+
+        let filled = wireframe.supported_by(wgpu::Features::POLYGON_MODE_POINT);
+        assert_eq!(filled.polygon_mode, wgpu::PolygonMode::Fill);
+        // The rest of the config is kept.
+        assert_eq!(filled.cull_mode, None);
+        assert_eq!(filled.topology, wireframe.topology);
+    }
+}
@@ -1,6 +1,10 @@
 #![feature(inherent_associated_types)]
 #![feature(const_trait_impl)]
 #![feature(trait_upcasting)]
+#![feature(mapped_lock_guards)]
+
+#[macro_use]
+mod trace;
 
 mod versioned_index_id;
 pub use versioned_index_id::*;
@@ -17,17 +21,47 @@ pub use resource::*;
 mod job;
 pub use job::*;
 
+mod shader;
+pub use shader::*;
+
 mod scheduler;
 pub use scheduler::*;
 
+mod commands;
+pub use commands::*;
+
+mod draw_command;
+pub use draw_command::*;
+
 mod scene;
 pub use scene::*;
 
+mod binary;
+
 mod gpu;
 pub use gpu::*;
 
+mod input;
+pub use input::*;
+
+mod mouse;
+pub use mouse::*;
+
+mod profiling;
+pub use profiling::*;
+
+mod rng;
+pub use rng::*;
+
+mod viewport_event;
+pub use viewport_event::*;
+
 mod instance;
 pub use instance::*;
 
+pub use bytemuck;
+pub use erased_serde;
+pub use serde;
+pub use serde_json;
 pub use wgpu;
 pub use winit;
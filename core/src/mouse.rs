@@ -0,0 +1,193 @@
+use std::collections::{HashMap, HashSet};
+
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, MouseScrollDelta},
+};
+
+use crate::ViewportId;
+
+// Scrolling by pixels, e.g., on touchpads, is converted to lines with this height.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other(u16),
+}
+
+impl MouseButton {
+    pub fn from_winit(button: winit::event::MouseButton) -> Self {
+        return match button {
+            winit::event::MouseButton::Left => MouseButton::Left,
+            winit::event::MouseButton::Right => MouseButton::Right,
+            winit::event::MouseButton::Middle => MouseButton::Middle,
+            winit::event::MouseButton::Other(index) => MouseButton::Other(index),
+        };
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Cursor {
+    position: [f32; 2],
+    viewport_size: PhysicalSize<u32>,
+}
+
+// The state of the mouse of a scene, see `SystemResources::mouse`. Like `Input`, it is updated
+// between frames. The cursor position is tracked per viewport, so with several windows a job
+// knows which viewport the cursor is over. The buttons and the scroll wheel are shared by all
+// viewports.
+#[derive(Debug, Default)]
+pub struct Mouse {
+    cursors: HashMap<ViewportId, Cursor>,
+    hovered_viewport: Option<ViewportId>,
+    pressed: HashSet<MouseButton>,
+    just_pressed: HashSet<MouseButton>,
+    just_released: HashSet<MouseButton>,
+    scroll_delta: [f32; 2],
+}
+
+impl Mouse {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    // The position of the cursor in physical pixels relative to the top left corner of the
+    // viewport. None if the cursor is not over the viewport.
+    pub fn cursor_position(&self, viewport_id: ViewportId) -> Option<[f32; 2]> {
+        return Some(self.cursors.get(&viewport_id)?.position);
+    }
+
+    // The position of the cursor in the coordinates of clip space, i.e., x goes from -1 at the
+    // left to 1 at the right edge of the viewport and y from 1 at the top to -1 at the bottom,
+    // e.g., to unproject it with the camera of the viewport.
+    pub fn normalized_cursor_position(&self, viewport_id: ViewportId) -> Option<[f32; 2]> {
+        let cursor = self.cursors.get(&viewport_id)?;
+        let width = cursor.viewport_size.width.max(1) as f32;
+        let height = cursor.viewport_size.height.max(1) as f32;
+        return Some([
+            cursor.position[0] / width * 2.0 - 1.0,
+            1.0 - cursor.position[1] / height * 2.0,
+        ]);
+    }
+
+    // The viewport the cursor moved over last, if it is still over it.
+    pub fn hovered_viewport(&self) -> Option<ViewportId> {
+        return self.hovered_viewport;
+    }
+
+    pub fn is_pressed(&self, button: MouseButton) -> bool {
+        return self.pressed.contains(&button);
+    }
+
+    pub fn just_pressed(&self, button: MouseButton) -> bool {
+        return self.just_pressed.contains(&button);
+    }
+
+    pub fn just_released(&self, button: MouseButton) -> bool {
+        return self.just_released.contains(&button);
+    }
+
+    // How far the wheel was scrolled since the last frame in lines, horizontally and vertically.
+    // Positive values scroll to the right and up.
+    pub fn scroll_delta(&self) -> [f32; 2] {
+        return self.scroll_delta;
+    }
+
+    // Moves the cursor within the viewport, the size is used to calculate the normalized position.
+    pub fn move_cursor(
+        &mut self,
+        viewport_id: ViewportId,
+        position: [f32; 2],
+        viewport_size: PhysicalSize<u32>,
+    ) {
+        self.cursors.insert(viewport_id, Cursor { position, viewport_size });
+        self.hovered_viewport = Some(viewport_id);
+    }
+
+    pub fn leave_viewport(&mut self, viewport_id: ViewportId) {
+        self.cursors.remove(&viewport_id);
+        if self.hovered_viewport == Some(viewport_id) {
+            self.hovered_viewport = None;
+        }
+    }
+
+    pub fn press(&mut self, button: MouseButton) {
+        if self.pressed.insert(button) {
+            self.just_pressed.insert(button);
+        }
+    }
+
+    pub fn release(&mut self, button: MouseButton) {
+        if self.pressed.remove(&button) {
+            self.just_released.insert(button);
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        self.just_released.extend(self.pressed.drain());
+    }
+
+    pub fn handle_mouse_input(&mut self, state: ElementState, button: winit::event::MouseButton) {
+        match state {
+            ElementState::Pressed => self.press(MouseButton::from_winit(button)),
+            ElementState::Released => self.release(MouseButton::from_winit(button)),
+        }
+    }
+
+    pub fn handle_mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        let [x, y] = match delta {
+            MouseScrollDelta::LineDelta(x, y) => [x, y],
+            MouseScrollDelta::PixelDelta(position) => [
+                position.x as f32 / PIXELS_PER_LINE,
+                position.y as f32 / PIXELS_PER_LINE,
+            ],
+        };
+        self.scroll_delta[0] += x;
+        self.scroll_delta[1] += y;
+    }
+
+    // Forgets the button changes and the scrolling of the frame that ended. The cursor positions
+    // are kept.
+    pub fn end_frame(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.scroll_delta = [0.0, 0.0];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::VersionedIndexId;
+    use winit::dpi::PhysicalPosition;
+
+    #[test]
+    fn cursor_events_are_accumulated_per_frame() {
+        let [first, second] = [0, 1].map(ViewportId::from_index);
+        let mut mouse = Mouse::new();
+        mouse.move_cursor(first, [10.0, 10.0], PhysicalSize::new(100, 50));
+        mouse.move_cursor(first, [75.0, 12.5], PhysicalSize::new(100, 50));
+        mouse.move_cursor(second, [1.0, 2.0], PhysicalSize::new(10, 10));
+        mouse.leave_viewport(second);
+        assert_eq!(mouse.cursor_position(first), Some([75.0, 12.5]));
+        assert_eq!(mouse.normalized_cursor_position(first), Some([0.5, 0.5]));
+        assert_eq!(mouse.cursor_position(second), None);
+        assert_eq!(mouse.hovered_viewport(), None);
+
+        mouse.handle_mouse_input(ElementState::Pressed, winit::event::MouseButton::Left);
+        mouse.handle_mouse_wheel(MouseScrollDelta::LineDelta(0.0, 1.0));
+        mouse.handle_mouse_wheel(MouseScrollDelta::PixelDelta(PhysicalPosition::new(10.0, 40.0)));
+        assert!(mouse.just_pressed(MouseButton::Left) && mouse.is_pressed(MouseButton::Left));
+        assert_eq!(mouse.scroll_delta(), [0.5, 3.0]);
+
+        mouse.end_frame();
+        mouse.handle_mouse_input(ElementState::Released, winit::event::MouseButton::Left);
+        assert!(!mouse.just_pressed(MouseButton::Left) && !mouse.is_pressed(MouseButton::Left));
+        assert!(mouse.just_released(MouseButton::Left));
+        assert_eq!(mouse.scroll_delta(), [0.0, 0.0]);
+        assert_eq!(mouse.cursor_position(first), Some([75.0, 12.5]));
+    }
+}
@@ -0,0 +1,495 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use crate::{Error, Gpu, Result, SourceLocation};
+
+// Called with the profile of every frame while profiling, see `Scene::start_profiling`. The
+// profile is packed as described at `FrameProfile::to_bytes`.
+pub type ProfileCallback = Box<dyn FnMut(&[u8]) + Send>;
+
+// The worker index of the jobs that run on the thread that ticks the scene.
+pub const CALLING_THREAD: u32 = u32::MAX;
+
+const MAGIC: &[u8; 4] = b"OVPF";
+const VERSION: u32 = 2;
+const HEADER_SIZE: usize = 40;
+const RECORD_SIZE: usize = 32;
+// The number of times per frame and GPU a job can lock the encoder and get the commands it
+// recorded timed, see `GpuTimestamps`.
+const MAX_GPU_SPANS: usize = 512;
+
+// An execution of a job. Per viewport jobs are executed once per viewport, the invocation counts
+// the executions of the job in the frame, starting at 1. All times are in nanoseconds since the
+// scheduler was created and are taken from a monotonic clock.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct JobProfile {
+    pub job_index: u32,
+    pub worker: u32,
+    pub start: u64,
+    pub end: u64,
+    pub invocation: u32,
+}
+
+// The time the GPU spent on the commands a job recorded while it held the encoder of the frame,
+// see `SystemResources::encoder`. The times are in nanoseconds of the clock of the GPU, so they can
+// only be compared with the other times of the same GPU.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GpuJobProfile {
+    pub job_index: u32,
+    pub gpu: u32,
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameProfile {
+    pub frame: u64,
+    pub start: u64,
+    pub end: u64,
+    // Ordered by their start time.
+    pub jobs: Vec<JobProfile>,
+    // Ordered by the GPU and the order the commands were recorded in. The GPU times are read back
+    // without waiting for the GPU, so they belong to earlier frames, usually the previous one.
+    // Empty for GPUs that do not support timestamp queries.
+    pub gpu_jobs: Vec<GpuJobProfile>,
+}
+
+impl FrameProfile {
+    // Packs the profile, all numbers are little endian:
+    //
+    // offset  size  header
+    //      0     4  magic "OVPF"
+    //      4     4  version, currently 2
+    //      8     8  frame index
+    //     16     8  start of the frame
+    //     24     8  end of the frame
+    //     32     4  number of job records
+    //     36     4  number of GPU job records, reserved and always 0 in version 1
+    //
+    // followed by a record per job execution:
+    //
+    // offset  size  record
+    //      0     4  job index, the index of the job in the scheduler
+    //      4     4  worker index, `CALLING_THREAD` for jobs that run on the ticking thread
+    //      8     8  start of the job
+    //     16     8  end of the job
+    //     24     4  invocation
+    //     28     4  reserved, 0
+    //
+    // followed by a record per GPU job:
+    //
+    // offset  size  record
+    //      0     4  job index
+    //      4     4  GPU index
+    //      8     8  start of the commands on the GPU
+    //     16     8  end of the commands on the GPU
+    //     24     8  reserved, 0
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let record_count = self.jobs.len() + self.gpu_jobs.len();
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + RECORD_SIZE * record_count);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.frame.to_le_bytes());
+        bytes.extend_from_slice(&self.start.to_le_bytes());
+        bytes.extend_from_slice(&self.end.to_le_bytes());
+        bytes.extend_from_slice(&(self.jobs.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.gpu_jobs.len() as u32).to_le_bytes());
+        for job in &self.jobs {
+            bytes.extend_from_slice(&job.job_index.to_le_bytes());
+            bytes.extend_from_slice(&job.worker.to_le_bytes());
+            bytes.extend_from_slice(&job.start.to_le_bytes());
+            bytes.extend_from_slice(&job.end.to_le_bytes());
+            bytes.extend_from_slice(&job.invocation.to_le_bytes());
+            bytes.extend_from_slice(&0u32.to_le_bytes());
+        }
+        for job in &self.gpu_jobs {
+            bytes.extend_from_slice(&job.job_index.to_le_bytes());
+            bytes.extend_from_slice(&job.gpu.to_le_bytes());
+            bytes.extend_from_slice(&job.start.to_le_bytes());
+            bytes.extend_from_slice(&job.end.to_le_bytes());
+            bytes.extend_from_slice(&0u64.to_le_bytes());
+        }
+        return bytes;
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let u32_at = |offset: usize| {
+            return u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        };
+        let u64_at = |offset: usize| {
+            return u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        };
+
+        // Version 1 profiles have no GPU jobs, they parse like version 2 ones.
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC || !(1..=VERSION).contains(&u32_at(4))
+        {
+            return Err(Error::new("not a frame profile", SourceLocation::here()));
+        }
+        let job_count = u32_at(32) as usize;
+        let gpu_job_count = u32_at(36) as usize;
+        if bytes.len() != HEADER_SIZE + RECORD_SIZE * (job_count + gpu_job_count) {
+            return Err(Error::new(
+                format!(
+                    "expected {job_count} job and {gpu_job_count} GPU job records in the frame \
+                     profile"
+                ),
+                SourceLocation::here(),
+            ));
+        }
+        let jobs = (0..job_count)
+            .map(|index| {
+                let offset = HEADER_SIZE + RECORD_SIZE * index;
+                return JobProfile {
+                    job_index: u32_at(offset),
+                    worker: u32_at(offset + 4),
+                    start: u64_at(offset + 8),
+                    end: u64_at(offset + 16),
+                    invocation: u32_at(offset + 24),
+                };
+            })
+            .collect();
+        let gpu_jobs = (0..gpu_job_count)
+            .map(|index| {
+                let offset = HEADER_SIZE + RECORD_SIZE * (job_count + index);
+                return GpuJobProfile {
+                    job_index: u32_at(offset),
+                    gpu: u32_at(offset + 4),
+                    start: u64_at(offset + 8),
+                    end: u64_at(offset + 16),
+                };
+            })
+            .collect();
+        return Ok(Self {
+            frame: u64_at(8),
+            start: u64_at(16),
+            end: u64_at(24),
+            jobs,
+            gpu_jobs,
+        });
+    }
+}
+
+// Collects the job executions of a frame while profiling is enabled. Every worker appends to its
+// own buffer, so the workers do not wait for each other to record a job.
+pub(crate) struct Profiler {
+    enabled: AtomicBool,
+    epoch: Instant,
+    frame_start: AtomicU64,
+    // One buffer per worker, the last one is used by the calling thread.
+    buffers: Vec<Mutex<Vec<JobProfile>>>,
+}
+
+impl Profiler {
+    pub(crate) fn new(worker_count: usize) -> Self {
+        return Self {
+            enabled: AtomicBool::new(false),
+            epoch: Instant::now(),
+            frame_start: AtomicU64::new(0),
+            buffers: (0..worker_count + 1).map(|_| Mutex::new(Vec::new())).collect(),
+        };
+    }
+
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        // Forgets the jobs of a frame that was not finished while profiling.
+        for buffer in &self.buffers {
+            buffer.lock().unwrap().clear();
+        }
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    // The current time if profiling is enabled.
+    pub(crate) fn now(&self) -> Option<u64> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return None;
+        }
+        return Some(self.epoch.elapsed().as_nanos() as u64);
+    }
+
+    pub(crate) fn start_frame(&self) {
+        if let Some(now) = self.now() {
+            self.frame_start.store(now, Ordering::Relaxed);
+        }
+    }
+
+    // Records that the job ran from the start until now on the worker, None for the calling
+    // thread.
+    pub(crate) fn record_job(&self, job_index: usize, worker: Option<usize>, start: u64) {
+        let end = self.epoch.elapsed().as_nanos() as u64;
+        let buffer = worker.unwrap_or(self.buffers.len() - 1);
+        self.buffers[buffer].lock().unwrap().push(JobProfile {
+            job_index: job_index as u32,
+            worker: worker.map_or(CALLING_THREAD, |worker| worker as u32),
+            start,
+            end,
+            invocation: 0,
+        });
+    }
+
+    // Collects the jobs that were recorded since the frame started. Returns None if profiling is
+    // disabled.
+    pub(crate) fn finish_frame(&self, frame: u64) -> Option<FrameProfile> {
+        let end = self.now()?;
+        let mut jobs = Vec::new();
+        for buffer in &self.buffers {
+            jobs.append(&mut buffer.lock().unwrap());
+        }
+        jobs.sort_by_key(|job| job.start);
+        let mut invocations = HashMap::new();
+        for job in &mut jobs {
+            let invocation = invocations.entry(job.job_index).or_insert(0);
+            *invocation += 1;
+            job.invocation = *invocation;
+        }
+        return Some(FrameProfile {
+            frame,
+            start: self.frame_start.load(Ordering::Relaxed),
+            end,
+            jobs,
+            gpu_jobs: Vec::new(),
+        });
+    }
+}
+
+// Times the commands the jobs of a frame record on a GPU with timestamp queries while profiling is
+// enabled. A timestamp is written to the encoder of the frame when a job locks it and another one
+// when it releases it, so the commands in between are attributed to the job. The timestamps are
+// read back without waiting for the GPU, so the times of a frame are usually reported with the
+// profile of the next one.
+pub(crate) struct GpuTimestamps {
+    gpu: Arc<Gpu>,
+    enabled: AtomicBool,
+    query_set: wgpu::QuerySet,
+    // The job of every span of the current frame, the span i is written to the queries 2i and
+    // 2i + 1.
+    spans: Mutex<Vec<u32>>,
+    // Two buffers, so the timestamps of a frame can be resolved while the ones of the previous
+    // frame are still read back.
+    read_buffers: Mutex<Vec<ReadBuffer>>,
+}
+
+type MapResult = std::result::Result<(), wgpu::BufferAsyncError>;
+
+// A buffer the timestamps of a frame are resolved to. It is mapped after the frame is submitted
+// and read once the mapping finished.
+struct ReadBuffer {
+    buffer: wgpu::Buffer,
+    // The jobs of the spans that were resolved to the buffer, empty while the buffer is free.
+    spans: Vec<u32>,
+    // Set by the callback of `map_async`, None until the mapping finished. Every mapping gets its
+    // own result, so the callback of a cancelled mapping does not affect the next one.
+    map_result: Option<Arc<Mutex<Option<MapResult>>>>,
+}
+
+impl GpuTimestamps {
+    // Returns None if the GPU does not support timestamp queries.
+    pub(crate) fn new(gpu: Arc<Gpu>) -> Option<Self> {
+        if !gpu.supports_timestamps() {
+            return None;
+        }
+        let query_set = gpu.device().create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Job Timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2 * MAX_GPU_SPANS as u32,
+        });
+        let read_buffers = (0..2)
+            .map(|_| ReadBuffer {
+                buffer: gpu.device().create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Job Timestamp Read Buffer"),
+                    size: (2 * MAX_GPU_SPANS * std::mem::size_of::<u64>()) as u64,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                spans: Vec::new(),
+                map_result: None,
+            })
+            .collect();
+        return Some(Self {
+            gpu,
+            enabled: AtomicBool::new(false),
+            query_set,
+            spans: Mutex::new(Vec::new()),
+            read_buffers: Mutex::new(read_buffers),
+        });
+    }
+
+    // Disabling drops the timestamps that were not read yet, so they are not reported with the
+    // profile of a later frame once profiling is enabled again.
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            for read_buffer in &mut *self.read_buffers.lock().unwrap() {
+                if read_buffer.map_result.take().is_some() {
+                    // Cancels the mapping if it did not finish yet.
+                    read_buffer.buffer.unmap();
+                }
+                read_buffer.spans.clear();
+            }
+        }
+    }
+
+    // Writes the timestamp for the start of a span of the job and returns the query of its end.
+    // Returns None if profiling is disabled or the spans of the frame are used up.
+    pub(crate) fn begin_span(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        job_index: usize,
+    ) -> Option<u32> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let mut spans = self.spans.lock().unwrap();
+        if spans.len() == MAX_GPU_SPANS {
+            return None;
+        }
+        let query = 2 * spans.len() as u32;
+        spans.push(job_index as u32);
+        encoder.write_timestamp(&self.query_set, query);
+        return Some(query + 1);
+    }
+
+    pub(crate) fn end_span(&self, encoder: &mut wgpu::CommandEncoder, query: u32) {
+        encoder.write_timestamp(&self.query_set, query);
+    }
+
+    // Writes the timestamps of the frame to a free read buffer, before the encoder is finished.
+    // If the GPU did not finish the frames whose timestamps use both buffers yet, the timestamps
+    // of this frame are dropped.
+    pub(crate) fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        let spans = std::mem::take(&mut *self.spans.lock().unwrap());
+        if spans.is_empty() {
+            return;
+        }
+        let mut read_buffers = self.read_buffers.lock().unwrap();
+        let Some(read_buffer) = read_buffers.iter_mut().find(|buffer| buffer.spans.is_empty())
+        else {
+            return;
+        };
+        let query_count = 2 * spans.len() as u32;
+        encoder.resolve_query_set(&self.query_set, 0..query_count, &read_buffer.buffer, 0);
+        read_buffer.spans = spans;
+    }
+
+    // Starts reading the timestamps that were resolved by the submitted frame.
+    pub(crate) fn map(&self) {
+        for read_buffer in &mut *self.read_buffers.lock().unwrap() {
+            if read_buffer.spans.is_empty() || read_buffer.map_result.is_some() {
+                continue;
+            }
+            let map_result = Arc::new(Mutex::new(None));
+            let callback_result = map_result.clone();
+            let size = (2 * read_buffer.spans.len() * std::mem::size_of::<u64>()) as u64;
+            read_buffer
+                .buffer
+                .slice(..size)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    *callback_result.lock().unwrap() = Some(result);
+                });
+            read_buffer.map_result = Some(map_result);
+        }
+    }
+
+    // Returns the spans of the jobs whose timestamps were read back since the last call, without
+    // waiting for the GPU. Timestamps that cannot be read are dropped.
+    pub(crate) fn read(&self) -> Vec<GpuJobProfile> {
+        // Calls the callbacks of the mappings that finished.
+        self.gpu.device().poll(wgpu::Maintain::Poll);
+
+        let period = self.gpu.queue().get_timestamp_period() as f64;
+        let to_nanoseconds = |ticks: u64| (ticks as f64 * period) as u64;
+        let mut jobs = Vec::new();
+        for read_buffer in &mut *self.read_buffers.lock().unwrap() {
+            let Some(map_result) = &read_buffer.map_result else {
+                continue;
+            };
+            let Some(result) = map_result.lock().unwrap().take() else {
+                continue;
+            };
+            read_buffer.map_result = None;
+            let spans = std::mem::take(&mut read_buffer.spans);
+            if let Err(error) = result {
+                trace_error!(%error, "failed to read the GPU timestamps of the jobs");
+                continue;
+            }
+
+            let size = (2 * spans.len() * std::mem::size_of::<u64>()) as u64;
+            let timestamps = read_buffer.buffer.slice(..size).get_mapped_range();
+            jobs.extend(
+                timestamps
+                    .chunks_exact(2 * std::mem::size_of::<u64>())
+                    .zip(spans)
+                    .map(|(span, job_index)| {
+                        let (start, end) = span.split_at(std::mem::size_of::<u64>());
+                        return GpuJobProfile {
+                            job_index,
+                            gpu: self.gpu.index() as u32,
+                            start: to_nanoseconds(u64::from_le_bytes(start.try_into().unwrap())),
+                            end: to_nanoseconds(u64::from_le_bytes(end.try_into().unwrap())),
+                        };
+                    }),
+            );
+            drop(timestamps);
+            read_buffer.buffer.unmap();
+        }
+        return jobs;
+    }
+
+    // Forgets the spans of an aborted frame.
+    pub(crate) fn discard(&self) {
+        self.spans.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn profiles_survive_the_round_trip() {
+        let profile = FrameProfile {
+            frame: 7,
+            start: 100,
+            end: 900,
+            jobs: vec![
+                JobProfile {
+                    job_index: 0,
+                    worker: 1,
+                    start: 150,
+                    end: 300,
+                    invocation: 1,
+                },
+                JobProfile {
+                    job_index: 2,
+                    worker: CALLING_THREAD,
+                    start: 300,
+                    end: 800,
+                    invocation: 2,
+                },
+            ],
+            gpu_jobs: vec![GpuJobProfile {
+                job_index: 2,
+                gpu: 0,
+                start: 5000,
+                end: 7000,
+            }],
+        };
+        let bytes = profile.to_bytes();
+        assert_eq!(bytes.len(), HEADER_SIZE + 3 * RECORD_SIZE);
+        assert_eq!(&bytes[32..40], &[2, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(FrameProfile::from_bytes(&bytes).unwrap(), profile);
+        assert!(FrameProfile::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // Version 1 profiles have no GPU jobs.
+        let mut version_1 = bytes[..HEADER_SIZE + 2 * RECORD_SIZE].to_vec();
+        version_1[4..8].copy_from_slice(&1u32.to_le_bytes());
+        version_1[36..40].copy_from_slice(&0u32.to_le_bytes());
+        let profile = FrameProfile::from_bytes(&version_1).unwrap();
+        assert_eq!(profile.jobs.len(), 2);
+        assert!(profile.gpu_jobs.is_empty());
+    }
+}
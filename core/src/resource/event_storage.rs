@@ -0,0 +1,111 @@
+use crate::{Gpu, Resource, ResourceBindGroupEntry, ResourceId, ResourceStorage};
+use std::sync::{Arc, Mutex};
+
+// Stores the events of a single type. Events are double-buffered: events emitted during a frame
+// are collected separately and only become visible to readers in the next frame. After that
+// frame, they are cleared again. As jobs emit events while other jobs read the events of the
+// previous frame, emitting only requires a shared reference to the storage.
+pub struct EventStorage<E: Resource> {
+    events: Vec<E>,
+    emitted_events: Mutex<Vec<E>>,
+}
+
+impl<E: Resource + 'static> ResourceStorage for EventStorage<E> {
+    fn factory(_gpus: &[Arc<Gpu>], _resource_id: ResourceId) -> Box<dyn ResourceStorage> {
+        return Box::new(Self::new());
+    }
+
+    fn binding_count() -> u32 {
+        return 0;
+    }
+
+    fn reserve_capacity(&mut self, _entity_count: usize) {}
+
+    fn take_uploaded_bytes(&self) -> u64 {
+        // Events only live on the CPU.
+        return 0;
+    }
+
+    fn end_frame(&mut self) {
+        self.swap_buffers();
+    }
+
+    fn bind_group_layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+        return vec![];
+    }
+
+    fn bind_group_entries(&self, _gpu_index: usize) -> Vec<ResourceBindGroupEntry> {
+        return vec![];
+    }
+}
+
+impl<E: Resource> Default for EventStorage<E> {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl<E: Resource> EventStorage<E> {
+    pub fn new() -> Self {
+        return Self {
+            events: vec![],
+            emitted_events: Mutex::new(vec![]),
+        };
+    }
+
+    pub fn emit(&self, event: E) {
+        self.emitted_events.lock().unwrap().push(event);
+    }
+
+    // The events emitted during the previous frame.
+    pub fn read(&self) -> &[E] {
+        return &self.events;
+    }
+
+    // Makes the event readable right away instead of in the next frame. Used for events that are
+    // emitted between frames, see `SceneState::emit_between_frames`.
+    pub fn emit_readable(&mut self, event: E) {
+        self.events.push(event);
+    }
+
+    // Makes the events emitted since the last call readable and clears the events that were
+    // readable before.
+    pub fn swap_buffers(&mut self) {
+        let emitted_events = self.emitted_events.get_mut().unwrap();
+        std::mem::swap(&mut self.events, emitted_events);
+        emitted_events.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::VersionedIndexId;
+
+    #[derive(Debug, PartialEq)]
+    struct Collision(u32);
+
+    crate::test_resource!(Collision, Event, EventStorage<Collision>);
+
+    #[test]
+    fn events_are_visible_for_one_frame() {
+        let mut storage: Box<dyn ResourceStorage> =
+            EventStorage::<Collision>::factory(&[], ResourceId::from_index(100));
+
+        let events = storage.as_event::<Collision>().unwrap();
+        events.emit(Collision(1));
+        events.emit(Collision(2));
+        assert!(events.read().is_empty());
+
+        storage.end_frame();
+        let events = storage.as_event::<Collision>().unwrap();
+        assert_eq!(events.read(), &[Collision(1), Collision(2)]);
+        events.emit(Collision(3));
+
+        storage.end_frame();
+        assert_eq!(storage.as_event::<Collision>().unwrap().read(), &[Collision(3)]);
+
+        storage.end_frame();
+        assert!(storage.as_event::<Collision>().unwrap().read().is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::Gpu;
+use std::{
+    ops::Range,
+    sync::{Arc, RwLock},
+};
+
+// A storage buffer on a single GPU that is reallocated with a larger size if the uploaded data
+// does not fit anymore. Reallocating creates a new wgpu::Buffer, so bind groups that reference
+// the old buffer have to be recreated afterwards.
+pub(crate) struct GrowableBuffer {
+    gpu: Arc<Gpu>,
+    label: String,
+    buffer: RwLock<Arc<wgpu::Buffer>>,
+}
+
+impl GrowableBuffer {
+    pub(crate) fn new(gpu: &Arc<Gpu>, label: String, size: u64) -> Self {
+        let buffer = Self::create_buffer(gpu, &label, size);
+        return Self {
+            gpu: gpu.clone(),
+            label,
+            buffer: RwLock::new(Arc::new(buffer)),
+        };
+    }
+
+    pub(crate) fn buffer(&self) -> Arc<wgpu::Buffer> {
+        return self.buffer.read().unwrap().clone();
+    }
+
+    // Uploads the data to the beginning of the buffer. Returns true if the buffer had to be
+    // reallocated to fit the data.
+    pub(crate) fn write(&self, data: &[u8]) -> bool {
+        let mut buffer = self.buffer.write().unwrap();
+        let size: u64 = data.len().try_into().unwrap();
+        let reallocate = size > buffer.size();
+        if reallocate {
+            *buffer = Arc::new(Self::create_buffer(
+                &self.gpu,
+                &self.label,
+                size.next_power_of_two(),
+            ));
+        }
+        self.gpu.queue().write_buffer(&buffer, 0, data);
+        return reallocate;
+    }
+
+    // Reallocates the buffer if it is smaller than the given size, so uploading that much data
+    // does not reallocate it. The contents of a reallocated buffer are lost. Returns true if the
+    // buffer was reallocated.
+    pub(crate) fn reserve(&self, size: u64) -> bool {
+        let mut buffer = self.buffer.write().unwrap();
+        if size <= buffer.size() {
+            return false;
+        }
+        *buffer = Arc::new(Self::create_buffer(
+            &self.gpu,
+            &self.label,
+            size.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT),
+        ));
+        return true;
+    }
+
+    // Uploads the given byte ranges of the data, assuming the rest of the buffer is up to date.
+    // If the data does not fit anymore, the buffer is reallocated and all of the data is uploaded.
+    // Returns the number of uploaded bytes and whether the buffer was reallocated.
+    pub(crate) fn write_ranges(&self, data: &[u8], ranges: &[Range<usize>]) -> (u64, bool) {
+        if data.len() as u64 > self.buffer.read().unwrap().size() {
+            return (data.len() as u64, self.write(data));
+        }
+
+        let buffer = self.buffer.read().unwrap();
+        let mut uploaded_bytes = 0;
+        for range in ranges {
+            // Buffer writes have to be aligned to four bytes.
+            let alignment = wgpu::COPY_BUFFER_ALIGNMENT as usize;
+            let start = range.start / alignment * alignment;
+            let end = range.end.next_multiple_of(alignment).min(data.len());
+            self.gpu
+                .queue()
+                .write_buffer(&buffer, start as u64, &data[start..end]);
+            uploaded_bytes += (end - start) as u64;
+        }
+        return (uploaded_bytes, false);
+    }
+
+    fn create_buffer(gpu: &Gpu, label: &str, size: u64) -> wgpu::Buffer {
+        let usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+        // Tests copy from the buffer to read back what was uploaded.
+        #[cfg(test)]
+        let usage = usage | wgpu::BufferUsages::COPY_SRC;
+        return gpu.device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false,
+        });
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{IdMappedResourceStorage, Resource, VersionedIndexId};
+
+// Iterates over the ids that have a resource in two storages, see
+// `IdMappedResourceStorage::join`.
+pub struct Join<'a, Id: VersionedIndexId, A: Resource, B: Resource> {
+    a: &'a IdMappedResourceStorage<Id, A>,
+    b: &'a IdMappedResourceStorage<Id, B>,
+    walk_a: bool,
+    slot: usize,
+}
+
+// Like `Join`, but the resources of the second storage are mutable, see
+// `IdMappedResourceStorage::join_mut`.
+pub struct JoinMut<'a, Id: VersionedIndexId, A: Resource, B: Resource> {
+    a: &'a IdMappedResourceStorage<Id, A>,
+    b: &'a mut IdMappedResourceStorage<Id, B>,
+    walk_a: bool,
+    slot: usize,
+}
+
+impl<Id: VersionedIndexId + 'static, A: Resource + 'static> IdMappedResourceStorage<Id, A> {
+    // Returns the ids that have a resource in both storages together with the resources. The
+    // smaller storage is walked and the resources of the other one are looked up via its reverse
+    // array, so the order of the ids is the order of the smaller storage.
+    pub fn join<'a, B: Resource + 'static>(
+        &'a self,
+        other: &'a IdMappedResourceStorage<Id, B>,
+    ) -> Join<'a, Id, A, B> {
+        return Join {
+            a: self,
+            b: other,
+            walk_a: self.len() <= other.len(),
+            slot: 0,
+        };
+    }
+
+    // Like `join`, but the resources of the other storage can be modified. They are marked as
+    // changed when they are returned.
+    pub fn join_mut<'a, B: Resource + 'static>(
+        &'a self,
+        other: &'a mut IdMappedResourceStorage<Id, B>,
+    ) -> JoinMut<'a, Id, A, B> {
+        other.mark_gpu_buffers_stale(false);
+        return JoinMut {
+            a: self,
+            walk_a: self.len() <= other.len(),
+            b: other,
+            slot: 0,
+        };
+    }
+}
+
+// Returns the next id that has a resource in both storages and the slots of its resources. Only
+// the slots of one storage starting at `slot` are visited.
+fn next_match<Id: VersionedIndexId, A: Resource, B: Resource>(
+    a: &IdMappedResourceStorage<Id, A>,
+    b: &IdMappedResourceStorage<Id, B>,
+    walk_a: bool,
+    slot: &mut usize,
+) -> Option<(Id, usize, usize)> {
+    let slot_count = if walk_a { a.resources.len() } else { b.resources.len() };
+    while *slot < slot_count {
+        let current = *slot;
+        *slot += 1;
+        if walk_a {
+            if a.is_slot_occupied(current) {
+                let id = a.forward_array[current];
+                if let Some(b_slot) = b.slot(id) {
+                    return Some((id, current, b_slot));
+                }
+            }
+        } else if b.is_slot_occupied(current) {
+            let id = b.forward_array[current];
+            if let Some(a_slot) = a.slot(id) {
+                return Some((id, a_slot, current));
+            }
+        }
+    }
+    return None;
+}
+
+impl<'a, Id: VersionedIndexId, A: Resource, B: Resource> Iterator for Join<'a, Id, A, B> {
+    type Item = (Id, &'a A, &'a B);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, a_slot, b_slot) = next_match(self.a, self.b, self.walk_a, &mut self.slot)?;
+        // SAFETY: the slots are occupied, so the resources are initialized.
+        return Some(unsafe {
+            (
+                id,
+                self.a.resources[a_slot].assume_init_ref(),
+                self.b.resources[b_slot].assume_init_ref(),
+            )
+        });
+    }
+}
+
+impl<'a, Id: VersionedIndexId, A: Resource, B: Resource> Iterator for JoinMut<'a, Id, A, B> {
+    type Item = (Id, &'a A, &'a mut B);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, a_slot, b_slot) = next_match(self.a, self.b, self.walk_a, &mut self.slot)?;
+        self.b.changed_frames[b_slot] = self.b.current_frame;
+        // The resources are accessed through pointers into the vectors instead of indexing them,
+        // which would borrow the whole vector of b mutably on every call.
+        // SAFETY: the slots are occupied, so the resources are initialized. Every slot is visited
+        // once and the slots of the ids are distinct, so no resource is borrowed mutably twice.
+        return Some(unsafe {
+            let a = self.a.resources.as_ptr().add(a_slot);
+            let b = self.b.resources.as_mut_ptr().add(b_slot);
+            (id, (*a).assume_init_ref(), (*b).assume_init_mut())
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{EntityId, IdMappedResourceStorage, ResourceId, ResourceStorage, VersionedIndexId};
+
+    #[derive(Debug, PartialEq)]
+    struct Position(f32);
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(f32);
+
+    crate::test_resource!(Position, EntityComponent, IdMappedResourceStorage<EntityId, Position>);
+
+    crate::test_resource!(Velocity, EntityComponent, IdMappedResourceStorage<EntityId, Velocity>);
+
+    fn entity(index: usize) -> EntityId {
+        return EntityId::from_index(index);
+    }
+
+    #[test]
+    fn join_returns_ids_with_both_resources() {
+        let mut positions =
+            IdMappedResourceStorage::<EntityId, Position>::new(&[], ResourceId::from_index(100));
+        let mut velocities =
+            IdMappedResourceStorage::<EntityId, Velocity>::new(&[], ResourceId::from_index(101));
+        for i in 0..6 {
+            positions.insert(entity(i), Position(i as f32));
+        }
+        positions.remove(entity(4));
+        for i in [5, 4, 1, 9] {
+            velocities.insert(entity(i), Velocity(10.0 * i as f32));
+        }
+
+        let mut joined = positions
+            .join(&velocities)
+            .map(|(id, position, velocity)| (id.index(), position.0, velocity.0))
+            .collect::<Vec<_>>();
+        joined.sort_by_key(|(index, ..)| *index);
+        assert_eq!(joined, [(1, 1.0, 10.0), (5, 5.0, 50.0)]);
+
+        // The result does not depend on which storage is smaller.
+        let mut reversed = velocities
+            .join(&positions)
+            .map(|(id, velocity, position)| (id.index(), position.0, velocity.0))
+            .collect::<Vec<_>>();
+        reversed.sort_by_key(|(index, ..)| *index);
+        assert_eq!(reversed, joined);
+    }
+
+    #[test]
+    fn join_mut_modifies_the_second_storage() {
+        let mut velocities =
+            IdMappedResourceStorage::<EntityId, Velocity>::new(&[], ResourceId::from_index(101));
+        let mut positions =
+            IdMappedResourceStorage::<EntityId, Position>::new(&[], ResourceId::from_index(100));
+        for i in 0..4 {
+            positions.insert(entity(i), Position(0.0));
+        }
+        velocities.insert(entity(2), Velocity(2.0));
+        velocities.insert(entity(3), Velocity(3.0));
+        velocities.insert(entity(7), Velocity(7.0));
+        positions.end_frame();
+        let frame = positions.current_frame();
+
+        for (_, velocity, position) in velocities.join_mut(&mut positions) {
+            position.0 += velocity.0;
+        }
+        let moved = positions
+            .iter()
+            .map(|(id, position)| (id.index(), position.0))
+            .collect::<Vec<_>>();
+        assert_eq!(moved, [(0, 0.0), (1, 0.0), (2, 2.0), (3, 3.0)]);
+        assert_eq!(positions.changed_since(frame).collect::<Vec<_>>(), [entity(2), entity(3)]);
+
+        // Now the mutable storage is smaller and walked instead, which yields each resource once
+        // as well.
+        for i in 0..10 {
+            velocities.insert(entity(i), Velocity(1.0));
+        }
+        for (_, velocity, position) in velocities.join_mut(&mut positions) {
+            position.0 += velocity.0;
+        }
+        let moved = positions
+            .iter()
+            .map(|(id, position)| (id.index(), position.0))
+            .collect::<Vec<_>>();
+        assert_eq!(moved, [(0, 1.0), (1, 1.0), (2, 3.0), (3, 4.0)]);
+    }
+}
@@ -0,0 +1,1577 @@
+use crate::{
+    EntityId, Error, Gpu, IdMap, ResourceBinding, Result, SourceLocation, StandardVersionedIndexId,
+    VersionedIndexId, ViewportId,
+};
+use lazy_static::lazy_static;
+use std::{
+    any::Any,
+    mem::MaybeUninit,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+
+mod growable_buffer;
+use growable_buffer::*;
+
+mod sparse_resource_storage;
+pub use sparse_resource_storage::*;
+
+mod slice_resource_storage;
+pub use slice_resource_storage::*;
+
+mod scene_component_storage;
+pub use scene_component_storage::*;
+
+mod event_storage;
+pub use event_storage::*;
+
+mod viewport_component_storage;
+pub use viewport_component_storage::*;
+
+mod texture_storage;
+pub use texture_storage::*;
+
+mod join;
+pub use join::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResourceKind {
+    Event,
+    SceneComponent,
+    EntityComponent,
+    ViewportComponent,
+    Texture,
+}
+
+pub mod bind_group_index {
+    const SYSTEM: u32 = 0;
+    const COMPONENTS: u32 = 1;
+    // const REVERSE_ARRAY: u32 = 2;
+}
+
+pub type ResourceId = StandardVersionedIndexId<8>;
+
+pub trait Resource: Send + Sync + 'static {
+    type Type;
+    type Storage: ResourceStorage;
+
+    fn id() -> ResourceId;
+    fn kind() -> ResourceKind;
+    fn label() -> &'static str;
+    fn register();
+
+    // Creates the resource from its serialized representation, e.g., when loading a scene from
+    // JSON or from the binary format. The resource macro implements it for resources that derive
+    // `Deserialize`.
+    fn from_serialized(_deserializer: &mut dyn erased_serde::Deserializer) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        return Err(Error::new(
+            format!("{} cannot be deserialized", Self::label()),
+            SourceLocation::here(),
+        ));
+    }
+
+    // Returns the resource as a serializable value, e.g., when saving a scene, or None if the
+    // resource cannot be serialized. The resource macro implements it for resources that derive
+    // `Serialize`.
+    fn as_serializable(&self) -> Option<&dyn erased_serde::Serialize> {
+        return None;
+    }
+
+    // Returns the resources as bytes to upload them to the GPUs or None if the resource is not
+    // plain old data and only lives on the CPU. The resource macro implements it for resources
+    // that derive `Pod`.
+    fn gpu_bytes(_resources: &[Self]) -> Option<&[u8]>
+    where
+        Self: Sized,
+    {
+        return None;
+    }
+
+    // Returns true if the storage of the resource mirrors it to the GPUs.
+    fn is_uploaded_to_gpus() -> bool
+    where
+        Self: Sized,
+    {
+        return Self::gpu_bytes(&[]).is_some();
+    }
+}
+
+// Reinterprets plain old data resources as bytes. Used by the code generated by the resource
+// macro.
+pub fn resource_bytes<R: Resource + bytemuck::Pod>(resources: &[R]) -> &[u8] {
+    return bytemuck::cast_slice(resources);
+}
+
+// Takes the component out of the box, e.g., one that was recorded by a command. Fails if it is
+// not of the expected type.
+pub(crate) fn downcast_component<C: Any>(component: Box<dyn Any + Send>) -> Result<Box<C>> {
+    return component.downcast::<C>().map_err(|_| {
+        Error::new(
+            format!("the component is not a {}", std::any::type_name::<C>()),
+            SourceLocation::here(),
+        )
+    });
+}
+
+// Deserializes a resource with serde from any format. Used by the code generated by the resource
+// macro.
+pub fn deserialize_resource<R: Resource + serde::de::DeserializeOwned>(
+    deserializer: &mut dyn erased_serde::Deserializer,
+) -> Result<R> {
+    return erased_serde::deserialize(deserializer).map_err(|error| {
+        Error::new(
+            format!("failed to deserialize {}: {error}", R::label()),
+            SourceLocation::here(),
+        )
+    });
+}
+
+// pub trait EntityComponent: Resource {
+    // fn entity_component_id() -> ResourceId;
+// }
+
+// impl<C: EntityComponent> Resource for C {
+//     type Type = C;
+//     type Storage = IdMappedResourceStorage<EntityId, C>;
+
+//     fn id() -> ResourceId {
+//         return C::entity_component_id();
+//     }
+//     fn kind() -> ResourceKind {
+//         return ResourceKind::EntityComponent;
+//     }
+// }
+
+// What is bound to a binding of the resource bind group. In contrast to wgpu::BindingResource it
+// does not borrow the storage, so the entries can be kept while the storage is modified.
+#[derive(Clone)]
+pub enum ResourceBindingResource {
+    Buffer(Arc<wgpu::Buffer>),
+    TextureView(Arc<wgpu::TextureView>),
+    Sampler(Arc<wgpu::Sampler>),
+}
+
+impl ResourceBindingResource {
+    pub fn as_binding_resource(&self) -> wgpu::BindingResource<'_> {
+        return match self {
+            Self::Buffer(buffer) => buffer.as_entire_binding(),
+            Self::TextureView(view) => wgpu::BindingResource::TextureView(view),
+            Self::Sampler(sampler) => wgpu::BindingResource::Sampler(sampler),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct ResourceBindGroupEntry {
+    pub binding: u32,
+    pub resource: ResourceBindingResource,
+}
+
+impl ResourceBindGroupEntry {
+    pub fn buffer(binding: u32, buffer: Arc<wgpu::Buffer>) -> Self {
+        return Self {
+            binding,
+            resource: ResourceBindingResource::Buffer(buffer),
+        };
+    }
+}
+
+pub trait ResourceStorage: Send + Sync + Any {
+    fn factory(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Box<dyn ResourceStorage>
+    where
+        Self: Sized;
+
+    // The number of bindings the storage occupies in the resource bind groups, i.e., the number of
+    // entries it returns from `bind_group_layout_entries`.
+    fn binding_count() -> u32
+    where
+        Self: Sized;
+
+    // Makes room for resources of `entity_count` entities to avoid reallocations, e.g., while
+    // loading a scene.
+    fn reserve_capacity(&mut self, entity_count: usize);
+
+    // Returns the number of bytes written to GPU buffers since the last call.
+    fn take_uploaded_bytes(&self) -> u64;
+
+    // Returns true if a GPU buffer was reallocated since the last call. The bind group entries of
+    // the storage reference the new buffers, so the bind groups have to be recreated.
+    fn take_buffers_reallocated(&self) -> bool {
+        return false;
+    }
+
+    // Called by the scheduler after all jobs of a frame are finished.
+    fn end_frame(&mut self) {}
+
+    // Called when the viewport is removed from the scene, storages of viewport components drop
+    // its component.
+    fn remove_viewport(&mut self, _viewport_id: ViewportId) {}
+
+    // Returns the storage as an entity component storage or None if it stores other resources.
+    fn as_entity_component_storage(&self) -> Option<&dyn EntityComponentResourceStorage> {
+        return None;
+    }
+
+    fn as_entity_component_storage_mut(
+        &mut self,
+    ) -> Option<&mut dyn EntityComponentResourceStorage> {
+        return None;
+    }
+
+    // Returns the storage as a storage of entity component slices or None if it stores other
+    // resources.
+    fn as_entity_component_slice_storage(
+        &self,
+    ) -> Option<&dyn EntityComponentSliceResourceStorage> {
+        return None;
+    }
+
+    fn as_entity_component_slice_storage_mut(
+        &mut self,
+    ) -> Option<&mut dyn EntityComponentSliceResourceStorage> {
+        return None;
+    }
+
+    // The bindings of the storage start at 0. The scene moves them to the group and bindings it
+    // allocated for the resource, see `SceneState::resource_binding`.
+    fn bind_group_layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry>;
+    fn bind_group_entries(&self, gpu_index: usize) -> Vec<ResourceBindGroupEntry>;
+}
+
+// The storage of an entity component. Gives access to the components of an entity without knowing
+// the type of the component, e.g., to spawn or despawn entities.
+pub trait EntityComponentResourceStorage: ResourceStorage {
+    // Inserts the component for the entity or replaces the existing one. Fails if the component
+    // is not of the resource type of the storage.
+    fn insert_boxed(&mut self, entity_id: EntityId, component: Box<dyn Any + Send>) -> Result<()>;
+
+    // Deserializes the component, e.g., from JSON or the binary scene format, and inserts it for
+    // the entity.
+    fn insert_serialized(
+        &mut self,
+        entity_id: EntityId,
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Result<()>;
+
+    // Returns the component of the entity to serialize it. Returns None if the entity has no
+    // component or the resource cannot be serialized, see `Resource::as_serializable`.
+    fn serialize_component(&self, entity_id: EntityId) -> Option<&dyn erased_serde::Serialize>;
+
+    // Removes the component of the entity if it has one.
+    fn remove_entity(&mut self, entity_id: EntityId);
+
+    // Removes the components of all entities.
+    fn clear_entities(&mut self);
+
+    // The number of entities that have the component.
+    fn component_count(&self) -> usize;
+
+    // The number of slots the components are looked up in on the GPU, i.e., the number of
+    // instances to draw one per component. Storages with a reverse array return its length, the
+    // slots are the indices of the entities and the shader skips the ones the reverse array marks
+    // as empty. Densely packed storages return the number of components.
+    fn component_slot_bound(&self) -> usize;
+}
+
+// The storage of an entity component that consists of a slice of resources per entity, e.g., the
+// vertices of a mesh. Gives access to the slices without knowing the type of the resources.
+pub trait EntityComponentSliceResourceStorage: ResourceStorage {
+    // Replaces the slice of the entity. Fails if the slice is not a `Vec` of the resource type of
+    // the storage.
+    fn insert_slice_boxed(
+        &mut self,
+        entity_id: EntityId,
+        slice: Box<dyn Any + Send>,
+    ) -> Result<()>;
+
+    // Deserializes a sequence of resources and replaces the slice of the entity with it.
+    fn insert_slice_serialized(
+        &mut self,
+        entity_id: EntityId,
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Result<()>;
+
+    // Returns the resources of the slice of the entity to serialize them as a sequence. Returns
+    // None if the entity has no slice or the resource cannot be serialized, see
+    // `Resource::as_serializable`.
+    fn serialize_slice(&self, entity_id: EntityId) -> Option<Vec<&dyn erased_serde::Serialize>>;
+
+    // The number of resources in the slice of the entity or None if it has no slice.
+    fn slice_len(&self, entity_id: EntityId) -> Option<usize>;
+
+    // Removes the slice of the entity if it has one.
+    fn remove_entity(&mut self, entity_id: EntityId);
+
+    // Removes the slices of all entities.
+    fn clear_entities(&mut self);
+}
+
+impl dyn ResourceStorage {
+    // Returns the storage as its concrete type or None if it is a storage of another type.
+    pub fn downcast_ref<S: ResourceStorage>(&self) -> Option<&S> {
+        return (self as &dyn Any).downcast_ref::<S>();
+    }
+
+    pub fn downcast_mut<S: ResourceStorage>(&mut self) -> Option<&mut S> {
+        return (self as &mut dyn Any).downcast_mut::<S>();
+    }
+
+    pub fn as_entity_component<R: Resource>(&self) -> Option<&IdMappedResourceStorage<EntityId, R>> {
+        return self.downcast_ref();
+    }
+
+    pub fn as_entity_component_mut<R: Resource>(
+        &mut self,
+    ) -> Option<&mut IdMappedResourceStorage<EntityId, R>> {
+        return self.downcast_mut();
+    }
+
+    pub fn as_entity_component_slice<R: Resource>(
+        &self,
+    ) -> Option<&IdMappedResourceSliceStorage<EntityId, R>> {
+        return self.downcast_ref();
+    }
+
+    pub fn as_entity_component_slice_mut<R: Resource>(
+        &mut self,
+    ) -> Option<&mut IdMappedResourceSliceStorage<EntityId, R>> {
+        return self.downcast_mut();
+    }
+
+    pub fn as_scene_component<R: Resource>(&self) -> Option<&SceneComponentStorage<R>> {
+        return self.downcast_ref();
+    }
+
+    pub fn as_scene_component_mut<R: Resource>(&mut self) -> Option<&mut SceneComponentStorage<R>> {
+        return self.downcast_mut();
+    }
+
+    pub fn as_viewport_component<R: Resource>(&self) -> Option<&ViewportComponentStorage<R>> {
+        return self.downcast_ref();
+    }
+
+    pub fn as_viewport_component_mut<R: Resource>(
+        &mut self,
+    ) -> Option<&mut ViewportComponentStorage<R>> {
+        return self.downcast_mut();
+    }
+
+    pub fn as_texture<R: Resource + std::ops::Deref<Target = Texture>>(
+        &self,
+    ) -> Option<&TextureStorage<R>> {
+        return self.downcast_ref();
+    }
+
+    pub fn as_texture_mut<R: Resource + std::ops::Deref<Target = Texture>>(
+        &mut self,
+    ) -> Option<&mut TextureStorage<R>> {
+        return self.downcast_mut();
+    }
+
+    pub fn as_event<E: Resource>(&self) -> Option<&EventStorage<E>> {
+        return self.downcast_ref();
+    }
+
+    pub fn as_event_mut<E: Resource>(&mut self) -> Option<&mut EventStorage<E>> {
+        return self.downcast_mut();
+    }
+}
+
+impl<R: Resource> EntityComponentResourceStorage for IdMappedResourceStorage<EntityId, R> {
+    fn insert_boxed(&mut self, entity_id: EntityId, component: Box<dyn Any + Send>) -> Result<()> {
+        let component = downcast_component::<R>(component)?;
+        self.insert(entity_id, *component);
+        return Ok(());
+    }
+
+    fn insert_serialized(
+        &mut self,
+        entity_id: EntityId,
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Result<()> {
+        self.insert(entity_id, R::from_serialized(deserializer)?);
+        return Ok(());
+    }
+
+    fn serialize_component(&self, entity_id: EntityId) -> Option<&dyn erased_serde::Serialize> {
+        return self.get(entity_id)?.as_serializable();
+    }
+
+    fn remove_entity(&mut self, entity_id: EntityId) {
+        self.remove(entity_id);
+    }
+
+    fn clear_entities(&mut self) {
+        self.clear();
+    }
+
+    fn component_count(&self) -> usize {
+        return self.len();
+    }
+
+    fn component_slot_bound(&self) -> usize {
+        return self.reverse_array.len();
+    }
+}
+
+struct GpuResourceBuffer {
+    resource_buffer: GrowableBuffer,
+    reverse_array: GrowableBuffer,
+    upload_state: Mutex<UploadState>,
+}
+
+// Tracks what changed since the buffers of a GPU were uploaded the last time. Resources are
+// uploaded if they changed in the frame of the last upload or later, the reverse array is uploaded
+// as a whole if ids were inserted or removed.
+#[derive(Default)]
+struct UploadState {
+    // None if the buffers were never uploaded.
+    last_uploaded_frame: Option<u32>,
+    resources_changed: bool,
+    reverse_array_changed: bool,
+}
+
+pub struct IdMappedResourceStorage<Id: VersionedIndexId, R: Resource> {
+    // Stores all the resources. Note: not all slots contain valid resources for indices.
+    // If a resource is removed, it just gets marked as "free", so the list may contain holes.
+    // The locations of these holes are stored in a linked list. The first item in the list is
+    // stored in m_free_list_head which points to the first free slot. The next slot can be then
+    // looked up by indexing the m_forward_array with the first item index.
+    //
+    // Example:
+    //
+    //                  0 1 2 3 4 5 6 7 8 9
+    //
+    // resource:      [C C X C X X C]
+    // forward_array:  [0 5 5 3 X 4 8]
+    // reverse_array:  [0 X X 3 X 1 X X 5 X]
+    // free_list_head: 2
+    //
+    // reverse array stores the resource index for each id. The vector is indexed by index(id)
+    // and returns a resource index. If is_emplaced of the resource index is 1, the index
+    // provides information at which position in the resource array we have to look. if is_emplaced
+    // is 0 there is no associated resource for that id (indicated as an X). The resources not
+    // associated with any id are also indicated by an X in the resource array. This can be caused
+    // by removing a resource. The free spots in the resource array can be found by traversing
+    // a linked list. The first free index of the list is stored in free_list_head. The next free slot
+    // index can be found by looking up the value at the position of the previous free index in the
+    // forward_array. If the array contains 0xffffffff we are at the end of the list. For each slot
+    // that is not free, the forward_array stores the associated id of the resource.
+    resources: Vec<MaybeUninit<R>>,
+    gpu_buffers: Vec<GpuResourceBuffer>,
+    forward_array: Vec<Id>,
+    reverse_array: Vec<Id>, // Here id gets a little abused. Index refers to the actual index and version stores a "boolean" if the id has this resource.
+    free_list_head: usize,
+    // The number of occupied slots.
+    len: usize,
+    // The frame in which each resource was changed the last time, indexed like the resources.
+    changed_frames: Vec<u32>,
+    current_frame: u32,
+    uploaded_bytes: AtomicU64,
+    buffers_reallocated: AtomicBool,
+}
+
+impl<Id: VersionedIndexId + 'static, R: Resource + 'static> ResourceStorage
+    for IdMappedResourceStorage<Id, R>
+{
+    fn factory(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Box<dyn ResourceStorage> {
+        return Box::new(Self::new(gpus, resource_id));
+    }
+
+    fn binding_count() -> u32 {
+        return 2;
+    }
+
+    fn reserve_capacity(&mut self, entity_count: usize) {
+        self.resources.reserve(entity_count.saturating_sub(self.resources.len()));
+        self.forward_array
+            .reserve(entity_count.saturating_sub(self.forward_array.len()));
+        self.reverse_array
+            .reserve(entity_count.saturating_sub(self.reverse_array.len()));
+        self.changed_frames
+            .reserve(entity_count.saturating_sub(self.changed_frames.len()));
+
+        let resource_size = if R::is_uploaded_to_gpus() {
+            entity_count * std::mem::size_of::<R>()
+        } else {
+            0
+        };
+        let reverse_array_size = entity_count * std::mem::size_of::<Id>();
+        for buffer in &mut self.gpu_buffers {
+            let resources_reallocated = buffer.resource_buffer.reserve(resource_size as u64);
+            let reverse_array_reallocated =
+                buffer.reverse_array.reserve(reverse_array_size as u64);
+            if resources_reallocated || reverse_array_reallocated {
+                // The reallocated buffers are empty, so everything is uploaded again.
+                *buffer.upload_state.get_mut().unwrap() = UploadState::default();
+                self.buffers_reallocated.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn take_uploaded_bytes(&self) -> u64 {
+        return self.uploaded_bytes.swap(0, Ordering::Relaxed);
+    }
+
+    fn take_buffers_reallocated(&self) -> bool {
+        return self.buffers_reallocated.swap(false, Ordering::Relaxed);
+    }
+
+    fn end_frame(&mut self) {
+        self.current_frame = self.current_frame.wrapping_add(1);
+    }
+
+    fn as_entity_component_storage(&self) -> Option<&dyn EntityComponentResourceStorage> {
+        // Only storages indexed by entity ids store entity components.
+        let storage = (self as &dyn Any).downcast_ref::<IdMappedResourceStorage<EntityId, R>>()?;
+        return Some(storage);
+    }
+
+    fn as_entity_component_storage_mut(
+        &mut self,
+    ) -> Option<&mut dyn EntityComponentResourceStorage> {
+        let storage =
+            (self as &mut dyn Any).downcast_mut::<IdMappedResourceStorage<EntityId, R>>()?;
+        return Some(storage);
+    }
+
+    fn bind_group_layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+        return vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+    }
+
+    fn bind_group_entries(&self, gpu_index: usize) -> Vec<ResourceBindGroupEntry> {
+        return vec![
+            ResourceBindGroupEntry::buffer(0, self.gpu_buffers[gpu_index].resource_buffer.buffer()),
+            ResourceBindGroupEntry::buffer(1, self.gpu_buffers[gpu_index].reverse_array.buffer()),
+        ];
+    }
+}
+
+impl<Id: VersionedIndexId + 'static, R: Resource + 'static> IdMappedResourceStorage<Id, R> {
+    const FREE_LIST_END: usize = Id::MAX_VERSION;
+    const INITIAL_BUFFER_SIZE: u64 = 1024;
+
+    pub fn new(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Self {
+        let gpu_buffers = gpus.iter().map(|gpu| {
+            let resources = REGISTERED_RESOURCES.read().unwrap();
+            let resource = resources.get(resource_id).unwrap();
+
+            let resource_buffer = GrowableBuffer::new(
+                gpu,
+                format!("{} array", resource.label),
+                Self::INITIAL_BUFFER_SIZE,
+            );
+            let reverse_array = GrowableBuffer::new(
+                gpu,
+                format!("{} reverse array", resource.label),
+                Self::INITIAL_BUFFER_SIZE,
+            );
+
+            return GpuResourceBuffer {
+                reverse_array,
+                resource_buffer,
+                upload_state: Mutex::new(UploadState::default()),
+            };
+        });
+
+        return Self {
+            resources: vec![],
+            forward_array: vec![],
+            reverse_array: vec![],
+            free_list_head: Self::FREE_LIST_END,
+            len: 0,
+            changed_frames: vec![],
+            current_frame: 0,
+            gpu_buffers: gpu_buffers.collect(),
+            uploaded_bytes: AtomicU64::new(0),
+            buffers_reallocated: AtomicBool::new(false),
+        };
+    }
+
+    pub fn insert(&mut self, id: Id, resource: R) -> Option<R> {
+        if id.index() >= self.reverse_array.len() {
+            self.reverse_array
+                .resize_with(id.index() + 1, || Id::from_index_and_version(0, 0));
+        }
+
+        let reverse_ref = &mut self.reverse_array[id.index()];
+
+        return if reverse_ref.version() == 0 {
+            if self.free_list_head == Self::FREE_LIST_END {
+                debug_assert!(self.forward_array.len() == self.resources.len());
+                *reverse_ref = Id::from_index_and_version(self.forward_array.len(), 1);
+                self.forward_array.push(id);
+                self.resources.push(MaybeUninit::new(resource));
+                self.changed_frames.push(self.current_frame);
+            } else {
+                let insert_index = self.free_list_head;
+                self.free_list_head = self.forward_array[self.free_list_head].index();
+                self.resources[insert_index].write(resource);
+                self.forward_array[insert_index] = id;
+                self.changed_frames[insert_index] = self.current_frame;
+                *reverse_ref = Id::from_index_and_version(insert_index, 1);
+            }
+            self.len += 1;
+            self.mark_gpu_buffers_stale(true);
+
+            None
+        } else {
+            let forward_index = reverse_ref.index();
+            let result = unsafe { Some(self.resources[forward_index].assume_init_read()) };
+            self.resources[forward_index].write(resource);
+            self.changed_frames[forward_index] = self.current_frame;
+            self.mark_gpu_buffers_stale(false);
+            result
+        };
+    }
+
+    pub fn remove(&mut self, id: Id) -> Option<R> {
+        if id.index() >= self.reverse_array.len() {
+            return None;
+        }
+        let reverse_ref = &mut self.reverse_array[id.index()];
+
+        if reverse_ref.version() == 0 {
+            return None;
+        }
+
+        let index = reverse_ref.index();
+        self.forward_array[index] = Id::from_index_and_version(self.free_list_head, 0);
+        self.free_list_head = index;
+        *reverse_ref = Id::from_index_and_version(reverse_ref.index(), 0);
+        self.len -= 1;
+        self.mark_gpu_buffers_stale(true);
+        return Some(unsafe { self.resources[index].assume_init_read() });
+    }
+
+    // Removes all resources. The allocated memory is kept for the resources inserted afterwards.
+    pub fn clear(&mut self) {
+        self.drop_resources();
+        self.resources.clear();
+        self.forward_array.clear();
+        self.changed_frames.clear();
+        self.free_list_head = Self::FREE_LIST_END;
+        self.reverse_array.fill(Id::from_index_and_version(0, 0));
+        self.len = 0;
+        self.mark_gpu_buffers_stale(true);
+    }
+
+    pub fn contains(&self, id: Id) -> bool {
+        return self.slot(id).is_some();
+    }
+
+    // The id the resource at the index of the given id was inserted with. Resources are looked up
+    // by the index of the id only, so this tells whether the id refers to the same entity or to
+    // one that was removed and whose index was reused.
+    pub fn stored_id(&self, id: Id) -> Option<Id> {
+        return self.slot(id).map(|slot| self.forward_array[slot]);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    pub fn get(&self, id: Id) -> Option<&R> {
+        return if id.index() < self.reverse_array.len() {
+            let reverse = self.reverse_array[id.index()];
+            if reverse.version() == 1 {
+                Some(unsafe { self.resources[reverse.index()].assume_init_ref() })
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+    }
+
+    // Returns the resource and marks it as changed in the current frame.
+    pub fn get_mut(&mut self, id: Id) -> Option<&mut R> {
+        let slot = self.slot(id)?;
+        self.changed_frames[slot] = self.current_frame;
+        self.mark_gpu_buffers_stale(false);
+        return Some(unsafe { self.resources[slot].assume_init_mut() });
+    }
+
+    // Returns the resource without marking it as changed, e.g., for jobs that only modify it
+    // conditionally. Use `mark_changed` if it was modified after all.
+    pub fn peek_mut(&mut self, id: Id) -> Option<&mut R> {
+        let slot = self.slot(id)?;
+        return Some(unsafe { self.resources[slot].assume_init_mut() });
+    }
+
+    pub fn mark_changed(&mut self, id: Id) {
+        if let Some(slot) = self.slot(id) {
+            self.changed_frames[slot] = self.current_frame;
+            self.mark_gpu_buffers_stale(false);
+        }
+    }
+
+    // The number of frames that were finished since the storage was created, see
+    // `SceneState::end_frame`. A scene finishes a frame once per tick, so unlike
+    // `SystemResources::frame_id`, which counts the frames of a single scheduler, it does not
+    // advance with the fixed update steps.
+    pub fn current_frame(&self) -> u32 {
+        return self.current_frame;
+    }
+
+    // Returns the ids of all resources that were inserted or changed in the given frame or later.
+    pub fn changed_since(&self, frame: u32) -> impl Iterator<Item = Id> + '_ {
+        return self.changed_slots_since(frame).map(|slot| self.forward_array[slot]);
+    }
+
+    // Like `changed_since`, but also returns the resources, e.g., to only process the transforms
+    // that moved since a job ran the last time.
+    pub fn iter_changed_since(&self, frame: u32) -> impl Iterator<Item = (Id, &R)> + '_ {
+        return self.changed_slots_since(frame).map(|slot| {
+            (self.forward_array[slot], unsafe {
+                self.resources[slot].assume_init_ref()
+            })
+        });
+    }
+
+    // Uploads the resources that changed since the last upload to the GPUs. Adjacent changed
+    // resources are uploaded with a single write and nothing is uploaded if nothing changed.
+    pub fn update_gpu_buffers(&self) {
+        let resource_bytes = if R::is_uploaded_to_gpus() {
+            // SAFETY: every slot was written when it was occupied the first time. Plain old data
+            // is Copy, so the bytes of freed slots stay valid values of R.
+            let resources = unsafe {
+                std::slice::from_raw_parts(
+                    self.resources.as_ptr() as *const R,
+                    self.resources.len(),
+                )
+            };
+            R::gpu_bytes(resources).unwrap()
+        } else {
+            &[]
+        };
+        let reverse_array_bytes: &[u8] = bytemuck::cast_slice(&self.reverse_array);
+
+        for buffer in &self.gpu_buffers {
+            let mut upload_state = buffer.upload_state.lock().unwrap();
+            let resource_ranges = match upload_state.last_uploaded_frame {
+                None => std::iter::once(0..resource_bytes.len()).collect(),
+                Some(_) if !upload_state.resources_changed => vec![],
+                Some(frame) => self.changed_byte_ranges(frame),
+            };
+            if !resource_ranges.is_empty() && !resource_bytes.is_empty() {
+                let (uploaded_bytes, reallocated) =
+                    buffer.resource_buffer.write_ranges(resource_bytes, &resource_ranges);
+                if reallocated {
+                    self.buffers_reallocated.store(true, Ordering::Relaxed);
+                }
+                self.uploaded_bytes.fetch_add(uploaded_bytes, Ordering::Relaxed);
+            }
+
+            if upload_state.last_uploaded_frame.is_none() || upload_state.reverse_array_changed {
+                if buffer.reverse_array.write(reverse_array_bytes) {
+                    self.buffers_reallocated.store(true, Ordering::Relaxed);
+                }
+                self.uploaded_bytes
+                    .fetch_add(reverse_array_bytes.len() as u64, Ordering::Relaxed);
+            }
+
+            *upload_state = UploadState {
+                last_uploaded_frame: Some(self.current_frame),
+                resources_changed: false,
+                reverse_array_changed: false,
+            };
+        }
+    }
+
+    pub fn iter(&self) -> IdMappedResourceStorageIterator<'_, Id, R> {
+        return IdMappedResourceStorageIterator::new(self);
+    }
+}
+
+impl<Id: VersionedIndexId, R: Resource> IdMappedResourceStorage<Id, R> {
+    // The slot in the resource array that stores the resource of the id.
+    fn slot(&self, id: Id) -> Option<usize> {
+        let reverse = *self.reverse_array.get(id.index())?;
+        return (reverse.version() == 1).then_some(reverse.index());
+    }
+
+    // A slot in the resource array is occupied if the id stored in the forward array maps back to
+    // the slot via the reverse array. Free slots store the next free slot in the forward array
+    // which never maps back to them.
+    fn is_slot_occupied(&self, slot: usize) -> bool {
+        let id = self.forward_array[slot];
+        return id.index() < self.reverse_array.len()
+            && self.reverse_array[id.index()] == Id::from_index_and_version(slot, 1);
+    }
+
+    fn changed_slots_since(&self, frame: u32) -> impl Iterator<Item = usize> + '_ {
+        return (0..self.resources.len())
+            .filter(move |&slot| self.changed_frames[slot] >= frame && self.is_slot_occupied(slot));
+    }
+
+    // The byte ranges of the resources that changed since the frame, adjacent ones are merged.
+    // Each write has an overhead, so too many ranges are merged into a single one that spans them.
+    fn changed_byte_ranges(&self, frame: u32) -> Vec<Range<usize>> {
+        const MAX_RANGES: usize = 64;
+        let size = std::mem::size_of::<R>();
+        let mut ranges: Vec<Range<usize>> = vec![];
+        for slot in self.changed_slots_since(frame) {
+            match ranges.last_mut() {
+                Some(range) if range.end == slot * size => range.end += size,
+                _ => ranges.push(slot * size..(slot + 1) * size),
+            }
+        }
+        if ranges.len() > MAX_RANGES {
+            return std::iter::once(ranges[0].start..ranges[ranges.len() - 1].end).collect();
+        }
+        return ranges;
+    }
+
+    // Drops the resources of all occupied slots, the slots have to be freed afterwards.
+    fn drop_resources(&mut self) {
+        for slot in 0..self.resources.len() {
+            if self.is_slot_occupied(slot) {
+                unsafe {
+                    self.resources[slot].assume_init_drop();
+                }
+            }
+        }
+    }
+
+    fn mark_gpu_buffers_stale(&mut self, reverse_array_changed: bool) {
+        for buffer in &mut self.gpu_buffers {
+            let upload_state = buffer.upload_state.get_mut().unwrap();
+            upload_state.resources_changed = true;
+            upload_state.reverse_array_changed |= reverse_array_changed;
+        }
+    }
+}
+
+impl<Id: VersionedIndexId, R: Resource> Drop for IdMappedResourceStorage<Id, R> {
+    fn drop(&mut self) {
+        self.drop_resources();
+    }
+}
+
+pub struct IdMappedResourceStorageIterator<
+    'a,
+    Id: VersionedIndexId + 'static,
+    R: Resource + 'static,
+> {
+    storage: &'a IdMappedResourceStorage<Id, R>,
+    index: Option<usize>,
+}
+
+impl<'a, Id: VersionedIndexId + 'static, R: Resource + 'static>
+    IdMappedResourceStorageIterator<'a, Id, R>
+{
+    fn new(storage: &'a IdMappedResourceStorage<Id, R>) -> Self {
+        return Self {
+            storage,
+            index: Self::increment_to_valid_index(0, storage),
+        };
+    }
+
+    fn increment_to_valid_index(
+        mut start: usize,
+        storage: &'a IdMappedResourceStorage<Id, R>,
+    ) -> Option<usize> {
+        while start < storage.forward_array.len() {
+            if storage.is_slot_occupied(start) {
+                return Some(start);
+            }
+            start += 1;
+        }
+        return None;
+    }
+}
+
+impl<'a, Id: VersionedIndexId + 'static, R: Resource + 'static> Iterator
+    for IdMappedResourceStorageIterator<'a, Id, R>
+{
+    type Item = (Id, &'a R);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.index {
+            Some(index) => {
+                self.index = Self::increment_to_valid_index(index + 1, self.storage);
+                return Some((self.storage.forward_array[index], unsafe {
+                    self.storage.resources[index].assume_init_ref()
+                }));
+            }
+            None => None,
+        }
+    }
+}
+
+struct ResourceRegistration {
+    label: String,
+    kind: ResourceKind,
+    uploaded_to_gpus: bool,
+    binding_count: u32,
+    storage_factory: fn(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Box<dyn ResourceStorage>,
+}
+
+lazy_static! {
+    static ref REGISTERED_RESOURCES: RwLock<IdMap<ResourceId, ResourceRegistration>> =
+        RwLock::new(IdMap::new());
+}
+
+pub fn register_resource<C: Resource + 'static>() -> ResourceId {
+    // Resources that are not plain old data cannot be reinterpreted as bytes. They still work on
+    // the CPU, but shaders only see empty buffers for them, see `resource_is_uploaded_to_gpus`.
+    let resource_id = REGISTERED_RESOURCES
+        .write()
+        .unwrap()
+        .insert(ResourceRegistration {
+            label: C::label().to_string(),
+            kind: C::kind(),
+            uploaded_to_gpus: C::is_uploaded_to_gpus(),
+            binding_count: C::Storage::binding_count(),
+            storage_factory: C::Storage::factory,
+        })
+        .0;
+    trace_debug!(
+        resource = C::label(),
+        %resource_id,
+        uploaded_to_gpus = C::is_uploaded_to_gpus(),
+        "registered resource"
+    );
+    return resource_id;
+}
+
+// Returns the id of the registered resource with the given label, e.g., to look up the components
+// of an entity in a scene file.
+pub fn find_resource(label: &str) -> Option<ResourceId> {
+    return REGISTERED_RESOURCES
+        .read()
+        .unwrap()
+        .into_iter()
+        .find(|(_, resource)| resource.label == label)
+        .map(|(resource_id, _)| resource_id);
+}
+
+// Returns true if the registered resource is mirrored to the GPUs, i.e., it is plain old data.
+pub fn resource_is_uploaded_to_gpus(resource_id: ResourceId) -> bool {
+    return REGISTERED_RESOURCES
+        .read()
+        .unwrap()
+        .get(resource_id)
+        .is_some_and(|resource| resource.uploaded_to_gpus);
+}
+
+pub fn resource_label(resource_id: ResourceId) -> Option<String> {
+    return REGISTERED_RESOURCES
+        .read()
+        .unwrap()
+        .get(resource_id)
+        .map(|resource| resource.label.clone());
+}
+
+pub fn resource_kind(resource_id: ResourceId) -> Option<ResourceKind> {
+    return REGISTERED_RESOURCES
+        .read()
+        .unwrap()
+        .get(resource_id)
+        .map(|resource| resource.kind);
+}
+
+// Returns where the buffers of the registered resource are bound in scenes with the storages of
+// all registered resources, e.g., to generate the bindings of shaders. The bindings are allocated
+// like the ones of `SceneState::resource_binding` on GPUs with `bindings_per_group` bindings per
+// bind group, so registering more resources does not move the bindings of the ones registered
+// before.
+pub fn resource_binding(
+    resource_id: ResourceId,
+    bindings_per_group: u32,
+) -> Option<ResourceBinding> {
+    let resources = REGISTERED_RESOURCES.read().unwrap();
+    resources.get(resource_id)?;
+    let mut binding_counts = vec![0; resource_id.index() + 1];
+    for (id, resource) in &*resources {
+        if id.index() <= resource_id.index() {
+            binding_counts[id.index()] = resource.binding_count;
+        }
+    }
+    return crate::allocate_resource_bindings(binding_counts, bindings_per_group).pop();
+}
+
+// Implements `Resource` for a type of a test or benchmark, e.g.,
+// `test_resource!(Mass, EntityComponent, IdMappedResourceStorage<EntityId, Mass>)`. The resource
+// is labeled with the name of the type and registered the first time its id is requested. Further
+// functions of the implementation, e.g., `from_serialized`, follow in braces. Other crates use it
+// with the `test-support` feature.
+#[cfg(any(test, feature = "test-support"))]
+#[macro_export]
+macro_rules! test_resource {
+    ($resource:ident, $kind:ident, $storage:ty $(, { $($functions:tt)* })?) => {
+        impl $crate::Resource for $resource {
+            type Type = $resource;
+            type Storage = $storage;
+
+            fn id() -> $crate::ResourceId {
+                static ID: ::std::sync::OnceLock<$crate::ResourceId> = ::std::sync::OnceLock::new();
+                return *ID.get_or_init(|| $crate::register_resource::<$resource>());
+            }
+
+            fn kind() -> $crate::ResourceKind {
+                return $crate::ResourceKind::$kind;
+            }
+
+            fn label() -> &'static str {
+                return stringify!($resource);
+            }
+
+            fn register() {
+                Self::id();
+            }
+
+            $($($functions)*)?
+        }
+    };
+}
+
+pub fn make_resource_storages(
+    gpus: &[Arc<Gpu>],
+    entity_capacity: usize,
+) -> Vec<Option<Box<dyn ResourceStorage>>> {
+    let mut vec = Vec::new();
+
+    trace_debug!(
+        resource_count = REGISTERED_RESOURCES.read().unwrap().len(),
+        "creating resource storages"
+    );
+
+    for (resource_id, resource) in &*REGISTERED_RESOURCES.read().unwrap() {
+        if resource_id.index() >= vec.len() {
+            vec.resize_with(resource_id.index() + 1, || None);
+        }
+        let mut storage = (resource.storage_factory)(gpus, resource_id);
+        storage.reserve_capacity(entity_capacity);
+        vec[resource_id.index()] = Some(storage);
+    }
+
+    return vec;
+}
+
+// impl<'a, Id: VersionedIndexId + 'static, R: Resource + 'static> Iterator
+//     for &'a IdMappedResourceStorage<Id, R>
+// {
+//     type Item = (Id, &'a R);
+//     type IntoIter = IdMappedResourceStorageIterator<'a, Id, R>;
+
+//     fn into_iter(self) -> Self::IntoIter {
+//         return Self::IntoIter::new(self);
+//     }
+// }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Job, JobKind, Scene, SceneState, Scheduler, SystemResources};
+    use std::sync::Arc;
+
+    #[derive(Debug)]
+    struct R(Arc<u32>);
+
+    crate::test_resource!(R, EntityComponent, IdMappedResourceStorage<EntityId, R>);
+
+    #[test]
+    fn test() {
+        type Id = StandardVersionedIndexId;
+        let mut resource_storage =
+            IdMappedResourceStorage::<Id, R>::new(&[], ResourceId::from_index(100));
+
+        let id = Id::from_index(0);
+        assert!(resource_storage.insert(id, R(Arc::new(100))).is_none());
+
+        let recv = resource_storage.get(id);
+        assert!(recv.is_some());
+        assert_eq!(*recv.unwrap().0, 100);
+
+        let recv = resource_storage.insert(id, R(Arc::new(200)));
+        assert!(recv.is_some());
+        assert_eq!(*recv.unwrap().0, 100);
+
+        let recv = resource_storage.remove(id);
+        assert!(recv.is_some());
+        assert_eq!(*recv.unwrap().0, 200);
+
+        let recv = resource_storage.get(id);
+        assert!(recv.is_none());
+    }
+
+    #[test]
+    fn dropping_storage_drops_resources() {
+        type Id = StandardVersionedIndexId;
+        let values = (0..4).map(Arc::new).collect::<Vec<_>>();
+
+        {
+            let mut resource_storage =
+                IdMappedResourceStorage::<Id, R>::new(&[], ResourceId::from_index(100));
+            for (i, value) in values.iter().enumerate() {
+                resource_storage.insert(Id::from_index(i * 2), R(value.clone()));
+            }
+            assert!(values.iter().all(|value| Arc::strong_count(value) == 2));
+
+            // Removed and replaced resources are handed back to the caller.
+            drop(resource_storage.remove(Id::from_index(2)));
+            assert_eq!(Arc::strong_count(&values[1]), 1);
+            drop(resource_storage.insert(Id::from_index(0), R(values[3].clone())));
+            assert_eq!(Arc::strong_count(&values[0]), 1);
+            assert_eq!(Arc::strong_count(&values[3]), 3);
+
+            // Reuse the freed slot.
+            resource_storage.insert(Id::from_index(10), R(values[1].clone()));
+            assert_eq!(Arc::strong_count(&values[1]), 2);
+            drop(resource_storage.remove(Id::from_index(4)));
+            assert_eq!(Arc::strong_count(&values[2]), 1);
+        }
+
+        assert!(values.iter().all(|value| Arc::strong_count(value) == 1));
+    }
+
+    #[test]
+    fn reserved_storage_does_not_reallocate() {
+        type Id = StandardVersionedIndexId;
+        const COUNT: usize = 10_000;
+
+        let mut resource_storage =
+            IdMappedResourceStorage::<Id, R>::new(&[], ResourceId::from_index(100));
+        resource_storage.reserve_capacity(COUNT);
+        let resources = resource_storage.resources.as_ptr();
+        let forward_array = resource_storage.forward_array.as_ptr();
+        let reverse_array = resource_storage.reverse_array.as_ptr();
+
+        let mut ids = crate::IdStorage::<Id>::with_capacity(COUNT);
+        let id_capacity = ids.capacity();
+
+        for i in 0..COUNT {
+            resource_storage.insert(ids.reserve(), R(Arc::new(i as u32)));
+        }
+
+        assert_eq!(resources, resource_storage.resources.as_ptr());
+        assert_eq!(forward_array, resource_storage.forward_array.as_ptr());
+        assert_eq!(reverse_array, resource_storage.reverse_array.as_ptr());
+        assert_eq!(id_capacity, ids.capacity());
+    }
+
+    #[test]
+    fn downcast_storage() {
+        struct S;
+
+        crate::test_resource!(S, EntityComponent, IdMappedResourceStorage<EntityId, S>);
+
+        let mut storage: Box<dyn ResourceStorage> =
+            IdMappedResourceStorage::<EntityId, R>::factory(&[], ResourceId::from_index(100));
+
+        assert!(storage.as_entity_component::<R>().is_some());
+        assert!(storage.as_entity_component::<S>().is_none());
+        assert!(storage.downcast_ref::<SparseResourceStorage<EntityId, R>>().is_none());
+
+        let id = EntityId::from_index(0);
+        storage
+            .as_entity_component_mut::<R>()
+            .unwrap()
+            .insert(id, R(Arc::new(42)));
+        assert!(storage.as_entity_component_mut::<S>().is_none());
+        assert_eq!(*storage.as_entity_component::<R>().unwrap().get(id).unwrap().0, 42);
+    }
+
+    #[test]
+    fn registration_uses_resource_kind() {
+        struct RegisteredSceneComponent;
+
+        crate::test_resource!(
+            RegisteredSceneComponent,
+            SceneComponent,
+            SceneComponentStorage<RegisteredSceneComponent>
+        );
+
+        let resource_id = register_resource::<RegisteredSceneComponent>();
+        assert_eq!(resource_kind(resource_id), Some(ResourceKind::SceneComponent));
+
+        let storage_factory =
+            REGISTERED_RESOURCES.read().unwrap().get(resource_id).unwrap().storage_factory;
+        let storage = storage_factory(&[], resource_id);
+        assert!(storage
+            .downcast_ref::<SceneComponentStorage<RegisteredSceneComponent>>()
+            .is_some());
+    }
+
+    #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+    #[repr(C)]
+    struct Color([f32; 4]);
+
+    crate::test_resource!(Color, EntityComponent, IdMappedResourceStorage<EntityId, Color>, {
+        fn gpu_bytes(resources: &[Self]) -> Option<&[u8]> {
+            return Some(resource_bytes(resources));
+        }
+    });
+
+    #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+    #[repr(C)]
+    struct Size(f32);
+
+    crate::test_resource!(Size, EntityComponent, IdMappedResourceStorage<EntityId, Size>, {
+        fn gpu_bytes(resources: &[Self]) -> Option<&[u8]> {
+            return Some(resource_bytes(resources));
+        }
+    });
+
+    fn upload_colors_and_sizes_job(_: &SystemResources, state: &SceneState) -> Result<()> {
+        state.resource_storage_ref::<Color>().unwrap().update_gpu_buffers();
+        state.resource_storage_ref::<Size>().unwrap().update_gpu_buffers();
+        return Ok(());
+    }
+
+    #[test]
+    fn update_gpu_buffers_reports_uploaded_bytes() {
+        assert!(resource_is_uploaded_to_gpus(Color::id()));
+        // Storages without GPUs upload nothing.
+        let mut cpu_only = IdMappedResourceStorage::<EntityId, Color>::new(&[], Color::id());
+        cpu_only.insert(EntityId::from_index(0), Color([1.0; 4]));
+        cpu_only.update_gpu_buffers();
+        assert_eq!(cpu_only.take_uploaded_bytes(), 0);
+
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+
+        // Only the storages of this test are kept, the GPUs cannot bind the resources of all
+        // tests.
+        Size::register();
+        let gpus = [gpu.clone()];
+        let mut storages = make_resource_storages(&gpus, 0);
+        for (index, storage) in storages.iter_mut().enumerate() {
+            if ![Color::id(), Size::id()].iter().any(|id| id.index() == index) {
+                *storage = None;
+            }
+        }
+        let state = Arc::new(SceneState::from_storages(&gpus, storages, 0).unwrap());
+        let mut jobs = IdMap::new();
+        jobs.insert(Job::new(JobKind::Update, upload_colors_and_sizes_job, &[]))
+            .1
+            .set_executed_per_viewport(false);
+        let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 0).unwrap();
+        let fixed_scheduler =
+            Scheduler::from_jobs(&jobs, JobKind::FixedUpdate, state.clone(), 0).unwrap();
+        let mut scene =
+            Scene::from_schedulers(state.clone(), scheduler, fixed_scheduler, &Default::default());
+        // The stats are ordered by the resource ids, which depend on the order the tests run in.
+        let expected_stats = |color_bytes: usize, size_bytes: usize| {
+            let mut stats = vec![
+                (Color::id(), color_bytes as u64),
+                (Size::id(), size_bytes as u64),
+            ];
+            stats.sort_by_key(|(resource_id, _)| resource_id.index());
+            return stats;
+        };
+
+        let entities = [(); 2].map(|()| state.entities().write().unwrap().reserve());
+        for entity_id in entities {
+            state.resource_storage_mut::<Color>().unwrap().insert(entity_id, Color([1.0; 4]));
+            state.resource_storage_mut::<Size>().unwrap().insert(entity_id, Size(1.0));
+        }
+        // The first upload contains all resources and the reverse arrays.
+        scene.tick(0.0).unwrap();
+        let id_size = std::mem::size_of::<EntityId>();
+        assert_eq!(scene.upload_stats(), expected_stats(2 * (16 + id_size), 2 * (4 + id_size)));
+
+        // Nothing changed.
+        scene.tick(0.0).unwrap();
+        assert_eq!(scene.upload_stats(), expected_stats(0, 0));
+
+        // Only the changed color is uploaded.
+        state.resource_storage_mut::<Color>().unwrap().get_mut(entities[1]).unwrap().0 = [0.5; 4];
+        scene.tick(0.0).unwrap();
+        assert_eq!(scene.upload_stats(), expected_stats(16, 0));
+
+        // Uploading more than fits into the initial buffer reallocates it.
+        let mut changed = IdMappedResourceStorage::<EntityId, Color>::new(&[gpu], Color::id());
+        for i in 0..100 {
+            changed.insert(EntityId::from_index(i), Color([0.0; 4]));
+        }
+        assert!(!changed.take_buffers_reallocated());
+        changed.update_gpu_buffers();
+        assert!(changed.take_buffers_reallocated());
+        assert!(!changed.take_buffers_reallocated());
+    }
+
+    #[test]
+    fn update_gpu_buffers_only_uploads_changed_resources() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+
+        #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+        #[repr(C)]
+        struct Level(u32);
+
+        crate::test_resource!(Level, EntityComponent, IdMappedResourceStorage<EntityId, Level>, {
+            fn gpu_bytes(resources: &[Self]) -> Option<&[u8]> {
+                return Some(resource_bytes(resources));
+            }
+        });
+
+        let resource_id = Level::id();
+        let gpus = std::slice::from_ref(&gpu);
+        let mut storage = IdMappedResourceStorage::<EntityId, Level>::new(gpus, resource_id);
+        for i in 0..10 {
+            storage.insert(EntityId::from_index(i), Level(i as u32));
+        }
+        storage.update_gpu_buffers();
+        assert_eq!(storage.take_uploaded_bytes(), 10 * 4 + 10 * 4);
+
+        // Nothing changed.
+        storage.end_frame();
+        storage.update_gpu_buffers();
+        assert_eq!(storage.take_uploaded_bytes(), 0);
+
+        // Only the changed resources are uploaded, the reverse array stays the same.
+        storage.end_frame();
+        for i in [3, 4, 7] {
+            storage.get_mut(EntityId::from_index(i)).unwrap().0 = 100 + i as u32;
+        }
+        storage.update_gpu_buffers();
+        assert_eq!(storage.take_uploaded_bytes(), 3 * 4);
+
+        // Changes after an upload in the same frame are uploaded as well.
+        storage.get_mut(EntityId::from_index(9)).unwrap().0 = 109;
+        storage.update_gpu_buffers();
+        assert!(storage.take_uploaded_bytes() > 0);
+
+        let readback_buffer = gpu.device().create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 10 * 4,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = gpu.device().create_command_encoder(&Default::default());
+        let resource_buffer = storage.gpu_buffers[0].resource_buffer.buffer();
+        encoder.copy_buffer_to_buffer(&resource_buffer, 0, &readback_buffer, 0, 10 * 4);
+        gpu.queue().submit(std::iter::once(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        gpu.device().poll(wgpu::Maintain::Wait);
+        let levels: Vec<u32> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        assert_eq!(levels, [0, 1, 2, 103, 104, 5, 6, 107, 8, 109]);
+    }
+
+    #[test]
+    fn storages_allocate_buffers_on_every_gpu() {
+        let gpus = crate::test_gpus();
+        if gpus.len() < 2 {
+            return;
+        }
+
+        #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+        #[repr(C)]
+        struct Mass(f32);
+
+        crate::test_resource!(Mass, EntityComponent, IdMappedResourceStorage<EntityId, Mass>, {
+            fn gpu_bytes(resources: &[Self]) -> Option<&[u8]> {
+                return Some(resource_bytes(resources));
+            }
+        });
+
+        let resource_id = Mass::id();
+        let mut storage = IdMappedResourceStorage::<EntityId, Mass>::new(&gpus, resource_id);
+        for i in 0..10 {
+            storage.insert(EntityId::from_index(i), Mass(i as f32));
+        }
+        storage.update_gpu_buffers();
+        assert_eq!(storage.gpu_buffers.len(), gpus.len());
+        for gpu in &gpus {
+            assert!(!storage.bind_group_entries(gpu.index()).is_empty());
+        }
+        // Every GPU gets the same data.
+        assert_eq!(storage.take_uploaded_bytes(), gpus.len() as u64 * (10 * 4 + 10 * 4));
+    }
+
+    #[test]
+    fn resources_that_are_not_plain_old_data_stay_on_the_cpu() {
+        // The padding between the fields is uninitialized, so the struct cannot be Pod.
+        #[repr(C)]
+        struct Padded {
+            flag: u8,
+            value: u32,
+        }
+
+        crate::test_resource!(Padded, EntityComponent, IdMappedResourceStorage<EntityId, Padded>);
+
+        let resource_id = Padded::id();
+        assert!(!resource_is_uploaded_to_gpus(resource_id));
+        assert!(Padded::gpu_bytes(&[Padded { flag: 1, value: 2 }]).is_none());
+
+        let mut storage = IdMappedResourceStorage::<EntityId, Padded>::new(&[], resource_id);
+        storage.insert(EntityId::from_index(0), Padded { flag: 1, value: 2 });
+        let padded = storage.get(EntityId::from_index(0)).unwrap();
+        assert_eq!((padded.flag, padded.value), (1, 2));
+
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        let mut storage = IdMappedResourceStorage::<EntityId, Padded>::new(&[gpu], resource_id);
+        storage.insert(EntityId::from_index(0), Padded { flag: 1, value: 2 });
+        storage.update_gpu_buffers();
+
+        // Only the reverse array is uploaded, the resource itself is still accessible on the CPU.
+        assert_eq!(storage.take_uploaded_bytes(), std::mem::size_of::<EntityId>() as u64);
+        let padded = storage.get(EntityId::from_index(0)).unwrap();
+        assert_eq!((padded.flag, padded.value), (1, 2));
+    }
+
+    #[test]
+    fn iterating_skips_long_runs_of_free_slots() {
+        type Id = StandardVersionedIndexId;
+        const COUNT: usize = 100_000;
+
+        let mut resource_storage =
+            IdMappedResourceStorage::<Id, R>::new(&[], ResourceId::from_index(100));
+        for i in 0..COUNT {
+            resource_storage.insert(Id::from_index(i), R(Arc::new(i as u32)));
+        }
+        for i in 0..COUNT - 1 {
+            resource_storage.remove(Id::from_index(i));
+        }
+
+        let remaining = resource_storage
+            .iter()
+            .map(|(id, r)| (id, *r.0))
+            .collect::<Vec<_>>();
+        assert_eq!(remaining, vec![(Id::from_index(COUNT - 1), (COUNT - 1) as u32)]);
+    }
+
+    #[test]
+    fn peek_mut_does_not_mark_resources_as_changed() {
+        type Id = StandardVersionedIndexId;
+        let mut resource_storage =
+            IdMappedResourceStorage::<Id, R>::new(&[], ResourceId::from_index(100));
+        let (first, second) = (Id::from_index(0), Id::from_index(1));
+        resource_storage.insert(first, R(Arc::new(1)));
+        resource_storage.insert(second, R(Arc::new(2)));
+        let inserted_frame = resource_storage.current_frame();
+        resource_storage.end_frame();
+        let frame = resource_storage.current_frame();
+
+        resource_storage.peek_mut(first).unwrap().0 = Arc::new(10);
+        resource_storage.get_mut(second).unwrap().0 = Arc::new(20);
+        assert_eq!(resource_storage.changed_since(frame).collect::<Vec<_>>(), vec![second]);
+        assert_eq!(resource_storage.changed_since(inserted_frame).count(), 2);
+
+        resource_storage.mark_changed(first);
+        assert_eq!(resource_storage.changed_since(frame).count(), 2);
+
+        resource_storage.remove(second);
+        assert_eq!(resource_storage.changed_since(frame).collect::<Vec<_>>(), vec![first]);
+    }
+
+    #[test]
+    fn len_contains_and_clear_track_occupied_slots() {
+        type Id = StandardVersionedIndexId;
+        let value = Arc::new(0);
+        let mut resource_storage =
+            IdMappedResourceStorage::<Id, R>::new(&[], ResourceId::from_index(100));
+        assert!(resource_storage.is_empty());
+
+        for i in 0..5 {
+            resource_storage.insert(Id::from_index(i), R(value.clone()));
+        }
+        resource_storage.remove(Id::from_index(1));
+        resource_storage.remove(Id::from_index(3));
+        // Replacing a resource does not change the length, removing a missing one neither.
+        resource_storage.insert(Id::from_index(0), R(value.clone()));
+        resource_storage.remove(Id::from_index(3));
+        // Reuses the slot of a removed resource.
+        resource_storage.insert(Id::from_index(7), R(value.clone()));
+        assert_eq!(resource_storage.len(), 4);
+        assert!(!resource_storage.is_empty());
+        let contained = (0..8)
+            .filter(|&i| resource_storage.contains(Id::from_index(i)))
+            .collect::<Vec<_>>();
+        assert_eq!(contained, [0, 2, 4, 7]);
+        assert!(!resource_storage.contains(Id::from_index(100)));
+        assert_eq!(Arc::strong_count(&value), 5);
+
+        resource_storage.clear();
+        assert_eq!(resource_storage.len(), 0);
+        assert!(resource_storage.is_empty());
+        assert!((0..8).all(|i| !resource_storage.contains(Id::from_index(i))));
+        assert_eq!(resource_storage.iter().count(), 0);
+        assert_eq!(Arc::strong_count(&value), 1);
+
+        // The storage can be used again after clearing it.
+        resource_storage.insert(Id::from_index(3), R(value.clone()));
+        resource_storage.insert(Id::from_index(1), R(value.clone()));
+        resource_storage.remove(Id::from_index(3));
+        assert_eq!(resource_storage.len(), 1);
+        assert_eq!(resource_storage.iter().map(|(id, _)| id.index()).collect::<Vec<_>>(), [1]);
+        drop(resource_storage);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn iter_changed_since_skips_unchanged_resources() {
+        type Id = StandardVersionedIndexId;
+        let mut resource_storage =
+            IdMappedResourceStorage::<Id, R>::new(&[], ResourceId::from_index(100));
+        let changed = |storage: &IdMappedResourceStorage<Id, R>, frame| {
+            return storage
+                .iter_changed_since(frame)
+                .map(|(id, r)| (id.index(), *r.0))
+                .collect::<Vec<_>>();
+        };
+
+        // Frame 0: all resources are new.
+        for i in 0..4 {
+            resource_storage.insert(Id::from_index(i), R(Arc::new(i as u32)));
+        }
+        assert_eq!(changed(&resource_storage, 0), [(0, 0), (1, 1), (2, 2), (3, 3)]);
+        resource_storage.end_frame();
+
+        // Frame 1: reading does not count as a change.
+        assert_eq!(resource_storage.get(Id::from_index(0)).map(|r| *r.0), Some(0));
+        resource_storage.get_mut(Id::from_index(1)).unwrap().0 = Arc::new(10);
+        assert_eq!(changed(&resource_storage, 1), [(1, 10)]);
+        resource_storage.end_frame();
+
+        // Frame 2: replacing a resource is a change, too.
+        resource_storage.insert(Id::from_index(3), R(Arc::new(30)));
+        assert_eq!(changed(&resource_storage, 2), [(3, 30)]);
+        assert_eq!(changed(&resource_storage, 1), [(1, 10), (3, 30)]);
+        resource_storage.end_frame();
+
+        // Frame 3: nothing changed.
+        assert_eq!(changed(&resource_storage, 3), []);
+        assert_eq!(changed(&resource_storage, 0).len(), 4);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{Gpu, Resource, ResourceBindGroupEntry, ResourceId, ResourceStorage, SimpleStorage};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
+
+use super::{GrowableBuffer, REGISTERED_RESOURCES};
+
+struct GpuSceneComponentBuffer {
+    resource_buffer: GrowableBuffer,
+}
+
+// Stores a single, optional resource per scene, e.g., the active camera or the gravity. In
+// contrast to entity components, scene components are not indexed by any id.
+pub struct SceneComponentStorage<R: Resource> {
+    resource: SimpleStorage<R>,
+    gpu_buffers: Vec<GpuSceneComponentBuffer>,
+    uploaded_bytes: AtomicU64,
+    buffers_reallocated: AtomicBool,
+}
+
+impl<R: Resource + 'static> ResourceStorage for SceneComponentStorage<R> {
+    fn factory(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Box<dyn ResourceStorage> {
+        return Box::new(Self::new(gpus, resource_id));
+    }
+
+    fn binding_count() -> u32 {
+        return 1;
+    }
+
+    fn reserve_capacity(&mut self, _entity_count: usize) {}
+
+    fn take_uploaded_bytes(&self) -> u64 {
+        return self.uploaded_bytes.swap(0, Ordering::Relaxed);
+    }
+
+    fn take_buffers_reallocated(&self) -> bool {
+        return self.buffers_reallocated.swap(false, Ordering::Relaxed);
+    }
+
+    fn bind_group_layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+        return vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+    }
+
+    fn bind_group_entries(&self, gpu_index: usize) -> Vec<ResourceBindGroupEntry> {
+        let buffer = self.gpu_buffers[gpu_index].resource_buffer.buffer();
+        return vec![ResourceBindGroupEntry::buffer(0, buffer)];
+    }
+}
+
+impl<R: Resource + 'static> SceneComponentStorage<R> {
+    pub fn new(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Self {
+        let gpu_buffers = gpus.iter().map(|gpu| {
+            let resources = REGISTERED_RESOURCES.read().unwrap();
+            let resource = resources.get(resource_id).unwrap();
+
+            let resource_buffer = GrowableBuffer::new(
+                gpu,
+                resource.label.clone(),
+                std::mem::size_of::<R>().max(16).try_into().unwrap(),
+            );
+
+            return GpuSceneComponentBuffer {
+                resource_buffer,
+            };
+        });
+
+        return Self {
+            resource: SimpleStorage::new(),
+            gpu_buffers: gpu_buffers.collect(),
+            uploaded_bytes: AtomicU64::new(0),
+            buffers_reallocated: AtomicBool::new(false),
+        };
+    }
+
+    pub fn set(&mut self, resource: R) -> &R {
+        return self.resource.emplace(resource);
+    }
+
+    pub fn reset(&mut self) {
+        self.resource.reset();
+    }
+
+    pub fn get(&self) -> Option<&R> {
+        return self.resource.get();
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut R> {
+        return self.resource.get_mut();
+    }
+
+    pub fn update_gpu_buffers(&self) {
+        let resource_slice = self
+            .resource
+            .get()
+            .and_then(|resource| R::gpu_bytes(std::slice::from_ref(resource)));
+        if let Some(resource_slice) = resource_slice {
+            for buffer in &self.gpu_buffers {
+                if buffer.resource_buffer.write(resource_slice) {
+                    self.buffers_reallocated.store(true, Ordering::Relaxed);
+                }
+                self.uploaded_bytes
+                    .fetch_add(resource_slice.len() as u64, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::VersionedIndexId;
+
+    #[derive(Debug, PartialEq)]
+    struct Gravity(f32);
+
+    crate::test_resource!(Gravity, SceneComponent, SceneComponentStorage<Gravity>);
+
+    #[test]
+    fn scene_component_storage_works() {
+        let mut storage: Box<dyn ResourceStorage> =
+            SceneComponentStorage::<Gravity>::factory(&[], ResourceId::from_index(100));
+        assert!(storage.as_entity_component::<Gravity>().is_none());
+
+        let gravity = storage.as_scene_component_mut::<Gravity>().unwrap();
+        assert!(gravity.get().is_none());
+        assert_eq!(gravity.set(Gravity(-9.81)), &Gravity(-9.81));
+        gravity.get_mut().unwrap().0 = -1.62;
+
+        assert_eq!(
+            storage.as_scene_component::<Gravity>().unwrap().get(),
+            Some(&Gravity(-1.62))
+        );
+
+        storage.as_scene_component_mut::<Gravity>().unwrap().reset();
+        assert!(storage.as_scene_component::<Gravity>().unwrap().get().is_none());
+    }
+}
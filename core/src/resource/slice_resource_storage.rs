@@ -0,0 +1,662 @@
+use crate::{
+    EntityComponentSliceResourceStorage, EntityId, Error, Gpu, Resource, ResourceBindGroupEntry,
+    ResourceId, ResourceStorage, Result, SourceLocation, VersionedIndexId,
+};
+use std::{
+    any::Any,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use super::{downcast_component, GrowableBuffer, REGISTERED_RESOURCES};
+
+struct GpuSliceResourceBuffer {
+    resource_buffer: GrowableBuffer,
+    block_table: GrowableBuffer,
+}
+
+// A contiguous range in the resource array of a slice storage. The first `size` elements of the
+// block are initialized, the remaining `capacity - size` elements are not.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct SliceBlock {
+    offset: usize,
+    size: usize,
+    capacity: usize,
+}
+
+// Stores a variable number of resources per id, e.g., the vertex positions of a mesh. All slices
+// are stored in a single resource array so it can be mirrored to a single GPU buffer. Every id
+// owns a block in that array. If a slice outgrows its block, it is either extended into an
+// adjacent free block or moved to a new block and the old one is returned to the free list.
+//
+// Example:
+//
+//                0 1 2 3 4 5 6 7 8 9
+//
+// resources:    [A A A _ B _ _ C C _]
+// used_blocks:  [(0, 3, 4) X (4, 1, 3) (7, 2, 3)]   (offset, size, capacity), indexed by id
+// free_blocks:  []
+//
+// `_` denotes uninitialized capacity inside of a block.
+//
+// On the GPU, the resource array is mirrored as is. Additionally, a block table is uploaded that
+// stores the offset and the size of the slice for every id index, so a shader can find the slice
+// of an entity via `resources[block_table[index].offset + i]`. Ids without a slice have a size
+// of 0.
+pub struct IdMappedResourceSliceStorage<Id: VersionedIndexId, R: Resource> {
+    resources: Vec<MaybeUninit<R>>,
+    used_blocks: Vec<Option<(Id, SliceBlock)>>,
+    free_blocks: Vec<SliceBlock>,
+    gpu_buffers: Vec<GpuSliceResourceBuffer>,
+    uploaded_bytes: AtomicU64,
+    buffers_reallocated: AtomicBool,
+}
+
+impl<Id: VersionedIndexId + 'static, R: Resource + 'static> ResourceStorage
+    for IdMappedResourceSliceStorage<Id, R>
+{
+    fn factory(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Box<dyn ResourceStorage> {
+        return Box::new(Self::new(gpus, resource_id));
+    }
+
+    fn binding_count() -> u32 {
+        return 2;
+    }
+
+    fn reserve_capacity(&mut self, entity_count: usize) {
+        self.used_blocks
+            .reserve(entity_count.saturating_sub(self.used_blocks.len()));
+    }
+
+    fn take_uploaded_bytes(&self) -> u64 {
+        return self.uploaded_bytes.swap(0, Ordering::Relaxed);
+    }
+
+    fn take_buffers_reallocated(&self) -> bool {
+        return self.buffers_reallocated.swap(false, Ordering::Relaxed);
+    }
+
+    fn as_entity_component_slice_storage(
+        &self,
+    ) -> Option<&dyn EntityComponentSliceResourceStorage> {
+        // Only storages indexed by entity ids store entity components.
+        let storage =
+            (self as &dyn Any).downcast_ref::<IdMappedResourceSliceStorage<EntityId, R>>()?;
+        return Some(storage);
+    }
+
+    fn as_entity_component_slice_storage_mut(
+        &mut self,
+    ) -> Option<&mut dyn EntityComponentSliceResourceStorage> {
+        let storage =
+            (self as &mut dyn Any).downcast_mut::<IdMappedResourceSliceStorage<EntityId, R>>()?;
+        return Some(storage);
+    }
+
+    fn bind_group_layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+        return vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+    }
+
+    fn bind_group_entries(&self, gpu_index: usize) -> Vec<ResourceBindGroupEntry> {
+        return vec![
+            ResourceBindGroupEntry::buffer(0, self.gpu_buffers[gpu_index].resource_buffer.buffer()),
+            ResourceBindGroupEntry::buffer(1, self.gpu_buffers[gpu_index].block_table.buffer()),
+        ];
+    }
+}
+
+impl<R: Resource> EntityComponentSliceResourceStorage for IdMappedResourceSliceStorage<EntityId, R> {
+    fn insert_slice_boxed(
+        &mut self,
+        entity_id: EntityId,
+        slice: Box<dyn Any + Send>,
+    ) -> Result<()> {
+        let slice = downcast_component::<Vec<R>>(slice)?;
+        self.insert_slice(entity_id, *slice);
+        return Ok(());
+    }
+
+    fn insert_slice_serialized(
+        &mut self,
+        entity_id: EntityId,
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Result<()> {
+        let slice = serde::de::DeserializeSeed::deserialize(
+            SliceSeed::<R>(PhantomData),
+            deserializer,
+        )
+        .map_err(|error| {
+            Error::new(
+                format!("failed to deserialize {} slice: {error}", R::label()),
+                SourceLocation::here(),
+            )
+        })?;
+        self.insert_slice(entity_id, slice);
+        return Ok(());
+    }
+
+    fn serialize_slice(&self, entity_id: EntityId) -> Option<Vec<&dyn erased_serde::Serialize>> {
+        return self
+            .get(entity_id)?
+            .iter()
+            .map(R::as_serializable)
+            .collect();
+    }
+
+    fn slice_len(&self, entity_id: EntityId) -> Option<usize> {
+        return self.get(entity_id).map(<[R]>::len);
+    }
+
+    fn remove_entity(&mut self, entity_id: EntityId) {
+        self.remove(entity_id);
+    }
+
+    fn clear_entities(&mut self) {
+        self.clear();
+    }
+}
+
+impl<Id: VersionedIndexId + 'static, R: Resource + 'static> IdMappedResourceSliceStorage<Id, R> {
+    const INITIAL_BUFFER_SIZE: u64 = 1024;
+
+    pub fn new(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Self {
+        let gpu_buffers = gpus.iter().map(|gpu| {
+            let resources = REGISTERED_RESOURCES.read().unwrap();
+            let resource = resources.get(resource_id).unwrap();
+
+            let resource_buffer = GrowableBuffer::new(
+                gpu,
+                format!("{} array", resource.label),
+                Self::INITIAL_BUFFER_SIZE,
+            );
+            let block_table = GrowableBuffer::new(
+                gpu,
+                format!("{} block table", resource.label),
+                Self::INITIAL_BUFFER_SIZE,
+            );
+
+            return GpuSliceResourceBuffer {
+                block_table,
+                resource_buffer,
+            };
+        });
+
+        return Self {
+            resources: vec![],
+            used_blocks: vec![],
+            free_blocks: vec![],
+            gpu_buffers: gpu_buffers.collect(),
+            uploaded_bytes: AtomicU64::new(0),
+            buffers_reallocated: AtomicBool::new(false),
+        };
+    }
+
+    pub fn update_gpu_buffers(&self) {
+        let block_table = self
+            .used_blocks
+            .iter()
+            .map(|block| match block {
+                Some((_, block)) => [block.offset as u32, block.size as u32],
+                None => [0, 0],
+            })
+            .collect::<Vec<_>>();
+        let block_table_bytes: &[u8] = bytemuck::cast_slice(&block_table);
+
+        // The capacity of the blocks beyond their size is uninitialized, so the slices are copied
+        // block by block and the remaining bytes are uploaded as zeros.
+        let mut resource_bytes = vec![];
+        if R::is_uploaded_to_gpus() {
+            resource_bytes.resize(self.resources.len() * std::mem::size_of::<R>(), 0);
+            for (id, block) in self.used_blocks.iter().flatten() {
+                let bytes = R::gpu_bytes(self.get(*id).unwrap()).unwrap();
+                let offset = block.offset * std::mem::size_of::<R>();
+                resource_bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+            }
+        }
+
+        for buffer in &self.gpu_buffers {
+            if buffer.resource_buffer.write(&resource_bytes) {
+                self.buffers_reallocated.store(true, Ordering::Relaxed);
+            }
+            self.uploaded_bytes
+                .fetch_add(resource_bytes.len() as u64, Ordering::Relaxed);
+
+            if buffer.block_table.write(block_table_bytes) {
+                self.buffers_reallocated.store(true, Ordering::Relaxed);
+            }
+            self.uploaded_bytes
+                .fetch_add(block_table_bytes.len() as u64, Ordering::Relaxed);
+        }
+    }
+
+    // Replaces the slice of the id with the given resources.
+    pub fn insert_slice<I>(&mut self, id: Id, resources: I)
+    where
+        I: IntoIterator<Item = R>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let resources = resources.into_iter();
+        self.drop_slice(id);
+        let block = self.reserve_for_index(id, resources.len());
+
+        let mut size = 0;
+        for (index, resource) in resources.enumerate() {
+            self.resources[block.offset + index].write(resource);
+            size += 1;
+        }
+        self.set_block_size(id, size);
+    }
+
+    // Appends a resource to the slice of the id. Creates the slice if the id does not have one.
+    pub fn push(&mut self, id: Id, resource: R) {
+        let size = self.slice_size(id);
+        let block = self.reserve_for_index(id, size + 1);
+        self.resources[block.offset + size].write(resource);
+        self.set_block_size(id, size + 1);
+    }
+
+    // Inserts a resource at the given position of the slice of the id, shifting all resources
+    // after it to the right.
+    pub fn insert(&mut self, id: Id, index: usize, resource: R) {
+        let size = self.slice_size(id);
+        assert!(index <= size, "insertion index (is {index}) should be <= len (is {size})");
+
+        let block = self.reserve_for_index(id, size + 1);
+        unsafe {
+            let position = self.resources[block.offset + index..].as_mut_ptr();
+            std::ptr::copy(position, position.add(1), size - index);
+        }
+        self.resources[block.offset + index].write(resource);
+        self.set_block_size(id, size + 1);
+    }
+
+    // Removes the slice of the id and returns its capacity to the free list.
+    pub fn remove(&mut self, id: Id) {
+        self.drop_slice(id);
+        if let Some(Some((_, block))) = self.used_blocks.get(id.index()).copied() {
+            self.used_blocks[id.index()] = None;
+            self.free_block(block);
+        }
+    }
+
+    // Removes all slices. The allocated memory is kept for the slices inserted afterwards.
+    pub fn clear(&mut self) {
+        let ids = self.used_blocks.iter().flatten().map(|(id, _)| *id).collect::<Vec<_>>();
+        for id in ids {
+            self.remove(id);
+        }
+    }
+
+    pub fn get(&self, id: Id) -> Option<&[R]> {
+        let (_, block) = self.used_blocks.get(id.index())?.as_ref()?;
+        return Some(unsafe {
+            std::slice::from_raw_parts(
+                self.resources[block.offset..].as_ptr() as *const R,
+                block.size,
+            )
+        });
+    }
+
+    pub fn get_mut(&mut self, id: Id) -> Option<&mut [R]> {
+        let (_, block) = self.used_blocks.get(id.index())?.as_ref()?;
+        return Some(unsafe {
+            std::slice::from_raw_parts_mut(
+                self.resources[block.offset..].as_mut_ptr() as *mut R,
+                block.size,
+            )
+        });
+    }
+
+    fn slice_size(&self, id: Id) -> usize {
+        return match self.used_blocks.get(id.index()) {
+            Some(Some((_, block))) => block.size,
+            _ => 0,
+        };
+    }
+
+    // Makes sure the block of the id can hold at least `capacity` resources and returns it. The
+    // initialized resources are moved along if the block has to be relocated.
+    fn reserve_for_index(&mut self, id: Id, capacity: usize) -> SliceBlock {
+        if id.index() >= self.used_blocks.len() {
+            self.used_blocks.resize_with(id.index() + 1, || None);
+        }
+
+        let block = match self.used_blocks[id.index()] {
+            Some((_, block)) if block.capacity >= capacity => block,
+            Some((_, block)) => {
+                if let Some(grown_block) = self.grow_in_place(block, capacity) {
+                    grown_block
+                } else {
+                    let mut new_block = self.allocate_block(capacity);
+                    unsafe {
+                        std::ptr::copy_nonoverlapping(
+                            self.resources[block.offset..].as_ptr(),
+                            self.resources[new_block.offset..].as_mut_ptr(),
+                            block.size,
+                        );
+                    }
+                    new_block.size = block.size;
+                    self.free_block(block);
+                    new_block
+                }
+            }
+            None => self.allocate_block(capacity),
+        };
+
+        self.used_blocks[id.index()] = Some((id, block));
+        return block;
+    }
+
+    // Tries to extend the block into the free block directly behind it or at the end of the
+    // resource array.
+    fn grow_in_place(&mut self, block: SliceBlock, capacity: usize) -> Option<SliceBlock> {
+        let end = block.offset + block.capacity;
+        let additional = capacity - block.capacity;
+
+        if end == self.resources.len() {
+            self.resources.resize_with(end + additional, || MaybeUninit::uninit());
+            return Some(SliceBlock { capacity, ..block });
+        }
+
+        let free_index = self
+            .free_blocks
+            .iter()
+            .position(|free| free.offset == end && free.capacity >= additional)?;
+        let free = &mut self.free_blocks[free_index];
+        if free.capacity == additional {
+            self.free_blocks.swap_remove(free_index);
+        } else {
+            free.offset += additional;
+            free.capacity -= additional;
+        }
+        return Some(SliceBlock { capacity, ..block });
+    }
+
+    fn allocate_block(&mut self, capacity: usize) -> SliceBlock {
+        if let Some(free_index) = self
+            .free_blocks
+            .iter()
+            .position(|free| free.capacity >= capacity)
+        {
+            let free = &mut self.free_blocks[free_index];
+            let block = SliceBlock {
+                offset: free.offset,
+                size: 0,
+                capacity,
+            };
+            if free.capacity == capacity {
+                self.free_blocks.swap_remove(free_index);
+            } else {
+                free.offset += capacity;
+                free.capacity -= capacity;
+            }
+            return block;
+        }
+
+        let offset = self.resources.len();
+        self.resources
+            .resize_with(offset + capacity, || MaybeUninit::uninit());
+        return SliceBlock {
+            offset,
+            size: 0,
+            capacity,
+        };
+    }
+
+    // Returns the capacity of the block to the free list. The resources of the block must already
+    // be dropped or moved out.
+    fn free_block(&mut self, block: SliceBlock) {
+        if block.capacity == 0 {
+            return;
+        }
+        let mut freed = SliceBlock { size: 0, ..block };
+
+        // Merge with the adjacent free blocks so the free list does not get fragmented.
+        while let Some(index) = self.free_blocks.iter().position(|free| {
+            free.offset + free.capacity == freed.offset
+                || freed.offset + freed.capacity == free.offset
+        }) {
+            let free = self.free_blocks.swap_remove(index);
+            freed.offset = freed.offset.min(free.offset);
+            freed.capacity += free.capacity;
+        }
+
+        if freed.offset + freed.capacity == self.resources.len() {
+            self.resources.truncate(freed.offset);
+        } else {
+            self.free_blocks.push(freed);
+        }
+    }
+}
+
+impl<Id: VersionedIndexId, R: Resource> IdMappedResourceSliceStorage<Id, R> {
+    // Drops the resources of the slice of the id but keeps its block.
+    fn drop_slice(&mut self, id: Id) {
+        if let Some(Some((_, block))) = self.used_blocks.get_mut(id.index()) {
+            let size = block.size;
+            block.size = 0;
+            unsafe {
+                std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(
+                    self.resources[block.offset..].as_mut_ptr() as *mut R,
+                    size,
+                ));
+            }
+        }
+    }
+
+    fn set_block_size(&mut self, id: Id, size: usize) {
+        if let Some(Some((_, block))) = self.used_blocks.get_mut(id.index()) {
+            debug_assert!(size <= block.capacity);
+            block.size = size;
+        }
+    }
+}
+
+impl<Id: VersionedIndexId, R: Resource> Drop for IdMappedResourceSliceStorage<Id, R> {
+    fn drop(&mut self) {
+        for (_, block) in self.used_blocks.iter().flatten() {
+            unsafe {
+                std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(
+                    self.resources[block.offset..].as_mut_ptr() as *mut R,
+                    block.size,
+                ));
+            }
+        }
+    }
+}
+
+// Deserializes a sequence of resources with `Resource::from_serialized`, the resource type does
+// not implement `Deserialize` itself.
+struct SliceSeed<R>(PhantomData<R>);
+
+impl<'de, R: Resource> serde::de::DeserializeSeed<'de> for SliceSeed<R> {
+    type Value = Vec<R>;
+
+    fn deserialize<D: serde::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Vec<R>, D::Error> {
+        return deserializer.deserialize_seq(self);
+    }
+}
+
+impl<'de, R: Resource> serde::de::Visitor<'de> for SliceSeed<R> {
+    type Value = Vec<R>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return write!(formatter, "a sequence of {} resources", R::label());
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(
+        self,
+        mut sequence: A,
+    ) -> std::result::Result<Vec<R>, A::Error> {
+        // The length hint is read from the file, it is not trusted to reserve memory.
+        let mut slice = Vec::new();
+        while let Some(resource) = sequence.next_element_seed(ResourceSeed(PhantomData))? {
+            slice.push(resource);
+        }
+        return Ok(slice);
+    }
+}
+
+struct ResourceSeed<R>(PhantomData<R>);
+
+impl<'de, R: Resource> serde::de::DeserializeSeed<'de> for ResourceSeed<R> {
+    type Value = R;
+
+    fn deserialize<D: serde::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<R, D::Error> {
+        let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
+        return R::from_serialized(&mut deserializer)
+            .map_err(|error| serde::de::Error::custom(error.message()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{EntityId, StandardVersionedIndexId};
+    use std::sync::Arc;
+
+    struct R(Arc<u32>);
+
+    crate::test_resource!(R, EntityComponent, IdMappedResourceSliceStorage<EntityId, R>);
+
+    type Id = StandardVersionedIndexId;
+
+    fn new_storage() -> IdMappedResourceSliceStorage<Id, R> {
+        return IdMappedResourceSliceStorage::new(&[], ResourceId::from_index(100));
+    }
+
+    #[test]
+    fn dropping_slice_storage_drops_resources() {
+        let values = (0..6).map(Arc::new).collect::<Vec<_>>();
+
+        {
+            let mut storage = new_storage();
+            storage.insert_slice(
+                Id::from_index(0),
+                values[0..3].iter().map(|v| R(v.clone())).collect::<Vec<_>>(),
+            );
+            storage.insert_slice(
+                Id::from_index(5),
+                values[3..6].iter().map(|v| R(v.clone())).collect::<Vec<_>>(),
+            );
+            assert!(values.iter().all(|value| Arc::strong_count(value) == 2));
+            assert_eq!(
+                storage.get(Id::from_index(0)).unwrap().iter().map(|r| *r.0).collect::<Vec<_>>(),
+                vec![0, 1, 2]
+            );
+
+            // Replacing a slice with a shorter one drops the old elements and leaves unused
+            // capacity in the block.
+            storage.insert_slice(Id::from_index(0), [R(values[0].clone())]);
+            assert_eq!(Arc::strong_count(&values[0]), 2);
+            assert_eq!(Arc::strong_count(&values[1]), 1);
+            assert_eq!(Arc::strong_count(&values[2]), 1);
+            assert_eq!(storage.get(Id::from_index(0)).unwrap().len(), 1);
+        }
+
+        assert!(values.iter().all(|value| Arc::strong_count(value) == 1));
+    }
+
+    fn values(storage: &IdMappedResourceSliceStorage<Id, R>, id: Id) -> Vec<u32> {
+        return storage.get(id).unwrap().iter().map(|r| *r.0).collect();
+    }
+
+    fn offset(storage: &IdMappedResourceSliceStorage<Id, R>, id: Id) -> usize {
+        return storage.used_blocks[id.index()].unwrap().1.offset;
+    }
+
+    #[test]
+    fn push_grows_into_adjacent_free_block() {
+        let value = Arc::new(0);
+        let (a, b, c) = (Id::from_index(0), Id::from_index(1), Id::from_index(2));
+
+        {
+            let mut storage = new_storage();
+            storage.insert_slice(a, [R(Arc::new(1)), R(Arc::new(2))]);
+            storage.insert_slice(b, [R(value.clone()), R(value.clone())]);
+            storage.insert_slice(c, [R(Arc::new(5))]);
+
+            storage.remove(b);
+            assert_eq!(Arc::strong_count(&value), 1);
+            assert!(storage.get(b).is_none());
+
+            storage.push(a, R(Arc::new(3)));
+            storage.push(a, R(Arc::new(4)));
+            assert_eq!(offset(&storage, a), 0);
+            assert_eq!(values(&storage, a), vec![1, 2, 3, 4]);
+            assert_eq!(values(&storage, c), vec![5]);
+            assert!(storage.free_blocks.is_empty());
+
+            // The slice now reaches up to the slice of c, so it has to be relocated.
+            storage.insert(a, 0, R(value.clone()));
+            assert_eq!(offset(&storage, a), 5);
+            assert_eq!(values(&storage, a), vec![0, 1, 2, 3, 4]);
+            assert_eq!(Arc::strong_count(&value), 2);
+        }
+
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn push_relocates_slice() {
+        let values_a = (0..3).map(Arc::new).collect::<Vec<_>>();
+        let (a, b) = (Id::from_index(0), Id::from_index(1));
+
+        {
+            let mut storage = new_storage();
+            storage.insert_slice(a, values_a[0..2].iter().map(|v| R(v.clone())).collect::<Vec<_>>());
+            storage.insert_slice(b, [R(Arc::new(10)), R(Arc::new(11))]);
+
+            storage.push(a, R(values_a[2].clone()));
+            assert_eq!(offset(&storage, a), 4);
+            assert_eq!(values(&storage, a), vec![0, 1, 2]);
+            assert_eq!(values(&storage, b), vec![10, 11]);
+            assert_eq!(
+                storage.free_blocks,
+                vec![SliceBlock { offset: 0, size: 0, capacity: 2 }]
+            );
+            assert!(values_a.iter().all(|value| Arc::strong_count(value) == 2));
+
+            // A new slice reuses the freed block.
+            storage.push(Id::from_index(2), R(Arc::new(20)));
+            assert_eq!(offset(&storage, Id::from_index(2)), 0);
+
+            storage.get_mut(a).unwrap()[1] = R(Arc::new(42));
+            assert_eq!(values(&storage, a), vec![0, 42, 2]);
+            assert_eq!(Arc::strong_count(&values_a[1]), 1);
+        }
+
+        assert!(values_a.iter().all(|value| Arc::strong_count(value) == 1));
+    }
+}
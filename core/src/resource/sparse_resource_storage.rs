@@ -0,0 +1,298 @@
+use crate::{
+    EntityComponentResourceStorage, EntityId, Gpu, Resource, ResourceBindGroupEntry, ResourceId,
+    ResourceStorage, Result, VersionedIndexId,
+};
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use super::{downcast_component, GrowableBuffer, REGISTERED_RESOURCES};
+
+struct GpuSparseResourceBuffer {
+    resource_buffer: GrowableBuffer,
+    id_buffer: GrowableBuffer,
+}
+
+// An alternative to the IdMappedResourceStorage for resources that are only attached to a few
+// ids. The IdMappedResourceStorage uses a reverse array that is indexed by the index of the id,
+// so a single id with a large index forces an array with that many entries. This storage looks
+// up the position of a resource via a hash map instead, so the memory usage only depends on the
+// number of stored resources.
+//
+// The resources and their ids are stored densely packed. Removing a resource moves the last one
+// into the freed slot. On the GPU, the resources and the ids are uploaded as two arrays of equal
+// length, i.e., the resource for an id has to be searched in the id array.
+pub struct SparseResourceStorage<Id: VersionedIndexId, R: Resource> {
+    resources: Vec<R>,
+    ids: Vec<Id>,
+    indices: HashMap<usize, usize>, // Maps the index of an id to its position in resources/ids.
+    gpu_buffers: Vec<GpuSparseResourceBuffer>,
+    uploaded_bytes: AtomicU64,
+    buffers_reallocated: AtomicBool,
+}
+
+impl<Id: VersionedIndexId + 'static, R: Resource + 'static> ResourceStorage
+    for SparseResourceStorage<Id, R>
+{
+    fn factory(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Box<dyn ResourceStorage> {
+        return Box::new(Self::new(gpus, resource_id));
+    }
+
+    fn binding_count() -> u32 {
+        return 2;
+    }
+
+    fn reserve_capacity(&mut self, _entity_count: usize) {
+        // Sparse resources are only attached to a few entities, so reserving space for all of
+        // them would defeat the purpose of this storage.
+    }
+
+    fn take_uploaded_bytes(&self) -> u64 {
+        return self.uploaded_bytes.swap(0, Ordering::Relaxed);
+    }
+
+    fn take_buffers_reallocated(&self) -> bool {
+        return self.buffers_reallocated.swap(false, Ordering::Relaxed);
+    }
+
+    fn as_entity_component_storage(&self) -> Option<&dyn EntityComponentResourceStorage> {
+        let storage = (self as &dyn Any).downcast_ref::<SparseResourceStorage<EntityId, R>>()?;
+        return Some(storage);
+    }
+
+    fn as_entity_component_storage_mut(
+        &mut self,
+    ) -> Option<&mut dyn EntityComponentResourceStorage> {
+        let storage =
+            (self as &mut dyn Any).downcast_mut::<SparseResourceStorage<EntityId, R>>()?;
+        return Some(storage);
+    }
+
+    fn bind_group_layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+        return vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+    }
+
+    fn bind_group_entries(&self, gpu_index: usize) -> Vec<ResourceBindGroupEntry> {
+        return vec![
+            ResourceBindGroupEntry::buffer(0, self.gpu_buffers[gpu_index].resource_buffer.buffer()),
+            ResourceBindGroupEntry::buffer(1, self.gpu_buffers[gpu_index].id_buffer.buffer()),
+        ];
+    }
+}
+
+impl<R: Resource> EntityComponentResourceStorage for SparseResourceStorage<EntityId, R> {
+    fn insert_boxed(&mut self, entity_id: EntityId, component: Box<dyn Any + Send>) -> Result<()> {
+        let component = downcast_component::<R>(component)?;
+        self.insert(entity_id, *component);
+        return Ok(());
+    }
+
+    fn insert_serialized(
+        &mut self,
+        entity_id: EntityId,
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Result<()> {
+        self.insert(entity_id, R::from_serialized(deserializer)?);
+        return Ok(());
+    }
+
+    fn serialize_component(&self, entity_id: EntityId) -> Option<&dyn erased_serde::Serialize> {
+        return self.get(entity_id)?.as_serializable();
+    }
+
+    fn remove_entity(&mut self, entity_id: EntityId) {
+        self.remove(entity_id);
+    }
+
+    fn clear_entities(&mut self) {
+        self.clear();
+    }
+
+    fn component_count(&self) -> usize {
+        return self.len();
+    }
+
+    fn component_slot_bound(&self) -> usize {
+        return self.len();
+    }
+}
+
+impl<Id: VersionedIndexId + 'static, R: Resource + 'static> SparseResourceStorage<Id, R> {
+    const INITIAL_BUFFER_SIZE: u64 = 1024;
+
+    pub fn new(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Self {
+        let gpu_buffers = gpus.iter().map(|gpu| {
+            let resources = REGISTERED_RESOURCES.read().unwrap();
+            let resource = resources.get(resource_id).unwrap();
+
+            let resource_buffer = GrowableBuffer::new(
+                gpu,
+                format!("{} array", resource.label),
+                Self::INITIAL_BUFFER_SIZE,
+            );
+            let id_buffer = GrowableBuffer::new(
+                gpu,
+                format!("{} id array", resource.label),
+                Self::INITIAL_BUFFER_SIZE,
+            );
+
+            return GpuSparseResourceBuffer {
+                id_buffer,
+                resource_buffer,
+            };
+        });
+
+        return Self {
+            resources: vec![],
+            ids: vec![],
+            indices: HashMap::new(),
+            gpu_buffers: gpu_buffers.collect(),
+            uploaded_bytes: AtomicU64::new(0),
+            buffers_reallocated: AtomicBool::new(false),
+        };
+    }
+
+    pub fn insert(&mut self, id: Id, resource: R) -> Option<R> {
+        return if let Some(&position) = self.indices.get(&id.index()) {
+            self.ids[position] = id;
+            Some(std::mem::replace(&mut self.resources[position], resource))
+        } else {
+            self.indices.insert(id.index(), self.resources.len());
+            self.ids.push(id);
+            self.resources.push(resource);
+            None
+        };
+    }
+
+    pub fn remove(&mut self, id: Id) -> Option<R> {
+        let position = self.indices.remove(&id.index())?;
+
+        if position != self.resources.len() - 1 {
+            let last_id = *self.ids.last().unwrap();
+            self.indices.insert(last_id.index(), position);
+        }
+        self.ids.swap_remove(position);
+        return Some(self.resources.swap_remove(position));
+    }
+
+    pub fn clear(&mut self) {
+        self.resources.clear();
+        self.ids.clear();
+        self.indices.clear();
+    }
+
+    pub fn get(&self, id: Id) -> Option<&R> {
+        return self
+            .indices
+            .get(&id.index())
+            .map(|position| &self.resources[*position]);
+    }
+
+    pub fn get_mut(&mut self, id: Id) -> Option<&mut R> {
+        return self
+            .indices
+            .get(&id.index())
+            .map(|position| &mut self.resources[*position]);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.resources.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.resources.is_empty();
+    }
+
+    pub fn update_gpu_buffers(&self) {
+        let resource_bytes = R::gpu_bytes(&self.resources).unwrap_or(&[]);
+        let id_bytes: &[u8] = bytemuck::cast_slice(&self.ids);
+
+        for buffer in &self.gpu_buffers {
+            if buffer.resource_buffer.write(resource_bytes) {
+                self.buffers_reallocated.store(true, Ordering::Relaxed);
+            }
+            self.uploaded_bytes
+                .fetch_add(resource_bytes.len() as u64, Ordering::Relaxed);
+
+            if buffer.id_buffer.write(id_bytes) {
+                self.buffers_reallocated.store(true, Ordering::Relaxed);
+            }
+            self.uploaded_bytes
+                .fetch_add(id_bytes.len() as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Id, &R)> {
+        return self.ids.iter().copied().zip(self.resources.iter());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::StandardVersionedIndexId;
+    use std::sync::Arc;
+
+    struct R(Arc<u32>);
+
+    crate::test_resource!(R, EntityComponent, SparseResourceStorage<EntityId, R>);
+
+    #[test]
+    fn sparse_storage_works() {
+        type Id = StandardVersionedIndexId;
+        let mut storage = SparseResourceStorage::<Id, R>::new(&[], ResourceId::from_index(100));
+
+        let first = Id::from_index(0);
+        let second = Id::from_index(1_000_000);
+        assert!(storage.insert(first, R(Arc::new(1))).is_none());
+        assert!(storage.insert(second, R(Arc::new(2))).is_none());
+        assert_eq!(storage.len(), 2);
+        assert_eq!(*storage.get(first).unwrap().0, 1);
+        assert_eq!(*storage.get(second).unwrap().0, 2);
+
+        // Only the two inserted entries should take up space, regardless of the index.
+        assert!(storage.resources.capacity() < 16);
+        assert!(storage.ids.capacity() < 16);
+        assert!(storage.indices.capacity() < 16);
+
+        let replaced = storage.insert(second, R(Arc::new(3)));
+        assert_eq!(*replaced.unwrap().0, 2);
+        assert_eq!(storage.len(), 2);
+
+        let removed = storage.remove(first);
+        assert_eq!(*removed.unwrap().0, 1);
+        assert!(storage.get(first).is_none());
+        assert_eq!(*storage.get(second).unwrap().0, 3);
+        assert_eq!(
+            storage.iter().map(|(id, r)| (id, *r.0)).collect::<Vec<_>>(),
+            vec![(second, 3)]
+        );
+        assert!(storage.remove(first).is_none());
+    }
+}
@@ -0,0 +1,408 @@
+use crate::{
+    Error, Gpu, Resource, ResourceBindGroupEntry, ResourceBindingResource, ResourceId,
+    ResourceStorage, Result, SimpleStorage, SourceLocation,
+};
+use std::{
+    num::NonZeroU32,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use super::REGISTERED_RESOURCES;
+
+// The pixels of a two-dimensional image, row by row without padding. Only uncompressed formats
+// that can be sampled with filtering are supported, e.g., the 8 bit RGBA formats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Texture {
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    data: Vec<u8>,
+}
+
+impl Texture {
+    pub fn new(
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        data: Vec<u8>,
+    ) -> Result<Self> {
+        let info = format.describe();
+        if info.is_compressed()
+            || !info.required_features.is_empty()
+            || info.sample_type != (wgpu::TextureSampleType::Float { filterable: true })
+        {
+            return Err(Error::new(
+                format!("{format:?} textures are not supported"),
+                SourceLocation::here(),
+            ));
+        }
+        if width == 0 || height == 0 {
+            return Err(Error::new("textures cannot be empty", SourceLocation::here()));
+        }
+        let expected_len = width as usize * height as usize * info.block_size as usize;
+        if data.len() != expected_len {
+            return Err(Error::new(
+                format!(
+                    "expected {expected_len} bytes for a {width}x{height} {format:?} texture, got \
+                     {}",
+                    data.len()
+                ),
+                SourceLocation::here(),
+            ));
+        }
+        return Ok(Self {
+            width,
+            height,
+            format,
+            data,
+        });
+    }
+
+    // Creates a texture from 8 bit RGBA pixels in the sRGB color space, e.g., decoded PNG files.
+    pub fn from_rgba8(width: u32, height: u32, data: Vec<u8>) -> Result<Self> {
+        return Self::new(width, height, wgpu::TextureFormat::Rgba8UnormSrgb, data);
+    }
+
+    pub fn width(&self) -> u32 {
+        return self.width;
+    }
+
+    pub fn height(&self) -> u32 {
+        return self.height;
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        return self.format;
+    }
+
+    pub fn data(&self) -> &[u8] {
+        return &self.data;
+    }
+
+    fn size(&self) -> wgpu::Extent3d {
+        return wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        };
+    }
+}
+
+struct GpuTexture {
+    texture: wgpu::Texture,
+    view: Arc<wgpu::TextureView>,
+    sampler: Arc<wgpu::Sampler>,
+    size: wgpu::Extent3d,
+    format: wgpu::TextureFormat,
+    // True if the texture changed since it was uploaded the last time.
+    stale: bool,
+}
+
+// Stores a single, optional texture per scene, like `SceneComponentStorage`. The texture is
+// uploaded to a wgpu::Texture on every GPU and bound together with a sampler, shaders declare them
+// as `@resource(Albedo, 0) var albedo: texture_2d<f32>;` and `@resource(Albedo, 1) var
+// albedo_sampler: sampler;`. Until a texture is uploaded, a single transparent pixel is bound.
+pub struct TextureStorage<R: Resource> {
+    resource: SimpleStorage<R>,
+    gpus: Vec<Arc<Gpu>>,
+    gpu_textures: Vec<Mutex<GpuTexture>>,
+    label: String,
+    uploaded_bytes: AtomicU64,
+    buffers_reallocated: AtomicBool,
+}
+
+impl<R: Resource + Deref<Target = Texture>> ResourceStorage for TextureStorage<R> {
+    fn factory(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Box<dyn ResourceStorage> {
+        return Box::new(Self::new(gpus, resource_id));
+    }
+
+    fn binding_count() -> u32 {
+        return 2;
+    }
+
+    fn reserve_capacity(&mut self, _entity_count: usize) {}
+
+    fn take_uploaded_bytes(&self) -> u64 {
+        return self.uploaded_bytes.swap(0, Ordering::Relaxed);
+    }
+
+    fn take_buffers_reallocated(&self) -> bool {
+        return self.buffers_reallocated.swap(false, Ordering::Relaxed);
+    }
+
+    fn bind_group_layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+        return vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ];
+    }
+
+    fn bind_group_entries(&self, gpu_index: usize) -> Vec<ResourceBindGroupEntry> {
+        let gpu_texture = self.gpu_textures[gpu_index].lock().unwrap();
+        return vec![
+            ResourceBindGroupEntry {
+                binding: 0,
+                resource: ResourceBindingResource::TextureView(gpu_texture.view.clone()),
+            },
+            ResourceBindGroupEntry {
+                binding: 1,
+                resource: ResourceBindingResource::Sampler(gpu_texture.sampler.clone()),
+            },
+        ];
+    }
+}
+
+impl<R: Resource + Deref<Target = Texture>> TextureStorage<R> {
+    pub fn new(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Self {
+        let label = REGISTERED_RESOURCES
+            .read()
+            .unwrap()
+            .get(resource_id)
+            .map_or_else(|| R::label().to_string(), |resource| resource.label.clone());
+        let placeholder = wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        };
+        let gpu_textures = gpus.iter().map(|gpu| {
+            let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+            let (texture, view) = Self::create_texture(gpu, &label, placeholder, format);
+            gpu.queue().write_texture(
+                texture.as_image_copy(),
+                &[0; 4],
+                wgpu::ImageDataLayout::default(),
+                placeholder,
+            );
+            let sampler = gpu.device().create_sampler(&wgpu::SamplerDescriptor {
+                label: Some(&label),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            });
+            return Mutex::new(GpuTexture {
+                texture,
+                view: Arc::new(view),
+                sampler: Arc::new(sampler),
+                size: placeholder,
+                format,
+                stale: true,
+            });
+        });
+
+        return Self {
+            resource: SimpleStorage::new(),
+            gpus: gpus.to_vec(),
+            gpu_textures: gpu_textures.collect(),
+            label,
+            uploaded_bytes: AtomicU64::new(0),
+            buffers_reallocated: AtomicBool::new(false),
+        };
+    }
+
+    pub fn set(&mut self, resource: R) -> &R {
+        self.mark_gpu_textures_stale();
+        return self.resource.emplace(resource);
+    }
+
+    // Removes the texture. The texture that was uploaded the last time stays bound.
+    pub fn reset(&mut self) {
+        self.resource.reset();
+    }
+
+    pub fn get(&self) -> Option<&R> {
+        return self.resource.get();
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut R> {
+        self.mark_gpu_textures_stale();
+        return self.resource.get_mut();
+    }
+
+    // Uploads the texture to the GPUs if it changed since the last upload. The wgpu::Texture is
+    // recreated if the dimensions or the format changed, which requires new bind groups. Fails if
+    // the texture is larger than a GPU supports, the previous texture stays bound on that GPU.
+    pub fn update_gpu_buffers(&self) -> Result<()> {
+        let Some(texture) = self.resource.get() else {
+            return Ok(());
+        };
+        for (gpu, gpu_texture) in self.gpus.iter().zip(&self.gpu_textures) {
+            let mut gpu_texture = gpu_texture.lock().unwrap();
+            if !gpu_texture.stale {
+                continue;
+            }
+            let max_dimension = gpu.device().limits().max_texture_dimension_2d;
+            if texture.width > max_dimension || texture.height > max_dimension {
+                return Err(Error::new(
+                    format!(
+                        "the {}x{} texture {} cannot be uploaded, the GPU supports at most \
+                         {max_dimension} pixels per dimension",
+                        texture.width, texture.height, self.label
+                    ),
+                    SourceLocation::here(),
+                ));
+            }
+
+            if gpu_texture.size != texture.size() || gpu_texture.format != texture.format {
+                let (new_texture, view) =
+                    Self::create_texture(gpu, &self.label, texture.size(), texture.format);
+                gpu_texture.texture = new_texture;
+                gpu_texture.view = Arc::new(view);
+                gpu_texture.size = texture.size();
+                gpu_texture.format = texture.format;
+                self.buffers_reallocated.store(true, Ordering::Relaxed);
+            }
+            gpu.queue().write_texture(
+                gpu_texture.texture.as_image_copy(),
+                &texture.data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(
+                        texture.width * texture.format.describe().block_size as u32,
+                    ),
+                    rows_per_image: NonZeroU32::new(texture.height),
+                },
+                texture.size(),
+            );
+            gpu_texture.stale = false;
+            self.uploaded_bytes
+                .fetch_add(texture.data.len() as u64, Ordering::Relaxed);
+        }
+        return Ok(());
+    }
+
+    fn create_texture(
+        gpu: &Gpu,
+        label: &str,
+        size: wgpu::Extent3d,
+        format: wgpu::TextureFormat,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = gpu.device().create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        return (texture, view);
+    }
+
+    fn mark_gpu_textures_stale(&mut self) {
+        for gpu_texture in &mut self.gpu_textures {
+            gpu_texture.get_mut().unwrap().stale = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Albedo(Texture);
+
+    impl Deref for Albedo {
+        type Target = Texture;
+
+        fn deref(&self) -> &Texture {
+            return &self.0;
+        }
+    }
+
+    crate::test_resource!(Albedo, Texture, TextureStorage<Albedo>);
+
+    #[test]
+    fn textures_validate_their_data() {
+        assert!(Texture::from_rgba8(2, 2, vec![0; 16]).is_ok());
+        assert!(Texture::from_rgba8(2, 2, vec![0; 15]).is_err());
+        assert!(Texture::from_rgba8(0, 2, vec![]).is_err());
+        assert!(Texture::new(1, 1, wgpu::TextureFormat::R32Float, vec![0; 4]).is_err());
+        assert!(Texture::new(1, 1, wgpu::TextureFormat::Bgra8Unorm, vec![0; 4]).is_ok());
+    }
+
+    #[test]
+    fn texture_storage_binds_texture_and_sampler() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+
+        Albedo::register();
+        let mut storage: Box<dyn ResourceStorage> =
+            TextureStorage::<Albedo>::factory(std::slice::from_ref(&gpu), Albedo::id());
+        let layout_entries = storage.bind_group_layout_entries();
+        assert_eq!(layout_entries.len(), 2);
+        assert!(matches!(layout_entries[0].ty, wgpu::BindingType::Texture { .. }));
+        assert!(matches!(layout_entries[1].ty, wgpu::BindingType::Sampler(_)));
+
+        let pixels = [255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 255];
+        let albedo = storage.as_texture_mut::<Albedo>().unwrap();
+        albedo.set(Albedo(Texture::from_rgba8(2, 2, pixels.to_vec()).unwrap()));
+        albedo.update_gpu_buffers().unwrap();
+        assert_eq!(storage.take_uploaded_bytes(), 16);
+        // The placeholder is replaced with a texture of the new size.
+        assert!(storage.take_buffers_reallocated());
+
+        // Nothing is uploaded if the texture did not change.
+        storage.as_texture::<Albedo>().unwrap().update_gpu_buffers().unwrap();
+        assert_eq!(storage.take_uploaded_bytes(), 0);
+
+        let entries = storage.bind_group_entries(gpu.index());
+        assert_eq!(entries.iter().map(|entry| entry.binding).collect::<Vec<_>>(), [0, 1]);
+        assert!(matches!(entries[0].resource, ResourceBindingResource::TextureView(_)));
+        assert!(matches!(entries[1].resource, ResourceBindingResource::Sampler(_)));
+
+        // The entries fit the layout.
+        let layout = gpu.device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &layout_entries,
+        });
+        gpu.device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &entries
+                .iter()
+                .map(|entry| wgpu::BindGroupEntry {
+                    binding: entry.binding,
+                    resource: entry.resource.as_binding_resource(),
+                })
+                .collect::<Vec<_>>(),
+        });
+    }
+
+    #[test]
+    fn textures_larger_than_the_gpu_supports_are_not_uploaded() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        Albedo::register();
+        let mut storage = TextureStorage::<Albedo>::new(std::slice::from_ref(&gpu), Albedo::id());
+        let width = gpu.device().limits().max_texture_dimension_2d + 1;
+        let pixels = vec![0; width as usize * 4];
+        storage.set(Albedo(Texture::from_rgba8(width, 1, pixels).unwrap()));
+
+        let error = storage.update_gpu_buffers().unwrap_err();
+        assert!(error.message().contains("cannot be uploaded"), "{error}");
+        assert_eq!(storage.take_uploaded_bytes(), 0);
+        assert!(!storage.take_buffers_reallocated());
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    Gpu, IdMappedResourceStorage, Resource, ResourceBindGroupEntry, ResourceId, ResourceStorage,
+    ViewportId,
+};
+use std::sync::Arc;
+
+// Stores an optional resource per viewport, e.g., the color the viewport is cleared to. On the
+// GPU, the resources are mirrored like entity components, indexed by the index of the viewport id
+// instead of the entity id.
+pub struct ViewportComponentStorage<R: Resource> {
+    storage: IdMappedResourceStorage<ViewportId, R>,
+}
+
+impl<R: Resource + 'static> ResourceStorage for ViewportComponentStorage<R> {
+    fn factory(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Box<dyn ResourceStorage> {
+        return Box::new(Self::new(gpus, resource_id));
+    }
+
+    fn binding_count() -> u32 {
+        return IdMappedResourceStorage::<ViewportId, R>::binding_count();
+    }
+
+    // There are only a few viewports, the capacity is meant for entities.
+    fn reserve_capacity(&mut self, _entity_count: usize) {}
+
+    fn take_uploaded_bytes(&self) -> u64 {
+        return self.storage.take_uploaded_bytes();
+    }
+
+    fn take_buffers_reallocated(&self) -> bool {
+        return self.storage.take_buffers_reallocated();
+    }
+
+    fn end_frame(&mut self) {
+        self.storage.end_frame();
+    }
+
+    fn remove_viewport(&mut self, viewport_id: ViewportId) {
+        self.storage.remove(viewport_id);
+    }
+
+    fn bind_group_layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+        return self.storage.bind_group_layout_entries();
+    }
+
+    fn bind_group_entries(&self, gpu_index: usize) -> Vec<ResourceBindGroupEntry> {
+        return self.storage.bind_group_entries(gpu_index);
+    }
+}
+
+impl<R: Resource + 'static> ViewportComponentStorage<R> {
+    pub fn new(gpus: &[Arc<Gpu>], resource_id: ResourceId) -> Self {
+        return Self {
+            storage: IdMappedResourceStorage::new(gpus, resource_id),
+        };
+    }
+
+    // Sets the resource of the viewport and returns the previous one.
+    pub fn insert(&mut self, viewport_id: ViewportId, resource: R) -> Option<R> {
+        return self.storage.insert(viewport_id, resource);
+    }
+
+    pub fn remove(&mut self, viewport_id: ViewportId) -> Option<R> {
+        return self.storage.remove(viewport_id);
+    }
+
+    pub fn get(&self, viewport_id: ViewportId) -> Option<&R> {
+        return self.storage.get(viewport_id);
+    }
+
+    pub fn get_mut(&mut self, viewport_id: ViewportId) -> Option<&mut R> {
+        return self.storage.get_mut(viewport_id);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ViewportId, &R)> {
+        return self.storage.iter();
+    }
+
+    pub fn update_gpu_buffers(&self) {
+        self.storage.update_gpu_buffers();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::VersionedIndexId;
+
+    #[derive(Debug, PartialEq)]
+    struct Exposure(f32);
+
+    crate::test_resource!(Exposure, ViewportComponent, ViewportComponentStorage<Exposure>);
+
+    #[test]
+    fn viewport_component_storage_works() {
+        let mut storage: Box<dyn ResourceStorage> =
+            ViewportComponentStorage::<Exposure>::factory(&[], ResourceId::from_index(100));
+
+        // Viewport components are not entity components, even though both are indexed by ids of
+        // the same type.
+        assert!(storage.as_entity_component_storage_mut().is_none());
+
+        let first_viewport = ViewportId::from_index(0);
+        let second_viewport = ViewportId::from_index(1);
+        let exposures = storage.as_viewport_component_mut::<Exposure>().unwrap();
+        assert!(exposures.insert(first_viewport, Exposure(1.0)).is_none());
+        assert!(exposures.insert(second_viewport, Exposure(2.0)).is_none());
+        exposures.get_mut(second_viewport).unwrap().0 = 0.5;
+
+        let exposures = storage.as_viewport_component::<Exposure>().unwrap();
+        assert_eq!(exposures.get(first_viewport), Some(&Exposure(1.0)));
+        assert_eq!(exposures.get(second_viewport), Some(&Exposure(0.5)));
+        assert!(exposures.get(ViewportId::from_index(2)).is_none());
+    }
+}
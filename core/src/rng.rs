@@ -0,0 +1,121 @@
+use std::ops::Range;
+
+// A small random number generator (PCG32), see `SystemResources::rng`. The numbers only depend on
+// the seed and on the order they are drawn in, so a scene that is seeded the same way and runs its
+// jobs in the same order, e.g., without workers, behaves the same way every time, e.g., to replay
+// a game or to test it. It is not suited for cryptography.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    // The seed of the scenes that do not configure one, see `SceneConfig::rng_seed`.
+    pub const DEFAULT_SEED: u64 = 0x853c_49e6_748f_ea9b;
+
+    const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+    const INCREMENT: u64 = 1_442_695_040_888_963_407;
+
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self { state: 0 };
+        rng.step();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.step();
+        return rng;
+    }
+
+    fn step(&mut self) {
+        self.state = self
+            .state
+            .wrapping_mul(Self::MULTIPLIER)
+            .wrapping_add(Self::INCREMENT);
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let state = self.state;
+        self.step();
+        let xorshifted = (((state >> 18) ^ state) >> 27) as u32;
+        return xorshifted.rotate_right((state >> 59) as u32);
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        return (self.next_u32() as u64) << 32 | self.next_u32() as u64;
+    }
+
+    // Returns a number in [0, 1) with 24 bits of randomness, i.e., all of them are representable.
+    pub fn next_f32(&mut self) -> f32 {
+        return (self.next_u32() >> 8) as f32 / (1 << 24) as f32;
+    }
+
+    // Returns a number in the range, e.g., `rng.gen_range(0..6)` or `rng.gen_range(-1.0..1.0)`.
+    // Panics if the range is empty.
+    pub fn gen_range<T: RngRange>(&mut self, range: Range<T>) -> T {
+        return T::sample(self, range);
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        return Self::new(Self::DEFAULT_SEED);
+    }
+}
+
+// The types `Rng::gen_range` can return.
+pub trait RngRange: Sized {
+    fn sample(rng: &mut Rng, range: Range<Self>) -> Self;
+}
+
+impl RngRange for u32 {
+    fn sample(rng: &mut Rng, range: Range<Self>) -> Self {
+        assert!(range.start < range.end, "cannot sample the empty range {range:?}");
+        // Scales the number to the length of the range instead of taking the remainder, which
+        // keeps the bias for long ranges small.
+        let len = (range.end - range.start) as u64;
+        return range.start + ((rng.next_u32() as u64 * len) >> 32) as u32;
+    }
+}
+
+impl RngRange for i32 {
+    fn sample(rng: &mut Rng, range: Range<Self>) -> Self {
+        assert!(range.start < range.end, "cannot sample the empty range {range:?}");
+        let len = range.end.abs_diff(range.start) as u64;
+        return range.start.wrapping_add(((rng.next_u32() as u64 * len) >> 32) as i32);
+    }
+}
+
+impl RngRange for f32 {
+    fn sample(rng: &mut Rng, range: Range<Self>) -> Self {
+        assert!(range.start < range.end, "cannot sample the empty range {range:?}");
+        // Rounding can make the result equal to the end of tiny ranges.
+        let value = range.start + rng.next_f32() * (range.end - range.start);
+        return if value < range.end { value } else { range.start };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn numbers_are_in_their_range() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            assert!((3..9).contains(&rng.gen_range(3u32..9)));
+            assert!((-5..-2).contains(&rng.gen_range(-5i32..-2)));
+            assert!((-1.0..1.0).contains(&rng.gen_range(-1.0f32..1.0)));
+            assert!((0.0..1.0).contains(&rng.next_f32()));
+        }
+        assert_eq!(rng.gen_range(i32::MIN..i32::MIN + 1), i32::MIN);
+
+        // All values of a small range are drawn eventually.
+        let mut drawn = [false; 6];
+        for _ in 0..100 {
+            drawn[rng.gen_range(0u32..6) as usize] = true;
+        }
+        assert_eq!(drawn, [true; 6]);
+
+        // Different seeds give different numbers.
+        let [mut first, mut second] = [Rng::new(1), Rng::new(2)];
+        assert_ne!([first.next_u64(), first.next_u64()], [second.next_u64(), second.next_u64()]);
+    }
+}
@@ -1,61 +1,848 @@
 use std::{
     any::Any,
-    sync::{Arc, RwLock, RwLockWriteGuard},
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc, MappedRwLockReadGuard, MappedRwLockWriteGuard, Mutex, MutexGuard, RwLock,
+        RwLockReadGuard, RwLockWriteGuard,
+    },
+    ops::Deref,
     thread, marker::PhantomData,
+    time::{Duration, Instant},
 };
 
-use winit::dpi::PhysicalSize;
+use winit::{dpi::PhysicalSize, window::WindowId};
 
 use crate::{
-    make_resource_storages, Gpu, IdMap, IdStorage,
-    Instance, JobKind, Resource, ResourceId, ResourceStorage, Result, Scheduler,
-    StandardVersionedIndexId, VersionedIndexId,
+    find_resource, make_resource_storages, resource_label, Error, FrameListener, Gpu, IdMap,
+    IdMappedResourceSliceStorage, IdMappedResourceStorage, IdMappedResourceStorageIterator,
+    IdStorage, Input, Instance, JobKind, Mouse, ProfileCallback, Resource, ResourceBindGroupEntry,
+    ResourceId, ResourceStorage, Result, Rng, Scheduler, SourceLocation, StandardVersionedIndexId,
+    Texture, TextureStorage, VersionedIndexId, ViewportEvent, DEFAULT_FIXED_DELTA_TIME,
 };
 
 pub type EntityId = StandardVersionedIndexId<8>;
 pub type ViewportId = StandardVersionedIndexId<8>;
 
-pub struct EntityDescriptor {}
+// Describes an entity that is spawned at the end of the frame, i.e., the components it starts
+// with.
+pub struct EntityDescriptor {
+    components: Vec<(ResourceId, Box<dyn Any + Send>)>,
+}
 
 impl EntityDescriptor {
     pub fn new() -> Self {
-        EntityDescriptor {}
+        EntityDescriptor { components: vec![] }
+    }
+
+    pub fn with<C: Resource>(mut self, component: C) -> Self {
+        self.components.push((C::id(), Box::new(component)));
+        return self;
+    }
+
+    // Adds a component that is stored as a slice of resources, e.g., the vertices of a mesh.
+    pub fn with_slice<C: Resource>(mut self, components: Vec<C>) -> Self {
+        self.components.push((C::id(), Box::new(components)));
+        return self;
+    }
+
+    pub(crate) fn into_components(self) -> Vec<(ResourceId, Box<dyn Any + Send>)> {
+        return self.components;
+    }
+}
+
+// The depth buffer of the viewports of a scene.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DepthBufferConfig {
+    pub format: wgpu::TextureFormat,
+    // The depth the buffer is cleared to at the beginning of every frame, see
+    // `Viewport::depth_stencil_attachment`. If None, the depth of the previous frame is kept.
+    pub clear_value: Option<f32>,
+}
+
+impl Default for DepthBufferConfig {
+    fn default() -> Self {
+        return Self {
+            format: wgpu::TextureFormat::Depth32Float,
+            clear_value: Some(1.0),
+        };
+    }
+}
+
+// A depth texture with the size of its viewport.
+struct DepthBuffer {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl DepthBuffer {
+    // Multisampled viewports need a depth buffer with the same number of samples.
+    fn new(
+        gpu: &Gpu,
+        format: wgpu::TextureFormat,
+        size: PhysicalSize<u32>,
+        sample_count: u32,
+    ) -> Self {
+        let texture = gpu.device().create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Buffer"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        return Self { texture, view };
+    }
+}
+
+// The texture multisampled viewports render to. It is resolved into the texture of the viewport
+// at the end of every render pass, see `Viewport::color_attachment`.
+struct MultisampledTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl MultisampledTarget {
+    fn new(
+        gpu: &Gpu,
+        format: wgpu::TextureFormat,
+        size: PhysicalSize<u32>,
+        sample_count: u32,
+    ) -> Self {
+        let texture = gpu.device().create_texture(&wgpu::TextureDescriptor {
+            label: Some("Multisampled Viewport"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        return Self { texture, view };
+    }
+}
+
+// Options for adding a viewport that presents to a window.
+#[derive(Debug, Clone)]
+pub struct ViewportConfig {
+    // How the frames are presented, e.g., `Fifo` waits for the vertical blank, which saves
+    // battery, and `Immediate` presents right away, which is useful for benchmarks. Modes that
+    // the surface does not support fall back to a supported one, see `choose_present_mode`.
+    // Defaults to `Fifo`.
+    pub present_mode: wgpu::PresentMode,
+    // The index of the GPU of the instance that renders the viewport, see `Instance::gpus`. The
+    // GPU has to be able to present to the window. Defaults to the first GPU.
+    pub gpu_index: usize,
+    // The number of samples per pixel, e.g., 4 to smooth the edges of triangles. Counts that the
+    // GPU does not support for the format of the surface fall back to the highest supported
+    // lower count, see `Gpu::supports_sample_count`. Defaults to 1.
+    pub sample_count: u32,
+}
+
+impl Default for ViewportConfig {
+    fn default() -> Self {
+        return Self {
+            present_mode: wgpu::PresentMode::Fifo,
+            gpu_index: 0,
+            sample_count: 1,
+        };
+    }
+}
+
+// Returns the requested present mode if the surface supports it. Otherwise, `Immediate` falls
+// back to `Mailbox`, which does not block the rendering either, and all other modes fall back to
+// `Fifo`, which every surface supports. The automatic modes are resolved by wgpu itself.
+fn choose_present_mode(
+    requested: wgpu::PresentMode,
+    supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    use wgpu::PresentMode;
+
+    if matches!(requested, PresentMode::AutoVsync | PresentMode::AutoNoVsync)
+        || supported.contains(&requested)
+    {
+        return requested;
     }
+    if requested == PresentMode::Immediate && supported.contains(&PresentMode::Mailbox) {
+        return PresentMode::Mailbox;
+    }
+    return PresentMode::Fifo;
+}
+
+// Returns the requested sample count if the GPU supports it for the format and the highest
+// supported lower count otherwise. A single sample is always supported.
+fn choose_sample_count(gpu: &Gpu, format: wgpu::TextureFormat, requested: u32) -> u32 {
+    return [8, 4, 2]
+        .into_iter()
+        .find(|&count| count <= requested && gpu.supports_sample_count(format, count))
+        .unwrap_or(1);
+}
+
+// What a viewport renders to.
+enum RenderTarget {
+    // The surface of a window. Its texture is acquired at the beginning of every tick and
+    // presented at the end.
+    Surface {
+        // Boxed, so the enum is not much larger than an offscreen target.
+        surface: Box<wgpu::Surface>,
+        config: wgpu::SurfaceConfiguration,
+        // The present modes the surface supports for the GPU of the viewport.
+        present_modes: Vec<wgpu::PresentMode>,
+        texture: Option<wgpu::SurfaceTexture>,
+    },
+    // A texture that is rendered to without presenting it, e.g., for rendering tests or to
+    // generate images on a server. It can be copied from after the tick.
+    Offscreen(wgpu::Texture),
+}
+
+fn create_offscreen_texture(
+    gpu: &Gpu,
+    size: PhysicalSize<u32>,
+    format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+    return gpu.device().create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen Viewport"),
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
 }
 
 pub struct Viewport {
     gpu: Arc<Gpu>,
-    surface: wgpu::Surface,
-    surface_config: wgpu::SurfaceConfiguration,
-    texture: Option<wgpu::SurfaceTexture>,
+    target: RenderTarget,
     texture_view: Option<wgpu::TextureView>,
+    depth_buffer_config: Option<DepthBufferConfig>,
+    // None while the viewport has no area.
+    depth_buffer: Option<DepthBuffer>,
+    sample_count: u32,
+    // None while the viewport has no area or a single sample per pixel.
+    multisampled_target: Option<MultisampledTarget>,
 }
 
 impl Viewport {
+    fn new(
+        gpu: Arc<Gpu>,
+        target: RenderTarget,
+        depth_buffer_config: Option<DepthBufferConfig>,
+        sample_count: u32,
+    ) -> Self {
+        let mut viewport = Self {
+            gpu,
+            target,
+            texture_view: None,
+            depth_buffer_config,
+            depth_buffer: None,
+            sample_count,
+            multisampled_target: None,
+        };
+        viewport.configure();
+        return viewport;
+    }
+
+    // A viewport that renders to a texture instead of a window. Offscreen textures cannot be
+    // empty, so the size is at least one pixel.
+    pub(crate) fn offscreen(
+        gpu: Arc<Gpu>,
+        size: PhysicalSize<u32>,
+        format: wgpu::TextureFormat,
+        depth_buffer_config: Option<DepthBufferConfig>,
+    ) -> Self {
+        let size = PhysicalSize::new(size.width.max(1), size.height.max(1));
+        let texture = create_offscreen_texture(&gpu, size, format);
+        return Self::new(gpu, RenderTarget::Offscreen(texture), depth_buffer_config, 1);
+    }
+
     pub fn gpu(&self) -> &Arc<Gpu> {
         &self.gpu
     }
 
-    pub fn surface(&self) -> &wgpu::Surface {
-        &self.surface
+    // None for offscreen viewports.
+    pub fn surface(&self) -> Option<&wgpu::Surface> {
+        return match &self.target {
+            RenderTarget::Surface { surface, .. } => Some(surface.as_ref()),
+            RenderTarget::Offscreen(_) => None,
+        };
+    }
+
+    // None for offscreen viewports.
+    pub fn surface_config(&self) -> Option<&wgpu::SurfaceConfiguration> {
+        return match &self.target {
+            RenderTarget::Surface { config, .. } => Some(config),
+            RenderTarget::Offscreen(_) => None,
+        };
+    }
+
+    pub fn is_offscreen(&self) -> bool {
+        return matches!(self.target, RenderTarget::Offscreen(_));
     }
 
-    pub fn texture(&self) -> Option<&wgpu::SurfaceTexture> {
-        self.texture.as_ref()
+    // The texture the jobs render to in this tick. Surface textures are only available while
+    // the jobs run, offscreen textures can also be copied from after the tick.
+    pub fn texture(&self) -> Option<&wgpu::Texture> {
+        return match &self.target {
+            RenderTarget::Surface { texture, .. } => {
+                texture.as_ref().map(|texture| &texture.texture)
+            }
+            RenderTarget::Offscreen(texture) => Some(texture),
+        };
     }
 
     pub fn texture_view(&self) -> Option<&wgpu::TextureView> {
         self.texture_view.as_ref()
     }
 
-    pub fn surface_config(&self) -> &wgpu::SurfaceConfiguration {
-        &self.surface_config
+    // The format of the texture the viewport renders to.
+    pub fn format(&self) -> wgpu::TextureFormat {
+        return match &self.target {
+            RenderTarget::Surface { config, .. } => config.format,
+            RenderTarget::Offscreen(texture) => texture.format(),
+        };
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        return match &self.target {
+            RenderTarget::Surface { config, .. } => PhysicalSize::new(config.width, config.height),
+            RenderTarget::Offscreen(texture) => {
+                PhysicalSize::new(texture.width(), texture.height())
+            }
+        };
+    }
+
+    // The format of the depth buffer or None if the viewport has no depth buffer. The pipelines
+    // of the jobs test and write depth if it has one.
+    pub fn depth_format(&self) -> Option<wgpu::TextureFormat> {
+        return self.depth_buffer_config.map(|config| config.format);
+    }
+
+    // The number of samples per pixel of the color and depth attachments. The pipelines of the
+    // jobs are created with it, so their render passes have to use `color_attachment`.
+    pub fn sample_count(&self) -> u32 {
+        return self.sample_count;
+    }
+
+    // Whether the GPU of the viewport can render to its format with the given number of samples,
+    // see `Gpu::supports_sample_count`.
+    pub fn supports_sample_count(&self, sample_count: u32) -> bool {
+        return self.gpu.supports_sample_count(self.format(), sample_count);
+    }
+
+    // The texture multisampled viewports render to, it has the size and format of the viewport.
+    // None for viewports with a single sample per pixel.
+    pub fn multisampled_texture(&self) -> Option<&wgpu::Texture> {
+        return self.multisampled_target.as_ref().map(|target| &target.texture);
+    }
+
+    // The color attachment of render passes that draw to the viewport. Multisampled viewports
+    // render to their multisampled texture, which is resolved into the texture of the viewport at
+    // the end of every pass. The multisampled texture is stored as well, so the following passes
+    // can load it. None while the viewport has no texture view.
+    pub fn color_attachment(
+        &self,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> Option<wgpu::RenderPassColorAttachment<'_>> {
+        let view = self.texture_view()?;
+        let ops = wgpu::Operations { load, store: true };
+        return Some(match &self.multisampled_target {
+            Some(target) => wgpu::RenderPassColorAttachment {
+                view: &target.view,
+                resolve_target: Some(view),
+                ops,
+            },
+            None => wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops,
+            },
+        });
+    }
+
+    pub fn depth_texture(&self) -> Option<&wgpu::Texture> {
+        return self.depth_buffer.as_ref().map(|depth_buffer| &depth_buffer.texture);
+    }
+
+    pub fn depth_texture_view(&self) -> Option<&wgpu::TextureView> {
+        return self.depth_buffer.as_ref().map(|depth_buffer| &depth_buffer.view);
+    }
+
+    // The depth attachment of render passes that draw to the viewport with the pipeline of their
+    // job. The first pass of a frame, e.g., the one clearing the surface, passes `clear` to reset
+    // the depth buffer to the clear value of its config. The other passes keep the depth.
+    pub fn depth_stencil_attachment(
+        &self,
+        clear: bool,
+    ) -> Option<wgpu::RenderPassDepthStencilAttachment<'_>> {
+        let config = self.depth_buffer_config?;
+        let load = match config.clear_value {
+            Some(clear_value) if clear => wgpu::LoadOp::Clear(clear_value),
+            _ => wgpu::LoadOp::Load,
+        };
+        return Some(wgpu::RenderPassDepthStencilAttachment {
+            view: self.depth_texture_view()?,
+            depth_ops: Some(wgpu::Operations { load, store: true }),
+            stencil_ops: None,
+        });
+    }
+
+    // Copies the texture of the viewport to the CPU and blocks until the copy arrived, e.g., for
+    // screenshots or to compare the rendering to a reference image in tests. The pixels are
+    // returned row by row as RGBA with 8 bits per channel. The values are not converted, so they
+    // are sRGB encoded if the viewport has an sRGB format and linear otherwise. Only 8 bit RGBA
+    // and BGRA formats are supported. Surface textures only exist while the jobs of a tick run and
+    // usually cannot be copied from, so this is mostly useful for offscreen viewports.
+    pub fn read_pixels(&self) -> Result<Vec<u8>> {
+        let Some(texture) = self.texture() else {
+            return Err(Error::new(
+                "the viewport has no texture to read from",
+                SourceLocation::here(),
+            ));
+        };
+        let swap_red_and_blue = match texture.format() {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            format => {
+                return Err(Error::new(
+                    format!("cannot read the pixels of a viewport with the format {format:?}"),
+                    SourceLocation::here(),
+                ));
+            }
+        };
+        if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            return Err(Error::new(
+                "the texture of the viewport cannot be copied from",
+                SourceLocation::here(),
+            ));
+        }
+
+        // The rows of the copy are padded to the alignment required by wgpu.
+        let size = texture.size();
+        let row_size = size.width as usize * 4;
+        let padded_row_size =
+            row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize);
+        let readback_buffer = self.gpu.device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Viewport Readback"),
+            size: (padded_row_size * size.height as usize) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .gpu
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_row_size as u32),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        self.gpu.queue().submit(std::iter::once(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        // Waiting for the device calls the callback.
+        self.gpu.device().poll(wgpu::Maintain::Wait);
+        if let Err(error) = receiver.recv().unwrap() {
+            return Err(Error::new(
+                format!("failed to read the pixels of the viewport: {error}"),
+                SourceLocation::here(),
+            ));
+        }
+
+        let mut pixels = Vec::with_capacity(row_size * size.height as usize);
+        for row in slice.get_mapped_range().chunks_exact(padded_row_size) {
+            pixels.extend_from_slice(&row[..row_size]);
+        }
+        if swap_red_and_blue {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        return Ok(pixels);
+    }
+
+    // Surfaces cannot be configured with a size of zero, e.g., while the window is minimized.
+    fn has_area(&self) -> bool {
+        let size = self.size();
+        return size.width > 0 && size.height > 0;
+    }
+
+    // Changes the size of the surface or recreates the offscreen texture with the new size. The
+    // viewport has to be configured afterwards.
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        match &mut self.target {
+            RenderTarget::Surface { config, .. } => {
+                config.width = size.width;
+                config.height = size.height;
+            }
+            RenderTarget::Offscreen(texture) => {
+                *texture = create_offscreen_texture(&self.gpu, size, texture.format());
+            }
+        }
+    }
+
+    // Changes the number of samples per pixel. The count has to be supported, see
+    // `supports_sample_count`, and the viewport has to be configured afterwards.
+    fn set_sample_count(&mut self, sample_count: u32) {
+        self.sample_count = sample_count;
+    }
+
+    // Offscreen viewports are not presented, so they have no present mode.
+    fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        if let RenderTarget::Surface {
+            config,
+            present_modes,
+            ..
+        } = &mut self.target
+        {
+            config.present_mode = choose_present_mode(present_mode, present_modes);
+        }
+    }
+
+    // Configures the surface and recreates the depth buffer and the multisampled texture with the
+    // size of the viewport. Does nothing while the viewport has no area.
+    fn configure(&mut self) {
+        if !self.has_area() {
+            return;
+        }
+        if let RenderTarget::Surface {
+            surface, config, ..
+        } = &self.target
+        {
+            surface.configure(self.gpu.device(), config);
+        }
+        let size = self.size();
+        let sample_count = self.sample_count;
+        self.depth_buffer = self
+            .depth_buffer_config
+            .map(|config| DepthBuffer::new(&self.gpu, config.format, size, sample_count));
+        self.multisampled_target = (sample_count > 1)
+            .then(|| MultisampledTarget::new(&self.gpu, self.format(), size, sample_count));
+    }
+
+    // Acquires the texture the jobs render to in this tick. Viewports that skip the frame, e.g.,
+    // because the window is minimized or the surface timed out, have no texture view afterwards.
+    fn acquire_texture(&mut self) -> Result<()> {
+        self.texture_view = None;
+        if !self.has_area() {
+            return Ok(());
+        }
+        let texture = match &mut self.target {
+            RenderTarget::Surface {
+                surface,
+                config,
+                texture,
+                ..
+            } => {
+                *texture = acquire_surface_texture(&self.gpu, surface, config)?;
+                texture.as_ref().map(|texture| &texture.texture)
+            }
+            RenderTarget::Offscreen(texture) => Some(&*texture),
+        };
+        self.texture_view = texture
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        return Ok(());
+    }
+
+    // Drops the texture of the tick. Surface textures are presented unless `present` is false,
+    // e.g., because acquiring the textures of the other viewports failed.
+    fn release_texture(&mut self, present: bool) {
+        self.texture_view = None;
+        if let RenderTarget::Surface { texture, .. } = &mut self.target {
+            if let Some(texture) = texture.take() {
+                if present {
+                    texture.present();
+                }
+            }
+        }
+    }
+}
+
+// Acquires the next texture of the surface. Returns None if the viewport has to skip the frame.
+fn acquire_surface_texture(
+    gpu: &Gpu,
+    surface: &wgpu::Surface,
+    config: &wgpu::SurfaceConfiguration,
+) -> Result<Option<wgpu::SurfaceTexture>> {
+    let mut reconfigured = false;
+    loop {
+        let error = match surface.get_current_texture() {
+            Ok(texture) => return Ok(Some(texture)),
+            Err(error) => error,
+        };
+        match recover_from_surface_error(&error, reconfigured) {
+            SurfaceRecovery::Reconfigure => {
+                surface.configure(gpu.device(), config);
+                reconfigured = true;
+            }
+            SurfaceRecovery::SkipFrame => return Ok(None),
+            SurfaceRecovery::Fail => {
+                return Err(Error::new(
+                    format!("failed to acquire the surface texture: {error}"),
+                    SourceLocation::here(),
+                ));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SurfaceRecovery {
+    Reconfigure,
+    SkipFrame,
+    Fail,
+}
+
+// Lost or outdated surfaces, e.g., after the window was resized or moved to another monitor, are
+// reconfigured and acquired again once. If that does not help or the surface timed out, the
+// viewport skips the frame. Running out of memory fails the tick.
+fn recover_from_surface_error(error: &wgpu::SurfaceError, reconfigured: bool) -> SurfaceRecovery {
+    return match error {
+        wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated if !reconfigured => {
+            SurfaceRecovery::Reconfigure
+        }
+        wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Timeout => {
+            SurfaceRecovery::SkipFrame
+        }
+        wgpu::SurfaceError::OutOfMemory => SurfaceRecovery::Fail,
+    };
+}
+
+// Where the buffers of a resource are bound in the pipelines of a scene. The buffers of the
+// resource occupy consecutive bindings starting at `binding` of the bind group `group`. Group 0 is
+// the system bind group, so the resource groups start at 1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResourceBinding {
+    pub group: u32,
+    pub binding: u32,
+}
+
+// Allocates the bindings of resources with the given numbers of bindings contiguously in the order
+// of the resource ids. When a group is full, the next resource starts a new group. The resources
+// that occupy no bindings, e.g., the ones without a storage, get the binding of the next one.
+pub(crate) fn allocate_resource_bindings(
+    binding_counts: impl IntoIterator<Item = u32>,
+    bindings_per_group: u32,
+) -> Vec<ResourceBinding> {
+    let mut next_binding = ResourceBinding { group: 1, binding: 0 };
+    return binding_counts
+        .into_iter()
+        .map(|binding_count| {
+            if next_binding.binding > 0 && next_binding.binding + binding_count > bindings_per_group
+            {
+                next_binding = ResourceBinding {
+                    group: next_binding.group + 1,
+                    binding: 0,
+                };
+            }
+            let binding = next_binding;
+            next_binding.binding += binding_count;
+            return binding;
+        })
+        .collect();
+}
+
+// The limits of the GPUs of a scene that concern the resource bind groups, i.e., the lowest ones
+// of all GPUs. Group 0 is the system bind group, so the resources can use one group less than the
+// GPUs support. Only the number of bindings is limited per group, the number of buffers, textures
+// and samplers is limited for all groups of a pipeline together, so splitting the bindings into
+// groups does not get around these limits.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct BindingLimits {
+    pub(crate) bindings_per_group: u32,
+    pub(crate) groups: u32,
+    pub(crate) storage_buffers: u32,
+    pub(crate) sampled_textures: u32,
+    pub(crate) samplers: u32,
+}
+
+impl BindingLimits {
+    pub(crate) fn of_gpus(gpus: &[Arc<Gpu>]) -> Self {
+        let unlimited = Self {
+            bindings_per_group: u32::MAX,
+            groups: u32::MAX,
+            storage_buffers: u32::MAX,
+            sampled_textures: u32::MAX,
+            samplers: u32::MAX,
+        };
+        return gpus.iter().fold(unlimited, |limits, gpu| {
+            let gpu_limits = gpu.device().limits();
+            return Self {
+                bindings_per_group: limits
+                    .bindings_per_group
+                    .min(gpu_limits.max_bindings_per_bind_group),
+                groups: limits.groups.min(gpu_limits.max_bind_groups.saturating_sub(1)),
+                storage_buffers: limits
+                    .storage_buffers
+                    .min(gpu_limits.max_storage_buffers_per_shader_stage),
+                sampled_textures: limits
+                    .sampled_textures
+                    .min(gpu_limits.max_sampled_textures_per_shader_stage),
+                samplers: limits.samplers.min(gpu_limits.max_samplers_per_shader_stage),
+            };
+        });
+    }
+
+    // Fails if pipelines with the resource bind groups with these layout entries exceed a limit.
+    fn check(&self, group_layout_entries: &[Vec<wgpu::BindGroupLayoutEntry>]) -> Result<()> {
+        if group_layout_entries.len() > self.groups as usize {
+            return Err(Error::new(
+                format!(
+                    "the resources need {} bind groups, but the GPUs support {} besides the \
+                     system bind group",
+                    group_layout_entries.len(),
+                    self.groups
+                ),
+                SourceLocation::here(),
+            ));
+        }
+
+        let count = |is_counted: fn(&wgpu::BindingType) -> bool| {
+            return group_layout_entries
+                .iter()
+                .flatten()
+                .filter(|entry| is_counted(&entry.ty))
+                .map(|entry| entry.count.map_or(1, |count| count.get()))
+                .sum::<u32>();
+        };
+        let counts = [
+            (
+                "storage buffers",
+                count(|ty| {
+                    return matches!(
+                        ty,
+                        wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { .. },
+                            ..
+                        }
+                    );
+                }),
+                self.storage_buffers,
+            ),
+            (
+                "sampled textures",
+                count(|ty| matches!(ty, wgpu::BindingType::Texture { .. })),
+                self.sampled_textures,
+            ),
+            (
+                "samplers",
+                count(|ty| matches!(ty, wgpu::BindingType::Sampler(_))),
+                self.samplers,
+            ),
+        ];
+        for (name, count, limit) in counts {
+            if count > limit {
+                return Err(Error::new(
+                    format!(
+                        "the resources need {count} {name}, but the GPUs support {limit} per \
+                         shader stage"
+                    ),
+                    SourceLocation::here(),
+                ));
+            }
+        }
+        return Ok(());
     }
 }
 
+// The resource bind groups of a single GPU. The bind group entries of all storages are kept, so
+// only the entries of storages that reallocated their buffers have to be queried again when the
+// bind groups are recreated.
 struct ResourceBindings {
-    group_layout: wgpu::BindGroupLayout,
-    group: wgpu::BindGroup,
+    gpu: Arc<Gpu>,
+    group_layouts: Vec<wgpu::BindGroupLayout>,
+    groups: RwLock<Arc<Vec<wgpu::BindGroup>>>,
+    // The same bindings for compute pipelines, which can write the buffers.
+    compute_group_layouts: Vec<wgpu::BindGroupLayout>,
+    compute_groups: RwLock<Arc<Vec<wgpu::BindGroup>>>,
+    entries: Mutex<Vec<Vec<ResourceBindGroupEntry>>>, // Indexed by the index of the resource id.
+    // Pipelines that are not created by the scheduler, see `SceneState::render_pipeline`.
+    render_pipelines: Mutex<HashMap<RenderPipelineKey, Arc<wgpu::RenderPipeline>>>,
+}
+
+// Identifies a render pipeline by its label, the format of its color target and its sample count.
+pub type RenderPipelineKey = (&'static str, wgpu::TextureFormat, u32);
+
+impl ResourceBindings {
+    // Recreates the render and compute bind groups from the entries.
+    fn recreate_groups(
+        &self,
+        entries: &[Vec<ResourceBindGroupEntry>],
+        bindings: &[ResourceBinding],
+    ) {
+        *self.groups.write().unwrap() = Arc::new(Self::create_groups(
+            &self.gpu,
+            &self.group_layouts,
+            entries,
+            bindings,
+        ));
+        *self.compute_groups.write().unwrap() = Arc::new(Self::create_groups(
+            &self.gpu,
+            &self.compute_group_layouts,
+            entries,
+            bindings,
+        ));
+    }
+
+    // The entries of the storages start at binding 0, they are moved to the group and bindings
+    // allocated for the resources.
+    fn create_groups(
+        gpu: &Gpu,
+        group_layouts: &[wgpu::BindGroupLayout],
+        entries: &[Vec<ResourceBindGroupEntry>],
+        bindings: &[ResourceBinding],
+    ) -> Vec<wgpu::BindGroup> {
+        let mut group_entries = group_layouts.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+        for (entries, binding) in entries.iter().zip(bindings) {
+            group_entries[binding.group as usize - 1].extend(entries.iter().map(|entry| {
+                return wgpu::BindGroupEntry {
+                    binding: binding.binding + entry.binding,
+                    resource: entry.resource.as_binding_resource(),
+                };
+            }));
+        }
+
+        return group_layouts
+            .iter()
+            .zip(group_entries)
+            .map(|(group_layout, entries)| {
+                return gpu.device().create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Resources"),
+                    layout: group_layout,
+                    entries: &entries,
+                });
+            })
+            .collect();
+    }
 }
 
 pub struct SceneState {
@@ -63,62 +850,164 @@ pub struct SceneState {
     viewports: Arc<RwLock<IdMap<ViewportId, Viewport>>>,
     resources: Arc<Vec<Option<RwLock<Box<dyn ResourceStorage>>>>>,
     resource_bindings: Arc<Vec<ResourceBindings>>,
+    bindings: Vec<ResourceBinding>, // Indexed by the index of the resource id.
+    input: RwLock<Input>,
+    mouse: RwLock<Mouse>,
+    rng: Mutex<Rng>,
 }
 
 impl SceneState {
-    pub fn new(instance: &Instance) -> Self {
-        let mut bind_group_entries = Vec::new();
-        let resources = make_resource_storages(instance);
+    pub fn new(instance: &Instance) -> Result<Self> {
+        return Self::with_capacity(instance, 0);
+    }
 
-        for r in &resources {
-            if let Some(r) = r {
-                bind_group_entries.append(&mut r.bind_group_layout_entries());
-            }
+    // Creates the state with the entity id storage and all resource storages pre-allocated for
+    // `entity_capacity` entities. Fails if the GPUs cannot bind the buffers of all resources.
+    pub fn with_capacity(instance: &Instance, entity_capacity: usize) -> Result<Self> {
+        return Self::for_gpus(instance.gpus(), entity_capacity);
+    }
+
+    // Creates the state for the given GPUs without an instance, e.g., for headless rendering.
+    pub fn for_gpus(gpus: &[Arc<Gpu>], entity_capacity: usize) -> Result<Self> {
+        return Self::from_storages(
+            gpus,
+            make_resource_storages(gpus, entity_capacity),
+            entity_capacity,
+        );
+    }
+
+    // Creates the state from the given storages instead of the storages of the registered
+    // resources. The storage at position i must be the one of the resource with index i.
+    pub(crate) fn from_storages(
+        gpus: &[Arc<Gpu>],
+        resources: Vec<Option<Box<dyn ResourceStorage>>>,
+        entity_capacity: usize,
+    ) -> Result<Self> {
+        return Self::from_storages_with_limits(
+            gpus,
+            resources,
+            entity_capacity,
+            BindingLimits::of_gpus(gpus),
+        );
+    }
+
+    pub(crate) fn from_storages_with_limits(
+        gpus: &[Arc<Gpu>],
+        resources: Vec<Option<Box<dyn ResourceStorage>>>,
+        entity_capacity: usize,
+        limits: BindingLimits,
+    ) -> Result<Self> {
+        // The bind groups are created from the current buffers, so buffers that were reallocated
+        // while reserving capacity do not require new ones.
+        for storage in resources.iter().flatten() {
+            storage.take_buffers_reallocated();
+        }
+
+        let layout_entries = resources
+            .iter()
+            .map(|r| r.as_ref().map_or(vec![], |r| r.bind_group_layout_entries()))
+            .collect::<Vec<_>>();
+        let bindings = allocate_resource_bindings(
+            layout_entries.iter().map(|entries| entries.len() as u32),
+            limits.bindings_per_group,
+        );
+        let group_count = bindings.iter().map(|binding| binding.group).max().unwrap_or(1);
+        let mut group_layout_entries = vec![Vec::new(); group_count as usize];
+        for (entries, binding) in layout_entries.into_iter().zip(&bindings) {
+            group_layout_entries[binding.group as usize - 1].extend(entries.into_iter().map(
+                |entry| wgpu::BindGroupLayoutEntry {
+                    binding: binding.binding + entry.binding,
+                    ..entry
+                },
+            ));
         }
+        limits.check(&group_layout_entries)?;
+
+        // Compute shaders see the same buffers, but can write them.
+        let compute_group_layout_entries = group_layout_entries
+            .iter()
+            .map(|entries| {
+                return entries
+                    .iter()
+                    .map(|&entry| wgpu::BindGroupLayoutEntry {
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: match entry.ty {
+                            wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { .. },
+                                has_dynamic_offset,
+                                min_binding_size,
+                            } => wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset,
+                                min_binding_size,
+                            },
+                            ty => ty,
+                        },
+                        ..entry
+                    })
+                    .collect::<Vec<_>>();
+            })
+            .collect::<Vec<_>>();
 
-        let bindings = instance
-            .gpus()
+        let resource_bindings = gpus
             .iter()
             .map(|gpu| {
-                let group_layout =
-                    gpu.device()
-                        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                            label: Some("Resources"),
-                            entries: &bind_group_entries,
-                        });
-
-                let mut entries = Vec::new();
-
-                for r in &resources {
-                    if let Some(r) = r {
-                        entries.append(&mut r.bind_group_entries(gpu.index()));
-                    }
-                }
+                let create_group_layouts = |group_layout_entries: &[Vec<_>]| {
+                    return group_layout_entries
+                        .iter()
+                        .map(|entries| {
+                            return gpu.device().create_bind_group_layout(
+                                &wgpu::BindGroupLayoutDescriptor {
+                                    label: Some("Resources"),
+                                    entries,
+                                },
+                            );
+                        })
+                        .collect::<Vec<_>>();
+                };
+                let group_layouts = create_group_layouts(&group_layout_entries);
+                let compute_group_layouts = create_group_layouts(&compute_group_layout_entries);
 
-                let group = gpu.device().create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("Resources"),
-                    layout: &group_layout,
-                    entries: &entries,
-                });
+                let entries = resources
+                    .iter()
+                    .map(|r| r.as_ref().map_or(vec![], |r| r.bind_group_entries(gpu.index())))
+                    .collect::<Vec<_>>();
+                let groups =
+                    ResourceBindings::create_groups(gpu, &group_layouts, &entries, &bindings);
+                let compute_groups = ResourceBindings::create_groups(
+                    gpu,
+                    &compute_group_layouts,
+                    &entries,
+                    &bindings,
+                );
 
                 return ResourceBindings {
-                    group_layout,
-                    group,
+                    gpu: gpu.clone(),
+                    group_layouts,
+                    groups: RwLock::new(Arc::new(groups)),
+                    compute_group_layouts,
+                    compute_groups: RwLock::new(Arc::new(compute_groups)),
+                    entries: Mutex::new(entries),
+                    render_pipelines: Mutex::new(HashMap::new()),
                 };
             })
             .collect();
 
-        return Self {
-            entities: Arc::new(RwLock::new(IdStorage::new())),
+        return Ok(Self {
+            entities: Arc::new(RwLock::new(IdStorage::with_capacity(entity_capacity))),
             viewports: Arc::new(RwLock::new(IdMap::new())),
             resources: Arc::new(
                 resources
                     .into_iter()
-                    .map(|r| r.map(|r| RwLock::new(r)))
+                    .map(|r| r.map(RwLock::new))
                     .collect(),
             ),
-            resource_bindings: Arc::new(bindings),
-        };
+            resource_bindings: Arc::new(resource_bindings),
+            bindings,
+            input: RwLock::new(Input::new()),
+            mouse: RwLock::new(Mouse::new()),
+            rng: Mutex::new(Rng::default()),
+        });
     }
 
     pub fn entities(&self) -> &RwLock<IdStorage<StandardVersionedIndexId<8>>> {
@@ -129,93 +1018,1271 @@ impl SceneState {
         self.viewports.as_ref()
     }
 
-    pub fn resource_storage(&self, id: ResourceId) -> Option<&RwLock<Box<dyn ResourceStorage>>> {
-        return self.resources[id.index()].as_ref();
+    // The keyboard state of the scene, see `Input`.
+    pub fn input(&self) -> RwLockReadGuard<'_, Input> {
+        return self.input.read().unwrap();
     }
 
-    pub fn resource_storage_mut<R: Resource>(&self) -> Option<MutableResourceStorageAccess<'_, R>> {
-        if let Some(storage) = self.resources[R::id().index()].as_ref() {
-            return Some(MutableResourceStorageAccess::new(storage.write().unwrap()));
-        }
-        todo!();
+    // Updates the keyboard state, e.g., from the events of the windows of the scene. Must not be
+    // called by jobs, they read the input of the frame with `SystemResources::input`.
+    pub fn input_mut(&self) -> RwLockWriteGuard<'_, Input> {
+        return self.input.write().unwrap();
     }
 
-    pub fn resource_bind_group_layout(&self, gpu_index: usize) -> &wgpu::BindGroupLayout {
-        &self.resource_bindings[gpu_index].group_layout
+    // The mouse state of the scene, see `Mouse`.
+    pub fn mouse(&self) -> RwLockReadGuard<'_, Mouse> {
+        return self.mouse.read().unwrap();
     }
 
-    pub fn resource_bind_group(&self, gpu_index: usize) -> &wgpu::BindGroup {
-        &self.resource_bindings[gpu_index].group
+    // Updates the mouse state, like `input_mut` it must not be called by jobs.
+    pub fn mouse_mut(&self) -> RwLockWriteGuard<'_, Mouse> {
+        return self.mouse.write().unwrap();
     }
-}
 
-pub struct MutableResourceStorageAccess<'scene, R: Resource> {
-    guard: RwLockWriteGuard<'scene, Box<dyn ResourceStorage>>,
-    phantom: PhantomData<R>,
-    // storage: &'scene R::Storage,
-}
+    // The random number generator of the scene, see `SystemResources::rng`.
+    pub fn rng(&self) -> MutexGuard<'_, Rng> {
+        return self.rng.lock().unwrap();
+    }
 
-impl<'scene, R: Resource> MutableResourceStorageAccess<'scene, R> {
-    fn new(guard: RwLockWriteGuard<'scene, Box<dyn ResourceStorage>>) -> Self {
-        return Self { guard, phantom: PhantomData };
+    // Restarts the random numbers of the scene with the seed, e.g., to replay a recorded game.
+    pub fn seed_rng(&self, seed: u64) {
+        *self.rng() = Rng::new(seed);
     }
-}
 
-impl<R: Resource> std::ops::Deref for MutableResourceStorageAccess<'_, R> {
-    type Target = R::Storage;
+    // Returns the ids of all entities that exist, e.g., to inspect the scene in an editor. The
+    // entities are locked for reading until the iterator is dropped.
+    pub fn entity_ids(&self) -> EntityIds<'_> {
+        return EntityIds {
+            entities: self.entities.read().unwrap(),
+            index: 0,
+        };
+    }
 
-    fn deref(&self) -> &Self::Target {
-        return (&**self.guard as &dyn Any)
-            .downcast_ref::<R::Storage>()
-            .unwrap();
+    // Returns the entities that have the component or None if the resource is not an entity
+    // component of this scene. The components are locked for reading until the result is dropped.
+    pub fn entities_with<R: Resource>(&self) -> Option<EntityComponents<'_, R>> {
+        let storage = self.resources.get(R::id().index())?.as_ref()?;
+        let storage = RwLockReadGuard::filter_map(storage.read().unwrap(), |storage| {
+            storage.as_entity_component::<R>()
+        })
+        .ok()?;
+        return Some(EntityComponents { storage });
     }
-}
 
-impl<R: Resource> std::ops::DerefMut for MutableResourceStorageAccess<'_, R> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        return (&mut **self.guard as &mut dyn Any)
-            .downcast_mut::<R::Storage>()
-            .unwrap();
+    pub fn resource_storage(&self, id: ResourceId) -> Option<&RwLock<Box<dyn ResourceStorage>>> {
+        return self.resources[id.index()].as_ref();
     }
-}
 
-pub struct Scene {
-    game_time: f32,
-    state: Arc<SceneState>,
-    scheduler: Scheduler,
-    viewports_changed: bool,
-}
+    // The number of entities with the component, e.g., the number of instances to draw for it.
+    // Returns 0 for resources that are not entity components or have no storage in this scene.
+    pub fn component_count(&self, id: ResourceId) -> usize {
+        let Some(storage) = self.resources.get(id.index()).and_then(Option::as_ref) else {
+            return 0;
+        };
+        let storage = storage.read().unwrap();
+        return storage.as_entity_component_storage().map_or(0, |s| s.component_count());
+    }
 
-impl Scene {
-    pub async fn new(instance: &Instance) -> Self {
-        let state = Arc::new(SceneState::new(instance));
+    // The number of slots the component is looked up in on the GPU, see
+    // `EntityComponentResourceStorage::component_slot_bound`. Returns 0 like `component_count`.
+    pub fn component_slot_bound(&self, id: ResourceId) -> usize {
+        let Some(storage) = self.resources.get(id.index()).and_then(Option::as_ref) else {
+            return 0;
+        };
+        let storage = storage.read().unwrap();
+        return storage.as_entity_component_storage().map_or(0, |s| s.component_slot_bound());
+    }
 
-        return Self {
-            viewports_changed: false,
-            game_time: 0.0,
-            scheduler: Scheduler::new(
-                instance,
-                JobKind::Update,
-                state.clone(),
-                thread::available_parallelism()
-                    .map(|c| -> usize { c.into() })
-                    .unwrap_or(4),
-            ),
-            state,
+    // Drops the viewport and its components, see `Scene::remove_viewport`. Must not be called
+    // while jobs run, the jobs of a frame are executed for the viewports that exist when they are
+    // scheduled.
+    pub(crate) fn remove_viewport(&self, viewport_id: ViewportId) -> bool {
+        let mut viewports = self.viewports.write().unwrap();
+        if !viewports.contains(viewport_id) {
+            return false;
+        }
+        drop(viewports.remove(viewport_id));
+        drop(viewports);
+        for storage in self.resources.iter().flatten() {
+            storage.write().unwrap().remove_viewport(viewport_id);
+        }
+        return true;
+    }
+
+    // Emits an event from outside of the jobs, e.g., for the events of the windows. Like the events
+    // emitted by jobs, it can be read by the jobs that run next. Must not be called by jobs, they
+    // emit events with `SystemResources::emit`. Events without a storage in this scene are
+    // dropped.
+    pub fn emit_between_frames<E: Resource>(&self, event: E) {
+        let Some(storage) = self.resources.get(E::id().index()).and_then(Option::as_ref) else {
+            return;
         };
+        if let Some(storage) = storage.write().unwrap().as_event_mut::<E>() {
+            storage.emit_readable(event);
+        }
     }
 
-    pub fn state(&self) -> &Arc<SceneState> {
-        &self.state
+    // Returns the typed storage of the resource or None if the resource has no storage in this
+    // scene, e.g., because it was registered after the scene was created.
+    pub fn resource_storage_ref<R: Resource>(&self) -> Option<ResourceStorageAccess<'_, R>> {
+        let storage = self.resources.get(R::id().index())?.as_ref()?;
+        return Some(ResourceStorageAccess::new(storage.read().unwrap()));
     }
 
-    pub fn add_viewport(
-        &mut self,
-        gpu: Arc<Gpu>,
-        surface: wgpu::Surface,
-        size: PhysicalSize<u32>,
+    pub fn resource_storage_mut<R: Resource>(&self) -> Option<MutableResourceStorageAccess<'_, R>> {
+        let storage = self.resources.get(R::id().index())?.as_ref()?;
+        return Some(MutableResourceStorageAccess::new(storage.write().unwrap()));
+    }
+
+    // Returns the storage of an entity component that stores a slice of resources per entity or
+    // None if the resource is not stored in a slice storage.
+    pub fn slice_storage<R: Resource>(
+        &self,
+    ) -> Option<MappedRwLockReadGuard<'_, IdMappedResourceSliceStorage<EntityId, R>>> {
+        let storage = self.resources.get(R::id().index())?.as_ref()?;
+        return RwLockReadGuard::filter_map(storage.read().unwrap(), |storage| {
+            storage.as_entity_component_slice::<R>()
+        })
+        .ok();
+    }
+
+    pub fn slice_storage_mut<R: Resource>(
+        &self,
+    ) -> Option<MappedRwLockWriteGuard<'_, IdMappedResourceSliceStorage<EntityId, R>>> {
+        let storage = self.resources.get(R::id().index())?.as_ref()?;
+        return RwLockWriteGuard::filter_map(storage.write().unwrap(), |storage| {
+            storage.as_entity_component_slice_mut::<R>()
+        })
+        .ok();
+    }
+
+    // Returns the component of the viewport or None if the viewport does not have the component
+    // or the resource is not a viewport component.
+    pub fn viewport_component<R: Resource>(
+        &self,
+        viewport_id: ViewportId,
+    ) -> Option<MappedRwLockReadGuard<'_, R>> {
+        let storage = self.resources.get(R::id().index())?.as_ref()?;
+        return RwLockReadGuard::filter_map(storage.read().unwrap(), |storage| {
+            storage.as_viewport_component::<R>()?.get(viewport_id)
+        })
+        .ok();
+    }
+
+    // Returns the component of the scene or None if it is not set or the resource is not a scene
+    // component.
+    pub fn scene_component<R: Resource>(&self) -> Option<MappedRwLockReadGuard<'_, R>> {
+        let storage = self.resources.get(R::id().index())?.as_ref()?;
+        return RwLockReadGuard::filter_map(storage.read().unwrap(), |storage| {
+            storage.as_scene_component::<R>()?.get()
+        })
+        .ok();
+    }
+
+    // Sets the component of the scene. Like `emit_between_frames`, it must not be called by jobs
+    // that do not write the resource. Components without a storage in this scene are dropped.
+    pub fn set_scene_component<R: Resource>(&self, component: R) {
+        let Some(storage) = self.resources.get(R::id().index()).and_then(Option::as_ref) else {
+            return;
+        };
+        if let Some(storage) = storage.write().unwrap().as_scene_component_mut::<R>() {
+            storage.set(component);
+        }
+    }
+
+    pub fn reset_scene_component<R: Resource>(&self) {
+        let Some(storage) = self.resources.get(R::id().index()).and_then(Option::as_ref) else {
+            return;
+        };
+        if let Some(storage) = storage.write().unwrap().as_scene_component_mut::<R>() {
+            storage.reset();
+        }
+    }
+
+    // Returns the storage of a texture resource or None if the resource is not a texture, e.g., to
+    // upload the texture before drawing with it.
+    pub fn texture_storage<R: Resource + Deref<Target = Texture>>(
+        &self,
+    ) -> Option<MappedRwLockReadGuard<'_, TextureStorage<R>>> {
+        let storage = self.resources.get(R::id().index())?.as_ref()?;
+        return RwLockReadGuard::filter_map(storage.read().unwrap(), |storage| {
+            storage.as_texture::<R>()
+        })
+        .ok();
+    }
+
+    // Sets the texture of the scene. Like `set_scene_component`, it must not be called by jobs
+    // that do not write the resource. Textures without a storage in this scene are dropped.
+    pub fn set_texture<R: Resource + Deref<Target = Texture>>(&self, texture: R) {
+        let Some(storage) = self.resources.get(R::id().index()).and_then(Option::as_ref) else {
+            return;
+        };
+        if let Some(storage) = storage.write().unwrap().as_texture_mut::<R>() {
+            storage.set(texture);
+        }
+    }
+
+    // Adds a component or a slice of components to the entity, e.g., when it is spawned.
+    // Fails if the resource is not a registered entity component or the component is not of its
+    // type.
+    pub(crate) fn insert_entity_component(
+        &self,
+        entity_id: EntityId,
+        resource_id: ResourceId,
+        component: Box<dyn Any + Send>,
+    ) -> Result<()> {
+        let mut storage = self.entity_component_storage(resource_id)?.write().unwrap();
+        if let Some(storage) = storage.as_entity_component_storage_mut() {
+            return storage.insert_boxed(entity_id, component);
+        }
+        let Some(storage) = storage.as_entity_component_slice_storage_mut() else {
+            return Err(Self::not_an_entity_component(resource_id));
+        };
+        return storage.insert_slice_boxed(entity_id, component);
+    }
+
+    pub(crate) fn remove_entity_component(
+        &self,
+        entity_id: EntityId,
+        resource_id: ResourceId,
+    ) -> Result<()> {
+        let mut storage = self.entity_component_storage(resource_id)?.write().unwrap();
+        if let Some(storage) = storage.as_entity_component_storage_mut() {
+            storage.remove_entity(entity_id);
+        } else if let Some(storage) = storage.as_entity_component_slice_storage_mut() {
+            storage.remove_entity(entity_id);
+        } else {
+            return Err(Self::not_an_entity_component(resource_id));
+        }
+        return Ok(());
+    }
+
+    fn entity_component_storage(
+        &self,
+        resource_id: ResourceId,
+    ) -> Result<&RwLock<Box<dyn ResourceStorage>>> {
+        return self.resource_storage(resource_id).ok_or_else(|| {
+            Error::new(
+                format!("the component {resource_id} is not registered"),
+                SourceLocation::here(),
+            )
+        });
+    }
+
+    fn not_an_entity_component(resource_id: ResourceId) -> Error {
+        return Error::new(
+            format!("the resource {resource_id} is not an entity component"),
+            SourceLocation::here(),
+        );
+    }
+
+    // Removes all components of the entity, e.g., after it was despawned.
+    pub(crate) fn remove_entity_components(&self, entity_id: EntityId) {
+        for storage in self.resources.iter().flatten() {
+            let mut storage = storage.write().unwrap();
+            if let Some(storage) = storage.as_entity_component_storage_mut() {
+                storage.remove_entity(entity_id);
+            } else if let Some(storage) = storage.as_entity_component_slice_storage_mut() {
+                storage.remove_entity(entity_id);
+            }
+        }
+    }
+
+    // Despawns all entities and frees their ids. The ids keep their versions like despawned ones
+    // do, so ids that are still referred to do not refer to the entities spawned afterwards, e.g.,
+    // by loading the same scene description again, see `IdStorage::claim`. The scene and viewport
+    // components are kept.
+    pub fn clear_entities(&self) {
+        for storage in self.resources.iter().flatten() {
+            let mut storage = storage.write().unwrap();
+            if let Some(storage) = storage.as_entity_component_storage_mut() {
+                storage.clear_entities();
+            } else if let Some(storage) = storage.as_entity_component_slice_storage_mut() {
+                storage.clear_entities();
+            }
+        }
+        self.entities.write().unwrap().clear();
+    }
+
+    // Spawns the entities of a scene description and returns their ids. The description lists the
+    // components of every entity by the label of their resource, e.g.:
+    //
+    // { "entities": [
+    //     { "id": 0, "Position": { "x": 0.0, "y": 1.0 }, "VertexPosition": [[0, 0, 0]] },
+    // ] }
+    //
+    // Components that are stored as slices are given as an array of resources. Entities with an
+    // optional "id" are spawned with exactly that id if its index is free. Otherwise, e.g., when
+    // the scene is loaded twice, the entity gets a new id and the ids that components reference,
+    // e.g., a parent, are replaced by it. If a component cannot be loaded, the error names the
+    // entity and the component and none of the entities are spawned.
+    pub fn load_json(&self, scene: serde_json::Value) -> Result<Vec<EntityId>> {
+        let serde_json::Value::Object(mut scene) = scene else {
+            return Err(Error::new("expected a scene object", SourceLocation::here()));
+        };
+        let entities = match scene.remove("entities") {
+            Some(serde_json::Value::Array(entities)) => entities,
+            None => vec![],
+            Some(_) => {
+                return Err(Error::new("expected an array of entities", SourceLocation::here()))
+            }
+        };
+
+        let mut loaded_entities = Vec::with_capacity(entities.len());
+        for (index, entity) in entities.into_iter().enumerate() {
+            let serde_json::Value::Object(mut components) = entity else {
+                return Err(Error::new(
+                    format!("expected an object for entity {index}"),
+                    SourceLocation::here(),
+                ));
+            };
+            let given_id = components.remove("id").map(parse_entity_id).transpose();
+            let given_id = given_id.map_err(|error| {
+                Error::new(format!("{} (entity {index})", error.message()), error.source().clone())
+            })?;
+            loaded_entities.push((given_id, components.into_iter().collect()));
+        }
+
+        return self.load_entities(loaded_entities, |entity_id, label, value| {
+            let mut deserializer = <dyn erased_serde::Deserializer>::erase(value);
+            return self.insert_serialized_component(entity_id, label, &mut deserializer);
+        });
+    }
+
+    // Spawns the entities with their given ids and components by the label of their resource,
+    // see `load_json`. `insert` deserializes a component and inserts it for the entity.
+    fn load_entities<L: AsRef<str>, C>(
+        &self,
+        entities: Vec<LoadedEntity<L, C>>,
+        insert: impl Fn(EntityId, &str, C) -> Result<()>,
+    ) -> Result<Vec<EntityId>> {
+        let mut given_ids = Vec::with_capacity(entities.len());
+        let mut unique_given_ids = HashSet::with_capacity(entities.len());
+        for (index, (given_id, _)) in entities.iter().enumerate() {
+            if given_id.is_some_and(|given_id| !unique_given_ids.insert(given_id)) {
+                return Err(Error::new(
+                    format!("the id is given twice (entity {index})"),
+                    SourceLocation::here(),
+                ));
+            }
+            given_ids.push(*given_id);
+        }
+
+        let (entity_ids, remapping) = self.claim_entity_ids(&given_ids);
+        let loaded = with_entity_id_remapping(remapping, || {
+            for (index, (_, components)) in entities.into_iter().enumerate() {
+                let entity_id = entity_ids[index];
+                for (label, component) in components {
+                    let label = label.as_ref();
+                    insert(entity_id, label, component).map_err(|error| {
+                        Error::new(
+                            format!("{} (component {label} of entity {index})", error.message()),
+                            error.source().clone(),
+                        )
+                    })?;
+                }
+            }
+            return Ok(());
+        });
+        if let Err(error) = loaded {
+            return Err(self.unload_entities(entity_ids.iter().copied(), error));
+        }
+
+        return Ok(entity_ids);
+    }
+
+    // Claims the given ids of loaded entities and reserves new ids for the others. The given ids
+    // are claimed first, so the entities without an id cannot take their indices. Given ids whose
+    // index is in use get a new id, like ones with an index so large that claiming it would grow
+    // the id storage by more than the number of loaded entities. Returns the ids of all entities
+    // and the replaced ids by their raw value, see `with_entity_id_remapping`.
+    fn claim_entity_ids(
+        &self,
+        given_ids: &[Option<EntityId>],
+    ) -> (Vec<EntityId>, HashMap<u32, u32>) {
+        let mut entities = self.entities.write().unwrap();
+        entities.reserve_exact(given_ids.len());
+        let index_limit = entities.index_bound() + given_ids.len();
+        let claimed_ids = given_ids
+            .iter()
+            .map(|given_id| {
+                return given_id.filter(|id| {
+                    return id.index() < index_limit && entities.claim(*id).is_ok();
+                });
+            })
+            .collect::<Vec<_>>();
+
+        let mut remapping = HashMap::new();
+        let entity_ids = given_ids
+            .iter()
+            .zip(claimed_ids)
+            .map(|(given_id, claimed_id)| {
+                if let Some(claimed_id) = claimed_id {
+                    return claimed_id;
+                }
+                let entity_id = entities.reserve();
+                if let Some(given_id) = given_id {
+                    remapping.insert(bytemuck::cast(*given_id), bytemuck::cast(entity_id));
+                }
+                return entity_id;
+            })
+            .collect();
+        return (entity_ids, remapping);
+    }
+
+    fn insert_serialized_component(
+        &self,
+        entity_id: EntityId,
+        label: &str,
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Result<()> {
+        let storage = find_resource(label)
+            .and_then(|resource_id| self.resources.get(resource_id.index())?.as_ref())
+            .ok_or_else(|| {
+                Error::new(format!("unknown resource {label}"), SourceLocation::here())
+            })?;
+        let mut storage = storage.write().unwrap();
+
+        if let Some(storage) = storage.as_entity_component_storage_mut() {
+            return storage.insert_serialized(entity_id, deserializer);
+        } else if let Some(storage) = storage.as_entity_component_slice_storage_mut() {
+            return storage.insert_slice_serialized(entity_id, deserializer);
+        } else {
+            return Err(Error::new(
+                format!("{label} is not an entity component"),
+                SourceLocation::here(),
+            ));
+        }
+    }
+
+    // Despawns the entities that were spawned by a load that failed and returns its error.
+    fn unload_entities(
+        &self,
+        entity_ids: impl IntoIterator<Item = EntityId>,
+        error: Error,
+    ) -> Error {
+        for entity_id in entity_ids {
+            self.remove_entity_components(entity_id);
+            self.entities.write().unwrap().free(entity_id);
+        }
+        return error;
+    }
+
+    // Returns the scene description of all entities in the format of `load_json`, including their
+    // ids. Components whose resources cannot be serialized, e.g., ones that are calculated by jobs,
+    // are left out.
+    pub fn save_json(&self) -> Result<serde_json::Value> {
+        let entities = RefCell::new(Vec::new());
+        self.save_entities(
+            |entity_id| {
+                let mut components = serde_json::Map::new();
+                components.insert("id".to_string(), serde_json::json!(entity_id));
+                entities.borrow_mut().push(components);
+            },
+            |label, component| {
+                let value = serde_json::to_value(component).map_err(|error| {
+                    Error::new(
+                        format!("failed to serialize {label}: {error}"),
+                        SourceLocation::here(),
+                    )
+                })?;
+                let mut entities = entities.borrow_mut();
+                entities.last_mut().unwrap().insert(label.to_string(), value);
+                return Ok(());
+            },
+        )?;
+
+        let entities = entities.into_inner().into_iter().map(serde_json::Value::Object);
+        let entities = entities.collect::<Vec<_>>();
+        return Ok(serde_json::json!({ "entities": entities }));
+    }
+
+    // Saves the entities like `save_json`, but in a compact binary format that is faster to load
+    // and smaller than JSON, see `binary.rs`. The components are still recorded with the labels of
+    // their resources, so loading reports unknown components.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let encoder = RefCell::new(crate::binary::SceneEncoder::new());
+        self.save_entities(
+            |entity_id| encoder.borrow_mut().add_entity(entity_id),
+            |label, component| encoder.borrow_mut().add_component(label, component),
+        )?;
+        return Ok(encoder.into_inner().finish());
+    }
+
+    // Passes the id of every entity to `add_entity`, followed by its serializable components with
+    // the labels of their resources to `add_component`. Slices are passed as sequences.
+    fn save_entities(
+        &self,
+        mut add_entity: impl FnMut(EntityId),
+        mut add_component: impl FnMut(&str, &dyn erased_serde::Serialize) -> Result<()>,
+    ) -> Result<()> {
+        let mut storages = Vec::new();
+        for (index, storage) in self.resources.iter().enumerate() {
+            let Some(storage) = storage else {
+                continue;
+            };
+            let storage = storage.read().unwrap();
+            if storage.as_entity_component_storage().is_some()
+                || storage.as_entity_component_slice_storage().is_some()
+            {
+                let label = resource_label(ResourceId::from_index(index)).unwrap();
+                storages.push((label, storage));
+            }
+        }
+
+        for entity_id in self.entity_ids() {
+            add_entity(entity_id);
+            for (label, storage) in &storages {
+                match storage.as_entity_component_storage() {
+                    Some(storage) => {
+                        if let Some(component) = storage.serialize_component(entity_id) {
+                            add_component(label, component)?;
+                        }
+                    }
+                    None => {
+                        let storage = storage.as_entity_component_slice_storage().unwrap();
+                        if let Some(slice) = storage.serialize_slice(entity_id) {
+                            add_component(label, &slice)?;
+                        }
+                    }
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    // Spawns the entities of a scene that was saved with `to_bytes` and returns their ids. The
+    // components are deserialized directly from the bytes they were saved as.
+    pub fn load_bytes(&self, bytes: &[u8]) -> Result<Vec<EntityId>> {
+        return self.load_binary_scene(&crate::binary::decode_scene(bytes)?);
+    }
+
+    fn load_binary_scene(&self, scene: &crate::binary::BinaryScene) -> Result<Vec<EntityId>> {
+        let entities = scene
+            .entities()
+            .map(|(entity_id, components)| (Some(entity_id), components))
+            .collect();
+        return self.load_entities(entities, |entity_id, label, bytes| {
+            return crate::binary::deserialize_component(bytes, |deserializer| {
+                return self.insert_serialized_component(entity_id, label, deserializer);
+            });
+        });
+    }
+
+    // Blocks until the GPUs finished all work that was submitted so far, e.g., before buffers that
+    // are referenced by submitted command buffers are dropped.
+    pub fn wait_for_gpus(&self) {
+        for bindings in self.resource_bindings.iter() {
+            bindings.gpu.device().poll(wgpu::Maintain::Wait);
+        }
+    }
+
+    // The GPUs the resources of the scene are uploaded to.
+    pub fn gpus(&self) -> impl Iterator<Item = &Arc<Gpu>> {
+        return self.resource_bindings.iter().map(|bindings| &bindings.gpu);
+    }
+
+    // Acquires the textures the viewports render to in this tick. Viewports that skip the frame
+    // have no texture, so the jobs do not render to them.
+    pub(crate) fn acquire_viewport_textures(&self) -> Result<()> {
+        let mut viewports = self.viewports.write().unwrap();
+        for (_id, viewport) in &mut *viewports {
+            if let Err(error) = viewport.acquire_texture() {
+                // Release the textures acquired so far, so they can be acquired again.
+                for (_id, viewport) in &mut *viewports {
+                    viewport.release_texture(false);
+                }
+                return Err(error);
+            }
+        }
+        return Ok(());
+    }
+
+    // Presents the surface textures after the jobs ran. Offscreen viewports are not presented,
+    // their textures keep what was rendered to them.
+    pub(crate) fn present_viewports(&self) {
+        for (_id, viewport) in &mut *self.viewports.write().unwrap() {
+            viewport.release_texture(true);
+        }
+    }
+
+    // Reads the pixels the jobs rendered to the viewport, see `Viewport::read_pixels`.
+    pub fn read_viewport_pixels(&self, viewport_id: ViewportId) -> Result<Vec<u8>> {
+        let viewports = self.viewports.read().unwrap();
+        let Some(viewport) = viewports.get(viewport_id) else {
+            return Err(Error::new(
+                format!("viewport {viewport_id} does not exist"),
+                SourceLocation::here(),
+            ));
+        };
+        return viewport.read_pixels();
+    }
+
+    // Lets all resource storages know that the jobs of the current frame are finished, e.g., to
+    // make the events emitted during the frame readable. A scene calls it once per tick, after the
+    // fixed update and the update jobs ran. Without a scene, call it after every frame.
+    pub fn end_frame(&self) {
+        for storage in self.resources.iter().flatten() {
+            storage.write().unwrap().end_frame();
+        }
+    }
+
+    // Returns the number of bytes each resource storage uploaded to the GPUs since the last call.
+    pub fn take_upload_stats(&self) -> Vec<(ResourceId, u64)> {
+        return self
+            .resources
+            .iter()
+            .enumerate()
+            .filter_map(|(index, storage)| {
+                let storage = storage.as_ref()?;
+                return Some((
+                    ResourceId::from_index(index),
+                    storage.read().unwrap().take_uploaded_bytes(),
+                ));
+            })
+            .collect();
+    }
+
+    // The layouts of the resource bind groups of the GPU. The layout at index i is the one of
+    // group i + 1, so the pipelines of the scene use the system bind group layout followed by
+    // these.
+    pub fn resource_bind_group_layouts(&self, gpu_index: usize) -> &[wgpu::BindGroupLayout] {
+        &self.resource_bindings[gpu_index].group_layouts
+    }
+
+    // The resource bind groups of the GPU, render jobs bind the group at index i to group i + 1.
+    pub fn resource_bind_groups(&self, gpu_index: usize) -> Arc<Vec<wgpu::BindGroup>> {
+        return self.resource_bindings[gpu_index].groups.read().unwrap().clone();
+    }
+
+    // The layouts of the resource bind groups of compute pipelines. They bind the same buffers at
+    // the same groups and bindings as the render ones, but only for the compute stage and writable,
+    // so the shaders declare them as `var<storage, read_write>`.
+    pub fn compute_resource_bind_group_layouts(
+        &self,
+        gpu_index: usize,
+    ) -> &[wgpu::BindGroupLayout] {
+        &self.resource_bindings[gpu_index].compute_group_layouts
+    }
+
+    // The resource bind groups for compute passes, see `compute_resource_bind_group_layouts`.
+    pub fn compute_resource_bind_groups(&self, gpu_index: usize) -> Arc<Vec<wgpu::BindGroup>> {
+        return self.resource_bindings[gpu_index]
+            .compute_groups
+            .read()
+            .unwrap()
+            .clone();
+    }
+
+    // Returns the render pipeline of the GPU with the key or creates it, e.g., for passes that are
+    // not encoded by a job with a shader. The pipelines use the resource bind group layouts of this
+    // scene, so they are cached per scene and GPU instead of being shared between scenes.
+    pub fn render_pipeline(
+        &self,
+        gpu_index: usize,
+        key: RenderPipelineKey,
+        create: impl FnOnce() -> Result<wgpu::RenderPipeline>,
+    ) -> Result<Arc<wgpu::RenderPipeline>> {
+        let mut pipelines = self.resource_bindings[gpu_index]
+            .render_pipelines
+            .lock()
+            .unwrap();
+        if let Some(pipeline) = pipelines.get(&key) {
+            return Ok(pipeline.clone());
+        }
+        let pipeline = Arc::new(create()?);
+        pipelines.insert(key, pipeline.clone());
+        return Ok(pipeline);
+    }
+
+    pub fn resource_binding(&self, resource_id: ResourceId) -> Option<ResourceBinding> {
+        self.resources.get(resource_id.index())?.as_ref()?;
+        return Some(self.bindings[resource_id.index()]);
+    }
+
+    // Replaces the placeholders of resource bindings in WGSL source with the group and binding of
+    // the resource in this scene. `@resource(Mesh, 1)` stands for the second buffer of the
+    // resource with the label `Mesh`, e.g., `@group(1) @binding(5)`.
+    pub fn resolve_resource_bindings(&self, wgsl: &str) -> Result<String> {
+        const PLACEHOLDER: &str = "@resource(";
+
+        let mut resolved = String::with_capacity(wgsl.len());
+        let mut rest = wgsl;
+        while let Some(start) = rest.find(PLACEHOLDER) {
+            resolved.push_str(&rest[..start]);
+            rest = &rest[start + PLACEHOLDER.len()..];
+            let Some(end) = rest.find(')') else {
+                return Err(Error::new("unterminated resource binding", SourceLocation::here()));
+            };
+            let placeholder = &rest[..end];
+            rest = &rest[end + 1..];
+
+            let invalid = || {
+                return Error::new(
+                    format!("invalid resource binding @resource({placeholder})"),
+                    SourceLocation::here(),
+                );
+            };
+            let (label, offset) = placeholder.split_once(',').ok_or_else(invalid)?;
+            let offset = offset.trim().parse::<u32>().map_err(|_| invalid())?;
+            let binding = find_resource(label.trim())
+                .and_then(|resource_id| self.resource_binding(resource_id))
+                .ok_or_else(|| {
+                    return Error::new(
+                        format!("the scene has no storage for the resource {}", label.trim()),
+                        SourceLocation::here(),
+                    );
+                })?;
+            resolved.push_str(&format!(
+                "@group({}) @binding({})",
+                binding.group,
+                binding.binding + offset
+            ));
+        }
+        resolved.push_str(rest);
+        return Ok(resolved);
+    }
+
+    // Recreates the resource bind groups of all GPUs from the current bind group entries of all
+    // storages, e.g., after viewports were added.
+    pub fn rebuild_resource_bindings(&self) {
+        let storages = self
+            .resources
+            .iter()
+            .map(|storage| storage.as_ref().map(|storage| storage.read().unwrap()))
+            .collect::<Vec<_>>();
+        for storage in storages.iter().flatten() {
+            storage.take_buffers_reallocated();
+        }
+
+        for bindings in self.resource_bindings.iter() {
+            let mut entries = bindings.entries.lock().unwrap();
+            for (index, storage) in storages.iter().enumerate() {
+                entries[index] = storage
+                    .as_ref()
+                    .map(|storage| storage.bind_group_entries(bindings.gpu.index()))
+                    .unwrap_or_default();
+            }
+            bindings.recreate_groups(&entries, &self.bindings);
+        }
+    }
+
+    // Recreates the resource bind groups if storages reallocated their GPU buffers. Only the bind
+    // group entries of these storages are queried, the entries of all other storages are reused.
+    // Jobs that upload storages before drawing call this to bind the reallocated buffers.
+    pub fn update_resource_bindings(&self) {
+        let reallocated_storages = self
+            .resources
+            .iter()
+            .enumerate()
+            .filter_map(|(index, storage)| {
+                let storage = storage.as_ref()?.read().unwrap();
+                return storage.take_buffers_reallocated().then_some((index, storage));
+            })
+            .collect::<Vec<_>>();
+
+        if reallocated_storages.is_empty() {
+            return;
+        }
+
+        for bindings in self.resource_bindings.iter() {
+            let mut entries = bindings.entries.lock().unwrap();
+            for (index, storage) in &reallocated_storages {
+                entries[*index] = storage.bind_group_entries(bindings.gpu.index());
+            }
+            bindings.recreate_groups(&entries, &self.bindings);
+        }
+    }
+}
+
+pub struct ResourceStorageAccess<'scene, R: Resource> {
+    guard: RwLockReadGuard<'scene, Box<dyn ResourceStorage>>,
+    phantom: PhantomData<R>,
+}
+
+impl<'scene, R: Resource> ResourceStorageAccess<'scene, R> {
+    fn new(guard: RwLockReadGuard<'scene, Box<dyn ResourceStorage>>) -> Self {
+        return Self { guard, phantom: PhantomData };
+    }
+}
+
+impl<R: Resource> std::ops::Deref for ResourceStorageAccess<'_, R> {
+    type Target = R::Storage;
+
+    fn deref(&self) -> &Self::Target {
+        return self
+            .guard
+            .downcast_ref::<R::Storage>()
+            .expect("resource storage has an unexpected type");
+    }
+}
+
+pub struct MutableResourceStorageAccess<'scene, R: Resource> {
+    guard: RwLockWriteGuard<'scene, Box<dyn ResourceStorage>>,
+    phantom: PhantomData<R>,
+}
+
+impl<'scene, R: Resource> MutableResourceStorageAccess<'scene, R> {
+    fn new(guard: RwLockWriteGuard<'scene, Box<dyn ResourceStorage>>) -> Self {
+        return Self { guard, phantom: PhantomData };
+    }
+}
+
+impl<R: Resource> std::ops::Deref for MutableResourceStorageAccess<'_, R> {
+    type Target = R::Storage;
+
+    fn deref(&self) -> &Self::Target {
+        return self
+            .guard
+            .downcast_ref::<R::Storage>()
+            .expect("resource storage has an unexpected type");
+    }
+}
+
+impl<R: Resource> std::ops::DerefMut for MutableResourceStorageAccess<'_, R> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        return self
+            .guard
+            .downcast_mut::<R::Storage>()
+            .expect("resource storage has an unexpected type");
+    }
+}
+
+fn parse_entity_id(id: serde_json::Value) -> Result<EntityId> {
+    return serde_json::from_value(id).map_err(|error| {
+        Error::new(format!("invalid entity id: {error}"), SourceLocation::here())
+    });
+}
+
+// The given id of an entity that is loaded and its components by the label of their resource.
+type LoadedEntity<L, C> = (Option<EntityId>, Vec<(L, C)>);
+
+thread_local! {
+    static ENTITY_ID_REMAPPING: RefCell<HashMap<u32, u32>> = RefCell::new(HashMap::new());
+}
+
+// Runs `f` while the entity references that are deserialized on this thread are replaced
+// according to the remapping of their raw u32 values, see `deserialize_entity_reference`.
+fn with_entity_id_remapping<T>(remapping: HashMap<u32, u32>, f: impl FnOnce() -> T) -> T {
+    let previous_remapping = ENTITY_ID_REMAPPING.with(|ids| ids.replace(remapping));
+    let result = f();
+    ENTITY_ID_REMAPPING.with(|ids| ids.replace(previous_remapping));
+    return result;
+}
+
+// Deserializes the id of an entity that a component references, e.g., a parent, with
+// `#[serde(deserialize_with = "deserialize_entity_reference")]`. While a scene is loaded, the
+// references to entities that got new ids because theirs were in use are replaced by the new ids,
+// see `SceneState::load_json`. Other ids, e.g., resource ids, are deserialized as they are.
+pub fn deserialize_entity_reference<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<EntityId, D::Error> {
+    let entity_id: EntityId = serde::Deserialize::deserialize(deserializer)?;
+    let remapped_id = ENTITY_ID_REMAPPING
+        .with(|remapping| remapping.borrow().get(&bytemuck::cast(entity_id)).copied());
+    return Ok(remapped_id.map_or(entity_id, bytemuck::cast));
+}
+
+// Iterates over the entities of a scene while holding the lock on them, see
+// `SceneState::entity_ids`.
+pub struct EntityIds<'scene> {
+    entities: RwLockReadGuard<'scene, IdStorage<EntityId>>,
+    index: usize,
+}
+
+impl Iterator for EntityIds<'_> {
+    type Item = EntityId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.entities.index_bound() {
+            self.index += 1;
+            if let Some(entity_id) = self.entities.current_id_at(self.index - 1) {
+                return Some(entity_id);
+            }
+        }
+        return None;
+    }
+}
+
+// The components of all entities that have one, see `SceneState::entities_with`. The components
+// borrow the lock, so they are iterated via `iter` or a reference to this.
+pub struct EntityComponents<'scene, R: Resource> {
+    storage: MappedRwLockReadGuard<'scene, IdMappedResourceStorage<EntityId, R>>,
+}
+
+impl<R: Resource + 'static> EntityComponents<'_, R> {
+    pub fn iter(&self) -> IdMappedResourceStorageIterator<'_, EntityId, R> {
+        return self.storage.iter();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.storage.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.storage.is_empty();
+    }
+}
+
+impl<'a, R: Resource + 'static> IntoIterator for &'a EntityComponents<'_, R> {
+    type Item = (EntityId, &'a R);
+    type IntoIter = IdMappedResourceStorageIterator<'a, EntityId, R>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        return self.iter();
+    }
+}
+
+// A reconfiguration of a viewport that is applied at the beginning of the next tick.
+#[derive(Debug, Default, PartialEq)]
+struct ViewportChange {
+    added: bool,
+    size: Option<PhysicalSize<u32>>,
+    present_mode: Option<wgpu::PresentMode>,
+    sample_count: Option<u32>,
+}
+
+// The viewports that changed since the last tick. Multiple changes of the same viewport are
+// coalesced, so every viewport is reconfigured and gets its pipelines recreated at most once per
+// frame. Viewports that did not change keep their pipelines.
+#[derive(Default)]
+struct ViewportChanges {
+    changes: HashMap<ViewportId, ViewportChange>,
+}
+
+impl ViewportChanges {
+    fn mark_changed(&mut self, viewport_id: ViewportId) -> &mut ViewportChange {
+        return self.changes.entry(viewport_id).or_default();
+    }
+
+    // New viewports only need pipelines, their surfaces are configured when they are added.
+    fn add(&mut self, viewport_id: ViewportId) {
+        self.mark_changed(viewport_id).added = true;
+    }
+
+    // Minimized windows report a size of zero. Their surfaces keep the last size until the
+    // windows are restored.
+    fn resize(&mut self, viewport_id: ViewportId, size: PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        self.mark_changed(viewport_id).size = Some(size);
+    }
+
+    fn set_present_mode(&mut self, viewport_id: ViewportId, present_mode: wgpu::PresentMode) {
+        self.mark_changed(viewport_id).present_mode = Some(present_mode);
+    }
+
+    fn set_sample_count(&mut self, viewport_id: ViewportId, sample_count: u32) {
+        self.mark_changed(viewport_id).sample_count = Some(sample_count);
+    }
+
+    fn remove(&mut self, viewport_id: ViewportId) {
+        self.changes.remove(&viewport_id);
+    }
+
+    fn take(&mut self) -> HashMap<ViewportId, ViewportChange> {
+        return std::mem::take(&mut self.changes);
+    }
+}
+
+// Options for creating a scene.
+#[derive(Debug, Clone, Default)]
+pub struct SceneConfig {
+    // The number of entities the storages reserve space for.
+    pub entity_capacity: usize,
+    // The number of worker threads that run the jobs, defaults to the available parallelism. A
+    // single worker runs one job after another, which helps when debugging races. Without
+    // workers, the jobs run on the thread that ticks the scene in a deterministic order.
+    pub worker_count: Option<usize>,
+    // The depth buffer every viewport gets. Without one, the pipelines do not test depth.
+    pub depth_buffer: Option<DepthBufferConfig>,
+    // The length of the steps of the fixed update jobs in seconds, defaults to
+    // `DEFAULT_FIXED_DELTA_TIME`.
+    pub fixed_delta_time: Option<f32>,
+    // The maximum number of fixed update steps per tick, defaults to
+    // `FixedTimestep::DEFAULT_MAX_STEPS_PER_TICK`.
+    pub max_fixed_steps_per_tick: Option<usize>,
+    // The seed of the random number generator of the scene, defaults to `Rng::DEFAULT_SEED`.
+    pub rng_seed: Option<u64>,
+}
+
+// Splits the time that passed into steps of a fixed length for the fixed update jobs. Time that
+// does not fill a whole step is carried over to the next tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixedTimestep {
+    delta_time: f32,
+    max_steps_per_tick: usize,
+    accumulated_time: f32,
+}
+
+impl FixedTimestep {
+    pub const DEFAULT_MAX_STEPS_PER_TICK: usize = 8;
+
+    pub fn new(delta_time: f32, max_steps_per_tick: usize) -> Self {
+        assert!(delta_time > 0.0, "the fixed delta time must be positive");
+        return Self {
+            delta_time,
+            max_steps_per_tick,
+            accumulated_time: 0.0,
+        };
+    }
+
+    pub fn delta_time(&self) -> f32 {
+        return self.delta_time;
+    }
+
+    // Adds the time that passed since the last tick and returns the number of steps to run. If the
+    // steps take longer than the time they simulate, every tick would have to run more steps than
+    // the one before. Thus, at most `max_steps_per_tick` steps run and the scene slows down
+    // instead.
+    pub fn advance(&mut self, delta_time: f32) -> usize {
+        self.accumulated_time += delta_time;
+        let steps = (self.accumulated_time / self.delta_time) as usize;
+        if steps > self.max_steps_per_tick {
+            self.accumulated_time %= self.delta_time;
+            return self.max_steps_per_tick;
+        }
+        self.accumulated_time -= steps as f32 * self.delta_time;
+        return steps;
+    }
+
+    // How far the time of the scene is between the last and the next fixed step, from 0 right
+    // after a step to almost 1 right before the next one. The fixed update jobs only change the
+    // state in steps, so render jobs that run more often than them look choppy unless they
+    // interpolate between the state before and after the last step with this factor. This
+    // requires a copy of the state before the step, e.g., a component that the fixed update jobs
+    // copy the current state to before they change it.
+    pub fn interpolation_alpha(&self) -> f32 {
+        // Rounding errors can leave the accumulated time slightly outside of a step.
+        return (self.accumulated_time / self.delta_time).clamp(0.0, 1.0 - f32::EPSILON);
+    }
+}
+
+// Counters of a tick, returned by `Scene::tick`. The jobs include those of the fixed update steps
+// of the tick, per viewport jobs count once per viewport.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub jobs_executed: usize,
+    // The jobs whose run condition did not hold, see `Job::set_run_if`.
+    pub jobs_skipped: usize,
+    pub viewport_count: usize,
+    // The wall-clock time the tick took.
+    pub frame_duration: Duration,
+}
+
+// The scene description a scene was created with, `Scene::reset` spawns its entities again.
+enum InitialScene {
+    Json(serde_json::Value),
+    Binary(Vec<u8>),
+}
+
+impl InitialScene {
+    fn load(&self, state: &SceneState) -> Result<Vec<EntityId>> {
+        return match self {
+            InitialScene::Json(scene) => state.load_json(scene.clone()),
+            InitialScene::Binary(bytes) => state.load_bytes(bytes),
+        };
+    }
+}
+
+pub struct Scene {
+    game_time: f32,
+    time_scale: f32,
+    paused: bool,
+    state: Arc<SceneState>,
+    scheduler: Scheduler,
+    // Runs the fixed update jobs, see `FixedTimestep`.
+    fixed_scheduler: Scheduler,
+    fixed_timestep: FixedTimestep,
+    fixed_game_time: f32,
+    viewport_changes: ViewportChanges,
+    window_viewports: HashMap<WindowId, ViewportId>,
+    depth_buffer_config: Option<DepthBufferConfig>,
+    upload_stats: Vec<(ResourceId, u64)>,
+    wait_for_gpus_on_drop: bool,
+    // The scene description the scene was created from and the seed of its random numbers, see
+    // `reset`.
+    initial_scene: Option<InitialScene>,
+    rng_seed: u64,
+}
+
+impl Scene {
+    pub async fn new(instance: &Instance) -> Result<Self> {
+        return Self::with_capacity(instance, 0).await;
+    }
+
+    // Creates a scene that can hold `entity_count` entities without reallocating its storages.
+    // Fails if the registered jobs cannot be scheduled, see `Scheduler::new`.
+    pub async fn with_capacity(instance: &Instance, entity_count: usize) -> Result<Self> {
+        let config = SceneConfig {
+            entity_capacity: entity_count,
+            ..Default::default()
+        };
+        return Self::with_config(instance, config).await;
+    }
+
+    // Creates a scene with the given options. Fails if the registered jobs cannot be scheduled,
+    // see `Scheduler::new`, or if the GPUs cannot bind the buffers of all resources.
+    pub async fn with_config(instance: &Instance, config: SceneConfig) -> Result<Self> {
+        let state = Arc::new(SceneState::with_capacity(instance, config.entity_capacity)?);
+        let worker_count = config.worker_count.unwrap_or_else(|| {
+            thread::available_parallelism()
+                .map(|c| -> usize { c.into() })
+                .unwrap_or(4)
+        });
+
+        let scheduler = Scheduler::new(instance, JobKind::Update, state.clone(), worker_count)?;
+        let fixed_scheduler = scheduler.with_shared_workers(JobKind::FixedUpdate)?;
+        return Ok(Self::from_schedulers(state, scheduler, fixed_scheduler, &config));
+    }
+
+    // Creates a scene that runs the jobs of the schedulers, which must belong to the state. Only
+    // the options of the config that are not needed to create the schedulers are used.
+    pub(crate) fn from_schedulers(
+        state: Arc<SceneState>,
+        scheduler: Scheduler,
+        fixed_scheduler: Scheduler,
+        config: &SceneConfig,
+    ) -> Self {
+        let fixed_timestep = FixedTimestep::new(
+            config.fixed_delta_time.unwrap_or(DEFAULT_FIXED_DELTA_TIME),
+            config
+                .max_fixed_steps_per_tick
+                .unwrap_or(FixedTimestep::DEFAULT_MAX_STEPS_PER_TICK),
+        );
+        scheduler.set_fixed_delta_time(fixed_timestep.delta_time());
+        fixed_scheduler.set_fixed_delta_time(fixed_timestep.delta_time());
+        let rng_seed = config.rng_seed.unwrap_or(Rng::DEFAULT_SEED);
+        state.seed_rng(rng_seed);
+
+        return Self {
+            viewport_changes: ViewportChanges::default(),
+            window_viewports: HashMap::new(),
+            depth_buffer_config: config.depth_buffer,
+            upload_stats: Vec::new(),
+            wait_for_gpus_on_drop: true,
+            initial_scene: None,
+            rng_seed,
+            game_time: 0.0,
+            time_scale: 1.0,
+            paused: false,
+            scheduler,
+            fixed_scheduler,
+            fixed_timestep,
+            fixed_game_time: 0.0,
+            state,
+        };
+    }
+
+    // Creates a scene and spawns the entities of the JSON scene description, see
+    // `SceneState::load_json` for the format.
+    pub async fn from_json(instance: &Instance, json: &str) -> Result<Self> {
+        let scene: serde_json::Value = serde_json::from_str(json).map_err(|error| {
+            Error::new(format!("invalid scene: {error}"), SourceLocation::here())
+        })?;
+        // Sizes the storages for the entities of the scene, so loading does not reallocate them.
+        let entity_count = scene["entities"].as_array().map_or(0, Vec::len);
+        let mut this = Self::with_capacity(instance, entity_count).await?;
+        this.load_initial_scene(InitialScene::Json(scene))?;
+        return Ok(this);
+    }
+
+    // Creates a scene and spawns the entities of a binary scene, see `SceneState::to_bytes`.
+    pub async fn from_bytes(instance: &Instance, bytes: &[u8]) -> Result<Self> {
+        let scene = crate::binary::decode_scene(bytes)?;
+        let mut this = Self::with_capacity(instance, scene.entity_count()).await?;
+        this.state.load_binary_scene(&scene)?;
+        this.initial_scene = Some(InitialScene::Binary(bytes.to_vec()));
+        return Ok(this);
+    }
+
+    // Spawns the entities of the scene description and keeps it for `reset`.
+    fn load_initial_scene(&mut self, scene: InitialScene) -> Result<()> {
+        scene.load(&self.state)?;
+        self.initial_scene = Some(scene);
+        return Ok(());
+    }
+
+    // Despawns all entities and spawns the ones the scene was created with by `from_json` or
+    // `from_bytes` again, e.g., to restart a level. Scenes that were created empty are left
+    // empty. The entities get new ids, see `SceneState::clear_entities`. The random number
+    // generator starts over with the seed of the scene config. The viewports, the jobs and the
+    // game time are kept.
+    pub fn reset(&mut self) -> Result<()> {
+        self.state.clear_entities();
+        self.state.seed_rng(self.rng_seed);
+        if let Some(scene) = &self.initial_scene {
+            scene.load(&self.state)?;
+        }
+        return Ok(());
+    }
+
+    // Returns the entities of the scene in the format of `from_json`, e.g., to save the state of
+    // a game or the scene of an editor. See `SceneState::save_json` for the components that are
+    // left out.
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        return self.state.save_json();
+    }
+
+    // Saves the entities of the scene in a binary format, see `SceneState::to_bytes`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        return self.state.to_bytes();
+    }
+
+    // Waits until the GPUs finished the work submitted for the scene, see
+    // `SceneState::wait_for_gpus`.
+    pub fn finish(&self) {
+        self.state.wait_for_gpus();
+    }
+
+    // By default, dropping the scene waits for the GPUs to finish the submitted work before the
+    // GPU buffers of the scene are freed. Disable it if the scene is dropped after calling
+    // `finish` or if the GPUs are idle anyway.
+    pub fn set_wait_for_gpus_on_drop(&mut self, wait_for_gpus_on_drop: bool) {
+        self.wait_for_gpus_on_drop = wait_for_gpus_on_drop;
+    }
+
+    pub fn state(&self) -> &Arc<SceneState> {
+        &self.state
+    }
+
+    // The time the jobs of the scene simulated so far, i.e., the sum of the scaled delta times of
+    // the ticks.
+    pub fn game_time(&self) -> f32 {
+        return self.game_time;
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        return self.time_scale;
+    }
+
+    // Scales the time that passes in the scene, e.g., 0.5 for slow motion. The update jobs get the
+    // scaled delta time, and the fixed update jobs run their steps at the scaled rate.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        assert!(time_scale >= 0.0, "the time scale must not be negative");
+        self.time_scale = time_scale;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        return self.paused;
+    }
+
+    // While the scene is paused, the game time stands still and no fixed update steps run. The
+    // update jobs still run with a delta time of zero, so the viewports are rendered, e.g., while
+    // an editor pauses the game.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn add_viewport(
+        &mut self,
+        gpu: Arc<Gpu>,
+        surface: wgpu::Surface,
+        size: PhysicalSize<u32>,
+        viewport_config: ViewportConfig,
     ) -> ViewportId {
-        let surface_caps = surface.get_capabilities(&gpu.adapter());
+        let surface_caps = surface.get_capabilities(gpu.adapter());
         let surface_format = surface_caps
             .formats
             .iter()
@@ -228,62 +2295,1796 @@ impl Scene {
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_caps.present_modes[0],
+            present_mode: choose_present_mode(
+                viewport_config.present_mode,
+                &surface_caps.present_modes,
+            ),
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
         };
-        surface.configure(&gpu.device(), &config);
-        self.viewports_changed = true;
-        self.viewports()
-            .write()
+        let sample_count = choose_sample_count(&gpu, config.format, viewport_config.sample_count);
+        let target = RenderTarget::Surface {
+            surface: Box::new(surface),
+            config,
+            present_modes: surface_caps.present_modes,
+            texture: None,
+        };
+        let viewport = Viewport::new(gpu, target, self.depth_buffer_config, sample_count);
+        return self.insert_viewport(viewport);
+    }
+
+    // Adds a viewport that renders to a texture instead of a window, so scenes can be rendered
+    // without a window, e.g., in tests. The texture can be copied from after each tick, see
+    // `Viewport::texture`.
+    pub fn add_offscreen_viewport(
+        &mut self,
+        gpu: Arc<Gpu>,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> ViewportId {
+        let viewport = Viewport::offscreen(
+            gpu,
+            PhysicalSize::new(width, height),
+            format,
+            self.depth_buffer_config,
+        );
+        return self.insert_viewport(viewport);
+    }
+
+    fn insert_viewport(&mut self, viewport: Viewport) -> ViewportId {
+        let viewport_id = self.viewports().write().unwrap().insert(viewport).0;
+        self.viewport_changes.add(viewport_id);
+        return viewport_id;
+    }
+
+    // Removes the viewport, e.g., after its window was closed. Its surface, pipelines and
+    // components are dropped and the jobs that are executed per viewport do not run for it
+    // anymore. Returns false if the viewport does not exist.
+    pub fn remove_viewport(&mut self, viewport_id: ViewportId) -> bool {
+        if !self.state.remove_viewport(viewport_id) {
+            return false;
+        }
+        self.viewport_changes.remove(viewport_id);
+        self.window_viewports.retain(|_, id| *id != viewport_id);
+        // The viewport does not exist anymore, so its pipelines are only dropped.
+        self.scheduler.remove_viewport_pipelines(&[viewport_id]);
+        self.fixed_scheduler.remove_viewport_pipelines(&[viewport_id]);
+        return true;
+    }
+
+    // Associates the window with the viewport that presents to it, so the viewport is resized
+    // together with the window.
+    pub fn attach_window(&mut self, window_id: WindowId, viewport_id: ViewportId) {
+        self.window_viewports.insert(window_id, viewport_id);
+    }
+
+    pub fn viewport_of_window(&self, window_id: WindowId) -> Option<ViewportId> {
+        return self.window_viewports.get(&window_id).copied();
+    }
+
+    // Resizes the surface of the viewport at the beginning of the next tick. The pipelines of the
+    // viewport are recreated for the new size. Resizes to a size of zero are ignored.
+    pub fn resize_viewport(&mut self, viewport_id: ViewportId, size: PhysicalSize<u32>) {
+        self.viewport_changes.resize(viewport_id, size);
+    }
+
+    // Changes the present mode of the viewport at the beginning of the next tick. Unsupported
+    // modes fall back like the one of the `ViewportConfig`.
+    pub fn set_present_mode(&mut self, viewport_id: ViewportId, present_mode: wgpu::PresentMode) {
+        self.viewport_changes
+            .set_present_mode(viewport_id, present_mode);
+    }
+
+    // Changes the number of samples per pixel of the viewport at the beginning of the next tick,
+    // e.g., 4 to smooth the edges of triangles. The pipelines of the viewport are recreated with
+    // the new count. Fails if the viewport does not exist or its GPU does not support the count
+    // for its format, see `Viewport::supports_sample_count`.
+    pub fn set_viewport_sample_count(
+        &mut self,
+        viewport_id: ViewportId,
+        sample_count: u32,
+    ) -> Result<()> {
+        let viewports = self.viewports().read().unwrap();
+        let Some(viewport) = viewports.get(viewport_id) else {
+            return Err(Error::new(
+                format!("viewport {viewport_id} does not exist"),
+                SourceLocation::here(),
+            ));
+        };
+        if !viewport.supports_sample_count(sample_count) {
+            return Err(Error::new(
+                format!(
+                    "{sample_count} samples per pixel are not supported for the format {:?}",
+                    viewport.format()
+                ),
+                SourceLocation::here(),
+            ));
+        }
+        drop(viewports);
+        self.viewport_changes.set_sample_count(viewport_id, sample_count);
+        return Ok(());
+    }
+
+    // Reconfigures the surfaces of all viewports that changed since the last tick and recreates
+    // their pipelines. The resource bind groups are rebuilt if viewports were added.
+    fn apply_viewport_changes(&mut self) -> Result<()> {
+        let changes = self.viewport_changes.take();
+        if changes.is_empty() {
+            return Ok(());
+        }
+        if changes.values().any(|change| change.added) {
+            self.state.rebuild_resource_bindings();
+        }
+
+        let mut changed_viewport_ids = Vec::with_capacity(changes.len());
+        {
+            let mut viewports = self.viewports().write().unwrap();
+            for (viewport_id, change) in changes {
+                let Some(viewport) = viewports.get_mut(viewport_id) else {
+                    continue;
+                };
+                if change.size.is_some()
+                    || change.present_mode.is_some()
+                    || change.sample_count.is_some()
+                {
+                    if let Some(size) = change.size {
+                        viewport.resize(size);
+                        let event = ViewportEvent::Resized { viewport_id, size };
+                        self.state.emit_between_frames(event);
+                    }
+                    if let Some(present_mode) = change.present_mode {
+                        viewport.set_present_mode(present_mode);
+                    }
+                    if let Some(sample_count) = change.sample_count {
+                        viewport.set_sample_count(sample_count);
+                    }
+                    viewport.configure();
+                }
+                changed_viewport_ids.push(viewport_id);
+            }
+        }
+        self.scheduler
+            .configure_viewport_pipelines(&changed_viewport_ids)?;
+        self.fixed_scheduler
+            .configure_viewport_pipelines(&changed_viewport_ids)?;
+        return Ok(());
+    }
+
+    pub fn entities(&self) -> &Arc<RwLock<IdStorage>> {
+        return &self.state.entities;
+    }
+
+    pub fn viewports(&self) -> &Arc<RwLock<IdMap<ViewportId, Viewport>>> {
+        return &self.state.viewports;
+    }
+
+    pub fn resource_storage(
+        &self,
+        resource_id: ResourceId,
+    ) -> Option<&RwLock<Box<dyn ResourceStorage>>> {
+        return self.state.resources[resource_id.index()].as_ref();
+    }
+
+    // Reads the pixels that were rendered to the viewport in the last tick, see
+    // `Viewport::read_pixels`.
+    pub fn read_viewport_pixels(&self, viewport_id: ViewportId) -> Result<Vec<u8>> {
+        return self.state.read_viewport_pixels(viewport_id);
+    }
+
+    // Registers a listener that is called whenever a tick finished running the jobs.
+    pub fn add_frame_listener(&self, listener: FrameListener) {
+        self.scheduler.add_frame_listener(listener);
+    }
+
+    // Calls the callback with the profile of every following tick of the update jobs, packed as
+    // described at `FrameProfile::to_bytes`. The fixed update jobs are not profiled.
+    pub fn start_profiling(&self, callback: ProfileCallback) {
+        self.scheduler.start_profiling(callback);
+    }
+
+    pub fn stop_profiling(&self) {
+        self.scheduler.stop_profiling();
+    }
+
+    // The number of bytes each resource uploaded to the GPUs during the last tick.
+    pub fn upload_stats(&self) -> &[(ResourceId, u64)] {
+        return &self.upload_stats;
+    }
+
+    // Runs the jobs for a frame. The delta time is the real time that passed since the last tick,
+    // the jobs get it scaled by the time scale of the scene.
+    pub fn tick(&mut self, delta_time: f32) -> Result<FrameStats> {
+        let tick_start = Instant::now();
+        self.apply_viewport_changes()?;
+        self.state.acquire_viewport_textures()?;
+        let mut stats = FrameStats {
+            viewport_count: self.viewports().read().unwrap().len(),
+            ..Default::default()
+        };
+
+        let delta_time = if self.paused {
+            0.0
+        } else {
+            delta_time * self.time_scale
+        };
+
+        // The fixed update jobs catch up with the time that passed before the update jobs run. If a
+        // step fails, the remaining ones are skipped.
+        let mut result = Ok(());
+        for _ in 0..self.fixed_timestep.advance(delta_time) {
+            let fixed_delta_time = self.fixed_timestep.delta_time();
+            self.fixed_game_time += fixed_delta_time;
+            result = self
+                .fixed_scheduler
+                .run_jobs(self.fixed_game_time, fixed_delta_time);
+            stats.jobs_executed += self.fixed_scheduler.jobs_executed();
+            stats.jobs_skipped += self.fixed_scheduler.jobs_skipped();
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.scheduler
+            .set_interpolation_alpha(self.fixed_timestep.interpolation_alpha());
+
+        self.game_time += delta_time;
+        let result = result.and(self.scheduler.run_jobs(self.game_time, delta_time));
+        stats.jobs_executed += self.scheduler.jobs_executed();
+        stats.jobs_skipped += self.scheduler.jobs_skipped();
+        self.state.end_frame();
+        self.state.update_resource_bindings();
+        self.upload_stats = self.state.take_upload_stats();
+
+        self.state.present_viewports();
+        self.state.input_mut().end_frame();
+        self.state.mouse_mut().end_frame();
+
+        stats.frame_duration = tick_start.elapsed();
+        return result.map(|()| stats);
+    }
+}
+
+impl Drop for Scene {
+    fn drop(&mut self) {
+        if self.wait_for_gpus_on_drop {
+            self.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{EventStorage, Job, JobFunction, ResourceKind, SystemResources};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+    struct Health(u32);
+
+    crate::test_resource!(Health, EntityComponent, IdMappedResourceStorage<EntityId, Health>, {
+        fn from_serialized(deserializer: &mut dyn erased_serde::Deserializer) -> Result<Self> {
+            return crate::deserialize_resource(deserializer);
+        }
+
+        fn as_serializable(&self) -> Option<&dyn erased_serde::Serialize> {
+            return Some(self);
+        }
+    });
+
+    struct Unregistered;
+
+    impl Resource for Unregistered {
+        type Type = Unregistered;
+        type Storage = IdMappedResourceStorage<EntityId, Unregistered>;
+
+        fn id() -> ResourceId {
+            return ResourceId::from_index(100_000);
+        }
+
+        fn kind() -> ResourceKind {
+            ResourceKind::EntityComponent
+        }
+
+        fn label() -> &'static str {
+            "Unregistered"
+        }
+
+        fn register() {}
+    }
+
+    #[test]
+    fn fixed_timestep_runs_a_step_per_elapsed_delta_time() {
+        let mut fixed_timestep = FixedTimestep::new(0.25, 4);
+        // Frames that are shorter than a step are accumulated, longer ones run multiple steps.
+        let steps = [0.125, 0.125, 0.5, 0.375, 0.0, 0.125, 0.625]
+            .map(|delta_time| fixed_timestep.advance(delta_time));
+        assert_eq!(steps, [0, 1, 2, 1, 0, 1, 2]);
+
+        // A long frame runs at most the maximum number of steps and does not have to be caught up
+        // with later.
+        assert_eq!(fixed_timestep.advance(10.0), 4);
+        assert_eq!(fixed_timestep.advance(0.0), 0);
+        assert_eq!(fixed_timestep.advance(0.125), 1);
+    }
+
+    #[test]
+    fn interpolation_alpha_approaches_one_before_a_fixed_step() {
+        let mut fixed_timestep = FixedTimestep::new(DEFAULT_FIXED_DELTA_TIME, 4);
+        assert_eq!(fixed_timestep.interpolation_alpha(), 0.0);
+
+        // Ten ticks per step.
+        let mut previous_alpha = 0.0;
+        for _ in 0..100 {
+            let steps = fixed_timestep.advance(DEFAULT_FIXED_DELTA_TIME / 10.0);
+            let alpha = fixed_timestep.interpolation_alpha();
+            assert!((0.0..1.0).contains(&alpha));
+            if steps == 0 {
+                assert!(alpha > previous_alpha);
+            } else {
+                // The alpha before the step is either almost one or the step was caught up late
+                // due to rounding and the alpha starts over slightly above zero.
+                assert!(previous_alpha > 0.85);
+                assert!(alpha < 0.15);
+            }
+            previous_alpha = alpha;
+        }
+    }
+
+    static UPDATE_TIMES: Mutex<Vec<(f32, f32)>> = Mutex::new(Vec::new());
+    static FIXED_STEPS: AtomicUsize = AtomicUsize::new(0);
+
+    fn record_update_time_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        UPDATE_TIMES.lock().unwrap().push((sr.game_time(), sr.delta_time()));
+        return Ok(());
+    }
+
+    fn count_fixed_step_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        FIXED_STEPS.fetch_add(1, Ordering::Relaxed);
+        return Ok(());
+    }
+
+    #[test]
+    fn paused_scenes_keep_their_game_time_but_still_run_the_update_jobs() {
+        let state = Arc::new(SceneState::for_gpus(&[], 0).unwrap());
+        let mut jobs = IdMap::new();
+        for (kind, function) in [
+            (JobKind::Update, record_update_time_job as JobFunction),
+            (JobKind::FixedUpdate, count_fixed_step_job),
+        ] {
+            jobs.insert(Job::new(kind, function, &[])).1.set_executed_per_viewport(false);
+        }
+        let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 0).unwrap();
+        let fixed_scheduler =
+            Scheduler::from_jobs(&jobs, JobKind::FixedUpdate, state.clone(), 0).unwrap();
+        let config = SceneConfig {
+            fixed_delta_time: Some(0.25),
+            ..Default::default()
+        };
+        let mut scene = Scene::from_schedulers(state, scheduler, fixed_scheduler, &config);
+
+        scene.tick(0.5).unwrap();
+        scene.set_time_scale(0.5);
+        scene.tick(0.5).unwrap();
+        assert_eq!(scene.game_time(), 0.75);
+        assert_eq!(FIXED_STEPS.load(Ordering::Relaxed), 3);
+
+        scene.set_paused(true);
+        scene.tick(0.5).unwrap();
+        assert_eq!(scene.game_time(), 0.75);
+        assert_eq!(FIXED_STEPS.load(Ordering::Relaxed), 3);
+        assert_eq!(*UPDATE_TIMES.lock().unwrap(), [(0.5, 0.5), (0.75, 0.25), (0.75, 0.0)]);
+
+        scene.set_paused(false);
+        scene.tick(0.5).unwrap();
+        assert_eq!(scene.game_time(), 1.0);
+        assert_eq!(FIXED_STEPS.load(Ordering::Relaxed), 4);
+    }
+
+    struct Hit(u32);
+
+    crate::test_resource!(Hit, Event, EventStorage<Hit>);
+
+    static HITS_SENT: AtomicUsize = AtomicUsize::new(0);
+    static RECEIVED_HITS: Mutex<Vec<(Vec<u32>, usize)>> = Mutex::new(Vec::new());
+
+    fn hit_job(sr: &SystemResources, state: &SceneState) -> Result<()> {
+        sr.emit(Hit(HITS_SENT.fetch_add(1, Ordering::Relaxed) as u32));
+        let mut healths = state.resource_storage_mut::<Health>().unwrap();
+        for entity_id in state.entity_ids() {
+            healths.get_mut(entity_id).unwrap().0 -= 1;
+        }
+        return Ok(());
+    }
+
+    fn receive_hits_job(sr: &SystemResources, state: &SceneState) -> Result<()> {
+        let hits = sr.read::<Hit>().iter().map(|hit| hit.0).collect();
+        let healths = state.resource_storage_ref::<Health>().unwrap();
+        let changed = healths.changed_since(healths.current_frame()).count();
+        RECEIVED_HITS.lock().unwrap().push((hits, changed));
+        return Ok(());
+    }
+
+    #[test]
+    fn events_and_changes_of_all_fixed_steps_of_a_tick_are_kept() {
+        Health::register();
+        Hit::register();
+        let state = Arc::new(SceneState::for_gpus(&[], 0).unwrap());
+        let mut jobs = IdMap::new();
+        for (kind, function) in [
+            (JobKind::Update, receive_hits_job as JobFunction),
+            (JobKind::FixedUpdate, hit_job),
+        ] {
+            jobs.insert(Job::new(kind, function, &[])).1.set_executed_per_viewport(false);
+        }
+        let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 0).unwrap();
+        let fixed_scheduler =
+            Scheduler::from_jobs(&jobs, JobKind::FixedUpdate, state.clone(), 0).unwrap();
+        let config = SceneConfig {
+            fixed_delta_time: Some(0.25),
+            ..Default::default()
+        };
+        let mut scene = Scene::from_schedulers(state.clone(), scheduler, fixed_scheduler, &config);
+        let entity_id = state.entities().write().unwrap().reserve();
+        state.resource_storage_mut::<Health>().unwrap().insert(entity_id, Health(10));
+
+        for _ in 0..2 {
+            scene.tick(0.5).unwrap();
+        }
+        assert_eq!(*RECEIVED_HITS.lock().unwrap(), [(vec![], 1), (vec![0, 1], 1)]);
+        let healths = state.resource_storage_ref::<Health>().unwrap();
+        assert_eq!(healths.get(entity_id), Some(&Health(6)));
+    }
+
+    static DRAWN_NUMBERS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+    fn draw_numbers_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        let mut rng = sr.rng();
+        let numbers = [rng.next_u32(), rng.gen_range(0..100)];
+        DRAWN_NUMBERS.lock().unwrap().extend(numbers);
+        return Ok(());
+    }
+
+    #[test]
+    fn scenes_with_the_same_seed_draw_the_same_numbers() {
+        let mut jobs = IdMap::new();
+        for _ in 0..2 {
+            let (_, job) = jobs.insert(Job::new(JobKind::Update, draw_numbers_job, &[]));
+            job.set_executed_per_viewport(false);
+        }
+        let draw_numbers = |seed| {
+            let state = Arc::new(SceneState::for_gpus(&[], 0).unwrap());
+            let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 0).unwrap();
+            let fixed_scheduler =
+                Scheduler::from_jobs(&jobs, JobKind::FixedUpdate, state.clone(), 0).unwrap();
+            let config = SceneConfig {
+                rng_seed: Some(seed),
+                ..Default::default()
+            };
+            let mut scene = Scene::from_schedulers(state, scheduler, fixed_scheduler, &config);
+            for _ in 0..3 {
+                scene.tick(0.1).unwrap();
+            }
+            return std::mem::take(&mut *DRAWN_NUMBERS.lock().unwrap());
+        };
+
+        let numbers = draw_numbers(42);
+        assert_eq!(numbers.len(), 12);
+        assert_eq!(draw_numbers(42), numbers);
+        assert_ne!(draw_numbers(43), numbers);
+    }
+
+    fn noop_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        return Ok(());
+    }
+
+    #[test]
+    fn ticks_count_the_jobs_of_every_viewport() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        let mut jobs = IdMap::new();
+        for _ in 0..3 {
+            jobs.insert(Job::new(JobKind::Update, noop_job, &[]));
+        }
+        for worker_count in [0, 2] {
+            let state =
+                Arc::new(SceneState::from_storages(std::slice::from_ref(&gpu), vec![], 0).unwrap());
+            let scheduler =
+                Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), worker_count).unwrap();
+            let fixed_scheduler =
+                Scheduler::from_jobs(&jobs, JobKind::FixedUpdate, state.clone(), 0).unwrap();
+            let config = SceneConfig::default();
+            let mut scene = Scene::from_schedulers(state, scheduler, fixed_scheduler, &config);
+            for _ in 0..2 {
+                scene.add_offscreen_viewport(gpu.clone(), 4, 4, wgpu::TextureFormat::Rgba8Unorm);
+            }
+
+            let stats = scene.tick(0.0).unwrap();
+            assert_eq!(stats.viewport_count, 2);
+            assert_eq!(stats.jobs_executed, 3 * 2);
+            assert_eq!(stats.jobs_skipped, 0);
+        }
+    }
+
+    #[test]
+    fn reset_scenes_return_to_their_initial_entities() {
+        Health::register();
+        let state = Arc::new(SceneState::for_gpus(&[], 0).unwrap());
+        let jobs = IdMap::new();
+        let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 0).unwrap();
+        let fixed_scheduler =
+            Scheduler::from_jobs(&jobs, JobKind::FixedUpdate, state.clone(), 0).unwrap();
+        let mut scene =
+            Scene::from_schedulers(state.clone(), scheduler, fixed_scheduler, &Default::default());
+        let initial = serde_json::json!({
+            "entities": [{ "Health": 10 }, { "id": 3, "Health": 30 }],
+        });
+        scene.load_initial_scene(InitialScene::Json(initial)).unwrap();
+        let healths = || {
+            let healths = state.resource_storage_ref::<Health>().unwrap();
+            let mut healths = healths.iter().map(|(_, health)| health.0).collect::<Vec<_>>();
+            healths.sort();
+            return healths;
+        };
+        let number = state.rng().next_u32();
+
+        let initial_ids = state.entity_ids().collect::<Vec<_>>();
+        state.resource_storage_mut::<Health>().unwrap().get_mut(initial_ids[0]).unwrap().0 = 5;
+        let spawned = state.entities().write().unwrap().reserve();
+        state.resource_storage_mut::<Health>().unwrap().insert(spawned, Health(1));
+
+        scene.reset().unwrap();
+        assert_eq!(healths(), [10, 30]);
+        assert_eq!(state.entities().read().unwrap().len(), 2);
+        assert_eq!(state.rng().next_u32(), number);
+        // The ids from before the reset no longer refer to entities.
+        for id in initial_ids.into_iter().chain([spawned]) {
+            assert!(!state.entities().read().unwrap().contains(id));
+        }
+    }
+
+    #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, serde::Deserialize)]
+    #[repr(C)]
+    struct Mass(f32);
+
+    crate::test_resource!(Mass, EntityComponent, IdMappedResourceStorage<EntityId, Mass>, {
+        fn from_serialized(deserializer: &mut dyn erased_serde::Deserializer) -> Result<Self> {
+            return crate::deserialize_resource(deserializer);
+        }
+
+        fn gpu_bytes(resources: &[Self]) -> Option<&[u8]> {
+            return Some(crate::resource_bytes(resources));
+        }
+    });
+
+    #[test]
+    fn scenes_with_capacity_load_their_entities_without_reallocating() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        const ENTITY_COUNT: usize = 10_000;
+
+        Mass::register();
+        // Like `Scene::with_capacity`, which needs an instance. Only the storage of this test is
+        // kept, the GPUs cannot bind the resources of all tests.
+        let gpus = [gpu];
+        let mut storages = make_resource_storages(&gpus, ENTITY_COUNT);
+        for (index, storage) in storages.iter_mut().enumerate() {
+            if index != Mass::id().index() {
+                *storage = None;
+            }
+        }
+        let state = Arc::new(SceneState::from_storages(&gpus, storages, ENTITY_COUNT).unwrap());
+        let jobs = IdMap::new();
+        let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 0).unwrap();
+        let fixed_scheduler =
+            Scheduler::from_jobs(&jobs, JobKind::FixedUpdate, state.clone(), 0).unwrap();
+        let mut scene =
+            Scene::from_schedulers(state.clone(), scheduler, fixed_scheduler, &Default::default());
+        let id_capacity = state.entities().read().unwrap().capacity();
+
+        let entities = vec![serde_json::json!({ "Mass": 1.0 }); ENTITY_COUNT];
+        let initial = serde_json::json!({ "entities": entities });
+        scene.load_initial_scene(InitialScene::Json(initial)).unwrap();
+        state.resource_storage_ref::<Mass>().unwrap().update_gpu_buffers();
+
+        assert_eq!(state.entity_ids().count(), ENTITY_COUNT);
+        assert_eq!(state.entities().read().unwrap().capacity(), id_capacity);
+        for storage in state.resources.iter().flatten() {
+            assert!(!storage.read().unwrap().take_buffers_reallocated());
+        }
+    }
+
+    #[test]
+    fn typed_resource_storage_access() {
+        Health::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let entity = state.entities().write().unwrap().reserve();
+
+        state
+            .resource_storage_mut::<Health>()
             .unwrap()
-            .insert(Viewport {
-                gpu,
-                surface,
-                texture: None,
-                texture_view: None,
-                surface_config: config,
+            .insert(entity, Health(100));
+        assert_eq!(
+            state.resource_storage_ref::<Health>().unwrap().get(entity),
+            Some(&Health(100))
+        );
+
+        assert!(state.resource_storage_ref::<Unregistered>().is_none());
+        assert!(state.resource_storage_mut::<Unregistered>().is_none());
+    }
+
+    #[test]
+    fn entities_and_their_components_can_be_enumerated() {
+        Health::register();
+        VertexPosition::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let entities = {
+            let mut storage = state.entities().write().unwrap();
+            let entities = [0; 5].map(|_| storage.reserve());
+            storage.free(entities[1]);
+            entities
+        };
+        {
+            let mut health = state.resource_storage_mut::<Health>().unwrap();
+            health.insert(entities[0], Health(10));
+            health.insert(entities[3], Health(30));
+        }
+
+        assert_eq!(
+            state.entity_ids().collect::<Vec<_>>(),
+            [entities[0], entities[2], entities[3], entities[4]]
+        );
+
+        let health = state.entities_with::<Health>().unwrap();
+        assert_eq!(health.len(), 2);
+        assert_eq!(
+            health.iter().collect::<Vec<_>>(),
+            [(entities[0], &Health(10)), (entities[3], &Health(30))]
+        );
+        let mut total = 0;
+        for (_, Health(amount)) in &health {
+            total += amount;
+        }
+        assert_eq!(total, 40);
+
+        // Slices and unregistered resources are not entity components with one value per entity.
+        assert!(state.entities_with::<VertexPosition>().is_none());
+        assert!(state.entities_with::<Unregistered>().is_none());
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+    struct VertexPosition([f32; 3]);
+
+    crate::test_resource!(
+        VertexPosition,
+        EntityComponent,
+        IdMappedResourceSliceStorage<EntityId, VertexPosition>,
+        {
+            fn from_serialized(deserializer: &mut dyn erased_serde::Deserializer) -> Result<Self> {
+                return crate::deserialize_resource(deserializer);
+            }
+
+            fn as_serializable(&self) -> Option<&dyn erased_serde::Serialize> {
+                return Some(self);
+            }
+        }
+    );
+
+    #[test]
+    fn typed_slice_storage_access() {
+        Health::register();
+        VertexPosition::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let entity = state.entities().write().unwrap().reserve();
+        let vertices = [
+            VertexPosition([0.0, 0.0, 0.0]),
+            VertexPosition([1.0, 0.0, 0.0]),
+            VertexPosition([0.0, 1.0, 0.0]),
+        ];
+
+        state
+            .slice_storage_mut::<VertexPosition>()
+            .unwrap()
+            .insert_slice(entity, vertices);
+        assert_eq!(
+            state.slice_storage::<VertexPosition>().unwrap().get(entity),
+            Some(&vertices[..])
+        );
+
+        assert!(state.slice_storage::<Health>().is_none());
+        assert!(state.slice_storage_mut::<Unregistered>().is_none());
+    }
+
+    #[test]
+    fn slices_are_inserted_without_knowing_their_type() {
+        VertexPosition::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let entity = state.entities().write().unwrap().reserve();
+        let vertices = vec![
+            VertexPosition([0.0, 0.0, 0.0]),
+            VertexPosition([1.0, 0.0, 0.0]),
+            VertexPosition([0.0, 1.0, 0.0]),
+        ];
+
+        {
+            let mut storage = state
+                .resource_storage(VertexPosition::id())
+                .unwrap()
+                .write()
+                .unwrap();
+            assert!(storage.as_entity_component_storage_mut().is_none());
+            let storage = storage.as_entity_component_slice_storage_mut().unwrap();
+            storage.insert_slice_boxed(entity, Box::new(vertices.clone())).unwrap();
+            assert_eq!(storage.slice_len(entity), Some(3));
+        }
+        assert_eq!(
+            state.slice_storage::<VertexPosition>().unwrap().get(entity),
+            Some(&vertices[..])
+        );
+
+        state.remove_entity_components(entity);
+        assert!(state.slice_storage::<VertexPosition>().unwrap().get(entity).is_none());
+    }
+
+    #[test]
+    fn slices_are_loaded_from_json() {
+        VertexPosition::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let entities = state
+            .load_json(serde_json::json!({
+                "entities": [{
+                    "VertexPosition": [
+                        [0.0, 0.5, 0.0],
+                        [-0.5, -0.5, 0.0],
+                        [0.5, -0.5, 0.0],
+                    ],
+                }],
+            }))
+            .unwrap();
+        assert_eq!(entities.len(), 1);
+        assert_eq!(
+            state.slice_storage::<VertexPosition>().unwrap().get(entities[0]),
+            Some(
+                &[
+                    VertexPosition([0.0, 0.5, 0.0]),
+                    VertexPosition([-0.5, -0.5, 0.0]),
+                    VertexPosition([0.5, -0.5, 0.0]),
+                ][..]
+            )
+        );
+
+        // A single resource is not a slice.
+        let error = state
+            .load_json(serde_json::json!({
+                "entities": [{ "VertexPosition": [0.0, 0.5, 0.0] }],
+            }))
+            .unwrap_err();
+        assert!(error.message().starts_with("failed to deserialize VertexPosition"));
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Velocity {
+        x: f32,
+        y: f32,
+    }
+
+    crate::test_resource!(Velocity, EntityComponent, IdMappedResourceStorage<EntityId, Velocity>, {
+        fn from_serialized(deserializer: &mut dyn erased_serde::Deserializer) -> Result<Self> {
+            return crate::deserialize_resource(deserializer);
+        }
+    });
+
+    #[test]
+    fn malformed_components_are_reported() {
+        Health::register();
+        Velocity::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let load = |velocity| {
+            return state.load_json(serde_json::json!({
+                "entities": [
+                    { "Velocity": { "x": 1.0, "y": 2.0 } },
+                    { "Health": 10, "Velocity": velocity },
+                ],
+            }));
+        };
+
+        let error = load(serde_json::json!({ "x": "fast", "y": 2.0 })).unwrap_err();
+        assert!(error.message().starts_with("failed to deserialize Velocity: invalid type"));
+        assert!(error.message().ends_with("(component Velocity of entity 1)"));
+
+        let error = load(serde_json::json!({ "x": 1.0 })).unwrap_err();
+        assert!(error.message().contains("missing field `y`"));
+        assert!(error.message().ends_with("(component Velocity of entity 1)"));
+
+        // The entities of a failed load are not spawned.
+        assert_eq!(state.entity_ids().count(), 0);
+        assert!(state.entities_with::<Velocity>().unwrap().is_empty());
+        assert!(state.entities_with::<Health>().unwrap().is_empty());
+        assert_eq!(load(serde_json::json!({ "x": 1.0, "y": 0.0 })).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn saved_scenes_can_be_loaded_again() {
+        Health::register();
+        VertexPosition::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let first = state.entities().write().unwrap().reserve();
+        let second = state.entities().write().unwrap().reserve();
+        state.resource_storage_mut::<Health>().unwrap().insert(first, Health(7));
+        state
+            .slice_storage_mut::<VertexPosition>()
+            .unwrap()
+            .insert_slice(second, [VertexPosition([1.0, 2.0, 3.0])]);
+
+        let json = state.save_json().unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "entities": [
+                    { "id": 0, "Health": 7 },
+                    { "id": 1, "VertexPosition": [[1.0, 2.0, 3.0]] },
+                ],
             })
-            .0
+        );
+
+        let loaded = SceneState::for_gpus(&[], 0).unwrap();
+        let entities = loaded.load_json(json.clone()).unwrap();
+        let health = loaded.resource_storage_ref::<Health>().unwrap();
+        assert_eq!(health.get(entities[0]), Some(&Health(7)));
+        assert_eq!(
+            loaded.slice_storage::<VertexPosition>().unwrap().get(entities[1]),
+            Some(&[VertexPosition([1.0, 2.0, 3.0])][..])
+        );
+        assert_eq!(loaded.save_json().unwrap(), json);
     }
 
-    pub fn entities(&self) -> &Arc<RwLock<IdStorage>> {
-        return &self.state.entities;
+    #[test]
+    fn scenes_survive_a_binary_round_trip() {
+        Health::register();
+        VertexPosition::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let entities = state
+            .load_json(serde_json::json!({
+                "entities": [
+                    { "Health": 10, "VertexPosition": [[0.0, 0.5, 0.0], [0.1, -0.5, 2.0]] },
+                    { "Health": 20 },
+                    {},
+                ],
+            }))
+            .unwrap();
+        let bytes = state.to_bytes().unwrap();
+        assert!(bytes.len() < state.save_json().unwrap().to_string().len());
+
+        let loaded = SceneState::for_gpus(&[], 0).unwrap();
+        let loaded_entities = loaded.load_bytes(&bytes).unwrap();
+        assert_eq!(loaded_entities.len(), entities.len());
+        {
+            let health = state.resource_storage_ref::<Health>().unwrap();
+            let loaded_health = loaded.resource_storage_ref::<Health>().unwrap();
+            let vertices = state.slice_storage::<VertexPosition>().unwrap();
+            let loaded_vertices = loaded.slice_storage::<VertexPosition>().unwrap();
+            for (&entity, &loaded_entity) in entities.iter().zip(&loaded_entities) {
+                assert_eq!(health.get(entity), loaded_health.get(loaded_entity));
+                assert_eq!(vertices.get(entity), loaded_vertices.get(loaded_entity));
+            }
+        }
+
+        // Components of resources that are not registered are reported.
+        let mut encoder = crate::binary::SceneEncoder::new();
+        encoder.add_entity(EntityId::from_index(0));
+        encoder.add_component("Mana", &5).unwrap();
+        let error = loaded.load_bytes(&encoder.finish()).unwrap_err();
+        assert_eq!(error.message(), "unknown resource Mana (component Mana of entity 0)");
     }
 
-    pub fn viewports(&self) -> &Arc<RwLock<IdMap<ViewportId, Viewport>>> {
-        return &self.state.viewports;
+    #[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+    struct Parent(#[serde(deserialize_with = "crate::deserialize_entity_reference")] EntityId);
+
+    crate::test_resource!(Parent, EntityComponent, IdMappedResourceStorage<EntityId, Parent>, {
+        fn from_serialized(deserializer: &mut dyn erased_serde::Deserializer) -> Result<Self> {
+            return crate::deserialize_resource(deserializer);
+        }
+
+        fn as_serializable(&self) -> Option<&dyn erased_serde::Serialize> {
+            return Some(self);
+        }
+    });
+
+    #[test]
+    fn loaded_entities_keep_their_ids() {
+        Health::register();
+        Parent::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let removed = state.entities().write().unwrap().reserve();
+        let parent = state.entities().write().unwrap().reserve();
+        state.entities().write().unwrap().free(removed);
+        // Reuses the index of the removed entity with a new version.
+        let child = state.entities().write().unwrap().reserve();
+        state.resource_storage_mut::<Health>().unwrap().insert(parent, Health(3));
+        state.resource_storage_mut::<Parent>().unwrap().insert(child, Parent(parent));
+
+        let loaded = SceneState::for_gpus(&[], 0).unwrap();
+        let mut entities = loaded.load_bytes(&state.to_bytes().unwrap()).unwrap();
+        entities.sort_by_key(|entity_id| entity_id.index());
+        assert_eq!(entities, [child, parent]);
+        {
+            let parents = loaded.resource_storage_ref::<Parent>().unwrap();
+            let loaded_parent = parents.get(child).unwrap().0;
+            assert_eq!(loaded_parent, parent);
+            let health = loaded.resource_storage_ref::<Health>().unwrap();
+            assert_eq!(health.get(loaded_parent), Some(&Health(3)));
+        }
+
+        // Entities without an id do not take the indices of the given ones.
+        let fresh = SceneState::for_gpus(&[], 0).unwrap();
+        let entities = fresh
+            .load_json(serde_json::json!({
+                "entities": [{ "Health": 1 }, { "id": 0 }],
+            }))
+            .unwrap();
+        assert_eq!(entities, [EntityId::from_index(1), EntityId::from_index(0)]);
+
+        // Loading the scene again gives its entities new ids and the references are replaced.
+        let reloaded = loaded.load_json(state.save_json().unwrap()).unwrap();
+        assert_eq!(loaded.entity_ids().count(), 4);
+        for reloaded_id in &reloaded {
+            assert!(![child, parent].contains(reloaded_id));
+        }
+        let parents = loaded.resource_storage_ref::<Parent>().unwrap();
+        assert_eq!(parents.get(reloaded[0]).unwrap().0, reloaded[1]);
+        assert_eq!(parents.get(child).unwrap().0, parent);
     }
 
-    pub fn resource_storage(
-        &self,
-        resource_id: ResourceId,
-    ) -> Option<&RwLock<Box<dyn ResourceStorage>>> {
-        return self.state.resources[resource_id.index()].as_ref();
+    #[test]
+    fn large_and_duplicate_entity_ids_are_not_claimed() {
+        Parent::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let large_id = EntityId::from_index(EntityId::MAX_INDEX);
+        let entities = state
+            .load_json(serde_json::json!({
+                "entities": [{ "id": large_id }, { "id": 1, "Parent": large_id }],
+            }))
+            .unwrap();
+        assert_eq!(entities, [EntityId::from_index(0), EntityId::from_index(1)]);
+        assert_eq!(state.entities().read().unwrap().index_bound(), 2);
+        let parents = state.resource_storage_ref::<Parent>().unwrap();
+        assert_eq!(parents.get(entities[1]).unwrap().0, entities[0]);
+        drop(parents);
+
+        let error = state
+            .load_json(serde_json::json!({ "entities": [{ "id": 5 }, { "id": 5 }] }))
+            .unwrap_err();
+        assert_eq!(error.message(), "the id is given twice (entity 1)");
+        assert_eq!(state.entity_ids().count(), 2);
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Template(ResourceId);
+
+    crate::test_resource!(Template, EntityComponent, IdMappedResourceStorage<EntityId, Template>, {
+        fn from_serialized(deserializer: &mut dyn erased_serde::Deserializer) -> Result<Self> {
+            return crate::deserialize_resource(deserializer);
+        }
+    });
+
+    #[test]
+    fn only_entity_references_are_replaced_when_loading() {
+        Parent::register();
+        Template::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let taken = state.entities().write().unwrap().reserve();
+
+        // The entity gets a new id, as the index is in use. The resource id with the same raw
+        // value as the given entity id is kept.
+        let entities = state
+            .load_json(serde_json::json!({
+                "entities": [{ "id": taken, "Parent": taken, "Template": taken }],
+            }))
+            .unwrap();
+        assert_ne!(entities[0], taken);
+        let parents = state.resource_storage_ref::<Parent>().unwrap();
+        assert_eq!(parents.get(entities[0]).unwrap().0, entities[0]);
+        let templates = state.resource_storage_ref::<Template>().unwrap();
+        assert_eq!(templates.get(entities[0]).unwrap().0, ResourceId::from_index(0));
+    }
+
+    #[test]
+    fn out_of_range_entity_ids_are_not_loaded() {
+        Parent::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let out_of_range = 1_u64 << 32;
+
+        let error = state
+            .load_json(serde_json::json!({ "entities": [{ "id": out_of_range }] }))
+            .unwrap_err();
+        assert_eq!(
+            error.message(),
+            "invalid entity id: version 256 exceeds the maximum version 255 (entity 0)"
+        );
+        let error = state
+            .load_json(serde_json::json!({ "entities": [{ "Parent": out_of_range }] }))
+            .unwrap_err();
+        assert!(error.message().contains("version 256 exceeds the maximum version 255"));
+        assert_eq!(state.entity_ids().count(), 0);
+    }
+
+    // A storage with a single buffer per GPU that counts how often its bind group entries are
+    // queried.
+    struct CountingStorage {
+        gpus: Vec<Arc<Gpu>>,
+        buffers: Vec<Arc<wgpu::Buffer>>,
+        buffer_reallocated: AtomicBool,
+        bind_group_entries_calls: AtomicUsize,
+    }
+
+    impl CountingStorage {
+        fn new(gpus: &[Arc<Gpu>]) -> Self {
+            return Self {
+                gpus: gpus.to_vec(),
+                buffers: gpus.iter().map(|gpu| Arc::new(Self::create_buffer(gpu))).collect(),
+                buffer_reallocated: AtomicBool::new(false),
+                bind_group_entries_calls: AtomicUsize::new(0),
+            };
+        }
+
+        fn create_buffer(gpu: &Gpu) -> wgpu::Buffer {
+            return gpu.device().create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: 16,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            });
+        }
+
+        fn reallocate(&mut self) {
+            self.buffers = self
+                .gpus
+                .iter()
+                .map(|gpu| Arc::new(Self::create_buffer(gpu)))
+                .collect();
+            self.buffer_reallocated.store(true, Ordering::Relaxed);
+        }
+    }
+
+    impl ResourceStorage for CountingStorage {
+        fn factory(gpus: &[Arc<Gpu>], _resource_id: ResourceId) -> Box<dyn ResourceStorage> {
+            return Box::new(Self::new(gpus));
+        }
+
+        fn binding_count() -> u32 {
+            return 1;
+        }
+
+        fn reserve_capacity(&mut self, _entity_count: usize) {}
+
+        fn take_uploaded_bytes(&self) -> u64 {
+            return 0;
+        }
+
+        fn take_buffers_reallocated(&self) -> bool {
+            return self.buffer_reallocated.swap(false, Ordering::Relaxed);
+        }
+
+        fn bind_group_layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+            return vec![wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }];
+        }
+
+        fn bind_group_entries(&self, gpu_index: usize) -> Vec<ResourceBindGroupEntry> {
+            self.bind_group_entries_calls.fetch_add(1, Ordering::Relaxed);
+            return vec![ResourceBindGroupEntry::buffer(0, self.buffers[gpu_index].clone())];
+        }
+    }
+
+    #[test]
+    fn only_reallocated_storages_are_queried_for_bind_group_entries() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+
+        let gpus = [gpu];
+        let state = SceneState::from_storages(
+            &gpus,
+            vec![
+                Some(CountingStorage::factory(&gpus, ResourceId::from_index(0))),
+                None,
+                Some(CountingStorage::factory(&gpus, ResourceId::from_index(2))),
+            ],
+            0,
+        )
+        .unwrap();
+        let calls = |index: usize| {
+            return state.resources[index]
+                .as_ref()
+                .unwrap()
+                .read()
+                .unwrap()
+                .downcast_ref::<CountingStorage>()
+                .unwrap()
+                .bind_group_entries_calls
+                .load(Ordering::Relaxed);
+        };
+        assert_eq!((calls(0), calls(2)), (1, 1));
+
+        // Nothing changed, so the bind group is kept.
+        let bind_group = state.resource_bind_groups(0);
+        state.update_resource_bindings();
+        assert!(Arc::ptr_eq(&bind_group, &state.resource_bind_groups(0)));
+        assert_eq!((calls(0), calls(2)), (1, 1));
+
+        state.resources[2]
+            .as_ref()
+            .unwrap()
+            .write()
+            .unwrap()
+            .downcast_mut::<CountingStorage>()
+            .unwrap()
+            .reallocate();
+        state.update_resource_bindings();
+        assert!(!Arc::ptr_eq(&bind_group, &state.resource_bind_groups(0)));
+        assert_eq!((calls(0), calls(2)), (1, 2));
+
+        // Rebuilding queries all storages, even the ones that did not reallocate their buffers.
+        let bind_group = state.resource_bind_groups(0);
+        state.rebuild_resource_bindings();
+        assert!(!Arc::ptr_eq(&bind_group, &state.resource_bind_groups(0)));
+        assert_eq!((calls(0), calls(2)), (2, 3));
+    }
+
+    // A storage with a single buffer on one GPU that can be copied, so tests can read what compute
+    // jobs wrote to it.
+    struct ReadbackStorage {
+        buffer: Arc<wgpu::Buffer>,
+    }
+
+    impl ReadbackStorage {
+        fn new(gpu: &Gpu) -> Self {
+            let buffer = gpu.device().create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: 16,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            return Self {
+                buffer: Arc::new(buffer),
+            };
+        }
+    }
+
+    impl ResourceStorage for ReadbackStorage {
+        fn factory(gpus: &[Arc<Gpu>], _resource_id: ResourceId) -> Box<dyn ResourceStorage> {
+            return Box::new(Self::new(&gpus[0]));
+        }
+
+        fn binding_count() -> u32 {
+            return 1;
+        }
+
+        fn reserve_capacity(&mut self, _entity_count: usize) {}
+
+        fn take_uploaded_bytes(&self) -> u64 {
+            return 0;
+        }
+
+        fn bind_group_layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+            return vec![wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }];
+        }
+
+        fn bind_group_entries(&self, _gpu_index: usize) -> Vec<ResourceBindGroupEntry> {
+            return vec![ResourceBindGroupEntry::buffer(0, self.buffer.clone())];
+        }
+    }
+
+    const WRITE_ODD_NUMBERS: &str = "
+        @group(1) @binding(0)
+        var<storage, read_write> numbers: array<u32>;
+
+        @compute @workgroup_size(1)
+        fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+            numbers[id.x] = id.x * 2u + 1u;
+        }
+    ";
+
+    fn write_odd_numbers_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        return sr.dispatch_compute(3, 1);
+    }
+
+    #[test]
+    fn compute_jobs_write_storage_buffers() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        let storage = ReadbackStorage::new(&gpu);
+        let buffer = storage.buffer.clone();
+        let state = Arc::new(
+            SceneState::from_storages(std::slice::from_ref(&gpu), vec![Some(Box::new(storage))], 0)
+                .unwrap(),
+        );
+        let shader = crate::register_shader(crate::ShaderSource::wgsl(WRITE_ODD_NUMBERS));
+        let mut jobs = IdMap::new();
+        let (_, job) = jobs.insert(Job::new(JobKind::Update, write_odd_numbers_job, &[]));
+        job.set_executed_per_viewport(false);
+        job.set_compute_shader(shader);
+        let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state, 0).unwrap();
+        scheduler.run_jobs(0.0, 1.0).unwrap();
+
+        let readback_buffer = gpu.device().create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: buffer.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = gpu
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_buffer_to_buffer(&buffer, 0, &readback_buffer, 0, buffer.size());
+        gpu.queue().submit(std::iter::once(encoder.finish()));
+        let slice = readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        gpu.device().poll(wgpu::Maintain::Wait);
+        let numbers: Vec<u32> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        // Only the three invocations ran.
+        assert_eq!(numbers, [1, 3, 5, 0]);
+    }
+
+    fn dispatch_without_workgroup_size_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        return sr.dispatch_compute(3, 0);
+    }
+
+    #[test]
+    fn compute_dispatches_fail_for_empty_workgroups() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        let storage = ReadbackStorage::new(&gpu);
+        let state = Arc::new(
+            SceneState::from_storages(std::slice::from_ref(&gpu), vec![Some(Box::new(storage))], 0)
+                .unwrap(),
+        );
+        let shader = crate::register_shader(crate::ShaderSource::wgsl(WRITE_ODD_NUMBERS));
+        let mut jobs = IdMap::new();
+        let (_, job) = jobs.insert(Job::new(
+            JobKind::Update,
+            dispatch_without_workgroup_size_job,
+            &[],
+        ));
+        job.set_executed_per_viewport(false);
+        job.set_compute_shader(shader);
+        let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state, 0).unwrap();
+        let error = scheduler.run_jobs(0.0, 1.0).unwrap_err();
+        assert!(error.message().contains("workgroup size"), "{error}");
+    }
+
+    #[test]
+    fn bindings_are_allocated_contiguously() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+
+        // With a binding stride per resource id, the last resources would exceed the maximum
+        // number of bindings per bind group.
+        let mut storages = Vec::<Option<Box<dyn ResourceStorage>>>::new();
+        storages.resize_with(1000, || None);
+        for index in [0, 500, 998, 999] {
+            storages[index] = Some(Box::new(CountingStorage::new(std::slice::from_ref(&gpu))));
+        }
+        let state = SceneState::from_storages(std::slice::from_ref(&gpu), storages, 0).unwrap();
+        let binding = |index| {
+            let binding = state.resource_binding(ResourceId::from_index(index))?;
+            return Some((binding.group, binding.binding));
+        };
+        assert_eq!(
+            [0, 1, 500, 998, 999].map(binding),
+            [Some((1, 0)), None, Some((1, 1)), Some((1, 2)), Some((1, 3))]
+        );
+
+        // The bind groups are validated against the layout, which fails if the bindings of the
+        // entries differ from the ones of the layout.
+        state.rebuild_resource_bindings();
+    }
+
+    #[test]
+    fn resources_are_split_into_groups_at_the_limit() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+
+        let mut storages = Vec::<Option<Box<dyn ResourceStorage>>>::new();
+        for index in 0..6 {
+            storages.push((index != 1).then(|| {
+                let resource_id = ResourceId::from_index(index);
+                return CountingStorage::factory(std::slice::from_ref(&gpu), resource_id);
+            }));
+        }
+        let limits = BindingLimits {
+            bindings_per_group: 2,
+            ..BindingLimits::of_gpus(std::slice::from_ref(&gpu))
+        };
+        let gpus = std::slice::from_ref(&gpu);
+        let state = SceneState::from_storages_with_limits(gpus, storages, 0, limits).unwrap();
+        let binding = |index| {
+            let binding = state.resource_binding(ResourceId::from_index(index))?;
+            return Some((binding.group, binding.binding));
+        };
+        assert_eq!(
+            [0, 1, 2, 3, 4, 5].map(binding),
+            [Some((1, 0)), None, Some((1, 1)), Some((2, 0)), Some((2, 1)), Some((3, 0))]
+        );
+        assert_eq!(state.resource_bind_group_layouts(0).len(), 3);
+        assert_eq!(state.resource_bind_groups(0).len(), 3);
+
+        // The groups are validated against their layouts, which fails if the entries ended up in
+        // other groups or bindings than the layout entries.
+        state.rebuild_resource_bindings();
+
+        // Pipelines bind all groups after the system bind group.
+        let bind_group_layouts = std::iter::once(gpu.system_bind_group_layout())
+            .chain(state.resource_bind_group_layouts(0))
+            .collect::<Vec<_>>();
+        gpu.device().push_error_scope(wgpu::ErrorFilter::Validation);
+        gpu.device().create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        assert!(pollster::block_on(gpu.device().pop_error_scope()).is_none());
+    }
+
+    #[test]
+    fn scenes_fail_if_the_gpus_cannot_bind_all_resources() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        let storages = || {
+            return (0..4)
+                .map(|index| {
+                    let resource_id = ResourceId::from_index(index);
+                    return Some(CountingStorage::factory(std::slice::from_ref(&gpu), resource_id));
+                })
+                .collect::<Vec<_>>();
+        };
+        let limits = BindingLimits::of_gpus(std::slice::from_ref(&gpu));
+
+        // Splitting the bindings into groups does not raise the number of storage buffers.
+        let limits_with_few_buffers = BindingLimits {
+            bindings_per_group: 2,
+            storage_buffers: 3,
+            ..limits
+        };
+        let state = SceneState::from_storages_with_limits(
+            std::slice::from_ref(&gpu),
+            storages(),
+            0,
+            limits_with_few_buffers,
+        );
+        assert_eq!(
+            state.err().unwrap().message(),
+            "the resources need 4 storage buffers, but the GPUs support 3 per shader stage"
+        );
+
+        let limits_with_few_groups = BindingLimits {
+            bindings_per_group: 1,
+            groups: 3,
+            ..limits
+        };
+        let state = SceneState::from_storages_with_limits(
+            std::slice::from_ref(&gpu),
+            storages(),
+            0,
+            limits_with_few_groups,
+        );
+        assert_eq!(
+            state.err().unwrap().message(),
+            "the resources need 4 bind groups, but the GPUs support 3 besides the system bind group"
+        );
+    }
+
+    #[test]
+    fn waiting_for_gpus_finishes_submitted_work() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        let state = SceneState::from_storages(std::slice::from_ref(&gpu), vec![], 0).unwrap();
+        let buffer = gpu.device().create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 1 << 20,
+            usage: wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = gpu
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.clear_buffer(&buffer, 0, None);
+        gpu.queue().submit(std::iter::once(encoder.finish()));
+        drop(buffer);
+
+        state.wait_for_gpus();
+        // Polling reports whether the queue is empty.
+        assert!(gpu.device().poll(wgpu::Maintain::Poll));
+    }
+
+    #[test]
+    fn only_resized_viewports_are_reconfigured() {
+        let first_viewport = ViewportId::from_index(0);
+        let second_viewport = ViewportId::from_index(1);
+        let mut changes = ViewportChanges::default();
+        changes.add(first_viewport);
+        changes.add(second_viewport);
+        assert_eq!(changes.take().len(), 2);
+
+        // Multiple resizes within a frame result in a single reconfiguration with the last size.
+        changes.resize(second_viewport, PhysicalSize::new(800, 600));
+        changes.set_present_mode(second_viewport, wgpu::PresentMode::Fifo);
+        changes.resize(second_viewport, PhysicalSize::new(1024, 768));
+        // Minimizing a window does not resize its viewport.
+        changes.resize(first_viewport, PhysicalSize::new(0, 0));
+        let reconfigured = changes.take();
+        assert_eq!(reconfigured.len(), 1);
+        assert_eq!(
+            reconfigured[&second_viewport],
+            ViewportChange {
+                added: false,
+                size: Some(PhysicalSize::new(1024, 768)),
+                present_mode: Some(wgpu::PresentMode::Fifo),
+                sample_count: None,
+            }
+        );
+        assert!(changes.take().is_empty());
+    }
+
+    fn clear_viewport_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        return clear_viewport(sr, wgpu::Color::GREEN);
+    }
+
+    fn clear_viewport_to_red_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        return clear_viewport(sr, wgpu::Color::RED);
+    }
+
+    fn clear_viewport(sr: &SystemResources, color: wgpu::Color) -> Result<()> {
+        let Some(view) = sr.viewport().unwrap().texture_view() else {
+            return Ok(());
+        };
+        sr.encoder().begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(color),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn gpu_timestamps_are_non_decreasing() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        let state =
+            Arc::new(SceneState::from_storages(std::slice::from_ref(&gpu), vec![], 0).unwrap());
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let viewport = Viewport::offscreen(gpu.clone(), PhysicalSize::new(4, 4), format, None);
+        let viewport_id = state.viewports().write().unwrap().insert(viewport).0;
+
+        let mut jobs = IdMap::new();
+        let first = jobs.insert(Job::new(JobKind::Update, clear_viewport_job, &[])).0;
+        let (_, second) = jobs.insert(Job::new(JobKind::Update, clear_viewport_job, &[]));
+        second.add_dependency(first);
+        let mut scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 0).unwrap();
+        scheduler.configure_viewport_pipelines(&[viewport_id]).unwrap();
+        let profiles = Arc::new(Mutex::new(Vec::new()));
+        let callback_profiles = profiles.clone();
+        scheduler.start_profiling(Box::new(move |bytes| {
+            let profile = crate::FrameProfile::from_bytes(bytes).unwrap();
+            callback_profiles.lock().unwrap().push(profile);
+        }));
+        // The timestamps of a frame are read back without waiting for the GPU, so they are
+        // usually reported with the profile of the next frame.
+        for frame in 0..2 {
+            state.acquire_viewport_textures().unwrap();
+            scheduler.run_jobs(frame as f32, 1.0).unwrap();
+            state.present_viewports();
+            gpu.device().poll(wgpu::Maintain::Wait);
+        }
+
+        let profiles = profiles.lock().unwrap();
+        assert_eq!(profiles.len(), 2);
+        if !gpu.supports_timestamps() {
+            assert!(profiles.iter().all(|profile| profile.gpu_jobs.is_empty()));
+            return;
+        }
+        let profile = profiles.iter().find(|profile| !profile.gpu_jobs.is_empty()).unwrap();
+        // Contains the jobs of both frames if the GPU finished them before the profile was sent.
+        assert!(profile.gpu_jobs.len() == 2 || profile.gpu_jobs.len() == 4);
+        for gpu_jobs in profile.gpu_jobs.chunks(2) {
+            assert!(gpu_jobs.iter().all(|job| job.start <= job.end));
+            assert!(gpu_jobs[0].end <= gpu_jobs[1].start);
+        }
+    }
+
+    static RENDERED_VIEWPORTS: Mutex<Vec<ViewportId>> = Mutex::new(Vec::new());
+
+    fn record_viewport_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        RENDERED_VIEWPORTS.lock().unwrap().push(sr.viewport_id().unwrap());
+        return Ok(());
+    }
+
+    #[test]
+    fn removed_viewports_are_not_rendered_anymore() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        let state =
+            Arc::new(SceneState::from_storages(std::slice::from_ref(&gpu), vec![], 0).unwrap());
+        let [first, second] = [(); 2].map(|_| {
+            let size = PhysicalSize::new(4, 4);
+            let viewport =
+                Viewport::offscreen(gpu.clone(), size, wgpu::TextureFormat::Rgba8Unorm, None);
+            return state.viewports().write().unwrap().insert(viewport).0;
+        });
+
+        let mut jobs = IdMap::new();
+        jobs.insert(Job::new(JobKind::Update, record_viewport_job, &[]));
+        let mut scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 2).unwrap();
+        crate::run_with_timeout(move || {
+            scheduler.configure_viewport_pipelines(&[first, second]).unwrap();
+            scheduler.run_jobs(0.0, 1.0).unwrap();
+            let mut rendered = std::mem::take(&mut *RENDERED_VIEWPORTS.lock().unwrap());
+            rendered.sort_by_key(|viewport_id| viewport_id.index());
+            assert_eq!(rendered, [first, second]);
+
+            // The frame completes with the per viewport jobs of the remaining viewport.
+            assert!(state.remove_viewport(first));
+            assert!(!state.remove_viewport(first));
+            scheduler.configure_viewport_pipelines(&[first]).unwrap();
+            for _ in 0..2 {
+                scheduler.run_jobs(0.0, 1.0).unwrap();
+            }
+            assert_eq!(*RENDERED_VIEWPORTS.lock().unwrap(), [second, second]);
+            assert_eq!(state.viewports().read().unwrap().len(), 1);
+        });
+    }
+
+    // Draws a white triangle whose hypotenuse runs through the centers of the pixels on the
+    // diagonal from the top left to the bottom right corner of the viewport.
+    const DIAGONAL_SHADER: &str = "
+        @vertex
+        fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+            let x = f32(index & 1u) * 2.0 - 1.0;
+            let y = f32(index >> 1u) * 2.0 - 1.0;
+            return vec4<f32>(x, y, 0.0, 1.0);
+        }
+
+        @fragment
+        fn fs_main() -> @location(0) vec4<f32> {
+            return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+        }
+    ";
+
+    const DIAGONAL_SIZE: u32 = 8;
+
+    fn draw_diagonal_job(sr: &SystemResources, s: &SceneState) -> Result<()> {
+        let viewport = sr.viewport().unwrap();
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+        let Some(color_attachment) = viewport.color_attachment(clear) else {
+            return Ok(());
+        };
+        let resource_bind_groups = s.resource_bind_groups(viewport.gpu().index());
+        let mut encoder = sr.encoder();
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(color_attachment)],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(sr.pipeline().unwrap());
+        render_pass.set_bind_group(0, viewport.gpu().system_bind_group(), &[]);
+        for (index, group) in resource_bind_groups.iter().enumerate() {
+            render_pass.set_bind_group(1 + index as u32, group, &[]);
+        }
+        render_pass.draw(0..3, 0..1);
+        drop(render_pass);
+        return Ok(());
+    }
+
+    // Renders the diagonal with the given number of samples per pixel and returns the red channel
+    // of the pixels.
+    fn render_diagonal(gpu: &Arc<Gpu>, sample_count: u32) -> Vec<u8> {
+        let state =
+            Arc::new(SceneState::from_storages(std::slice::from_ref(gpu), vec![], 0).unwrap());
+        let size = PhysicalSize::new(DIAGONAL_SIZE, DIAGONAL_SIZE);
+        let mut viewport =
+            Viewport::offscreen(gpu.clone(), size, wgpu::TextureFormat::Rgba8Unorm, None);
+        viewport.set_sample_count(sample_count);
+        viewport.configure();
+        assert_eq!(viewport.multisampled_texture().is_some(), sample_count > 1);
+        let viewport_id = state.viewports().write().unwrap().insert(viewport).0;
+
+        let mut jobs = IdMap::new();
+        let mut job = Job::new(JobKind::Update, draw_diagonal_job, &[]);
+        job.set_shader(crate::register_shader(crate::ShaderSource::wgsl(DIAGONAL_SHADER)));
+        jobs.insert(job);
+        let mut scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 0).unwrap();
+        scheduler.configure_viewport_pipelines(&[viewport_id]).unwrap();
+        state.acquire_viewport_textures().unwrap();
+        scheduler.run_jobs(0.0, 1.0).unwrap();
+        state.present_viewports();
+        let pixels = state.read_viewport_pixels(viewport_id).unwrap();
+        return pixels.chunks_exact(4).map(|pixel| pixel[0]).collect();
     }
 
-    pub fn tick(&mut self, delta_time: f32) -> Result<()> {
-        if self.viewports_changed {
-            self.scheduler.configure_pipelines();
+    #[test]
+    fn multisampled_viewports_smooth_diagonal_edges() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        assert!(gpu.supports_sample_count(format, 1));
+        assert!(!gpu.supports_sample_count(format, 3));
+        if !gpu.supports_sample_count(format, 4) {
+            return;
+        }
+
+        // With a single sample, every pixel is either covered or not.
+        let aliased = render_diagonal(&gpu, 1);
+        assert!(aliased.iter().all(|&red| red == 0 || red == 255));
+        assert!(aliased.contains(&0) && aliased.contains(&255));
+
+        // The pixels on the diagonal are partially covered, the others are not affected.
+        let smoothed = render_diagonal(&gpu, 4);
+        for (index, (aliased, smoothed)) in aliased.iter().zip(&smoothed).enumerate() {
+            let (x, y) = (index as u32 % DIAGONAL_SIZE, index as u32 / DIAGONAL_SIZE);
+            if x == y {
+                assert!(*smoothed != 0 && *smoothed != 255);
+            } else {
+                assert_eq!(aliased, smoothed);
+            }
         }
+    }
+
+    #[test]
+    fn offscreen_viewports_are_rendered_without_a_window() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        let state =
+            Arc::new(SceneState::from_storages(std::slice::from_ref(&gpu), vec![], 0).unwrap());
+        let viewport = Viewport::offscreen(
+            gpu.clone(),
+            PhysicalSize::new(64, 1),
+            wgpu::TextureFormat::Rgba8Unorm,
+            None,
+        );
+        assert!(viewport.is_offscreen());
+        assert!(viewport.surface().is_none());
+        let viewport_id = state.viewports().write().unwrap().insert(viewport).0;
+
+        // The same steps as a tick of a scene, which needs a window system for its instance.
+        let mut jobs = IdMap::new();
+        jobs.insert(Job::new(JobKind::Update, clear_viewport_job, &[]));
+        let mut scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 0).unwrap();
+        scheduler.configure_viewport_pipelines(&[viewport_id]).unwrap();
+        state.acquire_viewport_textures().unwrap();
+        scheduler.run_jobs(0.0, 1.0).unwrap();
+        state.present_viewports();
 
-        for (_id, viewport) in &mut *self.viewports().write().unwrap() {
-            let texture = viewport.surface().get_current_texture().unwrap();
-            viewport.texture_view = Some(
-                texture
-                    .texture
-                    .create_view(&wgpu::TextureViewDescriptor::default()),
+        let readback_buffer = gpu.device().create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = gpu
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let viewports = state.viewports().read().unwrap();
+            let texture = viewports.get(viewport_id).unwrap().texture().unwrap();
+            encoder.copy_texture_to_buffer(
+                texture.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &readback_buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: std::num::NonZeroU32::new(256),
+                        rows_per_image: None,
+                    },
+                },
+                texture.size(),
             );
-            viewport.texture = Some(texture);
         }
-        self.game_time += delta_time;
-        let result = self.scheduler.run_jobs(self.game_time, delta_time);
+        gpu.queue().submit(std::iter::once(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        gpu.device().poll(wgpu::Maintain::Wait);
+        assert_eq!(slice.get_mapped_range()[..4], [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn viewport_pixels_are_read_as_rgba_rows() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        // The rows of 3 pixels are padded to 256 bytes by the copy, the channels of BGRA formats
+        // are swapped.
+        let state =
+            Arc::new(SceneState::from_storages(std::slice::from_ref(&gpu), vec![], 0).unwrap());
+        let format = wgpu::TextureFormat::Bgra8Unorm;
+        let viewport = Viewport::offscreen(gpu.clone(), PhysicalSize::new(3, 2), format, None);
+        let viewport_id = state.viewports().write().unwrap().insert(viewport).0;
+
+        let mut jobs = IdMap::new();
+        jobs.insert(Job::new(JobKind::Update, clear_viewport_to_red_job, &[]));
+        let mut scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 0).unwrap();
+        scheduler.configure_viewport_pipelines(&[viewport_id]).unwrap();
+        state.acquire_viewport_textures().unwrap();
+        scheduler.run_jobs(0.0, 1.0).unwrap();
+        state.present_viewports();
 
-        for (_id, viewport) in &mut *self.viewports().write().unwrap() {
-            viewport.texture_view = None;
-            viewport.texture.take().unwrap().present();
+        let pixels = state.read_viewport_pixels(viewport_id).unwrap();
+        assert_eq!(pixels, [255, 0, 0, 255].repeat(3 * 2));
+
+        assert!(state.read_viewport_pixels(ViewportId::from_index(1)).is_err());
+    }
+
+    #[test]
+    fn unsupported_present_modes_fall_back() {
+        use wgpu::PresentMode;
+
+        let all = [
+            PresentMode::Fifo,
+            PresentMode::FifoRelaxed,
+            PresentMode::Immediate,
+            PresentMode::Mailbox,
+        ];
+        for mode in all {
+            assert_eq!(choose_present_mode(mode, &all), mode);
         }
 
-        return result;
+        let fifo_only = [PresentMode::Fifo];
+        for mode in all {
+            assert_eq!(choose_present_mode(mode, &fifo_only), PresentMode::Fifo);
+        }
+        let with_mailbox = [PresentMode::Fifo, PresentMode::Mailbox];
+        let choose = |mode| choose_present_mode(mode, &with_mailbox);
+        assert_eq!(choose(PresentMode::Immediate), PresentMode::Mailbox);
+        assert_eq!(choose(PresentMode::FifoRelaxed), PresentMode::Fifo);
+        assert_eq!(choose(PresentMode::AutoNoVsync), PresentMode::AutoNoVsync);
+    }
+
+    #[test]
+    fn lost_surfaces_are_reconfigured_once() {
+        use wgpu::SurfaceError;
+
+        let recover = recover_from_surface_error;
+        assert_eq!(recover(&SurfaceError::Lost, false), SurfaceRecovery::Reconfigure);
+        assert_eq!(recover(&SurfaceError::Outdated, false), SurfaceRecovery::Reconfigure);
+        assert_eq!(recover(&SurfaceError::Lost, true), SurfaceRecovery::SkipFrame);
+        assert_eq!(recover(&SurfaceError::Outdated, true), SurfaceRecovery::SkipFrame);
+        assert_eq!(recover(&SurfaceError::Timeout, false), SurfaceRecovery::SkipFrame);
+        assert_eq!(recover(&SurfaceError::OutOfMemory, false), SurfaceRecovery::Fail);
     }
 }
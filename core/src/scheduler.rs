@@ -1,16 +1,23 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    any::Any,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    ops::{Deref, DerefMut},
+    panic::{self, AssertUnwindSafe},
     sync::{
-        atomic::{AtomicU32, AtomicUsize},
+        atomic::{AtomicU32, AtomicU64, AtomicUsize},
         mpsc::{self, Sender},
-        Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock,
+        Arc, Condvar, MappedMutexGuard, MappedRwLockReadGuard, Mutex, MutexGuard, PoisonError,
+        RwLock, RwLockReadGuard,
     },
     thread::{self, JoinHandle},
 };
 
 use crate::{
-    EntityDescriptor, EntityId, Error, Instance, JobFunction, JobId, JobKind, ResourceAccess,
-    SceneState, SourceLocation, Viewport, ViewportId,
+    shader_source, Command, Commands, EntityDescriptor, EntityId, Error, Gpu, GpuJobProfile,
+    GpuTimestamps, IdMap, IdStorage, Input, Instance, Job, JobFunction, JobId, JobKind, Mouse,
+    PipelineConfig, ProfileCallback, Profiler, Resource, ResourceAccess, Rng, RunCondition,
+    SceneState, ShaderId, ShaderSource, SourceLocation, Stage, VersionedIndexId, Viewport,
+    ViewportId,
 };
 
 struct SimpleCondvar<T> {
@@ -26,16 +33,11 @@ impl<T> SimpleCondvar<T> {
         };
     }
 
-    fn mutate_and_notify_all<F: Fn(&mut T)>(&self, f: F) {
+    fn mutate_and_notify_all<F: FnOnce(&mut T)>(&self, f: F) {
         f(&mut self.mutex.lock().unwrap());
         self.cond_var.notify_all();
     }
 
-    fn mutate_and_notify_one<F: Fn(&mut T)>(&self, f: F) {
-        f(&mut self.mutex.lock().unwrap());
-        self.cond_var.notify_one();
-    }
-
     fn get_mut(&self) -> Result<MutexGuard<'_, T>, PoisonError<MutexGuard<'_, T>>> {
         self.mutex.lock()
     }
@@ -56,23 +58,118 @@ impl<T> SimpleCondvar<T> {
             guard = self.cond_var.wait(guard).unwrap();
         }
     }
+}
+
+// The command encoder the jobs of a frame record their commands to on a single GPU. It is created
+// when a job needs it for the first time and submitted once all jobs of the frame are finished.
+struct FrameEncoder {
+    gpu: Arc<Gpu>,
+    encoder: Mutex<Option<wgpu::CommandEncoder>>,
+    // None if the GPU does not support timestamp queries.
+    timestamps: Option<GpuTimestamps>,
+}
+
+impl FrameEncoder {
+    fn new(gpu: Arc<Gpu>) -> Self {
+        return Self {
+            timestamps: GpuTimestamps::new(gpu.clone()),
+            gpu,
+            encoder: Mutex::new(None),
+        };
+    }
 
-    fn notify_one(&self) {
-        self.cond_var.notify_one();
+    // Locks the encoder for the job, which is timed on the GPU until the guard is dropped.
+    fn lock(&self, job_index: usize) -> EncoderGuard<'_> {
+        let mut encoder = MutexGuard::map(self.encoder.lock().unwrap(), |encoder| {
+            encoder.get_or_insert_with(|| {
+                self.gpu
+                    .device()
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("Frame"),
+                    })
+            })
+        });
+        let span = self.timestamps.as_ref().and_then(|timestamps| {
+            let end_query = timestamps.begin_span(&mut encoder, job_index)?;
+            return Some((timestamps, end_query));
+        });
+        return EncoderGuard { encoder, span };
     }
 
-    fn notify_all(&self) {
-        self.cond_var.notify_all();
+    fn submit(&self) {
+        if let Some(mut encoder) = self.encoder.lock().unwrap().take() {
+            if let Some(timestamps) = &self.timestamps {
+                timestamps.resolve(&mut encoder);
+            }
+            self.gpu.queue().submit(std::iter::once(encoder.finish()));
+            if let Some(timestamps) = &self.timestamps {
+                timestamps.map();
+            }
+        }
+    }
+
+    // Drops the commands of an aborted frame.
+    fn discard(&self) {
+        self.encoder.lock().unwrap().take();
+        if let Some(timestamps) = &self.timestamps {
+            timestamps.discard();
+        }
+    }
+
+    // The GPU times of the jobs of the submitted frames that are available, see
+    // `GpuTimestamps::read`.
+    fn read_timestamps(&self) -> Vec<GpuJobProfile> {
+        return self
+            .timestamps
+            .as_ref()
+            .map_or_else(Vec::new, GpuTimestamps::read);
+    }
+}
+
+// The command encoder of the frame, locked by a job, see `SystemResources::encoder`.
+pub struct EncoderGuard<'a> {
+    encoder: MappedMutexGuard<'a, wgpu::CommandEncoder>,
+    // The timestamps and the query of the end of the span while profiling.
+    span: Option<(&'a GpuTimestamps, u32)>,
+}
+
+impl Deref for EncoderGuard<'_> {
+    type Target = wgpu::CommandEncoder;
+
+    fn deref(&self) -> &Self::Target {
+        return &self.encoder;
+    }
+}
+
+impl DerefMut for EncoderGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        return &mut self.encoder;
+    }
+}
+
+impl Drop for EncoderGuard<'_> {
+    fn drop(&mut self) {
+        if let Some((timestamps, end_query)) = self.span {
+            timestamps.end_span(&mut self.encoder, end_query);
+        }
     }
 }
 
 pub struct SystemResources<'a> {
     game_time: f32,
     delta_time: f32,
-    entity_spawner: &'a Sender<EntityDescriptor>,
+    fixed_delta_time: f32,
+    interpolation_alpha: f32,
+    frame_id: u32,
     entity_despawner: &'a Sender<EntityId>,
+    commands: Commands<'a>,
+    job_index: usize,
+    viewport_id: Option<ViewportId>,
     viewport: Option<&'a Viewport>,
-    pipeline: Option<&'a wgpu::RenderPipeline>,
+    pipeline: Option<&'a JobPipeline>,
+    compute_pipeline: Option<&'a wgpu::ComputePipeline>,
+    encoders: &'a [FrameEncoder],
+    state: &'a SceneState,
 }
 
 impl SystemResources<'_> {
@@ -84,20 +181,201 @@ impl SystemResources<'_> {
         self.delta_time
     }
 
+    // The length of the steps of the fixed update jobs in seconds. For them, it equals the delta
+    // time, other jobs can use it, e.g., to interpolate between the steps.
+    pub fn fixed_delta_time(&self) -> f32 {
+        return self.fixed_delta_time;
+    }
+
+    // How far the scene is between the last and the next fixed update step, see
+    // `FixedTimestep::interpolation_alpha`. Always 0 for fixed update jobs.
+    pub fn interpolation_alpha(&self) -> f32 {
+        self.interpolation_alpha
+    }
+
+    // The index of the current frame. It increases by one every time the jobs run and is the same
+    // for all jobs of a frame, e.g., to run work every n frames. Wraps around after 2^32 frames.
+    pub fn frame_id(&self) -> u32 {
+        self.frame_id
+    }
+
     pub fn entity_despawner(&self) -> &Sender<EntityId> {
-        &self.entity_despawner
+        self.entity_despawner
+    }
+
+    // Spawns an entity after the frame and returns its id, see `Commands::spawn`.
+    pub fn spawn(&self, entity: EntityDescriptor) -> EntityId {
+        return self.commands.spawn(entity);
+    }
+
+    // Records structural changes that are applied after the frame, see `Commands`.
+    pub fn commands(&self) -> &Commands<'_> {
+        return &self.commands;
+    }
+
+    // The keyboard state of the scene. The keys that were pressed or released since the last frame
+    // are the same for all jobs of the frame.
+    pub fn input(&self) -> RwLockReadGuard<'_, Input> {
+        return self.state.input();
     }
 
-    pub fn entity_spawner(&self) -> &Sender<EntityDescriptor> {
-        &self.entity_spawner
+    // The mouse state of the scene, see `Mouse`. The cursor position of the viewport the job is
+    // executed for is `mouse().cursor_position(viewport_id)`.
+    pub fn mouse(&self) -> RwLockReadGuard<'_, Mouse> {
+        return self.state.mouse();
+    }
+
+    // The random number generator of the scene, see `Rng`. It is locked until the guard is
+    // dropped, so jobs should not hold it while they do other work. Jobs that run in parallel
+    // draw their numbers in the order they get the lock, so the numbers are only reproducible if
+    // the jobs run in a fixed order, e.g., with `Scheduler::run_jobs_deterministic`.
+    pub fn rng(&self) -> MutexGuard<'_, Rng> {
+        return self.state.rng();
     }
 
     pub fn viewport(&self) -> Option<&Viewport> {
         self.viewport
     }
 
+    pub fn viewport_id(&self) -> Option<ViewportId> {
+        return self.viewport_id;
+    }
+
+    // Returns the component of the viewport the job is executed for. Returns None if the job is
+    // not executed per viewport or the viewport does not have the component.
+    pub fn viewport_component<R: Resource>(&self) -> Option<MappedRwLockReadGuard<'_, R>> {
+        return self.state.viewport_component(self.viewport_id?);
+    }
+
+    // The render pipeline of the job for the current viewport. None if the job does not use a
+    // pipeline, see `Job::uses_pipeline`.
     pub fn pipeline(&self) -> Option<&wgpu::RenderPipeline> {
-        self.pipeline
+        self.pipeline.and_then(|pipeline| pipeline.pipeline.as_deref())
+    }
+
+    // The additional color targets of the job for the current viewport, see
+    // `Job::additional_color_targets`. They have the size of the viewport.
+    pub fn additional_color_targets(&self) -> &[wgpu::Texture] {
+        self.pipeline.map_or(&[], |pipeline| &pipeline.color_targets)
+    }
+
+    pub fn additional_color_target_views(&self) -> &[wgpu::TextureView] {
+        self.pipeline.map_or(&[], |pipeline| &pipeline.color_target_views)
+    }
+
+    // The command encoder of the frame for the GPU of the viewport or, for jobs that are not
+    // executed per viewport, the first GPU. All jobs of a frame share the encoder and it is
+    // submitted once after the last job, so the commands of a job are executed after the ones of
+    // its dependencies. The encoder is locked until the returned guard is dropped.
+    pub fn encoder(&self) -> EncoderGuard<'_> {
+        // The GPU of the viewport is one of the GPUs of the scene, so it has an encoder.
+        return self.encoders[self.gpu_index()].lock(self.job_index);
+    }
+
+    // The index of the GPU of the viewport or, for jobs that are not executed per viewport, 0.
+    fn gpu_index(&self) -> usize {
+        return self.viewport.map_or(0, |viewport| viewport.gpu().index());
+    }
+
+    // The compute pipeline of the job for the GPU of the viewport, see `encoder`. None if the job
+    // has no compute shader, see `Job::set_compute_shader`.
+    pub fn compute_pipeline(&self) -> Option<&wgpu::ComputePipeline> {
+        return self.compute_pipeline;
+    }
+
+    // Records a compute pass that runs the compute pipeline of the job for `invocation_count`
+    // invocations, e.g., one per entity. The shader declares `@workgroup_size(workgroup_size)`,
+    // the workgroup count is rounded up, so the shader has to ignore the invocations past the
+    // count. The system bind group and the resource bind groups are bound like for rendering, but
+    // the resource buffers can be written, see `SceneState::compute_resource_bind_groups`. What the
+    // shader writes is not copied back to the storages, and resources that are changed on the CPU
+    // are uploaded again by `update_gpu_buffers`, overwriting the results of the GPU. So a resource
+    // should either be written by compute jobs or on the CPU, not both. Fails if the workgroup size
+    // is 0.
+    pub fn dispatch_compute(
+        &self,
+        invocation_count: u32,
+        workgroup_size: u32,
+    ) -> crate::Result<()> {
+        let Some(pipeline) = self.compute_pipeline else {
+            return Err(Error::new(
+                "the job has no compute shader to dispatch",
+                SourceLocation::here(),
+            ));
+        };
+        if workgroup_size == 0 {
+            return Err(Error::new(
+                "the workgroup size of a compute dispatch must not be 0",
+                SourceLocation::here(),
+            ));
+        }
+        let workgroup_count = invocation_count.div_ceil(workgroup_size);
+        if workgroup_count == 0 {
+            return Ok(());
+        }
+        let gpu_index = self.gpu_index();
+        let gpu = &self.encoders[gpu_index].gpu;
+        let max_workgroup_count = gpu.device().limits().max_compute_workgroups_per_dimension;
+        if workgroup_count > max_workgroup_count {
+            return Err(Error::new(
+                format!(
+                    "{invocation_count} invocations need {workgroup_count} workgroups of size \
+                     {workgroup_size}, but the GPU supports at most {max_workgroup_count}"
+                ),
+                SourceLocation::here(),
+            ));
+        }
+
+        let resource_bind_groups = self.state.compute_resource_bind_groups(gpu_index);
+        let mut encoder = self.encoder_for(gpu_index)?;
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute"),
+        });
+        compute_pass.set_pipeline(pipeline);
+        compute_pass.set_bind_group(0, gpu.system_bind_group(), &[]);
+        for (index, bind_group) in resource_bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(index as u32 + 1, bind_group, &[]);
+        }
+        compute_pass.dispatch_workgroups(workgroup_count, 1, 1);
+        return Ok(());
+    }
+
+    // The command encoder of the frame for the GPU with the given index, see `encoder`. Fails if
+    // the scene has no GPU with the index.
+    pub fn encoder_for(&self, gpu_index: usize) -> crate::Result<EncoderGuard<'_>> {
+        let Some(encoder) = self.encoders.get(gpu_index) else {
+            return Err(Error::new(
+                format!("the scene has no GPU with index {gpu_index}"),
+                SourceLocation::here(),
+            ));
+        };
+        return Ok(encoder.lock(self.job_index));
+    }
+
+    // Emits an event that can be read by all jobs in the next frame.
+    pub fn emit<E: Resource>(&self, event: E) {
+        self.state
+            .resource_storage(E::id())
+            .expect("event is not registered")
+            .read()
+            .unwrap()
+            .as_event::<E>()
+            .expect("resource is not an event")
+            .emit(event);
+    }
+
+    // Returns the events emitted during the previous frame.
+    pub fn read<E: Resource>(&self) -> MappedRwLockReadGuard<'_, [E]> {
+        let storage = self
+            .state
+            .resource_storage(E::id())
+            .expect("event is not registered");
+        return RwLockReadGuard::map(storage.read().unwrap(), |storage| {
+            storage
+                .as_event::<E>()
+                .expect("resource is not an event")
+                .read()
+        });
     }
 }
 
@@ -109,16 +387,437 @@ struct JobState {
     dependencies_finished: AtomicUsize,
     required_for: Vec<usize>,
     executed_per_viewport: bool,
+    exclusive: bool,
     resource_access: Vec<ResourceAccess>,
+    additional_color_targets: Vec<wgpu::TextureFormat>,
+    shader: Option<ShaderId>,
+    uses_pipeline: bool,
+    pipeline_config: PipelineConfig,
+    run_if: Option<RunCondition>,
+    // The compute pipeline of the job for every GPU, indexed by the index of the GPU. Empty if the
+    // job has no compute shader.
+    compute_pipelines: Vec<Arc<wgpu::ComputePipeline>>,
+}
+
+// The render pipeline of a job for a viewport and the textures of its additional color targets.
+// The pipeline is shared with all jobs and viewports that have the same pipeline key.
+struct JobPipeline {
+    pipeline: Option<Arc<wgpu::RenderPipeline>>,
+    color_targets: Vec<wgpu::Texture>,
+    color_target_views: Vec<wgpu::TextureView>,
+}
+
+// Identifies identical pipelines. All pipelines of a scene use the resource bind group layout of
+// their GPU, so it is not part of the key. The config is the one supported by the GPU, so jobs
+// whose configs fall back to the same one share their pipelines.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    gpu_index: usize,
+    shader: Option<ShaderId>,
+    color_target_formats: Vec<wgpu::TextureFormat>,
+    depth_format: Option<wgpu::TextureFormat>,
+    sample_count: u32,
+    config: PipelineConfig,
+}
+
+// Creates every distinct pipeline once. Creating the pipelines per job and viewport would create
+// jobs × viewports pipelines, e.g., 32 for 16 rendering jobs and two viewports, although all of
+// them are identical if the jobs use the default shader and the viewports have the same formats.
+#[derive(Default)]
+struct PipelineCache {
+    pipelines: HashMap<PipelineKey, Arc<wgpu::RenderPipeline>>,
+    // Indexed by the index of the GPU and the compute shader.
+    compute_pipelines: HashMap<(usize, ShaderId), Arc<wgpu::ComputePipeline>>,
+}
+
+impl PipelineCache {
+    fn get_or_create(
+        &mut self,
+        gpu: &Gpu,
+        state: &SceneState,
+        key: PipelineKey,
+    ) -> crate::Result<Arc<wgpu::RenderPipeline>> {
+        if let Some(pipeline) = self.pipelines.get(&key) {
+            return Ok(pipeline.clone());
+        }
+        let resource_bind_group_layouts = state.resource_bind_group_layouts(gpu.index());
+        let pipeline = match key.shader {
+            None => create_render_pipeline(
+                gpu,
+                resource_bind_group_layouts,
+                gpu.shader_module(),
+                (
+                    ShaderSource::DEFAULT_VERTEX_ENTRY_POINT,
+                    ShaderSource::DEFAULT_FRAGMENT_ENTRY_POINT,
+                ),
+                &key,
+            ),
+            Some(shader_id) => {
+                let Some(source) = shader_source(shader_id) else {
+                    return Err(Error::new(
+                        format!("the shader {shader_id} is not registered"),
+                        SourceLocation::here(),
+                    ));
+                };
+                let wgsl = state.resolve_resource_bindings(&source.wgsl)?;
+                let shader_module =
+                    gpu.device().create_shader_module(wgpu::ShaderModuleDescriptor {
+                        label: None,
+                        source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+                    });
+                create_render_pipeline(
+                    gpu,
+                    resource_bind_group_layouts,
+                    &shader_module,
+                    (&source.vertex_entry_point, &source.fragment_entry_point),
+                    &key,
+                )
+            }
+        };
+        let pipeline = Arc::new(pipeline);
+        self.pipelines.insert(key, pipeline.clone());
+        return Ok(pipeline);
+    }
+
+    fn get_or_create_compute(
+        &mut self,
+        gpu: &Gpu,
+        state: &SceneState,
+        shader_id: ShaderId,
+    ) -> crate::Result<Arc<wgpu::ComputePipeline>> {
+        if let Some(pipeline) = self.compute_pipelines.get(&(gpu.index(), shader_id)) {
+            return Ok(pipeline.clone());
+        }
+        let Some(source) = shader_source(shader_id) else {
+            return Err(Error::new(
+                format!("the compute shader {shader_id} is not registered"),
+                SourceLocation::here(),
+            ));
+        };
+        let wgsl = state.resolve_resource_bindings(&source.wgsl)?;
+        let shader_module = gpu
+            .device()
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+            });
+        let pipeline = Arc::new(create_compute_pipeline(
+            gpu,
+            state.compute_resource_bind_group_layouts(gpu.index()),
+            &shader_module,
+            &source.compute_entry_point,
+        ));
+        self.compute_pipelines
+            .insert((gpu.index(), shader_id), pipeline.clone());
+        return Ok(pipeline);
+    }
+
+    fn len(&self) -> usize {
+        return self.pipelines.len();
+    }
 }
 
 struct ScheduledJob {
     job_index: usize,
     viewport_id: Option<ViewportId>,
+    // Whether the run condition of the job did not hold when the frame was scheduled, see
+    // `Scheduler::skipped_jobs`.
+    skipped: bool,
+}
+
+// The jobs that are ready to be executed and the bookkeeping required for exclusive jobs and
+// resource access.
+struct JobQueue {
+    jobs: VecDeque<ScheduledJob>,
+    running_jobs: usize,
+    exclusive_job_running: bool,
+    // The number of running jobs that read a resource and whether a running job writes it, indexed
+    // by the index of the resource id.
+    resource_readers: Vec<usize>,
+    resource_written: Vec<bool>,
+    // Set when a job of the current frame failed. The remaining jobs of the frame are skipped.
+    frame_aborted: bool,
+    // The viewports the per viewport jobs of the current frame run for. They are fixed when the
+    // frame starts, so the number of jobs of the frame does not change if viewports are added or
+    // removed while it runs.
+    viewport_ids: Vec<ViewportId>,
+    // Whether jobs of the current frame are left, the workers return to the pool otherwise.
+    frame_running: bool,
+    // The jobs of the current frame whose run condition did not hold, see
+    // `Scheduler::skipped_jobs`.
+    skipped_jobs: HashSet<(usize, Option<ViewportId>)>,
+}
+
+impl JobQueue {
+    fn new(resource_count: usize) -> Self {
+        return Self {
+            jobs: VecDeque::new(),
+            running_jobs: 0,
+            exclusive_job_running: false,
+            resource_readers: vec![0; resource_count],
+            resource_written: vec![false; resource_count],
+            frame_aborted: false,
+            viewport_ids: Vec::new(),
+            frame_running: false,
+            skipped_jobs: HashSet::new(),
+        };
+    }
+
+    // Enqueues the job once for every viewport of the frame or once if it is not executed per
+    // viewport.
+    fn push_back(&mut self, job_index: usize, executed_per_viewport: bool) {
+        if self.frame_aborted {
+            return;
+        }
+        if executed_per_viewport {
+            for &viewport_id in &self.viewport_ids {
+                self.jobs.push_back(ScheduledJob {
+                    job_index,
+                    viewport_id: Some(viewport_id),
+                    skipped: self.skipped_jobs.contains(&(job_index, Some(viewport_id))),
+                });
+            }
+        } else {
+            self.jobs.push_back(ScheduledJob {
+                job_index,
+                viewport_id: None,
+                skipped: self.skipped_jobs.contains(&(job_index, None)),
+            });
+        }
+    }
+
+    // Enqueues a job whose dependencies are finished. A per viewport job has nothing to run in a
+    // frame without viewports, so it counts as finished right away.
+    fn push_ready(&mut self, job_states: &[JobState], job_index: usize) {
+        let job = &job_states[job_index];
+        if job.executed_per_viewport && self.viewport_ids.is_empty() {
+            self.finish_dependency(job_states, job_index);
+        } else {
+            self.push_back(job_index, job.executed_per_viewport);
+        }
+    }
+
+    // Counts a finished execution of the job for its dependents and enqueues the ones whose
+    // dependencies are all finished. A per viewport dependency finishes once per viewport, or once
+    // if the frame has no viewports, see `push_ready`.
+    fn finish_dependency(&mut self, job_states: &[JobState], job_index: usize) {
+        let executions_per_viewport_job = self.viewport_ids.len().max(1);
+        for &dependent_job_index in &job_states[job_index].required_for {
+            let dependent_job = &job_states[dependent_job_index];
+            let dependency_count = dependent_job.regular_dependency_count
+                + dependent_job.per_viewport_dependency_count * executions_per_viewport_job;
+            if dependent_job
+                .dependencies_finished
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                == dependency_count - 1
+            {
+                self.push_ready(job_states, dependent_job_index);
+            }
+        }
+    }
+
+    fn start_frame(
+        &mut self,
+        viewport_ids: impl Iterator<Item = ViewportId>,
+        skipped_jobs: HashSet<(usize, Option<ViewportId>)>,
+    ) {
+        self.frame_aborted = false;
+        self.frame_running = true;
+        self.viewport_ids.clear();
+        self.viewport_ids.extend(viewport_ids);
+        self.skipped_jobs = skipped_jobs;
+    }
+
+    // Drops the queued jobs of the current frame and ignores jobs that are enqueued for the rest of
+    // the frame.
+    fn abort_frame(&mut self) {
+        self.frame_aborted = true;
+        self.jobs.clear();
+    }
+
+    // A job can start if no running job writes a resource it accesses and, if it writes a
+    // resource, no running job reads it.
+    fn can_start(&self, job: &JobState) -> bool {
+        return job.resource_access.iter().all(|access| {
+            let index = access.resource_id().index();
+            return !self.resource_written[index]
+                && (!access.writes() || self.resource_readers[index] == 0);
+        });
+    }
+
+    // Takes the next job that can be started. An exclusive job at the front of the queue blocks
+    // all other jobs until the running jobs are drained, it is executed alone afterwards. Other
+    // jobs are skipped while their resource access conflicts with a running job, but jobs are
+    // never started before an exclusive job that was queued earlier.
+    fn start_next(&mut self, job_states: &[JobState]) -> Option<ScheduledJob> {
+        // Skipped jobs do not run, so they can finish at any time.
+        if let Some(position) = self.jobs.iter().position(|job| job.skipped) {
+            self.running_jobs += 1;
+            return self.jobs.remove(position);
+        }
+        if self.exclusive_job_running {
+            return None;
+        }
+        if job_states[self.jobs.front()?.job_index].exclusive {
+            if self.running_jobs > 0 {
+                return None;
+            }
+            self.running_jobs += 1;
+            self.exclusive_job_running = true;
+            return self.jobs.pop_front();
+        }
+
+        let position = self
+            .jobs
+            .iter()
+            .take_while(|job| !job_states[job.job_index].exclusive)
+            .position(|job| self.can_start(&job_states[job.job_index]))?;
+        let job = self.jobs.remove(position)?;
+        for access in &job_states[job.job_index].resource_access {
+            let index = access.resource_id().index();
+            if access.writes() {
+                self.resource_written[index] = true;
+            } else {
+                self.resource_readers[index] += 1;
+            }
+        }
+        self.running_jobs += 1;
+        return Some(job);
+    }
+
+    fn finish(&mut self, scheduled_job: &ScheduledJob, job: &JobState) {
+        self.running_jobs -= 1;
+        if scheduled_job.skipped {
+            return;
+        }
+        if job.exclusive {
+            self.exclusive_job_running = false;
+            return;
+        }
+        for access in &job.resource_access {
+            let index = access.resource_id().index();
+            if access.writes() {
+                self.resource_written[index] = false;
+            } else {
+                self.resource_readers[index] -= 1;
+            }
+        }
+    }
+
+    // Whether every job of the frame finished or the frame was aborted and its running jobs are
+    // drained.
+    fn frame_finished(&self) -> bool {
+        return !self.frame_running || (self.frame_aborted && self.running_jobs == 0);
+    }
+}
+
+// The function the workers of a pool run for a frame, called with the index of the worker.
+type WorkerFunction = Arc<dyn Fn(usize) + Send + Sync>;
+
+struct WorkerPoolState {
+    function: Option<WorkerFunction>,
+    // Incremented for every run, so every worker calls the function once per run.
+    run: u64,
+    running_workers: usize,
+    shutdown: bool,
+}
+
+// The worker threads of one or more schedulers. The schedulers of a scene never run their frames
+// at the same time, so they share the threads instead of spawning their own.
+struct WorkerPool {
+    state: Arc<SimpleCondvar<WorkerPoolState>>,
+    threads: Vec<JoinHandle<()>>,
+    // The core each worker thread is pinned to, None if it is not pinned.
+    cores: Vec<Option<usize>>,
+    // Held while the workers run a function, so schedulers that share the pool run their frames
+    // one after another.
+    run_lock: Mutex<()>,
+}
+
+impl WorkerPool {
+    fn new(worker_count: usize) -> Arc<Self> {
+        let state = Arc::new(SimpleCondvar::new(WorkerPoolState {
+            function: None,
+            run: 0,
+            running_workers: 0,
+            shutdown: false,
+        }));
+        let (core_sender, core_receiver) = mpsc::channel::<(usize, Option<usize>)>();
+        let threads = (0..worker_count)
+            .map(|i| {
+                let state = state.clone();
+                let core_sender = core_sender.clone();
+                return thread::spawn(move || {
+                    trace_debug!(worker = i, "spawned worker");
+                    core_sender
+                        .send((i, pin_worker_thread(i)))
+                        .expect("channel send failure");
+
+                    let mut last_run = 0;
+                    loop {
+                        let function = state.wait_mut(|state| {
+                            if state.shutdown {
+                                return Some(None);
+                            }
+                            if state.run == last_run {
+                                return None;
+                            }
+                            last_run = state.run;
+                            return Some(state.function.clone());
+                        });
+                        let Some(function) = function else {
+                            break;
+                        };
+                        function(i);
+                        state.mutate_and_notify_all(|state| state.running_workers -= 1);
+                    }
+                });
+            })
+            .collect::<Vec<_>>();
+
+        let mut cores = vec![None; worker_count];
+        for (worker_index, core) in core_receiver.iter().take(worker_count) {
+            cores[worker_index] = core;
+        }
+        return Arc::new(Self {
+            state,
+            threads,
+            cores,
+            run_lock: Mutex::new(()),
+        });
+    }
+
+    fn worker_count(&self) -> usize {
+        return self.threads.len();
+    }
+
+    // Calls the function on every worker and returns once all of them returned from it.
+    fn run(&self, function: &WorkerFunction) {
+        let _run_guard = self.run_lock.lock().unwrap();
+        self.state.mutate_and_notify_all(|state| {
+            state.function = Some(function.clone());
+            state.run += 1;
+            state.running_workers = self.threads.len();
+        });
+        self.state.wait(|state| state.running_workers == 0);
+        self.state.get_mut().unwrap().function = None;
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.state.mutate_and_notify_all(|state| state.shutdown = true);
+        for thread in self.threads.drain(..) {
+            // A worker that panicked already reported the panic, there is nothing left to do.
+            thread.join().ok();
+        }
+    }
 }
 
 pub struct Scheduler {
-    worker: Vec<JoinHandle<()>>,
+    workers: Arc<WorkerPool>,
+    // Executes the jobs of a frame on a worker until the frame is finished.
+    worker_function: WorkerFunction,
     state: Arc<SceneState>,
 
     // The state of all jobs.
@@ -128,41 +827,393 @@ pub struct Scheduler {
     jobs_without_dependencies: Vec<usize>,
 
     // The jobs that are available for executing
-    available_jobs: Arc<SimpleCondvar<VecDeque<ScheduledJob>>>,
+    available_jobs: Arc<SimpleCondvar<JobQueue>>,
 
+    // The jobs of the current frame that finished, including the skipped ones, see `jobs_executed`.
     jobs_finished: Arc<AtomicUsize>,
-    frame_finished_receiver: mpsc::Receiver<crate::Result<()>>,
+    jobs_skipped: Arc<AtomicUsize>,
+    // The result of the current frame, set by the worker that finishes the last job or by the
+    // first job that fails.
+    frame_result: Arc<SimpleCondvar<Option<crate::Result<()>>>>,
 
     delta_time: Arc<AtomicU32>,
     game_time: Arc<AtomicU32>,
-    spawned_entities_receiver: mpsc::Receiver<EntityDescriptor>,
+    fixed_delta_time: Arc<AtomicU32>,
+    interpolation_alpha: Arc<AtomicU32>,
+    frame_id: Arc<AtomicU32>,
+    despawned_entities_sender: Sender<EntityId>,
     despawned_entities_receiver: mpsc::Receiver<EntityId>,
+    // The commands recorded by the jobs of the current frame. Every worker has its own queue, the
+    // last one is used by jobs that run on the calling thread.
+    command_queues: Arc<Vec<Mutex<Vec<Command>>>>,
+
+    pipelines: Arc<RwLock<HashMap<(usize, ViewportId), JobPipeline>>>,
+    pipeline_cache: PipelineCache,
+    // The command encoders of the current frame, indexed by the index of the GPU.
+    encoders: Arc<Vec<FrameEncoder>>,
+
+    // The index of the next frame and the listeners that are notified when a frame finished.
+    frame: AtomicU64,
+    frame_listeners: Mutex<Vec<FrameListener>>,
+
+    // Records the jobs of every frame while a profile callback is set, see `start_profiling`.
+    profiler: Arc<Profiler>,
+    profile_callback: Mutex<Option<ProfileCallback>>,
+}
+
+// The length of the steps of the fixed update jobs unless the scene configures another one.
+pub const DEFAULT_FIXED_DELTA_TIME: f32 = 1.0 / 60.0;
+
+// Called with the index and the result of every frame after it finished, including the spawning
+// and despawning of entities.
+pub type FrameListener = Box<dyn FnMut(u64, &crate::Result<()>) + Send>;
+
+// Pins the calling worker thread to a core. The workers are distributed round-robin over the
+// available cores. Returns the id of the core or None if the thread could not be pinned.
+#[cfg(feature = "thread-affinity")]
+fn pin_worker_thread(worker_index: usize) -> Option<usize> {
+    let core_ids = core_affinity::get_core_ids()?;
+    if core_ids.is_empty() {
+        return None;
+    }
+    let core_id = core_ids[worker_index % core_ids.len()];
+    return core_affinity::set_for_current(core_id).then_some(core_id.id);
+}
+
+#[cfg(not(feature = "thread-affinity"))]
+fn pin_worker_thread(_worker_index: usize) -> Option<usize> {
+    return None;
+}
+
+// Creates the render pipeline of a job that renders to color targets of the formats of the key.
+// The entry points are given as (vertex, fragment) and the fragment shader writes the color target
+// i to location i. With a depth format, fragments that are not closer than the depth stored in
+// the depth buffer are discarded. The sample count has to match the one of the attachments, see
+// `Viewport::sample_count`. The config has to be supported by the GPU, see
+// `PipelineConfig::supported_by`. The shader of the key is ignored, the module is passed instead.
+fn create_render_pipeline(
+    gpu: &Gpu,
+    resource_bind_group_layouts: &[wgpu::BindGroupLayout],
+    shader_module: &wgpu::ShaderModule,
+    (vertex_entry_point, fragment_entry_point): (&str, &str),
+    key: &PipelineKey,
+) -> wgpu::RenderPipeline {
+    let PipelineKey {
+        color_target_formats,
+        depth_format,
+        sample_count,
+        config,
+        ..
+    } = key;
+    let bind_group_layouts = std::iter::once(gpu.system_bind_group_layout())
+        .chain(resource_bind_group_layouts)
+        .collect::<Vec<_>>();
+    let render_pipeline_layout =
+        gpu.device()
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+    let targets = color_target_formats
+        .iter()
+        .map(|&format| {
+            return Some(wgpu::ColorTargetState {
+                format,
+                blend: config.blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            });
+        })
+        .collect::<Vec<_>>();
+
+    return gpu
+        .device()
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader_module,
+                entry_point: vertex_entry_point,
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader_module,
+                entry_point: fragment_entry_point,
+                targets: &targets,
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: config.topology,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: config.cull_mode,
+                polygon_mode: config.polygon_mode,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: *sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+}
+
+// Creates the compute pipeline of a job. Like render pipelines, it uses the system bind group
+// layout followed by the resource bind group layouts, but the resource buffers are writable.
+fn create_compute_pipeline(
+    gpu: &Gpu,
+    resource_bind_group_layouts: &[wgpu::BindGroupLayout],
+    shader_module: &wgpu::ShaderModule,
+    entry_point: &str,
+) -> wgpu::ComputePipeline {
+    let bind_group_layouts = std::iter::once(gpu.system_bind_group_layout())
+        .chain(resource_bind_group_layouts)
+        .collect::<Vec<_>>();
+    let compute_pipeline_layout =
+        gpu.device()
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Compute Pipeline Layout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+    return gpu
+        .device()
+        .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: shader_module,
+            entry_point,
+        });
+}
+
+// Converts the payload of a panic inside a job into an error.
+fn job_panic_error(job_id: JobId, payload: Box<dyn Any + Send>) -> Error {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    };
+    return Error::new(
+        format!("job {job_id} panicked: {message}"),
+        SourceLocation::here(),
+    );
+}
+
+// Makes the job wait for the dependency. Adding the same dependency again has no effect.
+fn add_job_state_dependency(jobs: &mut [JobState], job_index: usize, dependency_index: usize) {
+    if jobs[dependency_index].required_for.contains(&job_index) {
+        return;
+    }
+    if jobs[dependency_index].executed_per_viewport {
+        jobs[job_index].per_viewport_dependency_count += 1;
+    } else {
+        jobs[job_index].regular_dependency_count += 1;
+    }
+    jobs[dependency_index].required_for.push(job_index);
+}
+
+// Executes the job and converts a panic inside of it into an error.
+fn execute_job(
+    job: &JobState,
+    system_resources: &SystemResources,
+    state: &SceneState,
+) -> crate::Result<()> {
+    let _span = trace_span!("job", job_id = %job.id, viewport_id = ?system_resources.viewport_id);
+    return panic::catch_unwind(AssertUnwindSafe(|| (job.function)(system_resources, state)))
+        .unwrap_or_else(|payload| Err(job_panic_error(job.id, payload)));
+}
+
+// Checks the run condition of the job and converts a panic inside of it into an error. Jobs
+// without a condition always run.
+fn job_should_run(
+    job: &JobState,
+    system_resources: &SystemResources,
+    state: &SceneState,
+) -> crate::Result<bool> {
+    let Some(run_if) = job.run_if else {
+        return Ok(true);
+    };
+    return panic::catch_unwind(AssertUnwindSafe(|| run_if(system_resources, state)))
+        .map_err(|payload| job_panic_error(job.id, payload));
+}
+
+// Returns an error that names the jobs of a cycle if the dependencies of the jobs contain one. The
+// jobs of a cycle would wait for each other forever.
+fn check_dependency_cycles(jobs: &[JobState]) -> crate::Result<()> {
+    let mut dependencies = vec![Vec::new(); jobs.len()];
+    for (job_index, job) in jobs.iter().enumerate() {
+        for &dependent_job_index in &job.required_for {
+            dependencies[dependent_job_index].push(job_index);
+        }
+    }
+
+    // Repeatedly remove the jobs whose dependencies were all removed. The jobs that remain are part
+    // of a cycle or depend on one.
+    let mut remaining_dependencies = dependencies.iter().map(Vec::len).collect::<Vec<_>>();
+    let mut removable_jobs = (0..jobs.len())
+        .filter(|&job_index| remaining_dependencies[job_index] == 0)
+        .collect::<Vec<_>>();
+    while let Some(job_index) = removable_jobs.pop() {
+        for &dependent_job_index in &jobs[job_index].required_for {
+            remaining_dependencies[dependent_job_index] -= 1;
+            if remaining_dependencies[dependent_job_index] == 0 {
+                removable_jobs.push(dependent_job_index);
+            }
+        }
+    }
+
+    let Some(mut job_index) = (0..jobs.len()).find(|&i| remaining_dependencies[i] > 0) else {
+        return Ok(());
+    };
+
+    // Every remaining job has a remaining dependency, following them eventually revisits a job.
+    let mut path = Vec::new();
+    while !path.contains(&job_index) {
+        path.push(job_index);
+        job_index = *dependencies[job_index]
+            .iter()
+            .find(|&&dependency| remaining_dependencies[dependency] > 0)
+            .unwrap();
+    }
+    let cycle_start = path.iter().position(|&i| i == job_index).unwrap();
+    let cycle = path[cycle_start..]
+        .iter()
+        .map(|&i| jobs[i].id.to_string())
+        .collect::<Vec<_>>();
+
+    return Err(Error::new(
+        format!("the jobs {} form a dependency cycle", cycle.join(", ")),
+        SourceLocation::here(),
+    ));
+}
+
+// Returns an error if two jobs write the same resource without one of them depending on the
+// other, directly or transitively. The scheduler would still run them one after another, but
+// their order would change from frame to frame.
+fn check_unordered_writes(jobs: &[JobState]) -> crate::Result<()> {
+    // reachable[i][j] is true if job j (transitively) depends on job i.
+    let mut reachable = vec![vec![false; jobs.len()]; jobs.len()];
+    for (job_index, reachable_jobs) in reachable.iter_mut().enumerate() {
+        let mut stack = jobs[job_index].required_for.clone();
+        while let Some(dependent_job_index) = stack.pop() {
+            if !reachable_jobs[dependent_job_index] {
+                reachable_jobs[dependent_job_index] = true;
+                stack.extend(&jobs[dependent_job_index].required_for);
+            }
+        }
+    }
+
+    for (first_index, first_job) in jobs.iter().enumerate() {
+        for (second_index, second_job) in jobs.iter().enumerate().skip(first_index + 1) {
+            if reachable[first_index][second_index] || reachable[second_index][first_index] {
+                continue;
+            }
+            for first_access in first_job.resource_access.iter().filter(|a| a.writes()) {
+                if second_job
+                    .resource_access
+                    .iter()
+                    .any(|a| a.writes() && a.resource_id() == first_access.resource_id())
+                {
+                    return Err(Error::new(
+                        format!(
+                            "jobs {} and {} both write resource {} but neither depends on the other",
+                            first_job.id,
+                            second_job.id,
+                            first_access.resource_id()
+                        ),
+                        SourceLocation::here(),
+                    ));
+                }
+            }
+        }
+    }
 
-    pipelines: Arc<RwLock<HashMap<(usize, ViewportId), wgpu::RenderPipeline>>>,
+    return Ok(());
 }
 
 impl Scheduler {
+    // Creates a scheduler for the registered jobs of the given kind. Jobs run as soon as their
+    // dependencies are finished, except that a job is held back while a running job accesses one
+    // of its resources and one of the two writes it. Jobs that only read a resource run
+    // concurrently. As the order of two writers of the same resource would otherwise depend on
+    // timing, they must be ordered by a dependency, otherwise an error is returned. Dependency
+    // cycles and dependencies on jobs of another kind are rejected as well. The jobs of a stage
+    // depend on all jobs of the stages before it, see `Stage`.
     pub fn new(
         _instance: &Instance,
         kind: JobKind,
         state: Arc<SceneState>,
         worker_count: usize,
-    ) -> Self {
-        let mut worker: Vec<JoinHandle<()>> = Vec::with_capacity(worker_count);
+    ) -> crate::Result<Self> {
+        return Self::headless(kind, state, worker_count);
+    }
+
+    // Creates a scheduler for the registered jobs without an instance, e.g., to run the jobs of a
+    // scene without viewports on a server.
+    pub fn headless(
+        kind: JobKind,
+        state: Arc<SceneState>,
+        worker_count: usize,
+    ) -> crate::Result<Self> {
+        return Self::from_jobs(&crate::jobs(), kind, state, worker_count);
+    }
+
+    // Creates a scheduler for the jobs of the given kind from the map instead of the registered
+    // jobs.
+    pub(crate) fn from_jobs(
+        registered_jobs: &IdMap<JobId, Job>,
+        kind: JobKind,
+        state: Arc<SceneState>,
+        worker_count: usize,
+    ) -> crate::Result<Self> {
+        return Self::with_workers(registered_jobs, kind, state, WorkerPool::new(worker_count));
+    }
+
+    // Creates a scheduler for the registered jobs of the given kind that executes them on the
+    // workers of this scheduler, e.g., for the fixed update jobs of a scene. Schedulers that share
+    // their workers run their frames one after another.
+    pub fn with_shared_workers(&self, kind: JobKind) -> crate::Result<Self> {
+        return Self::with_workers(&crate::jobs(), kind, self.state.clone(), self.workers.clone());
+    }
 
+    fn with_workers(
+        registered_jobs: &IdMap<JobId, Job>,
+        kind: JobKind,
+        state: Arc<SceneState>,
+        workers: Arc<WorkerPool>,
+    ) -> crate::Result<Self> {
+        let worker_count = workers.worker_count();
         let mut jobs = Vec::<JobState>::new();
-        let mut jobs_without_dependencies = Vec::<usize>::new();
+        let mut job_stages = Vec::<(Stage, usize)>::new();
         let mut job_state_indices = HashMap::<JobId, usize>::new();
 
         let mut regular_job_count = 0_usize;
         let mut per_viewport_job_count = 0_usize;
+        let mut pipeline_cache = PipelineCache::default();
 
-        for (job_id, job) in crate::jobs()
+        for (job_id, job) in registered_jobs
             .into_iter()
             .filter(|(_, job)| job.kind() == kind)
         {
             let job_index = jobs.len();
             job_state_indices.insert(job_id, job_index);
+            let compute_pipelines = match job.compute_shader() {
+                Some(shader_id) => state
+                    .gpus()
+                    .map(|gpu| pipeline_cache.get_or_create_compute(gpu, &state, shader_id))
+                    .collect::<crate::Result<Vec<_>>>()?,
+                None => Vec::new(),
+            };
             jobs.push(JobState {
                 id: job_id,
                 function: job.function(),
@@ -170,64 +1221,137 @@ impl Scheduler {
                 per_viewport_dependency_count: 0,
                 dependencies_finished: AtomicUsize::new(0),
                 required_for: vec![],
-                executed_per_viewport: true,
+                executed_per_viewport: job.executed_per_viewport(),
+                exclusive: job.exclusive(),
                 resource_access: job.resource_access().to_vec(),
+                additional_color_targets: job.additional_color_targets().to_vec(),
+                shader: job.shader(),
+                uses_pipeline: job.uses_pipeline(),
+                pipeline_config: job.pipeline_config(),
+                run_if: job.run_if(),
+                compute_pipelines,
             });
-            per_viewport_job_count += 1;
-            if job.dependencies().len() == 0 {
-                jobs_without_dependencies.push(job_index);
+            if job.executed_per_viewport() {
+                per_viewport_job_count += 1;
+            } else {
+                regular_job_count += 1;
+            }
+            if let Some(stage) = job.stage() {
+                job_stages.push((stage, job_index));
             }
         }
 
-        for (job_id, job) in crate::jobs()
+        for (job_id, job) in registered_jobs
             .into_iter()
             .filter(|(_, job)| job.kind() == kind)
         {
             for dependency in job.dependencies() {
-                if jobs[job_state_indices[dependency]].executed_per_viewport {
-                    jobs[job_state_indices[&job_id]].per_viewport_dependency_count += 1;
-                } else {
-                    jobs[job_state_indices[&job_id]].regular_dependency_count += 1;
+                if !job_state_indices.contains_key(dependency) {
+                    let message = if registered_jobs.contains(*dependency) {
+                        format!("job {job_id} depends on job {dependency} of a different kind")
+                    } else {
+                        format!("job {job_id} depends on the unknown job {dependency}")
+                    };
+                    return Err(Error::new(message, SourceLocation::here()));
                 }
 
-                jobs[job_state_indices[dependency]]
-                    .required_for
-                    .push(job_state_indices[&job_id]);
+                add_job_state_dependency(
+                    &mut jobs,
+                    job_state_indices[&job_id],
+                    job_state_indices[dependency],
+                );
+            }
+        }
+
+        // Every job of a stage depends on all jobs of the previous stage that has jobs.
+        job_stages.sort();
+        let stages = job_stages
+            .chunk_by(|(first, _), (second, _)| first == second)
+            .collect::<Vec<_>>();
+        for stage_pair in stages.windows(2) {
+            for &(_, job_index) in stage_pair[1] {
+                for &(_, dependency_index) in stage_pair[0] {
+                    add_job_state_dependency(&mut jobs, job_index, dependency_index);
+                }
             }
         }
+        let jobs_without_dependencies = (0..jobs.len())
+            .filter(|&job_index| {
+                let job = &jobs[job_index];
+                return job.regular_dependency_count + job.per_viewport_dependency_count == 0;
+            })
+            .collect::<Vec<_>>();
+
+        check_dependency_cycles(&jobs)?;
+        check_unordered_writes(&jobs)?;
+        let resource_count = jobs
+            .iter()
+            .flat_map(|job| &job.resource_access)
+            .map(|access| access.resource_id().index() + 1)
+            .max()
+            .unwrap_or(0);
 
         let jobs = Arc::new(jobs);
         let pipelines = Arc::new(RwLock::new(HashMap::new()));
-        let available_jobs = Arc::new(SimpleCondvar::new(VecDeque::<ScheduledJob>::new()));
+        let encoders = Arc::new(
+            state
+                .gpus()
+                .map(|gpu| FrameEncoder::new(gpu.clone()))
+                .collect::<Vec<_>>(),
+        );
+        let available_jobs = Arc::new(SimpleCondvar::new(JobQueue::new(resource_count)));
         let jobs_finished = Arc::new(AtomicUsize::new(0));
+        let jobs_skipped = Arc::new(AtomicUsize::new(0));
         let game_time = Arc::new(AtomicU32::new(0));
         let delta_time = Arc::new(AtomicU32::new(0));
-        let (frame_finished_sender, frame_finished_receiver) = mpsc::channel::<crate::Result<()>>();
-        let (spawned_entities_sender, spawned_entities_receiver) =
-            mpsc::channel::<EntityDescriptor>();
+        let fixed_delta_time = Arc::new(AtomicU32::new(DEFAULT_FIXED_DELTA_TIME.to_bits()));
+        let interpolation_alpha = Arc::new(AtomicU32::new(0.0f32.to_bits()));
+        let frame_id = Arc::new(AtomicU32::new(0));
+        let frame_result = Arc::new(SimpleCondvar::new(None));
         let (despawned_entities_sender, despawned_entities_receiver) = mpsc::channel::<EntityId>();
+        let command_queues = Arc::new(
+            (0..worker_count + 1)
+                .map(|_| Mutex::new(Vec::new()))
+                .collect::<Vec<_>>(),
+        );
+        let profiler = Arc::new(Profiler::new(worker_count));
 
-        for i in 0..worker_count {
+        let worker_function: WorkerFunction = {
             let jobs = jobs.clone();
             let state = state.clone();
             let available_jobs = available_jobs.clone();
             let jobs_finished = jobs_finished.clone();
+            let jobs_skipped = jobs_skipped.clone();
             let game_time = game_time.clone();
             let delta_time = delta_time.clone();
-            let frame_finished_sender = frame_finished_sender.clone();
-            let spawned_entities_sender = spawned_entities_sender.clone();
+            let fixed_delta_time = fixed_delta_time.clone();
+            let interpolation_alpha = interpolation_alpha.clone();
+            let frame_id = frame_id.clone();
+            let frame_result = frame_result.clone();
             let despawned_entities_sender = despawned_entities_sender.clone();
+            let command_queues = command_queues.clone();
             let pipelines = pipelines.clone();
+            let encoders = encoders.clone();
+            let profiler = profiler.clone();
 
-            worker.push(thread::spawn(move || {
-                println!("[{i}]: spawned");
-
+            Arc::new(move |i| {
                 loop {
-                    let scheduled_job = available_jobs.wait_mut(|jobs| jobs.pop_front());
-                    let viewports = state.viewports().read().unwrap();
+                    let scheduled_job = available_jobs.wait_mut(|queue| {
+                        if queue.frame_finished() {
+                            return Some(None);
+                        }
+                        return queue.start_next(&jobs).map(Some);
+                    });
+                    let Some(scheduled_job) = scheduled_job else {
+                        break;
+                    };
+                    let viewports = state.viewports().read().unwrap();
                     let job_index = scheduled_job.job_index;
                     let viewport_id = scheduled_job.viewport_id;
                     let pipelines = pipelines.read().unwrap();
+                    // A viewport that was removed during the frame is skipped, but its jobs still
+                    // count as finished.
+                    let viewport = viewport_id.map(|id| viewports.get(id));
 
                     let system_resources = SystemResources {
                         game_time: f32::from_ne_bytes(
@@ -240,168 +1364,537 @@ impl Scheduler {
                                 .load(std::sync::atomic::Ordering::Relaxed)
                                 .to_ne_bytes(),
                         ),
-                        entity_spawner: &spawned_entities_sender,
+                        fixed_delta_time: f32::from_bits(
+                            fixed_delta_time.load(std::sync::atomic::Ordering::Relaxed),
+                        ),
+                        interpolation_alpha: f32::from_bits(
+                            interpolation_alpha.load(std::sync::atomic::Ordering::Relaxed),
+                        ),
+                        frame_id: frame_id.load(std::sync::atomic::Ordering::Relaxed),
                         entity_despawner: &despawned_entities_sender,
-                        viewport: scheduled_job
-                            .viewport_id
-                            .map(|id| viewports.get(id).unwrap()),
+                        commands: Commands::new(&command_queues[i], state.entities()),
+                        job_index,
+                        viewport_id,
+                        viewport: viewport.flatten(),
                         pipeline: viewport_id.and_then(|id| pipelines.get(&(job_index, id))),
+                        compute_pipeline: jobs[job_index]
+                            .compute_pipelines
+                            .get(viewport.flatten().map_or(0, |viewport| viewport.gpu().index()))
+                            .map(Arc::as_ref),
+                        encoders: &encoders,
+                        state: &state,
                     };
 
-                    if let Some(viewport_id) = viewport_id {
-                        println!("[{i}]: executing job {job_index} for {viewport_id}");
-                        // system_resources.viewport = Some(viewports.get(viewport_id).unwrap());
-                    } else {
-                        println!("[{i}]: executing job {job_index}");
+                    let job = &jobs[job_index];
+                    let result = match viewport {
+                        _ if scheduled_job.skipped => Ok(false),
+                        Some(None) => Ok(false),
+                        _ => {
+                            let start = profiler.now();
+                            let result = execute_job(job, &system_resources, &state);
+                            if let Some(start) = start {
+                                profiler.record_job(job_index, Some(i), start);
+                            }
+                            result.map(|()| true)
+                        }
+                    };
+                    if let Ok(false) = result {
+                        jobs_skipped.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                     }
 
-                    let job = &jobs[job_index];
-                    if let Err(error) = (job.function)(&system_resources, &state) {
-                        frame_finished_sender
-                            .send(Err(error))
-                            .expect("channel send failure");
-                    } else {
+                    // Finishing the job and enqueuing its dependents happens atomically. Thus, when
+                    // a frame is aborted, no worker can enqueue jobs of the aborted frame anymore
+                    // once all running jobs are finished.
+                    available_jobs.mutate_and_notify_all(|queue| {
+                        queue.finish(&scheduled_job, job);
+
+                        if let Err(error) = result {
+                            frame_result.mutate_and_notify_all(|frame_result| {
+                                frame_result.get_or_insert(Err(error));
+                            });
+                            queue.abort_frame();
+                            return;
+                        }
+
+                        let viewport_count = queue.viewport_ids.len();
                         let completed_jobs =
                             jobs_finished.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
                         if completed_jobs
-                            == regular_job_count
-                                + per_viewport_job_count * state.viewports().read().unwrap().len()
+                            == regular_job_count + per_viewport_job_count * viewport_count
                         {
-                            frame_finished_sender
-                                .send(Ok(()))
-                                .expect("channel send failure");
-                        } else {
-                            for dependent_job_index in &job.required_for {
-                                let dependent_job = &jobs[*dependent_job_index];
-                                if dependent_job
-                                    .dependencies_finished
-                                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
-                                    == (dependent_job.regular_dependency_count
-                                        + dependent_job.per_viewport_dependency_count
-                                            * state.viewports().read().unwrap().len())
-                                        - 1
-                                {
-                                    if dependent_job.executed_per_viewport {
-                                        for (viewport_id, _) in &*state.viewports().read().unwrap()
-                                        {
-                                            available_jobs.mutate_and_notify_one(|jobs| {
-                                                jobs.push_back(ScheduledJob {
-                                                    job_index: *dependent_job_index,
-                                                    viewport_id: Some(viewport_id),
-                                                });
-                                            });
-                                        }
-                                    } else {
-                                        available_jobs.mutate_and_notify_one(|jobs| {
-                                            jobs.push_back(ScheduledJob {
-                                                job_index: *dependent_job_index,
-                                                viewport_id: None,
-                                            });
-                                        });
-                                    }
-                                }
-                            }
+                            frame_result.mutate_and_notify_all(|frame_result| {
+                                frame_result.get_or_insert(Ok(()));
+                            });
+                            queue.frame_running = false;
+                            return;
                         }
-                    }
+
+                        queue.finish_dependency(&jobs, job_index);
+                    });
                 }
-            }));
-        }
+            })
+        };
 
-        return Self {
+        return Ok(Self {
             jobs_without_dependencies,
-            worker,
+            workers,
+            worker_function,
             jobs,
             available_jobs,
             jobs_finished,
-            frame_finished_receiver,
+            jobs_skipped,
+            frame_result,
             game_time,
             delta_time,
-            spawned_entities_receiver,
+            fixed_delta_time,
+            interpolation_alpha,
+            frame_id,
+            despawned_entities_sender,
             despawned_entities_receiver,
+            command_queues,
             state,
             pipelines,
-        };
+            pipeline_cache,
+            encoders,
+            frame: AtomicU64::new(0),
+            frame_listeners: Mutex::new(Vec::new()),
+            profiler,
+            profile_callback: Mutex::new(None),
+        });
+    }
+
+    // Returns the length of the longest chain of dependent jobs and the jobs of that chain in the
+    // order they run. No matter how many workers there are, a frame takes at least as long as
+    // executing these jobs one after another.
+    pub fn critical_path(&self) -> (usize, Vec<JobId>) {
+        // Order the jobs so that every job comes after its dependencies. The dependencies were
+        // checked for cycles when the scheduler was created, so all jobs are ordered.
+        let mut remaining_dependencies = vec![0; self.jobs.len()];
+        for job in self.jobs.iter() {
+            for &dependent_job_index in &job.required_for {
+                remaining_dependencies[dependent_job_index] += 1;
+            }
+        }
+        let mut order = self.jobs_without_dependencies.clone();
+        let mut next = 0;
+        while next < order.len() {
+            for &dependent_job_index in &self.jobs[order[next]].required_for {
+                remaining_dependencies[dependent_job_index] -= 1;
+                if remaining_dependencies[dependent_job_index] == 0 {
+                    order.push(dependent_job_index);
+                }
+            }
+            next += 1;
+        }
+
+        // The longest chain starting at each job and the job that follows it in that chain.
+        let mut chain_lengths = vec![0; self.jobs.len()];
+        let mut successors = vec![None; self.jobs.len()];
+        for &job_index in order.iter().rev() {
+            let successor = self.jobs[job_index]
+                .required_for
+                .iter()
+                .copied()
+                .max_by_key(|&dependent_job_index| chain_lengths[dependent_job_index]);
+            chain_lengths[job_index] = 1 + successor.map_or(0, |successor| chain_lengths[successor]);
+            successors[job_index] = successor;
+        }
+
+        let mut path = Vec::new();
+        let mut job_index = (0..self.jobs.len()).max_by_key(|&job_index| chain_lengths[job_index]);
+        while let Some(index) = job_index {
+            path.push(self.jobs[index].id);
+            job_index = successors[index];
+        }
+        return (path.len(), path);
+    }
+
+    pub fn worker_cores(&self) -> &[Option<usize>] {
+        return &self.workers.cores;
+    }
+
+    // The number of jobs that ran in the last frame. Per viewport jobs count once per viewport.
+    pub fn jobs_executed(&self) -> usize {
+        return self.jobs_finished.load(std::sync::atomic::Ordering::Relaxed) - self.jobs_skipped();
+    }
+
+    // The number of jobs that were skipped in the last frame, because their run condition did not
+    // hold or their viewport was removed during the frame.
+    pub fn jobs_skipped(&self) -> usize {
+        return self.jobs_skipped.load(std::sync::atomic::Ordering::Relaxed);
+    }
+
+    // The number of worker threads that execute the jobs.
+    pub fn worker_count(&self) -> usize {
+        return self.workers.worker_count();
+    }
+
+    pub fn add_frame_listener(&self, listener: FrameListener) {
+        self.frame_listeners.lock().unwrap().push(listener);
+    }
+
+    fn notify_frame_listeners(&self, frame: u64, result: &crate::Result<()>) {
+        if let Some(mut profile) = self.profiler.finish_frame(frame) {
+            for encoder in self.encoders.iter() {
+                profile.gpu_jobs.extend(encoder.read_timestamps());
+            }
+            if let Some(callback) = &mut *self.profile_callback.lock().unwrap() {
+                callback(&profile.to_bytes());
+            }
+        }
+        for listener in &mut *self.frame_listeners.lock().unwrap() {
+            listener(frame, result);
+        }
+    }
+
+    // Records when every job of the following frames starts and ends and on which worker, and
+    // calls the callback with the profile of the frame once it finished. Replaces the callback of
+    // a previous call.
+    pub fn start_profiling(&self, callback: ProfileCallback) {
+        *self.profile_callback.lock().unwrap() = Some(callback);
+        self.set_profiling_enabled(true);
+    }
+
+    pub fn stop_profiling(&self) {
+        self.set_profiling_enabled(false);
+        *self.profile_callback.lock().unwrap() = None;
+    }
+
+    fn set_profiling_enabled(&self, enabled: bool) {
+        self.profiler.set_enabled(enabled);
+        for encoder in self.encoders.iter() {
+            if let Some(timestamps) = &encoder.timestamps {
+                timestamps.set_enabled(enabled);
+            }
+        }
+    }
+
+    // Recreates the pipelines of all jobs for all viewports.
+    pub fn configure_pipelines(&mut self) -> crate::Result<()> {
+        let viewport_ids = (&*self.state.viewports().read().unwrap())
+            .into_iter()
+            .map(|(viewport_id, _)| viewport_id)
+            .collect::<Vec<_>>();
+        self.pipelines.write().unwrap().clear();
+        return self.configure_viewport_pipelines(&viewport_ids);
     }
 
-    pub fn configure_pipelines(&mut self) {
+    // Drops the pipelines and additional color targets of the viewports, e.g., after they were
+    // removed.
+    pub fn remove_viewport_pipelines(&mut self, viewport_ids: &[ViewportId]) {
+        self.pipelines
+            .write()
+            .unwrap()
+            .retain(|(_, viewport_id), _| !viewport_ids.contains(viewport_id));
+    }
+
+    // Recreates the pipelines and additional color targets of all jobs for the given viewports.
+    // The pipelines of the other viewports are kept. Jobs that neither use a pipeline nor have
+    // additional color targets get nothing. Fails if the shader of a job cannot be used.
+    pub fn configure_viewport_pipelines(
+        &mut self,
+        viewport_ids: &[ViewportId],
+    ) -> crate::Result<()> {
+        self.remove_viewport_pipelines(viewport_ids);
         let mut pipelines = self.pipelines.write().unwrap();
         let viewports = self.state.viewports().read().unwrap();
 
-        pipelines.clear();
-
         for (job_index, job) in self.jobs.iter().enumerate() {
-            for (viewport_id, viewport) in &*viewports {
-                let mut resource_storages = Vec::new();
-
-                for access in &job.resource_access {
-                    match access {
-                        ResourceAccess::Read(resource_id) => {
-                            resource_storages.push(
-                                self.state
-                                    .resource_storage(*resource_id)
-                                    .unwrap()
-                                    .read()
-                                    .unwrap(),
-                            );
-                        }
-                    }
-                }
+            if !job.uses_pipeline && job.additional_color_targets.is_empty() {
+                continue;
+            }
+            for &viewport_id in viewport_ids {
+                let Some(viewport) = viewports.get(viewport_id) else {
+                    continue;
+                };
+                let gpu = viewport.gpu();
+                let pipeline = job.uses_pipeline.then(|| {
+                    let key = PipelineKey {
+                        gpu_index: gpu.index(),
+                        shader: job.shader,
+                        color_target_formats: std::iter::once(viewport.format())
+                            .chain(job.additional_color_targets.iter().copied())
+                            .collect(),
+                        depth_format: viewport.depth_format(),
+                        sample_count: viewport.sample_count(),
+                        config: job.pipeline_config.supported_by(gpu.device().features()),
+                    };
+                    return self.pipeline_cache.get_or_create(gpu, &self.state, key);
+                });
+                let pipeline = pipeline.transpose()?;
 
-                let render_pipeline_layout = viewport.gpu().device().create_pipeline_layout(
-                    &wgpu::PipelineLayoutDescriptor {
-                        label: Some("Render Pipeline Layout"),
-                        bind_group_layouts: &[
-                            viewport.gpu().system_bind_group_layout(),
-                            self.state.resource_bind_group_layout(0),
-                        ],
-                        push_constant_ranges: &[],
-                    },
-                );
+                let color_targets = job
+                    .additional_color_targets
+                    .iter()
+                    .map(|&format| {
+                        return gpu.device().create_texture(&wgpu::TextureDescriptor {
+                            label: Some("Additional Color Target"),
+                            size: wgpu::Extent3d {
+                                width: viewport.size().width,
+                                height: viewport.size().height,
+                                depth_or_array_layers: 1,
+                            },
+                            mip_level_count: 1,
+                            // All attachments of a render pass have the same sample count.
+                            sample_count: viewport.sample_count(),
+                            dimension: wgpu::TextureDimension::D2,
+                            format,
+                            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                                | wgpu::TextureUsages::TEXTURE_BINDING
+                                | wgpu::TextureUsages::COPY_SRC,
+                            view_formats: &[],
+                        });
+                    })
+                    .collect::<Vec<_>>();
+                let color_target_views = color_targets
+                    .iter()
+                    .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+                    .collect();
 
                 pipelines.insert(
                     (job_index, viewport_id),
-                    viewport.gpu().device().create_render_pipeline(
-                        &wgpu::RenderPipelineDescriptor {
-                            label: Some("Render Pipeline"),
-                            layout: Some(&render_pipeline_layout),
-                            vertex: wgpu::VertexState {
-                                module: &viewport.gpu().shader_module(),
-                                entry_point: "vs_main",
-                                buffers: &[],
-                            },
-                            fragment: Some(wgpu::FragmentState {
-                                module: &viewport.gpu().shader_module(),
-                                entry_point: "fs_main",
-                                targets: &[Some(wgpu::ColorTargetState {
-                                    format: viewport.surface_config().format,
-                                    blend: Some(wgpu::BlendState::REPLACE),
-                                    write_mask: wgpu::ColorWrites::ALL,
-                                })],
-                            }),
-                            primitive: wgpu::PrimitiveState {
-                                topology: wgpu::PrimitiveTopology::TriangleList,
-                                strip_index_format: None,
-                                front_face: wgpu::FrontFace::Ccw,
-                                cull_mode: Some(wgpu::Face::Back),
-                                polygon_mode: wgpu::PolygonMode::Fill,
-                                unclipped_depth: false,
-                                conservative: false,
-                            },
-                            depth_stencil: None,
-                            multisample: wgpu::MultisampleState {
-                                count: 1,
-                                mask: !0,
-                                alpha_to_coverage_enabled: false,
-                            },
-                            multiview: None,
-                        },
-                    ),
+                    JobPipeline {
+                        pipeline,
+                        color_targets,
+                        color_target_views,
+                    },
                 );
             }
         }
+        return Ok(());
+    }
+
+    // The number of distinct render pipelines created for the jobs so far.
+    pub fn pipeline_count(&self) -> usize {
+        return self.pipeline_cache.len();
     }
 
+    // Runs the jobs of a frame. A scheduler without workers runs them on the calling thread, see
+    // `run_jobs_deterministic`.
     pub fn run_jobs(&self, game_time: f32, delta_time: f32) -> crate::Result<()> {
+        if self.workers.worker_count() == 0 {
+            return self.run_jobs_deterministic(game_time, delta_time);
+        }
+
+        let frame = self.start_frame(game_time, delta_time);
+        let _span = trace_span!("frame", frame);
+        for job in &*self.jobs {
+            job.dependencies_finished
+                .store(0, std::sync::atomic::Ordering::Relaxed);
+        }
+
+        let viewports = self.state.viewports().read().unwrap();
+        let skipped_jobs = match self.skipped_jobs(frame, &viewports) {
+            Ok(skipped_jobs) => skipped_jobs,
+            Err(error) => {
+                drop(viewports);
+                return self.finish_frame(frame, Err(error));
+            }
+        };
+
+        // The queue keeps its capacity between frames, so pushing the jobs does not allocate once
+        // the first frames are done.
+        self.available_jobs.mutate_and_notify_all(|jobs| {
+            let viewport_ids = (&*viewports).into_iter().map(|(viewport_id, _)| viewport_id);
+            jobs.start_frame(viewport_ids, skipped_jobs);
+            for &job_index in &self.jobs_without_dependencies {
+                jobs.push_ready(&self.jobs, job_index);
+            }
+            // A frame without jobs, e.g., with only per viewport jobs and no viewports, is
+            // finished right away.
+            jobs.frame_running = !jobs.jobs.is_empty();
+        });
+        drop(viewports);
+
+        // The workers return once the frame is finished. If it was aborted, they return once the
+        // jobs that were still running finished, so the next frame can reset the state of the
+        // jobs. Only the first error is reported if multiple jobs failed.
+        self.workers.run(&self.worker_function);
+        let result = self
+            .frame_result
+            .get_mut()
+            .unwrap()
+            .take()
+            .unwrap_or(Ok(()));
+        return self.finish_frame(frame, result);
+    }
+
+    // Runs the jobs of a frame one after another on the calling thread. Of the jobs whose
+    // dependencies are finished, the one that was registered first runs next. Per viewport jobs
+    // run for the viewports in the order of their ids. Thus, the jobs always run in the same
+    // order, which makes bugs that depend on the order reproducible.
+    pub fn run_jobs_deterministic(&self, game_time: f32, delta_time: f32) -> crate::Result<()> {
+        let frame = self.start_frame(game_time, delta_time);
+        let _span = trace_span!("frame", frame);
+        let viewports = self.state.viewports().read().unwrap();
+        let viewport_count = viewports.len();
+        let skipped_jobs = match self.skipped_jobs(frame, &viewports) {
+            Ok(skipped_jobs) => skipped_jobs,
+            Err(error) => {
+                drop(viewports);
+                return self.finish_frame(frame, Err(error));
+            }
+        };
+        let pipelines = self.pipelines.read().unwrap();
+
+        // Like in `run_jobs`, a per viewport job of a frame without viewports has nothing to run
+        // and finishes once, see `JobQueue::push_ready`.
+        let executions_per_viewport_job = viewport_count.max(1);
+        let mut remaining_dependencies = self
+            .jobs
+            .iter()
+            .map(|job| {
+                job.regular_dependency_count
+                    + job.per_viewport_dependency_count * executions_per_viewport_job
+            })
+            .collect::<Vec<_>>();
+        let mut ready_jobs = self
+            .jobs_without_dependencies
+            .iter()
+            .copied()
+            .collect::<BTreeSet<_>>();
+
+        while let Some(job_index) = ready_jobs.pop_first() {
+            let job = &self.jobs[job_index];
+            let viewport_ids = if job.executed_per_viewport {
+                (&*viewports).into_iter().map(|(id, _)| Some(id)).collect()
+            } else {
+                vec![None]
+            };
+            for &viewport_id in &viewport_ids {
+                if skipped_jobs.contains(&(job_index, viewport_id)) {
+                    self.jobs_finished
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    self.jobs_skipped
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    continue;
+                }
+                let system_resources = self.system_resources(
+                    frame,
+                    job_index,
+                    viewport_id,
+                    &viewports,
+                    Some(&pipelines),
+                );
+                let start = self.profiler.now();
+                let result = execute_job(job, &system_resources, &self.state);
+                if let Some(start) = start {
+                    self.profiler.record_job(job_index, None, start);
+                }
+                if let Err(error) = result {
+                    drop(pipelines);
+                    drop(viewports);
+                    return self.finish_frame(frame, Err(error));
+                }
+                self.jobs_finished
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }
+
+            for &dependent_job_index in &job.required_for {
+                remaining_dependencies[dependent_job_index] -= viewport_ids.len().max(1);
+                if remaining_dependencies[dependent_job_index] == 0 {
+                    ready_jobs.insert(dependent_job_index);
+                }
+            }
+        }
+        drop(pipelines);
+        drop(viewports);
+
+        return self.finish_frame(frame, Ok(()));
+    }
+
+    // The system resources of a job that runs on the calling thread during the frame. The
+    // pipelines are None while the run conditions are checked, see `skipped_jobs`.
+    fn system_resources<'a>(
+        &'a self,
+        frame: u64,
+        job_index: usize,
+        viewport_id: Option<ViewportId>,
+        viewports: &'a IdMap<ViewportId, Viewport>,
+        pipelines: Option<&'a HashMap<(usize, ViewportId), JobPipeline>>,
+    ) -> SystemResources<'a> {
+        let viewport = viewport_id.map(|id| viewports.get(id).unwrap());
+        return SystemResources {
+            game_time: f32::from_bits(self.game_time.load(std::sync::atomic::Ordering::Relaxed)),
+            delta_time: f32::from_bits(self.delta_time.load(std::sync::atomic::Ordering::Relaxed)),
+            fixed_delta_time: self.fixed_delta_time(),
+            interpolation_alpha: f32::from_bits(
+                self.interpolation_alpha
+                    .load(std::sync::atomic::Ordering::Relaxed),
+            ),
+            frame_id: frame as u32,
+            entity_despawner: &self.despawned_entities_sender,
+            commands: Commands::new(self.command_queues.last().unwrap(), self.state.entities()),
+            job_index,
+            viewport_id,
+            viewport,
+            pipeline: viewport_id
+                .zip(pipelines)
+                .and_then(|(id, pipelines)| pipelines.get(&(job_index, id))),
+            compute_pipeline: self.jobs[job_index]
+                .compute_pipelines
+                .get(viewport.map_or(0, |viewport| viewport.gpu().index()))
+                .map(Arc::as_ref),
+            encoders: &self.encoders,
+            state: &self.state,
+        };
+    }
+
+    // Checks the run conditions of the jobs when the frame is scheduled, i.e., before any of its
+    // jobs runs. Returns the jobs whose condition does not hold, with the viewport for per
+    // viewport jobs. Fails if a condition panics.
+    fn skipped_jobs(
+        &self,
+        frame: u64,
+        viewports: &IdMap<ViewportId, Viewport>,
+    ) -> crate::Result<HashSet<(usize, Option<ViewportId>)>> {
+        let mut skipped_jobs = HashSet::new();
+        for (job_index, job) in self.jobs.iter().enumerate() {
+            if job.run_if.is_none() {
+                continue;
+            }
+            let viewport_ids = if job.executed_per_viewport {
+                viewports.into_iter().map(|(id, _)| Some(id)).collect()
+            } else {
+                vec![None]
+            };
+            for viewport_id in viewport_ids {
+                let system_resources =
+                    self.system_resources(frame, job_index, viewport_id, viewports, None);
+                if !job_should_run(job, &system_resources, &self.state)? {
+                    skipped_jobs.insert((job_index, viewport_id));
+                }
+            }
+        }
+        return Ok(skipped_jobs);
+    }
+
+    // The length of the steps of the fixed update jobs, see `SystemResources::fixed_delta_time`.
+    pub fn fixed_delta_time(&self) -> f32 {
+        return f32::from_bits(
+            self.fixed_delta_time
+                .load(std::sync::atomic::Ordering::Relaxed),
+        );
+    }
+
+    pub fn set_fixed_delta_time(&self, fixed_delta_time: f32) {
+        self.fixed_delta_time.store(
+            fixed_delta_time.to_bits(),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+
+    // Set by the scene before the jobs run, see `SystemResources::interpolation_alpha`.
+    pub fn set_interpolation_alpha(&self, interpolation_alpha: f32) {
+        self.interpolation_alpha.store(
+            interpolation_alpha.to_bits(),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+
+    // Publishes the times and the id of the frame to the workers and returns the index of the
+    // frame.
+    fn start_frame(&self, game_time: f32, delta_time: f32) -> u64 {
         self.game_time.store(
             u32::from_ne_bytes(game_time.to_ne_bytes()),
             std::sync::atomic::Ordering::Relaxed,
@@ -410,58 +1903,1763 @@ impl Scheduler {
             u32::from_ne_bytes(delta_time.to_ne_bytes()),
             std::sync::atomic::Ordering::Relaxed,
         );
+        let frame = self
+            .frame
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.frame_id
+            .store(frame as u32, std::sync::atomic::Ordering::Relaxed);
         self.jobs_finished
             .store(0, std::sync::atomic::Ordering::Relaxed);
-        for job in &*self.jobs {
-            job.dependencies_finished
-                .store(0, std::sync::atomic::Ordering::Relaxed);
-        }
-
-        let viewports = self.state.viewports().read().unwrap();
-
-        println!("=== Start Frame ===");
-        //
-        // let entities = self.state.entities().read().unwrap();
+        self.jobs_skipped
+            .store(0, std::sync::atomic::Ordering::Relaxed);
+        self.profiler.start_frame();
+        return frame;
+    }
 
-        // Not sure whether the above or this is faster.
-        self.available_jobs.mutate_and_notify_all(|jobs| {
-            for j in &self.jobs_without_dependencies {
-                let job = &self.jobs[*j];
-                if job.executed_per_viewport {
-                    for (viewport_id, _) in &*viewports {
-                        println!("pushing {j} for {viewport_id}");
-                        jobs.push_back(ScheduledJob {
-                            job_index: *j,
-                            viewport_id: Some(viewport_id),
-                        });
+    // Submits the commands of the frame and applies the structural changes requested by the jobs
+    // if the frame succeeded. Notifies the frame listeners in any case.
+    fn finish_frame(&self, frame: u64, result: crate::Result<()>) -> crate::Result<()> {
+        if result.is_err() {
+            for encoder in self.encoders.iter() {
+                encoder.discard();
+            }
+            // The ids of the entities that are not spawned anymore are released.
+            let mut entities = self.state.entities().write().unwrap();
+            for queue in self.command_queues.iter() {
+                for command in queue.lock().unwrap().drain(..) {
+                    if let Command::Spawn(entity_id, _) = command {
+                        entities.free(entity_id);
                     }
-                } else {
-                    println!("pushing {j}");
-                    jobs.push_back(ScheduledJob {
-                        job_index: *j,
-                        viewport_id: None,
-                    });
                 }
             }
-        });
-
-        match self.frame_finished_receiver.recv() {
-            Ok(Ok(_)) => {}
-            Ok(Err(error)) => return Err(error),
-            Err(error) => return Err(Error::new(error.to_string(), SourceLocation::here())),
-        };
+            drop(entities);
+            // The despawns of the failed frame are discarded as well, otherwise the next frame
+            // would apply them.
+            for _ in self.despawned_entities_receiver.try_iter() {}
+            self.notify_frame_listeners(frame, &result);
+            return result;
+        }
+        for encoder in self.encoders.iter() {
+            encoder.submit();
+        }
 
         let mut entities = self.state.entities().write().unwrap();
 
         for entity_to_remove in self.despawned_entities_receiver.try_iter() {
-            println!("despawn entity: {}", entity_to_remove);
+            trace_debug!(entity_id = %entity_to_remove, "despawning entity");
             entities.free(entity_to_remove);
+            self.state.remove_entity_components(entity_to_remove);
+        }
+        // A command that cannot be applied does not stop the others, the first error is the
+        // result of the frame.
+        let mut result = result;
+        for queue in self.command_queues.iter() {
+            // The queue keeps its capacity, so recording commands does not allocate once the
+            // first frames are done.
+            for command in queue.lock().unwrap().drain(..) {
+                let command_result = self.apply_command(&mut entities, command);
+                if result.is_ok() {
+                    result = command_result;
+                }
+            }
+        }
+        drop(entities);
+
+        self.notify_frame_listeners(frame, &result);
+        return result;
+    }
+
+    // Adds the components of an entity whose id was reserved during the frame. Components that
+    // cannot be added are skipped.
+    fn spawn_entity(&self, entity_id: EntityId, entity: EntityDescriptor) -> crate::Result<()> {
+        let mut result = Ok(());
+        for (resource_id, component) in entity.into_components() {
+            let component_result =
+                self.state
+                    .insert_entity_component(entity_id, resource_id, component);
+            if result.is_ok() {
+                result = component_result;
+            }
+        }
+        trace_debug!(%entity_id, "spawned entity");
+        return result;
+    }
+
+    // Applies a command recorded by a job. Commands for entities that were already despawned are
+    // ignored, e.g., if two jobs despawned the same entity.
+    fn apply_command(
+        &self,
+        entities: &mut IdStorage<EntityId>,
+        command: Command,
+    ) -> crate::Result<()> {
+        return match command {
+            Command::Spawn(entity_id, entity) => self.spawn_entity(entity_id, entity),
+            Command::Despawn(entity_id) if entities.contains(entity_id) => {
+                entities.free(entity_id);
+                self.state.remove_entity_components(entity_id);
+                Ok(())
+            }
+            Command::Insert(entity_id, resource_id, component) if entities.contains(entity_id) => {
+                self.state
+                    .insert_entity_component(entity_id, resource_id, component)
+            }
+            Command::Remove(entity_id, resource_id) if entities.contains(entity_id) => {
+                self.state.remove_entity_component(entity_id, resource_id)
+            }
+            Command::Despawn(_) | Command::Insert(..) | Command::Remove(..) => Ok(()),
+        };
+    }
+}
+
+// Runs a test on another thread and fails if it does not finish in time, e.g., because a frame
+// never completes.
+#[cfg(test)]
+pub(crate) fn run_with_timeout(test: impl FnOnce() + Send + 'static) {
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+    let (sender, receiver) = mpsc::channel();
+    let test_thread = thread::spawn(move || {
+        test();
+        sender.send(()).unwrap();
+    });
+    match receiver.recv_timeout(TIMEOUT) {
+        Ok(()) => test_thread.join().unwrap(),
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            panic!("the test did not finish within {TIMEOUT:?}")
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            panic::resume_unwind(test_thread.join().unwrap_err())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        register_shader, EventStorage, FrameProfile, IdMappedResourceStorage, Key, Resource,
+        ResourceId, Result, VersionedIndexId, ViewportEvent, CALLING_THREAD,
+    };
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            OnceLock,
+        },
+        time::Duration,
+    };
+
+    fn make_scheduler(jobs: &IdMap<JobId, Job>, worker_count: usize) -> Scheduler {
+        return Scheduler::from_jobs(
+            jobs,
+            JobKind::Update,
+            Arc::new(SceneState::for_gpus(&[], 0).unwrap()),
+            worker_count,
+        )
+        .unwrap();
+    }
+
+    fn insert_job(jobs: &mut IdMap<JobId, Job>, function: JobFunction) -> JobId {
+        let (id, job) = jobs.insert(Job::new(JobKind::Update, function, &[]));
+        job.set_executed_per_viewport(false);
+        return id;
+    }
+
+    static RUNNING_JOBS: AtomicUsize = AtomicUsize::new(0);
+    static EXCLUSIVE_JOB_RUNNING: AtomicBool = AtomicBool::new(false);
+    static EXCLUSIVE_JOB_EXECUTIONS: AtomicUsize = AtomicUsize::new(0);
+    static OVERLAPPED: AtomicBool = AtomicBool::new(false);
+
+    fn parallel_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        RUNNING_JOBS.fetch_add(1, Ordering::SeqCst);
+        if EXCLUSIVE_JOB_RUNNING.load(Ordering::SeqCst) {
+            OVERLAPPED.store(true, Ordering::SeqCst);
+        }
+        thread::sleep(Duration::from_millis(2));
+        RUNNING_JOBS.fetch_sub(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn exclusive_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        EXCLUSIVE_JOB_RUNNING.store(true, Ordering::SeqCst);
+        if RUNNING_JOBS.load(Ordering::SeqCst) != 0 {
+            OVERLAPPED.store(true, Ordering::SeqCst);
+        }
+        thread::sleep(Duration::from_millis(5));
+        if RUNNING_JOBS.load(Ordering::SeqCst) != 0 {
+            OVERLAPPED.store(true, Ordering::SeqCst);
         }
-        for _entity_to_spawn in self.spawned_entities_receiver.try_iter() {
-            println!("spawned entity {}", entities.reserve());
+        EXCLUSIVE_JOB_EXECUTIONS.fetch_add(1, Ordering::SeqCst);
+        EXCLUSIVE_JOB_RUNNING.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    #[test]
+    fn exclusive_job_runs_alone() {
+        let mut jobs = IdMap::new();
+        let first = insert_job(&mut jobs, parallel_job);
+        for _ in 0..3 {
+            insert_job(&mut jobs, parallel_job);
+        }
+        let exclusive = insert_job(&mut jobs, exclusive_job);
+        jobs.get_mut(exclusive).unwrap().set_exclusive(true);
+        jobs.get_mut(exclusive).unwrap().add_dependency(first);
+        for _ in 0..3 {
+            let dependent = insert_job(&mut jobs, parallel_job);
+            jobs.get_mut(dependent).unwrap().add_dependency(exclusive);
+        }
+
+        let scheduler = make_scheduler(&jobs, 4);
+        for frame in 0..10 {
+            assert!(scheduler.run_jobs(frame as f32, 1.0).is_ok());
+        }
+
+        assert_eq!(EXCLUSIVE_JOB_EXECUTIONS.load(Ordering::SeqCst), 10);
+        assert!(!OVERLAPPED.load(Ordering::SeqCst));
+    }
+
+    fn noop_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        Ok(())
+    }
+
+    // The stage of every job that started or finished, in the order of the events.
+    static STAGE_EVENTS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+    static STAGED_JOBS_RUNNING: AtomicUsize = AtomicUsize::new(0);
+    static MAX_STAGED_JOBS_RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+    fn run_in_stage(stage_index: usize) -> Result<()> {
+        STAGE_EVENTS.lock().unwrap().push(stage_index);
+        let running = STAGED_JOBS_RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+        MAX_STAGED_JOBS_RUNNING.fetch_max(running, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(5));
+        STAGED_JOBS_RUNNING.fetch_sub(1, Ordering::SeqCst);
+        STAGE_EVENTS.lock().unwrap().push(stage_index);
+        return Ok(());
+    }
+
+    fn input_stage_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        return run_in_stage(0);
+    }
+
+    fn logic_stage_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        return run_in_stage(1);
+    }
+
+    fn render_stage_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        return run_in_stage(2);
+    }
+
+    #[test]
+    fn stages_run_one_after_another() {
+        let stages = ["Input", "Logic", "Render"].map(crate::register_stage);
+        assert_eq!(crate::register_stage("Logic"), stages[1]);
+        assert_eq!(crate::stage_name(stages[2]), "Render");
+
+        // The jobs are registered in the reverse order of their stages.
+        let mut jobs = IdMap::new();
+        let functions = [input_stage_job, logic_stage_job, render_stage_job];
+        for (stage, function) in stages.iter().zip(functions).rev() {
+            for _ in 0..3 {
+                let job_id = insert_job(&mut jobs, function);
+                jobs.get_mut(job_id).unwrap().set_stage(*stage);
+            }
+        }
+
+        let scheduler = make_scheduler(&jobs, 4);
+        for frame in 0..3 {
+            assert!(scheduler.run_jobs(frame as f32, 1.0).is_ok());
+            let events = std::mem::take(&mut *STAGE_EVENTS.lock().unwrap());
+            assert_eq!(events.len(), 18);
+            assert!(events.windows(2).all(|pair| pair[0] <= pair[1]));
+        }
+        assert!(MAX_STAGED_JOBS_RUNNING.load(Ordering::SeqCst) > 1);
+    }
+
+    static CONDITIONAL_RUNS: Mutex<Vec<(u32, &str)>> = Mutex::new(Vec::new());
+
+    fn on_even_frames(sr: &SystemResources, _: &SceneState) -> bool {
+        return sr.frame_id().is_multiple_of(2);
+    }
+
+    fn record_gated_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        CONDITIONAL_RUNS.lock().unwrap().push((sr.frame_id(), "gated"));
+        return Ok(());
+    }
+
+    fn record_dependent_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        CONDITIONAL_RUNS.lock().unwrap().push((sr.frame_id(), "dependent"));
+        return Ok(());
+    }
+
+    #[test]
+    fn skipped_jobs_do_not_hold_back_their_dependents() {
+        let mut jobs = IdMap::new();
+        let gated = insert_job(&mut jobs, record_gated_job);
+        jobs.get_mut(gated).unwrap().set_run_if(on_even_frames);
+        let dependent = insert_job(&mut jobs, record_dependent_job);
+        jobs.get_mut(dependent).unwrap().add_dependency(gated);
+        let last = insert_job(&mut jobs, noop_job);
+        jobs.get_mut(last).unwrap().add_dependency(dependent);
+
+        // The condition toggles between frames, with and without workers.
+        for worker_count in [0, 2] {
+            let scheduler = make_scheduler(&jobs, worker_count);
+            for frame in 0..4 {
+                assert!(scheduler.run_jobs(frame as f32, 1.0).is_ok());
+            }
+            let runs = std::mem::take(&mut *CONDITIONAL_RUNS.lock().unwrap());
+            let frame_ids = |name| {
+                return runs
+                    .iter()
+                    .filter(|(_, job)| *job == name)
+                    .map(|(frame_id, _)| *frame_id)
+                    .collect::<Vec<_>>();
+            };
+            assert_eq!(frame_ids("gated").len(), 2);
+            assert!(frame_ids("gated").iter().all(|frame_id| frame_id % 2 == 0));
+            assert_eq!(frame_ids("dependent").len(), 4);
+            // The last frame was odd, so the gated job was skipped.
+            assert_eq!(scheduler.jobs_executed(), 2);
+            assert_eq!(scheduler.jobs_skipped(), 1);
+        }
+    }
+
+    fn never(_: &SystemResources, _: &SceneState) -> bool {
+        return false;
+    }
+
+    fn failing_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        return Err(Error::new("the job should have been skipped", SourceLocation::here()));
+    }
+
+    #[test]
+    fn a_skipped_chain_still_finishes_the_frame() {
+        let mut jobs = IdMap::new();
+        let mut previous = None;
+        for _ in 0..3 {
+            let job_id = insert_job(&mut jobs, failing_job);
+            jobs.get_mut(job_id).unwrap().set_run_if(never);
+            if let Some(previous) = previous {
+                jobs.get_mut(job_id).unwrap().add_dependency(previous);
+            }
+            previous = Some(job_id);
+        }
+
+        for worker_count in [0, 2] {
+            let scheduler = make_scheduler(&jobs, worker_count);
+            for frame in 0..3 {
+                assert!(scheduler.run_jobs(frame as f32, 1.0).is_ok());
+                assert_eq!(scheduler.jobs_executed(), 0);
+                assert_eq!(scheduler.jobs_skipped(), 3);
+            }
         }
+    }
+
+    static FLAG_SET: AtomicBool = AtomicBool::new(false);
 
-        println!("=== End Frame ===");
+    fn set_flag(_: &SystemResources, _: &SceneState) -> Result<()> {
+        FLAG_SET.store(true, Ordering::SeqCst);
         return Ok(());
     }
+
+    fn flag_is_set(_: &SystemResources, _: &SceneState) -> bool {
+        return FLAG_SET.load(Ordering::SeqCst);
+    }
+
+    #[test]
+    fn run_conditions_are_checked_before_the_frame_runs() {
+        let mut jobs = IdMap::new();
+        let setter = insert_job(&mut jobs, set_flag);
+        let gated = insert_job(&mut jobs, noop_job);
+        jobs.get_mut(gated).unwrap().set_run_if(flag_is_set);
+        jobs.get_mut(gated).unwrap().add_dependency(setter);
+
+        // The flag is only set while the first frame runs, after its conditions were checked.
+        let scheduler = make_scheduler(&jobs, 2);
+        assert!(scheduler.run_jobs(0.0, 1.0).is_ok());
+        assert_eq!(scheduler.jobs_skipped(), 1);
+        assert!(scheduler.run_jobs(1.0, 1.0).is_ok());
+        assert_eq!(scheduler.jobs_skipped(), 0);
+        assert_eq!(scheduler.jobs_executed(), 2);
+    }
+
+    fn insert_job_accessing(
+        jobs: &mut IdMap<JobId, Job>,
+        function: JobFunction,
+        resource_access: ResourceAccess,
+    ) -> JobId {
+        let (id, job) = jobs.insert(Job::new(JobKind::Update, function, &[resource_access]));
+        job.set_executed_per_viewport(false);
+        return id;
+    }
+
+    static READERS_RUNNING: AtomicUsize = AtomicUsize::new(0);
+    static WRITER_RUNNING: AtomicBool = AtomicBool::new(false);
+    static ACCESS_OVERLAPPED: AtomicBool = AtomicBool::new(false);
+
+    fn reading_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        READERS_RUNNING.fetch_add(1, Ordering::SeqCst);
+        if WRITER_RUNNING.load(Ordering::SeqCst) {
+            ACCESS_OVERLAPPED.store(true, Ordering::SeqCst);
+        }
+        thread::sleep(Duration::from_millis(2));
+        READERS_RUNNING.fetch_sub(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn writing_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        if WRITER_RUNNING.swap(true, Ordering::SeqCst)
+            || READERS_RUNNING.load(Ordering::SeqCst) != 0
+        {
+            ACCESS_OVERLAPPED.store(true, Ordering::SeqCst);
+        }
+        thread::sleep(Duration::from_millis(2));
+        if READERS_RUNNING.load(Ordering::SeqCst) != 0 {
+            ACCESS_OVERLAPPED.store(true, Ordering::SeqCst);
+        }
+        WRITER_RUNNING.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    #[test]
+    fn writers_do_not_overlap_with_other_accesses() {
+        let resource_id = ResourceId::from_index(0);
+        let mut jobs = IdMap::new();
+        let first_writer =
+            insert_job_accessing(&mut jobs, writing_job, ResourceAccess::Write(resource_id));
+        let second_writer = insert_job_accessing(
+            &mut jobs,
+            writing_job,
+            ResourceAccess::ReadWrite(resource_id),
+        );
+        jobs.get_mut(second_writer)
+            .unwrap()
+            .add_dependency(first_writer);
+        for _ in 0..4 {
+            insert_job_accessing(&mut jobs, reading_job, ResourceAccess::Read(resource_id));
+        }
+
+        let scheduler = make_scheduler(&jobs, 4);
+        for frame in 0..10 {
+            assert!(scheduler.run_jobs(frame as f32, 1.0).is_ok());
+        }
+
+        assert!(!ACCESS_OVERLAPPED.load(Ordering::SeqCst));
+    }
+
+    static PANICKED: AtomicBool = AtomicBool::new(false);
+    static DEPENDENT_JOB_EXECUTIONS: AtomicUsize = AtomicUsize::new(0);
+
+    fn panic_once(_: &SystemResources, _: &SceneState) -> Result<()> {
+        if !PANICKED.swap(true, Ordering::SeqCst) {
+            panic!("something went wrong");
+        }
+        Ok(())
+    }
+
+    fn count_dependent_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        DEPENDENT_JOB_EXECUTIONS.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    #[test]
+    fn panicking_job_fails_the_frame() {
+        let mut jobs = IdMap::new();
+        let panicking_job = insert_job(&mut jobs, panic_once);
+        for _ in 0..4 {
+            insert_job(&mut jobs, noop_job);
+        }
+        let dependent_job = insert_job(&mut jobs, count_dependent_job);
+        jobs.get_mut(dependent_job)
+            .unwrap()
+            .add_dependency(panicking_job);
+        let scheduler = make_scheduler(&jobs, 2);
+
+        let error = scheduler.run_jobs(0.0, 1.0).unwrap_err();
+        assert!(error.message().contains(&panicking_job.to_string()));
+        assert!(error.message().contains("something went wrong"));
+        assert_eq!(DEPENDENT_JOB_EXECUTIONS.load(Ordering::SeqCst), 0);
+
+        for frame in 1..4 {
+            assert!(scheduler.run_jobs(frame as f32, 1.0).is_ok());
+        }
+        assert_eq!(DEPENDENT_JOB_EXECUTIONS.load(Ordering::SeqCst), 3);
+    }
+
+    fn encode_for_missing_gpu(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        sr.encoder_for(1)?;
+        return Ok(());
+    }
+
+    #[test]
+    fn encoders_of_missing_gpus_fail_the_frame() {
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, encode_for_missing_gpu);
+        let scheduler = make_scheduler(&jobs, 0);
+
+        let error = scheduler.run_jobs(0.0, 1.0).unwrap_err();
+        assert!(error.message().contains("the scene has no GPU with index 1"));
+    }
+
+    fn fail_in_second_frame(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        if sr.game_time() == 1.0 {
+            return Err(Error::new("second frame", SourceLocation::here()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn frame_listeners_are_notified_once_per_frame() {
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, fail_in_second_frame);
+        let scheduler = make_scheduler(&jobs, 2);
+
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let listener_frames = frames.clone();
+        scheduler.add_frame_listener(Box::new(move |frame, result| {
+            listener_frames.lock().unwrap().push((
+                frame,
+                result
+                    .as_ref()
+                    .copied()
+                    .map_err(|error| error.message().to_string()),
+            ));
+        }));
+
+        for frame in 0..3 {
+            scheduler.run_jobs(frame as f32, 1.0).ok();
+        }
+        assert_eq!(
+            *frames.lock().unwrap(),
+            vec![
+                (0, Ok(())),
+                (1, Err("second frame".to_string())),
+                (2, Ok(()))
+            ]
+        );
+    }
+
+    #[test]
+    fn profiles_contain_every_job_of_the_frame() {
+        let mut jobs = IdMap::new();
+        let first_job = insert_job(&mut jobs, noop_job);
+        for _ in 0..3 {
+            let job = insert_job(&mut jobs, noop_job);
+            jobs.get_mut(job).unwrap().add_dependency(first_job);
+        }
+        for worker_count in [0, 2] {
+            let scheduler = make_scheduler(&jobs, worker_count);
+            scheduler.run_jobs(0.0, 1.0).unwrap();
+
+            let profiles = Arc::new(Mutex::new(Vec::new()));
+            let callback_profiles = profiles.clone();
+            scheduler.start_profiling(Box::new(move |bytes| {
+                callback_profiles
+                    .lock()
+                    .unwrap()
+                    .push(FrameProfile::from_bytes(bytes).unwrap());
+            }));
+            scheduler.run_jobs(1.0, 1.0).unwrap();
+            scheduler.stop_profiling();
+            scheduler.run_jobs(2.0, 1.0).unwrap();
+
+            let profiles = profiles.lock().unwrap();
+            assert_eq!(profiles.len(), 1);
+            let profile = &profiles[0];
+            assert_eq!(profile.frame, 1);
+            assert_eq!(profile.jobs.len(), 4);
+            assert_eq!(profile.jobs[0].job_index, 0);
+            for job in &profile.jobs {
+                assert!(profile.start <= job.start && job.start <= job.end);
+                assert!(job.end <= profile.end);
+                assert_eq!(job.invocation, 1);
+                if worker_count == 0 {
+                    assert_eq!(job.worker, CALLING_THREAD);
+                } else {
+                    assert!(job.worker < worker_count as u32);
+                }
+            }
+        }
+    }
+
+    fn scheduler_error(jobs: &IdMap<JobId, Job>) -> String {
+        let state = Arc::new(SceneState::for_gpus(&[], 0).unwrap());
+        let Err(error) = Scheduler::from_jobs(jobs, JobKind::Update, state, 1) else {
+            panic!("expected an error");
+        };
+        return error.message().to_string();
+    }
+
+    #[test]
+    fn self_dependency_is_rejected() {
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, noop_job);
+        let job = insert_job(&mut jobs, noop_job);
+        jobs.get_mut(job).unwrap().add_dependency(job);
+
+        assert_eq!(
+            scheduler_error(&jobs),
+            format!("the jobs {job} form a dependency cycle")
+        );
+    }
+
+    #[test]
+    fn dependency_cycle_is_rejected() {
+        let mut jobs = IdMap::new();
+        let first = insert_job(&mut jobs, noop_job);
+        let second = insert_job(&mut jobs, noop_job);
+        let third = insert_job(&mut jobs, noop_job);
+        jobs.get_mut(second).unwrap().add_dependency(first);
+        jobs.get_mut(first).unwrap().add_dependency(second);
+        jobs.get_mut(third).unwrap().add_dependency(second);
+
+        let message = scheduler_error(&jobs);
+        assert!(message.contains(&first.to_string()));
+        assert!(message.contains(&second.to_string()));
+        assert!(!message.contains(&third.to_string()));
+    }
+
+    #[test]
+    fn dependency_on_other_job_kind_is_rejected() {
+        let mut jobs = IdMap::new();
+        let setup_job = jobs.insert(Job::new(JobKind::Setup, noop_job, &[])).0;
+        let job = insert_job(&mut jobs, noop_job);
+        jobs.get_mut(job).unwrap().add_dependency(setup_job);
+
+        assert_eq!(
+            scheduler_error(&jobs),
+            format!("job {job} depends on job {setup_job} of a different kind")
+        );
+    }
+
+    #[test]
+    fn critical_path_is_the_longest_dependency_chain() {
+        let mut jobs = IdMap::new();
+        let chain = (0..4)
+            .map(|_| insert_job(&mut jobs, noop_job))
+            .collect::<Vec<_>>();
+        for pair in chain.windows(2) {
+            jobs.get_mut(pair[1]).unwrap().add_dependency(pair[0]);
+        }
+
+        // Parallel branches that are shorter than the chain.
+        let branch_start = insert_job(&mut jobs, noop_job);
+        let branch_end = insert_job(&mut jobs, noop_job);
+        jobs.get_mut(branch_end).unwrap().add_dependency(branch_start);
+        jobs.get_mut(chain[3]).unwrap().add_dependency(branch_end);
+        let independent = insert_job(&mut jobs, noop_job);
+        let after_first = insert_job(&mut jobs, noop_job);
+        jobs.get_mut(after_first).unwrap().add_dependency(chain[0]);
+
+        let scheduler = make_scheduler(&jobs, 1);
+        let (length, path) = scheduler.critical_path();
+        assert_eq!(length, 4);
+        assert_eq!(path, chain);
+        assert!(!path.contains(&independent));
+
+        let empty = make_scheduler(&IdMap::new(), 1);
+        assert_eq!(empty.critical_path(), (0, vec![]));
+    }
+
+    // Covers the whole target with a single triangle and writes a different color to each of the
+    // two color targets.
+    const TWO_TARGETS_SHADER: &str = "
+        @vertex
+        fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+            let x = f32(i32(index & 1u) * 4 - 1);
+            let y = f32(i32(index >> 1u) * 4 - 1);
+            return vec4<f32>(x, y, 0.0, 1.0);
+        }
+
+        struct Targets {
+            @location(0) first: vec4<f32>,
+            @location(1) second: vec4<f32>,
+        };
+
+        @fragment
+        fn fs_main() -> Targets {
+            return Targets(vec4<f32>(1.0, 0.0, 0.0, 1.0), vec4<f32>(0.0, 0.0, 1.0, 1.0));
+        }
+    ";
+
+    #[test]
+    fn pipelines_render_to_multiple_color_targets() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        const SIZE: u32 = 64;
+        const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+        // Without storages, so the bind group does not depend on the resources registered by other
+        // tests.
+        let state = SceneState::from_storages(std::slice::from_ref(&gpu), vec![], 0).unwrap();
+        let shader_module = gpu.device().create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(TWO_TARGETS_SHADER.into()),
+        });
+        let pipeline = create_render_pipeline(
+            &gpu,
+            state.resource_bind_group_layouts(gpu.index()),
+            &shader_module,
+            ("vs_main", "fs_main"),
+            &PipelineKey {
+                gpu_index: gpu.index(),
+                shader: None,
+                color_target_formats: vec![FORMAT, FORMAT],
+                depth_format: None,
+                sample_count: 1,
+                config: PipelineConfig::default(),
+            },
+        );
+
+        let targets = (0..2)
+            .map(|_| {
+                return gpu.device().create_texture(&wgpu::TextureDescriptor {
+                    label: None,
+                    size: wgpu::Extent3d {
+                        width: SIZE,
+                        height: SIZE,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::COPY_SRC,
+                    view_formats: &[],
+                });
+            })
+            .collect::<Vec<_>>();
+        let views = targets
+            .iter()
+            .map(|target| target.create_view(&wgpu::TextureViewDescriptor::default()))
+            .collect::<Vec<_>>();
+        let readback_buffers = (0..2)
+            .map(|_| {
+                return gpu.device().create_buffer(&wgpu::BufferDescriptor {
+                    label: None,
+                    size: (SIZE * SIZE * 4) as u64,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                });
+            })
+            .collect::<Vec<_>>();
+
+        let mut encoder = gpu
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let color_attachments = views
+                .iter()
+                .map(|view| {
+                    return Some(wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        },
+                    });
+                })
+                .collect::<Vec<_>>();
+            let resource_bind_groups = state.resource_bind_groups(gpu.index());
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &color_attachments,
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, gpu.system_bind_group(), &[]);
+            for (index, group) in resource_bind_groups.iter().enumerate() {
+                render_pass.set_bind_group(1 + index as u32, group, &[]);
+            }
+            render_pass.draw(0..3, 0..1);
+        }
+        for (target, buffer) in targets.iter().zip(&readback_buffers) {
+            encoder.copy_texture_to_buffer(
+                target.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: std::num::NonZeroU32::new(SIZE * 4),
+                        rows_per_image: None,
+                    },
+                },
+                target.size(),
+            );
+        }
+        gpu.queue().submit(std::iter::once(encoder.finish()));
+
+        let pixels = readback_buffers
+            .iter()
+            .map(|buffer| {
+                let slice = buffer.slice(..);
+                slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+                gpu.device().poll(wgpu::Maintain::Wait);
+                return slice.get_mapped_range()[..4].to_vec();
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(pixels, [[255, 0, 0, 255], [0, 0, 255, 255]]);
+    }
+
+    // Covers the whole target with a red triangle for the vertices 0 to 2 and a green one for the
+    // vertices 3 to 5. The green triangle is closer to the camera.
+    const DEPTH_SHADER: &str = "
+        struct VertexOutput {
+            @builtin(position) position: vec4<f32>,
+            @location(0) @interpolate(flat) triangle: u32,
+        };
+
+        @vertex
+        fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+            let triangle = index / 3u;
+            let corner = index % 3u;
+            let x = f32(i32(corner & 1u) * 4 - 1);
+            let y = f32(i32(corner >> 1u) * 4 - 1);
+            let z = 0.75 - 0.5 * f32(triangle);
+            return VertexOutput(vec4<f32>(x, y, z, 1.0), triangle);
+        }
+
+        @fragment
+        fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+            return vec4<f32>(1.0 - f32(in.triangle), f32(in.triangle), 0.0, 1.0);
+        }
+    ";
+
+    #[test]
+    fn pipelines_with_a_depth_format_test_depth() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        const SIZE: u32 = 64;
+        const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+        let depth_config = crate::DepthBufferConfig::default();
+
+        let state = SceneState::from_storages(std::slice::from_ref(&gpu), vec![], 0).unwrap();
+        let shader_module = gpu.device().create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(DEPTH_SHADER.into()),
+        });
+        let pipeline = create_render_pipeline(
+            &gpu,
+            state.resource_bind_group_layouts(gpu.index()),
+            &shader_module,
+            ("vs_main", "fs_main"),
+            &PipelineKey {
+                gpu_index: gpu.index(),
+                shader: None,
+                color_target_formats: vec![FORMAT],
+                depth_format: Some(depth_config.format),
+                sample_count: 1,
+                config: PipelineConfig::default(),
+            },
+        );
+
+        let size = wgpu::Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1,
+        };
+        let create_target = |format| {
+            return gpu.device().create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+        };
+        let target = create_target(FORMAT);
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_texture = create_target(depth_config.format);
+        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let readback_buffer = gpu.device().create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (SIZE * SIZE * 4) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = gpu
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let resource_bind_groups = state.resource_bind_groups(gpu.index());
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(depth_config.clear_value.unwrap()),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, gpu.system_bind_group(), &[]);
+            for (index, group) in resource_bind_groups.iter().enumerate() {
+                render_pass.set_bind_group(1 + index as u32, group, &[]);
+            }
+            // The far triangle is drawn last, but the closer one stays visible.
+            render_pass.draw(3..6, 0..1);
+            render_pass.draw(0..3, 0..1);
+        }
+        encoder.copy_texture_to_buffer(
+            target.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(SIZE * 4),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        gpu.queue().submit(std::iter::once(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        gpu.device().poll(wgpu::Maintain::Wait);
+        assert_eq!(slice.get_mapped_range()[..4], [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn identical_pipelines_are_shared() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        let state = SceneState::from_storages(std::slice::from_ref(&gpu), vec![], 0).unwrap();
+        let key = |shader, color_target_formats: &[wgpu::TextureFormat]| PipelineKey {
+            gpu_index: gpu.index(),
+            shader: Some(shader),
+            color_target_formats: color_target_formats.to_vec(),
+            depth_format: None,
+            sample_count: 1,
+            config: PipelineConfig::default(),
+        };
+        let depth_shader = register_shader(ShaderSource::wgsl(DEPTH_SHADER));
+        let two_targets_shader = register_shader(ShaderSource::wgsl(TWO_TARGETS_SHADER));
+        let mut cache = PipelineCache::default();
+
+        // 16 jobs with the same shader rendering to two viewports of the same format.
+        let format = wgpu::TextureFormat::Bgra8Unorm;
+        let pipelines = (0..16 * 2)
+            .map(|_| cache.get_or_create(&gpu, &state, key(depth_shader, &[format])).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(cache.len(), 1);
+        assert!(pipelines.iter().all(|pipeline| Arc::ptr_eq(pipeline, &pipelines[0])));
+
+        let rgba_format = wgpu::TextureFormat::Rgba8Unorm;
+        cache.get_or_create(&gpu, &state, key(depth_shader, &[rgba_format])).unwrap();
+        cache.get_or_create(&gpu, &state, key(two_targets_shader, &[format, format])).unwrap();
+        assert_eq!(cache.len(), 3);
+
+        // Unregistered shaders fail instead of panicking.
+        let error = cache.get_or_create(&gpu, &state, key(ShaderId::from_index(1000), &[format]));
+        assert!(error.unwrap_err().message().contains("is not registered"));
+        // So do shaders that bind resources without a storage in the scene.
+        let unknown_resource_shader = register_shader(ShaderSource::wgsl(
+            "@resource(Unknown, 0) var<storage, read> unknown: array<u32>;",
+        ));
+        let error = cache.get_or_create(&gpu, &state, key(unknown_resource_shader, &[format]));
+        assert!(error.unwrap_err().message().contains("no storage for the resource Unknown"));
+        assert_eq!(cache.len(), 3);
+
+        // Shaders loaded at runtime can name their entry points differently.
+        let renamed_source = DEPTH_SHADER
+            .replace("vs_main", "vertex")
+            .replace("fs_main", "fragment");
+        let renamed_shader = register_shader(
+            ShaderSource::wgsl(renamed_source).with_entry_points("vertex", "fragment"),
+        );
+        cache.get_or_create(&gpu, &state, key(renamed_shader, &[format])).unwrap();
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn jobs_with_different_blending_get_distinct_pipelines() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        // The shader does not bind resources, so the scene does not need storages.
+        let shader = register_shader(ShaderSource::wgsl(DEPTH_SHADER));
+        let mut jobs = IdMap::new();
+        for blend in [wgpu::BlendState::REPLACE, wgpu::BlendState::ALPHA_BLENDING] {
+            let mut job = Job::new(JobKind::Update, noop_job, &[]);
+            job.set_shader(shader);
+            job.set_pipeline_config(PipelineConfig {
+                blend: Some(blend),
+                ..Default::default()
+            });
+            jobs.insert(job);
+        }
+        let state =
+            Arc::new(SceneState::from_storages(std::slice::from_ref(&gpu), vec![], 0).unwrap());
+        state.viewports().write().unwrap().insert(Viewport::offscreen(
+            gpu.clone(),
+            winit::dpi::PhysicalSize::new(4, 4),
+            wgpu::TextureFormat::Rgba8Unorm,
+            None,
+        ));
+        let mut scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state, 0).unwrap();
+        scheduler.configure_pipelines().unwrap();
+        assert_eq!(scheduler.pipeline_count(), 2);
+
+        let pipelines = scheduler.pipelines.read().unwrap();
+        let [replace, alpha_blending] = [0, 1].map(|job_index| {
+            let (_, job_pipeline) = pipelines
+                .iter()
+                .find(|((index, _), _)| *index == job_index)
+                .unwrap();
+            return job_pipeline.pipeline.clone().unwrap();
+        });
+        assert!(!Arc::ptr_eq(&replace, &alpha_blending));
+    }
+
+    // The texture the encoder test renders to and the buffer it is copied to.
+    struct EncoderTarget {
+        texture: wgpu::Texture,
+        view: wgpu::TextureView,
+        readback_buffer: wgpu::Buffer,
+    }
+
+    static ENCODER_TARGET: OnceLock<EncoderTarget> = OnceLock::new();
+
+    fn clear_target_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        let target = ENCODER_TARGET.get().unwrap();
+        sr.encoder().begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::RED),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        Ok(())
+    }
+
+    fn copy_target_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        let target = ENCODER_TARGET.get().unwrap();
+        sr.encoder().copy_texture_to_buffer(
+            target.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &target.readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(256),
+                    rows_per_image: None,
+                },
+            },
+            target.texture.size(),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn jobs_share_the_frame_encoder() {
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        let texture = gpu.device().create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: 64,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let readback_buffer = gpu.device().create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        ENCODER_TARGET.get_or_init(|| EncoderTarget {
+            texture,
+            view,
+            readback_buffer,
+        });
+
+        // The copy is registered first, it only sees the cleared texture because the commands are
+        // submitted in the order of the dependencies.
+        let mut jobs = IdMap::new();
+        let copy = insert_job(&mut jobs, copy_target_job);
+        let clear = insert_job(&mut jobs, clear_target_job);
+        jobs.get_mut(copy).unwrap().add_dependency(clear);
+
+        let state =
+            Arc::new(SceneState::from_storages(std::slice::from_ref(&gpu), vec![], 0).unwrap());
+        let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state, 2).unwrap();
+        assert!(scheduler.run_jobs(0.0, 1.0).is_ok());
+
+        let slice = ENCODER_TARGET.get().unwrap().readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        gpu.device().poll(wgpu::Maintain::Wait);
+        assert_eq!(slice.get_mapped_range()[..4], [255, 0, 0, 255]);
+    }
+
+    static STEPS: AtomicUsize = AtomicUsize::new(0);
+    static HELD_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+    fn movement_job(s: &SystemResources, _: &SceneState) -> Result<()> {
+        let input = s.input();
+        if input.just_pressed(Key::Right) {
+            STEPS.fetch_add(1, Ordering::SeqCst);
+        }
+        if input.is_pressed(Key::Right) {
+            HELD_FRAMES.fetch_add(1, Ordering::SeqCst);
+        }
+        return Ok(());
+    }
+
+    #[test]
+    fn jobs_read_the_input_of_the_frame() {
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, movement_job);
+        let state = Arc::new(SceneState::for_gpus(&[], 0).unwrap());
+        let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 1).unwrap();
+
+        state.input_mut().press(Key::Right);
+        for _ in 0..3 {
+            scheduler.run_jobs(0.0, 1.0).unwrap();
+            state.input_mut().end_frame();
+        }
+        state.input_mut().release(Key::Right);
+        scheduler.run_jobs(0.0, 1.0).unwrap();
+        assert_eq!(STEPS.load(Ordering::SeqCst), 1);
+        assert_eq!(HELD_FRAMES.load(Ordering::SeqCst), 3);
+    }
+
+    static RESIZES: Mutex<Vec<(ViewportId, u32, u32)>> = Mutex::new(Vec::new());
+
+    fn layout_job(s: &SystemResources, _: &SceneState) -> Result<()> {
+        for event in s.read::<ViewportEvent>().iter() {
+            if let ViewportEvent::Resized { viewport_id, size } = event {
+                RESIZES
+                    .lock()
+                    .unwrap()
+                    .push((*viewport_id, size.width, size.height));
+            }
+        }
+        return Ok(());
+    }
+
+    #[test]
+    fn viewport_events_reach_the_jobs_of_the_next_frame() {
+        ViewportEvent::register();
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, layout_job);
+        let state = Arc::new(SceneState::for_gpus(&[], 0).unwrap());
+        let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 1).unwrap();
+
+        let viewport_id = ViewportId::from_index(3);
+        let size = winit::dpi::PhysicalSize::new(640, 480);
+        state.emit_between_frames(ViewportEvent::Resized { viewport_id, size });
+        for _ in 0..2 {
+            scheduler.run_jobs(0.0, 1.0).unwrap();
+            state.end_frame();
+        }
+        assert_eq!(*RESIZES.lock().unwrap(), [(viewport_id, 640, 480)]);
+    }
+
+    static VIEWPORT_JOB_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    fn per_viewport_job(s: &SystemResources, _: &SceneState) -> Result<()> {
+        assert!(s.viewport().is_some());
+        VIEWPORT_JOB_RUNS.fetch_add(1, Ordering::SeqCst);
+        return Ok(());
+    }
+
+    static REGULAR_JOB_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    fn regular_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        REGULAR_JOB_RUNS.fetch_add(1, Ordering::SeqCst);
+        return Ok(());
+    }
+
+    #[test]
+    fn frames_finish_while_viewports_are_added_and_removed() {
+        // Without viewports, the per viewport jobs have nothing to run, but the jobs that depend
+        // on them still run.
+        let mut jobs = IdMap::new();
+        let first = insert_job(&mut jobs, regular_job);
+        let per_viewport = jobs.insert(Job::new(JobKind::Update, per_viewport_job, &[])).0;
+        let independent = jobs.insert(Job::new(JobKind::Update, per_viewport_job, &[])).0;
+        let last = insert_job(&mut jobs, regular_job);
+        jobs.get_mut(per_viewport).unwrap().add_dependency(first);
+        jobs.get_mut(last).unwrap().add_dependency(per_viewport);
+        jobs.get_mut(last).unwrap().add_dependency(independent);
+        let scheduler = make_scheduler(&jobs, 2);
+        crate::run_with_timeout(move || {
+            for _ in 0..20 {
+                REGULAR_JOB_RUNS.store(0, Ordering::SeqCst);
+                scheduler.run_jobs(0.0, 1.0).unwrap();
+                assert_eq!(REGULAR_JOB_RUNS.load(Ordering::SeqCst), 2);
+                scheduler.run_jobs_deterministic(0.0, 1.0).unwrap();
+                assert_eq!(REGULAR_JOB_RUNS.load(Ordering::SeqCst), 4);
+            }
+        });
+
+        let Some(gpu) = crate::test_gpu() else {
+            return;
+        };
+        // A regular job between two per viewport jobs waits for all viewports of the frame.
+        let mut jobs = IdMap::new();
+        let first = jobs.insert(Job::new(JobKind::Update, per_viewport_job, &[])).0;
+        let regular = insert_job(&mut jobs, noop_job);
+        let last = jobs.insert(Job::new(JobKind::Update, per_viewport_job, &[])).0;
+        jobs.get_mut(regular).unwrap().add_dependency(first);
+        jobs.get_mut(last).unwrap().add_dependency(regular);
+        let state =
+            Arc::new(SceneState::from_storages(std::slice::from_ref(&gpu), vec![], 0).unwrap());
+        let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 4).unwrap();
+
+        crate::run_with_timeout(move || {
+            let add_viewport = || {
+                let size = winit::dpi::PhysicalSize::new(1, 1);
+                let format = wgpu::TextureFormat::Rgba8Unorm;
+                let viewport = Viewport::offscreen(gpu.clone(), size, format, None);
+                return state.viewports().write().unwrap().insert(viewport).0;
+            };
+
+            // Between frames.
+            let mut viewport_ids = vec![add_viewport(), add_viewport()];
+            for frame in 0..20 {
+                if frame % 3 == 0 {
+                    state.remove_viewport(viewport_ids.remove(0));
+                } else {
+                    viewport_ids.push(add_viewport());
+                }
+                VIEWPORT_JOB_RUNS.store(0, Ordering::SeqCst);
+                scheduler.run_jobs(0.0, 1.0).unwrap();
+                assert_eq!(VIEWPORT_JOB_RUNS.load(Ordering::SeqCst), 2 * viewport_ids.len());
+            }
+
+            // Viewports are added while frames run, but only removed between them, see
+            // `SceneState::remove_viewport`.
+            let stop = AtomicBool::new(false);
+            thread::scope(|scope| {
+                scope.spawn(|| {
+                    while !stop.load(Ordering::SeqCst) {
+                        if state.viewports().read().unwrap().len() < 4 {
+                            add_viewport();
+                        }
+                        thread::yield_now();
+                    }
+                });
+                for _ in 0..200 {
+                    scheduler.run_jobs(0.0, 1.0).unwrap();
+                    let viewports = state.viewports().read().unwrap();
+                    let removed_viewport = viewports.into_iter().next().map(|(id, _)| id);
+                    drop(viewports);
+                    if let Some(viewport_id) = removed_viewport {
+                        state.remove_viewport(viewport_id);
+                    }
+                }
+                stop.store(true, Ordering::SeqCst);
+            });
+        });
+    }
+
+    #[test]
+    fn unordered_writers_are_rejected() {
+        let resource_id = ResourceId::from_index(0);
+        let mut jobs = IdMap::new();
+        let first_writer =
+            insert_job_accessing(&mut jobs, noop_job, ResourceAccess::Write(resource_id));
+        let reader = insert_job_accessing(&mut jobs, noop_job, ResourceAccess::Read(resource_id));
+        let second_writer =
+            insert_job_accessing(&mut jobs, noop_job, ResourceAccess::ReadWrite(resource_id));
+        let state = Arc::new(SceneState::for_gpus(&[], 0).unwrap());
+        assert!(Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 1).is_err());
+
+        // A transitive dependency orders the writers.
+        jobs.get_mut(reader).unwrap().add_dependency(first_writer);
+        jobs.get_mut(second_writer).unwrap().add_dependency(reader);
+        assert!(Scheduler::from_jobs(&jobs, JobKind::Update, state, 1).is_ok());
+    }
+
+    #[test]
+    fn dropping_scheduler_stops_workers() {
+        let state = Arc::new(SceneState::for_gpus(&[], 0).unwrap());
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, noop_job);
+
+        for _ in 0..20 {
+            let scheduler = Scheduler::from_jobs(&jobs, JobKind::Update, state.clone(), 4).unwrap();
+            assert!(scheduler.run_jobs(0.0, 1.0).is_ok());
+            drop(scheduler);
+
+            // Every worker holds a reference to the state until it exits.
+            assert_eq!(Arc::strong_count(&state), 1);
+        }
+    }
+
+    static EXECUTION_ORDER: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+    fn first_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        EXECUTION_ORDER.lock().unwrap().push(1);
+        Ok(())
+    }
+
+    fn second_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        EXECUTION_ORDER.lock().unwrap().push(2);
+        Ok(())
+    }
+
+    fn third_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        EXECUTION_ORDER.lock().unwrap().push(3);
+        Ok(())
+    }
+
+    #[test]
+    fn single_worker_honors_dependencies() {
+        // The jobs are registered in reverse order, so they only run in order because of their
+        // dependencies.
+        let mut jobs = IdMap::new();
+        let third = insert_job(&mut jobs, third_job);
+        let second = insert_job(&mut jobs, second_job);
+        let first = insert_job(&mut jobs, first_job);
+        jobs.get_mut(third).unwrap().add_dependency(second);
+        jobs.get_mut(second).unwrap().add_dependency(first);
+
+        let scheduler = make_scheduler(&jobs, 1);
+        assert_eq!(scheduler.worker_count(), 1);
+        for _ in 0..3 {
+            assert!(scheduler.run_jobs(0.0, 1.0).is_ok());
+        }
+        assert_eq!(*EXECUTION_ORDER.lock().unwrap(), [1, 2, 3, 1, 2, 3, 1, 2, 3]);
+    }
+
+    static FRAME_IDS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+    fn record_frame_id_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        FRAME_IDS.lock().unwrap().push(sr.frame_id());
+        Ok(())
+    }
+
+    static FIXED_DELTA_TIMES: Mutex<Vec<f32>> = Mutex::new(Vec::new());
+
+    fn record_fixed_delta_time_job(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        FIXED_DELTA_TIMES.lock().unwrap().push(sr.fixed_delta_time());
+        Ok(())
+    }
+
+    #[test]
+    fn jobs_know_the_fixed_delta_time() {
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, record_fixed_delta_time_job);
+
+        for worker_count in [2, 0] {
+            let scheduler = make_scheduler(&jobs, worker_count);
+            scheduler.run_jobs(0.0, 0.0).unwrap();
+            scheduler.set_fixed_delta_time(0.5);
+            scheduler.run_jobs(0.0, 0.0).unwrap();
+            let fixed_delta_times = std::mem::take(&mut *FIXED_DELTA_TIMES.lock().unwrap());
+            assert_eq!(fixed_delta_times, [DEFAULT_FIXED_DELTA_TIME, 0.5]);
+        }
+    }
+
+    #[test]
+    fn frame_id_increments_once_per_frame() {
+        let mut jobs = IdMap::new();
+        for _ in 0..4 {
+            insert_job(&mut jobs, record_frame_id_job);
+        }
+
+        // Workers and the deterministic mode see the same ids.
+        for worker_count in [3, 0] {
+            let scheduler = make_scheduler(&jobs, worker_count);
+            for frame_id in 0..3 {
+                scheduler.run_jobs(0.0, 0.0).unwrap();
+                let frame_ids = std::mem::take(&mut *FRAME_IDS.lock().unwrap());
+                assert_eq!(frame_ids, [frame_id; 4]);
+            }
+        }
+    }
+
+    static GOLDEN_ORDER: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+    fn record_job<const N: usize>(_: &SystemResources, _: &SceneState) -> Result<()> {
+        GOLDEN_ORDER.lock().unwrap().push(N);
+        Ok(())
+    }
+
+    #[test]
+    fn deterministic_order_is_stable() {
+        // 1 -> 4 -> 0 and 2 -> 0, 3 is independent and 5 depends on 3.
+        let mut jobs = IdMap::new();
+        let ids = [
+            insert_job(&mut jobs, record_job::<0>),
+            insert_job(&mut jobs, record_job::<1>),
+            insert_job(&mut jobs, record_job::<2>),
+            insert_job(&mut jobs, record_job::<3>),
+            insert_job(&mut jobs, record_job::<4>),
+            insert_job(&mut jobs, record_job::<5>),
+        ];
+        jobs.get_mut(ids[4]).unwrap().add_dependency(ids[1]);
+        jobs.get_mut(ids[0]).unwrap().add_dependency(ids[4]);
+        jobs.get_mut(ids[0]).unwrap().add_dependency(ids[2]);
+        jobs.get_mut(ids[5]).unwrap().add_dependency(ids[3]);
+
+        // Without workers, the jobs run on the calling thread in the same order every frame.
+        let scheduler = make_scheduler(&jobs, 0);
+        for _ in 0..3 {
+            assert!(scheduler.run_jobs(0.0, 1.0).is_ok());
+            let order = std::mem::take(&mut *GOLDEN_ORDER.lock().unwrap());
+            assert_eq!(order, [1, 2, 3, 4, 0, 5]);
+        }
+
+        // The parallel path executes the same jobs, only the order of independent ones differs.
+        let scheduler = make_scheduler(&jobs, 4);
+        assert!(scheduler.run_jobs(0.0, 1.0).is_ok());
+        let mut order = std::mem::take(&mut *GOLDEN_ORDER.lock().unwrap());
+        let position = |job| order.iter().position(|&j| j == job).unwrap();
+        assert!(position(1) < position(4) && position(4) < position(0));
+        assert!(position(2) < position(0) && position(3) < position(5));
+        order.sort();
+        assert_eq!(order, [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[cfg(feature = "thread-affinity")]
+    #[test]
+    fn workers_are_pinned_to_distinct_cores() {
+        let core_count = core_affinity::get_core_ids().map_or(0, |ids| ids.len());
+        let scheduler = make_scheduler(&IdMap::new(), core_count.min(4));
+
+        let mut cores = scheduler
+            .worker_cores()
+            .iter()
+            .map(|core| core.expect("worker is not pinned"))
+            .collect::<Vec<_>>();
+        cores.sort();
+        cores.dedup();
+        assert_eq!(cores.len(), core_count.min(4));
+    }
+
+    #[cfg(not(feature = "thread-affinity"))]
+    #[test]
+    fn workers_are_not_pinned_by_default() {
+        let scheduler = make_scheduler(&IdMap::new(), 2);
+        assert_eq!(scheduler.worker_cores(), &[None, None]);
+    }
+
+    static WORKER_THREADS: Mutex<Vec<thread::ThreadId>> = Mutex::new(Vec::new());
+
+    fn record_worker_thread_job(_: &SystemResources, _: &SceneState) -> Result<()> {
+        WORKER_THREADS.lock().unwrap().push(thread::current().id());
+        return Ok(());
+    }
+
+    #[test]
+    fn schedulers_with_shared_workers_run_their_jobs_on_the_same_threads() {
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, record_worker_thread_job);
+        let scheduler = make_scheduler(&jobs, 1);
+        let state = scheduler.state.clone();
+        let other_scheduler =
+            Scheduler::with_workers(&jobs, JobKind::Update, state, scheduler.workers.clone())
+                .unwrap();
+        assert_eq!(other_scheduler.worker_count(), 1);
+
+        for _ in 0..2 {
+            scheduler.run_jobs(0.0, 1.0).unwrap();
+            other_scheduler.run_jobs(0.0, 1.0).unwrap();
+        }
+        let threads = std::mem::take(&mut *WORKER_THREADS.lock().unwrap());
+        assert_eq!(threads.len(), 4);
+        assert!(threads.iter().all(|&thread| thread == threads[0]));
+        assert_ne!(threads[0], thread::current().id());
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    crate::test_resource!(Position, EntityComponent, IdMappedResourceStorage<EntityId, Position>);
+
+    fn spawn_entity(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        if sr.game_time() == 0.0 {
+            sr.spawn(EntityDescriptor::new().with(Position { x: 1.0, y: 2.0 }));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn spawned_entities_have_their_components() {
+        Position::register();
+
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, spawn_entity);
+
+        let scheduler = make_scheduler(&jobs, 1);
+        assert!(scheduler.run_jobs(0.0, 1.0).is_ok());
+
+        let entities = scheduler.state.entities().read().unwrap();
+        let positions = scheduler.state.resource_storage_ref::<Position>().unwrap();
+        assert_eq!(entities.len(), 1);
+        assert_eq!(
+            positions.iter().collect::<Vec<_>>(),
+            vec![(EntityId::from_index(0), &Position { x: 1.0, y: 2.0 })]
+        );
+    }
+
+    struct Follows(EntityId);
+
+    crate::test_resource!(Follows, EntityComponent, IdMappedResourceStorage<EntityId, Follows>);
+
+    fn spawn_leader_and_follower(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        if sr.frame_id() < 2 {
+            let leader = sr.spawn(EntityDescriptor::new().with(Position { x: 1.0, y: 2.0 }));
+            sr.spawn(EntityDescriptor::new().with(Follows(leader)));
+        }
+        if sr.frame_id() == 1 {
+            return Err(Error::new("second frame", SourceLocation::here()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn spawned_entities_are_known_before_the_end_of_the_frame() {
+        Position::register();
+        Follows::register();
+
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, spawn_leader_and_follower);
+
+        let scheduler = make_scheduler(&jobs, 1);
+        assert!(scheduler.run_jobs(0.0, 1.0).is_ok());
+        let follows = scheduler.state.resource_storage_ref::<Follows>().unwrap();
+        let (follower, Follows(leader)) = follows.iter().next().unwrap();
+        assert_ne!(follower, *leader);
+        let positions = scheduler.state.resource_storage_ref::<Position>().unwrap();
+        assert_eq!(positions.get(*leader), Some(&Position { x: 1.0, y: 2.0 }));
+        drop((follows, positions));
+
+        // The ids reserved in a failed frame are released again.
+        assert!(scheduler.run_jobs(1.0, 1.0).is_err());
+        assert_eq!(scheduler.state.entity_ids().count(), 2);
+        assert_eq!(scheduler.state.resource_storage_ref::<Follows>().unwrap().len(), 1);
+    }
+
+    fn spawn_and_despawn_entity(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        if sr.game_time() == 0.0 {
+            sr.spawn(EntityDescriptor::new().with(Position { x: 1.0, y: 2.0 }));
+        } else if sr.game_time() == 1.0 {
+            sr.entity_despawner().send(EntityId::from_index(0)).unwrap();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn despawned_entities_lose_their_components() {
+        Position::register();
+
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, spawn_and_despawn_entity);
+
+        let scheduler = make_scheduler(&jobs, 1);
+        for frame in 0..2 {
+            assert!(scheduler.run_jobs(frame as f32, 1.0).is_ok());
+        }
+
+        // The new entity reuses the index of the despawned one.
+        let entity_id = scheduler.state.entities().write().unwrap().reserve();
+        assert_eq!(entity_id.index(), 0);
+
+        let positions = scheduler.state.resource_storage_ref::<Position>().unwrap();
+        assert!(positions.get(entity_id).is_none());
+        assert_eq!(positions.iter().count(), 0);
+    }
+
+    fn despawn_entity_in_failing_frame(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        if sr.frame_id() == 0 {
+            sr.spawn(EntityDescriptor::new().with(Position { x: 1.0, y: 2.0 }));
+        } else if sr.frame_id() == 1 {
+            sr.entity_despawner().send(EntityId::from_index(0)).unwrap();
+            return Err(Error::new("second frame", SourceLocation::here()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn entities_despawned_in_failed_frames_are_kept() {
+        Position::register();
+
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, despawn_entity_in_failing_frame);
+
+        let scheduler = make_scheduler(&jobs, 1);
+        assert!(scheduler.run_jobs(0.0, 1.0).is_ok());
+        assert!(scheduler.run_jobs(1.0, 1.0).is_err());
+        assert!(scheduler.run_jobs(2.0, 1.0).is_ok());
+
+        let entity_id = scheduler.state.entity_ids().next().unwrap();
+        assert_eq!(entity_id.index(), 0);
+        let positions = scheduler.state.resource_storage_ref::<Position>().unwrap();
+        assert_eq!(positions.get(entity_id), Some(&Position { x: 1.0, y: 2.0 }));
+    }
+
+    fn restructure_entities(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        let first = EntityId::from_index(0);
+        let second = EntityId::from_index(1);
+        match sr.frame_id() {
+            0 => {
+                sr.commands()
+                    .spawn(EntityDescriptor::new().with(Position { x: 1.0, y: 1.0 }));
+                sr.commands().spawn(EntityDescriptor::new());
+            }
+            1 => {
+                sr.commands().remove::<Position>(first)?;
+                sr.commands().insert(second, Position { x: 2.0, y: 2.0 })?;
+            }
+            2 => {
+                sr.commands().despawn(first);
+                // Ignored, the entity is despawned before.
+                sr.commands().insert(first, Position { x: 3.0, y: 3.0 })?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn commands_change_entities_after_the_frame() {
+        Position::register();
+
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, restructure_entities);
+
+        for worker_count in [0, 2] {
+            let scheduler = make_scheduler(&jobs, worker_count);
+            let positions = || {
+                return scheduler
+                    .state
+                    .resource_storage_ref::<Position>()
+                    .unwrap()
+                    .iter()
+                    .map(|(entity_id, position)| (entity_id.index(), position.x))
+                    .collect::<Vec<_>>();
+            };
+
+            assert!(scheduler.run_jobs(0.0, 1.0).is_ok());
+            assert_eq!(scheduler.state.entity_ids().count(), 2);
+            assert_eq!(positions(), [(0, 1.0)]);
+
+            assert!(scheduler.run_jobs(1.0, 1.0).is_ok());
+            assert_eq!(positions(), [(1, 2.0)]);
+
+            assert!(scheduler.run_jobs(2.0, 1.0).is_ok());
+            assert_eq!(
+                scheduler.state.entity_ids().collect::<Vec<_>>(),
+                [EntityId::from_index(1)]
+            );
+            assert_eq!(positions(), [(1, 2.0)]);
+        }
+    }
+
+    static COMMAND_ERRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    fn record_mismatched_commands(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        let entity_id = sr.commands().spawn(EntityDescriptor::new());
+        let errors = [
+            sr.commands().insert_slice(entity_id, vec![Position { x: 0.0, y: 0.0 }]),
+            sr.commands().insert(entity_id, Ping(0.0)),
+            sr.commands().remove::<Ping>(entity_id),
+        ];
+        COMMAND_ERRORS
+            .lock()
+            .unwrap()
+            .extend(errors.into_iter().map(|result| result.unwrap_err().to_string()));
+
+        // Descriptors are only checked when the entity is spawned.
+        let position = vec![Position { x: 1.0, y: 1.0 }];
+        sr.commands()
+            .spawn(EntityDescriptor::new().with_slice(position));
+        return Ok(());
+    }
+
+    #[test]
+    fn mismatched_commands_fail_instead_of_panicking() {
+        Position::register();
+        Ping::register();
+
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, record_mismatched_commands);
+        let scheduler = make_scheduler(&jobs, 1);
+
+        let error = scheduler.run_jobs(0.0, 1.0).unwrap_err().to_string();
+        assert!(error.contains("is not a"), "{error}");
+        let errors = std::mem::take(&mut *COMMAND_ERRORS.lock().unwrap());
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].contains("Position is not stored as a slice"), "{}", errors[0]);
+        assert!(errors[1].contains("Ping is not an entity component"), "{}", errors[1]);
+        assert!(errors[2].contains("Ping is not an entity component"), "{}", errors[2]);
+        assert_eq!(scheduler.state.entity_ids().count(), 2);
+    }
+
+    struct Ping(f32);
+
+    crate::test_resource!(Ping, Event, EventStorage<Ping>);
+
+    static RECEIVED_PINGS: Mutex<Vec<Vec<f32>>> = Mutex::new(Vec::new());
+
+    fn send_ping(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        if sr.game_time() == 0.0 {
+            sr.emit(Ping(sr.game_time()));
+        }
+        Ok(())
+    }
+
+    fn receive_pings(sr: &SystemResources, _: &SceneState) -> Result<()> {
+        let pings = sr.read::<Ping>().iter().map(|ping| ping.0).collect();
+        RECEIVED_PINGS.lock().unwrap().push(pings);
+        Ok(())
+    }
+
+    #[test]
+    fn events_are_readable_in_the_next_frame() {
+        Ping::register();
+
+        let mut jobs = IdMap::new();
+        insert_job(&mut jobs, send_ping);
+        insert_job(&mut jobs, receive_pings);
+
+        let scheduler = make_scheduler(&jobs, 2);
+        for frame in 0..3 {
+            assert!(scheduler.run_jobs(frame as f32, 1.0).is_ok());
+            scheduler.state.end_frame();
+        }
+
+        assert_eq!(
+            *RECEIVED_PINGS.lock().unwrap(),
+            vec![vec![], vec![0.0], vec![]]
+        );
+    }
 }
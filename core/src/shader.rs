@@ -0,0 +1,87 @@
+use crate::{IdMap, StandardVersionedIndexId};
+use lazy_static::lazy_static;
+use std::sync::RwLock;
+
+pub type ShaderId = StandardVersionedIndexId;
+
+// The WGSL source of a shader and the names of the entry points of its vertex, fragment and
+// compute stage. A shader only needs the entry points of the pipelines it is used for. Jobs refer
+// to registered shaders by their id, see `set_job_shader` and `set_job_compute_shader`. Jobs
+// without a shader use the built-in shader of the GPU. The buffers of resources are bound with
+// placeholders like `@resource(Mesh, 0)`, which are replaced with the group and binding of the
+// resource in the scene, see `SceneState::resolve_resource_bindings`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderSource {
+    pub wgsl: String,
+    pub vertex_entry_point: String,
+    pub fragment_entry_point: String,
+    pub compute_entry_point: String,
+}
+
+impl ShaderSource {
+    // The entry points of the built-in shader, which are also the defaults for other shaders.
+    pub const DEFAULT_VERTEX_ENTRY_POINT: &'static str = "vs_main";
+    pub const DEFAULT_FRAGMENT_ENTRY_POINT: &'static str = "fs_main";
+    pub const DEFAULT_COMPUTE_ENTRY_POINT: &'static str = "cs_main";
+
+    pub fn wgsl(wgsl: impl Into<String>) -> Self {
+        return Self {
+            wgsl: wgsl.into(),
+            vertex_entry_point: Self::DEFAULT_VERTEX_ENTRY_POINT.to_string(),
+            fragment_entry_point: Self::DEFAULT_FRAGMENT_ENTRY_POINT.to_string(),
+            compute_entry_point: Self::DEFAULT_COMPUTE_ENTRY_POINT.to_string(),
+        };
+    }
+
+    pub fn with_entry_points(mut self, vertex: &str, fragment: &str) -> Self {
+        self.vertex_entry_point = vertex.to_string();
+        self.fragment_entry_point = fragment.to_string();
+        return self;
+    }
+
+    pub fn with_compute_entry_point(mut self, compute: &str) -> Self {
+        self.compute_entry_point = compute.to_string();
+        return self;
+    }
+}
+
+lazy_static! {
+    static ref REGISTERED_SHADERS: RwLock<IdMap<ShaderId, ShaderSource>> =
+        RwLock::new(IdMap::new());
+}
+
+// Registers a shader, e.g., one that was loaded from a file at runtime. The source is only
+// compiled when a pipeline is created with it, so errors surface when the pipelines of the jobs
+// using it are configured.
+pub fn register_shader(source: ShaderSource) -> ShaderId {
+    return REGISTERED_SHADERS.write().unwrap().insert(source).0;
+}
+
+pub fn shader_source(shader_id: ShaderId) -> Option<ShaderSource> {
+    return REGISTERED_SHADERS.read().unwrap().get(shader_id).cloned();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn registered_shaders_can_be_looked_up() {
+        let default_entry_points = register_shader(ShaderSource::wgsl("// first"));
+        let custom_entry_points = register_shader(
+            ShaderSource::wgsl(String::from("// second")).with_entry_points("vertex", "fragment"),
+        );
+        assert_ne!(default_entry_points, custom_entry_points);
+
+        let source = shader_source(default_entry_points).unwrap();
+        assert_eq!(source.wgsl, "// first");
+        assert_eq!(source.vertex_entry_point, "vs_main");
+        assert_eq!(source.fragment_entry_point, "fs_main");
+        assert_eq!(source.compute_entry_point, "cs_main");
+
+        let source = shader_source(custom_entry_points).unwrap();
+        assert_eq!(source.wgsl, "// second");
+        assert_eq!(source.vertex_entry_point, "vertex");
+        assert_eq!(source.fragment_entry_point, "fragment");
+    }
+}
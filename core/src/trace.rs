@@ -0,0 +1,129 @@
+// Instrumentation of the scheduler and the registries with `tracing`. With the `tracing` feature
+// disabled, the macros expand to nothing, so neither are their arguments evaluated nor is anything
+// printed.
+
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        ::tracing::debug_span!($($arg)*).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        $crate::trace::DisabledSpan
+    };
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! trace_debug {
+    ($($arg:tt)*) => {
+        ::tracing::debug!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_debug {
+    ($($arg:tt)*) => {{}};
+}
+
+// Reports errors that cannot be returned to the caller, e.g., the ones of the scenes ticked by the
+// event loop of the instance.
+#[cfg(feature = "tracing")]
+macro_rules! trace_error {
+    ($($arg:tt)*) => {
+        ::tracing::error!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_error {
+    // Reading a variable has no side effects, using it keeps it from being reported as unused.
+    (%$error:ident, $($arg:tt)*) => {{
+        let _ = &$error;
+    }};
+    ($($arg:tt)*) => {{}};
+}
+
+// Stands in for an entered span, so the guards of spans are bound the same way with and without
+// the feature.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct DisabledSpan;
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[cfg(not(feature = "tracing"))]
+    fn nothing_is_traced_without_the_feature() {
+        // The arguments are not evaluated, so this neither panics nor prints anything.
+        let super::DisabledSpan = trace_span!("frame", frame = unreachable!());
+        trace_debug!(resource = "Position", "registered {}", unreachable!());
+        trace_error!(error = %unreachable!(), "failed to tick a scene");
+    }
+
+    // Records the spans and events as text, e.g., "span frame frame=3".
+    #[cfg(feature = "tracing")]
+    struct RecordingSubscriber {
+        records: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[cfg(feature = "tracing")]
+    struct FieldRecorder<'a>(&'a mut String);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::field::Visit for FieldRecorder<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for RecordingSubscriber {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            return true;
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut record = format!("span {}", span.metadata().name());
+            span.record(&mut FieldRecorder(&mut record));
+            self.records.lock().unwrap().push(record);
+            return tracing::span::Id::from_u64(1);
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            let mut record = format!("{}", event.metadata().level());
+            event.record(&mut FieldRecorder(&mut record));
+            self.records.lock().unwrap().push(record);
+        }
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn spans_and_events_are_reported_with_their_fields() {
+        let subscriber = std::sync::Arc::new(RecordingSubscriber {
+            records: std::sync::Mutex::new(Vec::new()),
+        });
+        tracing::subscriber::with_default(subscriber.clone(), || {
+            let _span = trace_span!("frame", frame = 3);
+            trace_debug!(resource = "Position", "registered resource");
+            trace_error!(error = %"job 1 failed", "failed to tick a scene");
+        });
+        assert_eq!(
+            *subscriber.records.lock().unwrap(),
+            [
+                "span frame frame=3",
+                "DEBUG message=registered resource resource=\"Position\"",
+                "ERROR message=failed to tick a scene error=job 1 failed",
+            ]
+        );
+    }
+}
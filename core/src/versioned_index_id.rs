@@ -1,5 +1,9 @@
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{mem::size_of, fmt::Display, hash::Hash};
 
+use crate::{Error, Result, SourceLocation};
+
 // #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 // pub struct VersionedIndexId<const VERSION_BITS: usize> {
 //     id: i32,
@@ -49,7 +53,7 @@ use std::{mem::size_of, fmt::Display, hash::Hash};
 //     let next_id = id.next_version_id();
 // }
 
-pub trait VersionedIndexId: Send + Sync + Copy + Eq + Display + Hash {
+pub trait VersionedIndexId: Send + Sync + Copy + Eq + Display + Hash + Pod {
     const INDEX_BITS: usize;
     const VERSION_BITS: usize;
     const NUM_INDICES: usize;
@@ -60,6 +64,28 @@ pub trait VersionedIndexId: Send + Sync + Copy + Eq + Display + Hash {
     fn from_index(index: usize) -> Self;
     fn from_index_and_version(index: usize, version: usize) -> Self;
 
+    // Like `from_index` but returns an error instead of panicking if the index does not fit into
+    // the id, e.g., for indices read from a scene file.
+    fn try_from_index(index: usize) -> Result<Self> {
+        return Self::try_from_index_and_version(index, 0);
+    }
+
+    fn try_from_index_and_version(index: usize, version: usize) -> Result<Self> {
+        if index > Self::MAX_INDEX {
+            return Err(Error::new(
+                format!("index {index} exceeds the maximum index {}", Self::MAX_INDEX),
+                SourceLocation::here(),
+            ));
+        }
+        if version > Self::MAX_VERSION {
+            return Err(Error::new(
+                format!("version {version} exceeds the maximum version {}", Self::MAX_VERSION),
+                SourceLocation::here(),
+            ));
+        }
+        return Ok(Self::from_index_and_version(index, version));
+    }
+
     fn id(&self) -> i32;
     fn version(&self) -> usize;
     fn index(&self) -> usize;
@@ -67,17 +93,35 @@ pub trait VersionedIndexId: Send + Sync + Copy + Eq + Display + Hash {
     fn next_version_id(&self) -> Self;
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+// Ids are serialized as the plain u32, e.g., to reference entities in saved scenes.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
 pub struct StandardVersionedIndexId<const VERSION_BITS: usize = 8> {
     id: u32,
 }
 
+// The id is a plain u32, so it can be uploaded to the GPU as is. The derive macros of bytemuck do
+// not support const generics.
+unsafe impl<const VERSION_BITS: usize> Zeroable for StandardVersionedIndexId<VERSION_BITS> {}
+unsafe impl<const VERSION_BITS: usize> Pod for StandardVersionedIndexId<VERSION_BITS> {}
+
+// VERSION_BITS may be 0 or 32, so the index or the version can occupy all 32 bits of the id.
+// Shifting a u32 by 32 overflows, so these helpers handle that case explicitly.
+const fn shift_left(value: u32, bits: usize) -> u32 {
+    if bits >= u32::BITS as usize { 0 } else { value << bits }
+}
+
+const fn shift_right(value: u32, bits: usize) -> u32 {
+    if bits >= u32::BITS as usize { 0 } else { value >> bits }
+}
+
 impl<const VERSION_BITS: usize> StandardVersionedIndexId<VERSION_BITS> {
     pub const fn from_index_and_version(index: u32, version: u32) -> Self {
-        assert!(index < (1u32 << Self::INDEX_BITS));
-        assert!(version < (1u32 << Self::VERSION_BITS));
+        assert!((index as u64) < (1u64 << Self::INDEX_BITS));
+        assert!((version as u64) < (1u64 << Self::VERSION_BITS));
         return Self {
-            id: index + (version << Self::INDEX_BITS),
+            id: index | shift_left(version, Self::INDEX_BITS),
         }
     }
 }
@@ -91,22 +135,17 @@ impl<const VERSION_BITS: usize> VersionedIndexId for StandardVersionedIndexId<VE
     const MAX_VERSION: usize = Self::NUM_VERSIONS - 1;
 
     fn from_index(index: usize) -> Self {
-        assert!(index < (1 << Self::INDEX_BITS));
-        return Self {
-            id: index.try_into().unwrap(),
-        }
+        return VersionedIndexId::from_index_and_version(index, 0);
     }
 
     fn from_index_and_version(index: usize, version: usize) -> Self {
-        assert!(index < (1usize << Self::INDEX_BITS));
-        assert!(version < (1usize << Self::VERSION_BITS));
-        return Self {
-            id: (index + (version << Self::INDEX_BITS)).try_into().unwrap(),
-        }
+        assert!(index <= Self::MAX_INDEX);
+        assert!(version <= Self::MAX_VERSION);
+        return Self::from_index_and_version(index as u32, version as u32);
     }
 
     fn id(&self) -> i32 { i32::from_ne_bytes(self.id.to_ne_bytes()) }
-    fn version(&self) -> usize { (self.id >> Self::INDEX_BITS).try_into().unwrap() }
+    fn version(&self) -> usize { shift_right(self.id, Self::INDEX_BITS).try_into().unwrap() }
     fn index(&self) -> usize { <u32 as TryInto<usize>>::try_into(self.id).unwrap() & Self::MAX_INDEX }
 
     fn next_version_id(&self) -> Self {
@@ -114,6 +153,17 @@ impl<const VERSION_BITS: usize> VersionedIndexId for StandardVersionedIndexId<VE
     }
 }
 
+// Deserialized ids come from files, so their index and version are checked with the fallible
+// constructor instead of panicking.
+impl<'de, const VERSION_BITS: usize> Deserialize<'de> for StandardVersionedIndexId<VERSION_BITS> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let id = u64::deserialize(deserializer)?;
+        let index = id as usize & Self::MAX_INDEX;
+        let version = (id >> Self::INDEX_BITS) as usize;
+        return <Self as VersionedIndexId>::try_from_index_and_version(index, version)
+            .map_err(|error| serde::de::Error::custom(error.message()));
+    }
+}
 
 impl<const VERSION_BITS: usize> Display for StandardVersionedIndexId<VERSION_BITS> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -136,3 +186,54 @@ fn versioned_index_id_works() {
     assert_eq!(wrapped_around.index(), 23);
     assert_eq!(wrapped_around.version(), 0);
 }
+
+#[test]
+fn out_of_range_ids_are_rejected() {
+    type Id = StandardVersionedIndexId<8>;
+    let id = Id::try_from_index_and_version(Id::MAX_INDEX, Id::MAX_VERSION).unwrap();
+    assert_eq!((id.index(), id.version()), (Id::MAX_INDEX, Id::MAX_VERSION));
+    assert_eq!(Id::try_from_index(10).unwrap(), Id::from_index(10));
+
+    let error = Id::try_from_index(Id::NUM_INDICES).unwrap_err();
+    assert_eq!(error.message(), "index 16777216 exceeds the maximum index 16777215");
+    let error = Id::try_from_index_and_version(0, Id::NUM_VERSIONS).unwrap_err();
+    assert_eq!(error.message(), "version 256 exceeds the maximum version 255");
+}
+
+#[test]
+fn out_of_range_ids_are_not_deserialized() {
+    type Id = StandardVersionedIndexId<8>;
+    let id: Id = serde_json::from_str("4294967295").unwrap();
+    assert_eq!((id.index(), id.version()), (Id::MAX_INDEX, Id::MAX_VERSION));
+
+    let error = serde_json::from_str::<Id>("4294967296").unwrap_err();
+    assert_eq!(error.to_string(), "version 256 exceeds the maximum version 255");
+}
+
+#[test]
+fn versioned_index_id_without_version_bits_works() {
+    type Id = StandardVersionedIndexId<0>;
+    let id = Id::from_index(u32::MAX as usize);
+    assert_eq!(id.index(), u32::MAX as usize);
+    assert_eq!(id.version(), 0);
+
+    let next_id = id.next_version_id();
+    assert_eq!(next_id, id);
+
+    const CONST_ID: Id = Id::from_index_and_version(0xffff_fffe, 0);
+    assert_eq!(CONST_ID.index(), 0xffff_fffe);
+}
+
+#[test]
+fn versioned_index_id_without_index_bits_works() {
+    type Id = StandardVersionedIndexId<32>;
+    let id = Id::from_index_and_version(0, u32::MAX);
+    assert_eq!(id.index(), 0);
+    assert_eq!(id.version(), u32::MAX as usize);
+
+    let wrapped_around = id.next_version_id();
+    assert_eq!(wrapped_around.version(), 0);
+
+    const CONST_ID: Id = Id::from_index_and_version(0, 0x8000_0000);
+    assert_eq!(CONST_ID.version(), 0x8000_0000);
+}
@@ -0,0 +1,42 @@
+use std::sync::OnceLock;
+
+use winit::dpi::PhysicalSize;
+
+use crate::{register_resource, EventStorage, Resource, ResourceId, ResourceKind, ViewportId};
+
+// Changes of the windows of the viewports of a scene. The scene emits them between frames, see
+// `SceneState::emit_between_frames`, so the jobs of the next frame can react to them, e.g., to
+// recompute a layout after a resize or to save the game before the window closes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ViewportEvent {
+    // The viewport was resized, emitted when the new size is applied at the beginning of a tick.
+    Resized { viewport_id: ViewportId, size: PhysicalSize<u32> },
+    Focused { viewport_id: ViewportId, focused: bool },
+    // The user asked to close the window of the viewport. If the instance quits because of it,
+    // the scenes run one more frame, so the jobs see the event.
+    CloseRequested { viewport_id: ViewportId },
+}
+
+static VIEWPORT_EVENT_ID: OnceLock<ResourceId> = OnceLock::new();
+
+impl Resource for ViewportEvent {
+    type Type = ViewportEvent;
+    type Storage = EventStorage<ViewportEvent>;
+
+    // The event is registered on first use, so jobs can read it without registering it first.
+    fn id() -> ResourceId {
+        return *VIEWPORT_EVENT_ID.get_or_init(register_resource::<ViewportEvent>);
+    }
+
+    fn kind() -> ResourceKind {
+        return ResourceKind::Event;
+    }
+
+    fn label() -> &'static str {
+        return "ViewportEvent";
+    }
+
+    fn register() {
+        Self::id();
+    }
+}
@@ -0,0 +1,80 @@
+// The counting allocator replaces the global allocator of the whole test binary, so this test
+// lives in its own binary instead of the unit tests of the crate.
+
+use ovis_core::{
+    add_job_dependency, register_job, set_job_executed_per_viewport, JobFunction, JobId, JobKind,
+    Result, SceneState, Scheduler, SystemResources,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+// Counts the bytes allocated by threads that opted in, so allocations of the test harness are
+// ignored.
+struct CountingAllocator;
+
+thread_local! {
+    static COUNT_ALLOCATIONS: Cell<bool> = const { Cell::new(false) };
+}
+
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNT_ALLOCATIONS
+            .try_with(|count| count.get())
+            .unwrap_or(false)
+        {
+            ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::SeqCst);
+        }
+        return System.alloc(layout);
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Enables counting the allocations of the worker thread that executes the job.
+fn count_worker_allocations(_: &SystemResources, _: &SceneState) -> Result<()> {
+    COUNT_ALLOCATIONS.with(|count| count.set(true));
+    return Ok(());
+}
+
+fn register_update_job(function: JobFunction) -> JobId {
+    let job_id = register_job(JobKind::Update, function, &[]);
+    set_job_executed_per_viewport(job_id, false);
+    return job_id;
+}
+
+#[test]
+fn steady_state_frames_do_not_allocate() {
+    let first = register_update_job(count_worker_allocations);
+    for _ in 0..3 {
+        let dependent = register_update_job(count_worker_allocations);
+        add_job_dependency(dependent, first);
+    }
+
+    let state = Arc::new(SceneState::for_gpus(&[], 0).unwrap());
+    let scheduler = Scheduler::headless(JobKind::Update, state, 2).unwrap();
+    for frame in 0..3 {
+        assert!(scheduler.run_jobs(frame as f32, 1.0).is_ok());
+    }
+
+    COUNT_ALLOCATIONS.with(|count| count.set(true));
+    let allocated_bytes = ALLOCATED_BYTES.load(Ordering::SeqCst);
+    for frame in 3..13 {
+        assert!(scheduler.run_jobs(frame as f32, 1.0).is_ok());
+    }
+    COUNT_ALLOCATIONS.with(|count| count.set(false));
+
+    assert_eq!(ALLOCATED_BYTES.load(Ordering::SeqCst) - allocated_bytes, 0);
+}
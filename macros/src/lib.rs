@@ -2,65 +2,392 @@
 
 use proc_macro::{TokenStream, quote};
 use proc_macro2::Span;
-use syn::__private::ToTokens;
+use syn::{__private::ToTokens, parse::Parser, punctuated::Punctuated, Meta, Token};
+
+// Parses the arguments of the resource attribute, e.g., `EntityComponent, layout = sparse` and
+// returns the resource kind and the storage type that should be used for the resource.
+fn parse_resource_attribute(attribute: TokenStream, resource_ident: &proc_macro2::TokenStream) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let arguments = Punctuated::<Meta, Token![,]>::parse_terminated
+        .parse(attribute)
+        .expect("expected resource kind");
+    let mut arguments = arguments.into_iter();
+
+    let kind = match arguments.next() {
+        Some(Meta::Path(path)) => path.to_token_stream(),
+        _ => panic!("expected resource kind"),
+    };
+
+    // The storage depends on the kind of the resource. Registering a resource with the storage
+    // of another kind would silently index it by entity. The paths are fully qualified as the
+    // imports of the generated code are not visible to tokens created here.
+    let mut storage = match kind.to_string().as_str() {
+        "EntityComponent" => syn::parse_quote!(::ovis_core::IdMappedResourceStorage<::ovis_core::EntityId, #resource_ident>),
+        "SceneComponent" => syn::parse_quote!(::ovis_core::SceneComponentStorage<#resource_ident>),
+        "Event" => syn::parse_quote!(::ovis_core::EventStorage<#resource_ident>),
+        "ViewportComponent" => syn::parse_quote!(::ovis_core::ViewportComponentStorage<#resource_ident>),
+        "Texture" => syn::parse_quote!(::ovis_core::TextureStorage<#resource_ident>),
+        kind => panic!("{kind} resources are not supported yet"),
+    };
+    for argument in arguments {
+        match argument {
+            Meta::NameValue(name_value) if name_value.path.is_ident("layout") => {
+                match name_value.value.to_token_stream().to_string().as_str() {
+                    "sparse" => storage = syn::parse_quote!(::ovis_core::SparseResourceStorage<::ovis_core::EntityId, #resource_ident>),
+                    "slice" => storage = syn::parse_quote!(::ovis_core::IdMappedResourceSliceStorage<::ovis_core::EntityId, #resource_ident>),
+                    "dense" => {},
+                    layout => panic!("unknown layout: {layout}"),
+                }
+            }
+            _ => panic!("unexpected resource argument"),
+        }
+    }
+
+    return (kind, storage);
+}
+
+// Returns true if one of the attributes derives the trait, e.g., `Deserialize` for resources that
+// can be loaded from a scene file.
+fn derives(attributes: &[syn::Attribute], trait_name: &str) -> bool {
+    let mut derived = false;
+    for attribute in attributes.iter().filter(|attribute| attribute.path().is_ident("derive")) {
+        let _ = attribute.parse_nested_meta(|derive| {
+            if derive.path.segments.last().map_or(false, |segment| segment.ident == trait_name) {
+                derived = true;
+            }
+            return Ok(());
+        });
+    }
+    return derived;
+}
 
 #[proc_macro_attribute]
 pub fn resource(attribute: TokenStream, item: TokenStream) -> TokenStream {
     if let Ok(item_type) = syn::parse::<syn::ItemType>(item.clone()) {
-        let identifier = item_type.ident.to_token_stream();
-        let ty = item_type.ty.to_token_stream();
+        let attributes = &item_type.attrs;
+        let visibility = &item_type.vis;
+        let identifier = &item_type.ident;
+        let ty = &item_type.ty;
 
-        return quote!(
-            #[resource($attribute)]
-            pub struct $identifier {
-                inner: $ty,
+        // The attributes of the alias, e.g., derives, are forwarded to the newtype. Serde should
+        // treat the newtype like the aliased type, so it can be written as such in scene files.
+        let serde_attribute: proc_macro2::TokenStream =
+            if derives(attributes, "Serialize") || derives(attributes, "Deserialize") {
+                syn::parse_quote!(#[serde(transparent)])
+            } else {
+                proc_macro2::TokenStream::new()
+            };
+        // Pod requires a defined layout, the newtype has the same one as the aliased type.
+        let repr_attribute: proc_macro2::TokenStream = if derives(attributes, "Pod") {
+            syn::parse_quote!(#[repr(transparent)])
+        } else {
+            proc_macro2::TokenStream::new()
+        };
+        let attribute: proc_macro2::TokenStream = attribute.into();
+
+        let output: proc_macro2::TokenStream = syn::parse_quote!(
+            #[resource(#attribute)]
+            #(#attributes)*
+            #serde_attribute
+            #repr_attribute
+            #visibility struct #identifier {
+                inner: #ty,
             }
 
-            impl From<$ty> for $identifier {
-                fn from(value: $ty) -> Self {
+            impl From<#ty> for #identifier {
+                fn from(value: #ty) -> Self {
                     return Self { inner: value };
                 }
             }
 
-            impl std::ops::Deref for $identifier {
-                type Target = $ty;
+            impl std::ops::Deref for #identifier {
+                type Target = #ty;
 
                 fn deref(&self) -> &Self::Target {
                     return &self.inner;
                 }
             }
 
-            impl std::ops::DerefMut for $identifier {
+            impl std::ops::DerefMut for #identifier {
                 fn deref_mut(&mut self) -> &mut Self::Target {
                     return &mut self.inner;
                 }
             }
         );
+        return output.into();
     } else if let Ok(struct_type) = syn::parse::<syn::ItemStruct>(item.clone()) {
         let identifier = struct_type.ident.to_string();
-        let resource_ident = struct_type.ident;
+        let resource_ident = struct_type.ident.clone();
         let resource_id_ident = syn::Ident::new(&format!("{}_ID", identifier.to_string().to_uppercase()), Span::call_site()).to_token_stream();
         let resource_ident = resource_ident.to_token_stream();
 
+        // Tuple newtypes dereference to the wrapped value, so math on them works like on the
+        // wrapped type itself.
+        let deref_impl: proc_macro2::TokenStream = match &struct_type.fields {
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let ty = &fields.unnamed[0].ty;
+                syn::parse_quote!(
+                    impl std::ops::Deref for #resource_ident {
+                        type Target = #ty;
+
+                        fn deref(&self) -> &Self::Target {
+                            return &self.0;
+                        }
+                    }
+
+                    impl std::ops::DerefMut for #resource_ident {
+                        fn deref_mut(&mut self) -> &mut Self::Target {
+                            return &mut self.0;
+                        }
+                    }
+                )
+            }
+            syn::Fields::Unnamed(fields) => {
+                return syn::Error::new_spanned(
+                    fields,
+                    "tuple resources must have exactly one field, use named fields instead",
+                )
+                .to_compile_error()
+                .into();
+            }
+            _ => proc_macro2::TokenStream::new(),
+        };
+
+        let (kind, storage_type) = parse_resource_attribute(attribute, &resource_ident);
+        let deserialize_impl: proc_macro2::TokenStream = if derives(&struct_type.attrs, "Deserialize") {
+            syn::parse_quote!(
+                fn from_serialized(
+                    deserializer: &mut dyn ::ovis_core::erased_serde::Deserializer,
+                ) -> ::ovis_core::Result<Self> {
+                    return ::ovis_core::deserialize_resource(deserializer);
+                }
+            )
+        } else {
+            proc_macro2::TokenStream::new()
+        };
+        let serialize_impl: proc_macro2::TokenStream = if derives(&struct_type.attrs, "Serialize") {
+            syn::parse_quote!(
+                fn as_serializable(&self) -> Option<&dyn ::ovis_core::erased_serde::Serialize> {
+                    return Some(self);
+                }
+            )
+        } else {
+            proc_macro2::TokenStream::new()
+        };
+        let gpu_bytes_impl: proc_macro2::TokenStream = if derives(&struct_type.attrs, "Pod") {
+            syn::parse_quote!(
+                fn gpu_bytes(resources: &[Self]) -> Option<&[u8]> {
+                    return Some(::ovis_core::resource_bytes(resources));
+                }
+            )
+        } else {
+            proc_macro2::TokenStream::new()
+        };
+
         // println!("struct: {:?}", struct_type.to_token_stream());
         //
         return quote!(
             $item
 
-            use ovis_core::{Resource, ResourceId, ResourceKind, IdMappedResourceStorage, EntityId, register_resource};
-            static mut $resource_id_ident: ResourceId = ResourceId::from_index_and_version(0, 0);
+            use ovis_core::{Resource, ResourceId, ResourceKind, register_resource};
+            static $resource_id_ident: ::std::sync::OnceLock<ResourceId> =
+                ::std::sync::OnceLock::new();
 
             impl Resource for $resource_ident {
                 type Type = $resource_ident;
-                type Storage = IdMappedResourceStorage<EntityId, $resource_ident>;
+                type Storage = $storage_type;
 
-                fn id() -> ResourceId { unsafe { $resource_id_ident } }
-                fn kind() -> ResourceKind { ResourceKind::$attribute }
+                fn id() -> ResourceId {
+                    return *$resource_id_ident
+                        .get()
+                        .expect(concat!(stringify!($resource_ident), " is not registered"));
+                }
+                fn kind() -> ResourceKind { ResourceKind::$kind }
                 fn label() -> &'static str { stringify!($resource_ident) }
-                fn register() { unsafe { $resource_id_ident = register_resource::<Self>(); } }
+                // Registering a resource again keeps its id, so each resource is registered
+                // exactly once even if several threads register it at the same time.
+                fn register() { $resource_id_ident.get_or_init(register_resource::<Self>); }
+                $deserialize_impl
+                $serialize_impl
+                $gpu_bytes_impl
             }
+
+            $deref_impl
         );
     } else {
         panic!("expected type");
     }
 }
+
+// A parameter of a function annotated with the job macro.
+enum JobParameter {
+    Component { ty: syn::Type, mutable: bool },
+    DeltaTime,
+    GameTime,
+    FrameId,
+}
+
+fn parse_job_parameter(argument: &syn::FnArg) -> syn::Result<JobParameter> {
+    let syn::FnArg::Typed(argument) = argument else {
+        return Err(syn::Error::new_spanned(argument, "jobs cannot take self"));
+    };
+    match &*argument.ty {
+        syn::Type::Reference(reference) => {
+            return Ok(JobParameter::Component {
+                ty: (*reference.elem).clone(),
+                mutable: reference.mutability.is_some(),
+            });
+        }
+        syn::Type::Path(path) if path.path.is_ident("DeltaTime") => return Ok(JobParameter::DeltaTime),
+        syn::Type::Path(path) if path.path.is_ident("GameTime") => return Ok(JobParameter::GameTime),
+        syn::Type::Path(path) if path.path.is_ident("FrameId") => return Ok(JobParameter::FrameId),
+        ty => {
+            return Err(syn::Error::new_spanned(
+                ty,
+                "expected a component reference, DeltaTime, GameTime or FrameId",
+            ))
+        }
+    }
+}
+
+// Generates a job from a function that operates on the components of a single entity, e.g.:
+//
+// #[job]
+// fn integrate(position: &mut Position, velocity: &Velocity, delta_time: DeltaTime) { .. }
+//
+// Components taken by `&` are read, the ones taken by `&mut` are written. The function is called
+// for every entity that has all of the components. Besides the function itself, the macro
+// generates the job function `integrate_job` and `register_integrate_job`, which registers it as
+// an update job that runs once per frame. The function may return a `Result` to fail the frame.
+#[proc_macro_attribute]
+pub fn job(attribute: TokenStream, item: TokenStream) -> TokenStream {
+    let function = syn::parse_macro_input!(item as syn::ItemFn);
+    // Jobs run every frame unless the attribute asks for the fixed timestep, i.e.,
+    // `#[job(FixedUpdate)]`.
+    let kind = if attribute.is_empty() {
+        syn::Ident::new("Update", Span::call_site())
+    } else {
+        match syn::parse::<syn::Ident>(attribute) {
+            Ok(kind) if kind == "Update" || kind == "FixedUpdate" => kind,
+            Ok(kind) => {
+                return syn::Error::new_spanned(kind, "expected Update or FixedUpdate")
+                    .to_compile_error()
+                    .into()
+            }
+            Err(error) => return error.to_compile_error().into(),
+        }
+    };
+    let parameters = match function
+        .sig
+        .inputs
+        .iter()
+        .map(parse_job_parameter)
+        .collect::<syn::Result<Vec<_>>>()
+    {
+        Ok(parameters) => parameters,
+        Err(error) => return error.to_compile_error().into(),
+    };
+
+    let mut component_types: Vec<String> = Vec::new();
+    let mut storages = Vec::<proc_macro2::TokenStream>::new();
+    let mut components = Vec::<proc_macro2::TokenStream>::new();
+    let mut accesses = Vec::<proc_macro2::TokenStream>::new();
+    let mut arguments = Vec::<proc_macro2::TokenStream>::new();
+    for parameter in &parameters {
+        match parameter {
+            JobParameter::Component { ty, mutable } => {
+                // The storage of each component is locked once, taking a component twice would
+                // lock it twice.
+                let type_name = ty.to_token_stream().to_string();
+                if component_types.contains(&type_name) {
+                    return syn::Error::new_spanned(ty, "jobs can take each component only once")
+                        .to_compile_error()
+                        .into();
+                }
+                component_types.push(type_name);
+
+                let storage = syn::Ident::new(&format!("storage_{}", storages.len()), Span::call_site());
+                let component = syn::Ident::new(&format!("component_{}", storages.len()), Span::call_site());
+                let not_registered = syn::parse_quote!(
+                    ::ovis_core::Error::new(
+                        format!("{} is not registered", <#ty as ::ovis_core::Resource>::label()),
+                        ::ovis_core::SourceLocation::here(),
+                    )
+                );
+                let not_registered: proc_macro2::TokenStream = not_registered;
+                if *mutable {
+                    storages.push(syn::parse_quote!(
+                        let mut #storage = s.resource_storage_mut::<#ty>().ok_or_else(|| #not_registered)?;
+                    ));
+                    components.push(syn::parse_quote!(
+                        let Some(#component) = #storage.get_mut(entity_id) else { continue; };
+                    ));
+                    accesses.push(syn::parse_quote!(
+                        ::ovis_core::ResourceAccess::Write(<#ty as ::ovis_core::Resource>::id())
+                    ));
+                } else {
+                    storages.push(syn::parse_quote!(
+                        let #storage = s.resource_storage_ref::<#ty>().ok_or_else(|| #not_registered)?;
+                    ));
+                    components.push(syn::parse_quote!(
+                        let Some(#component) = #storage.get(entity_id) else { continue; };
+                    ));
+                    accesses.push(syn::parse_quote!(
+                        ::ovis_core::ResourceAccess::Read(<#ty as ::ovis_core::Resource>::id())
+                    ));
+                }
+                arguments.push(component.to_token_stream());
+            }
+            JobParameter::DeltaTime => arguments.push(syn::parse_quote!(::ovis_core::DeltaTime(sr.delta_time()))),
+            JobParameter::GameTime => arguments.push(syn::parse_quote!(::ovis_core::GameTime(sr.game_time()))),
+            JobParameter::FrameId => arguments.push(syn::parse_quote!(::ovis_core::FrameId(sr.frame_id()))),
+        }
+    }
+
+    let visibility = &function.vis;
+    let ident = &function.sig.ident;
+    let job_ident = syn::Ident::new(&format!("{ident}_job"), ident.span());
+    let register_ident = syn::Ident::new(&format!("register_{ident}_job"), ident.span());
+    let call: proc_macro2::TokenStream = match function.sig.output {
+        syn::ReturnType::Default => syn::parse_quote!(#ident(#(#arguments),*);),
+        syn::ReturnType::Type(..) => syn::parse_quote!(#ident(#(#arguments),*)?;),
+    };
+
+    // Jobs without components are called once per frame, the others once per entity that has all
+    // of the components. The entities are taken from the storage of the first component.
+    let body: proc_macro2::TokenStream = if storages.is_empty() {
+        call
+    } else {
+        syn::parse_quote!(
+            #(#storages)*
+            let entity_ids = storage_0.iter().map(|(entity_id, _)| entity_id).collect::<Vec<_>>();
+            for entity_id in entity_ids {
+                #(#components)*
+                #call
+            }
+        )
+    };
+
+    let output: proc_macro2::TokenStream = syn::parse_quote!(
+        #function
+
+        #[allow(unused_variables)]
+        #visibility fn #job_ident(
+            sr: &::ovis_core::SystemResources,
+            s: &::ovis_core::SceneState,
+        ) -> ::ovis_core::Result<()> {
+            #body
+            return Ok(());
+        }
+
+        #visibility fn #register_ident() -> ::ovis_core::JobId {
+            let job_id = ::ovis_core::register_job(
+                ::ovis_core::JobKind::#kind,
+                #job_ident,
+                &[#(#accesses),*],
+            );
+            ::ovis_core::set_job_executed_per_viewport(job_id, false);
+            return job_id;
+        }
+    );
+    return output.into();
+}
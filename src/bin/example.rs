@@ -1,7 +1,7 @@
 #![feature(trait_upcasting)]
 
-use ovis_runtime::{load_runtime, Position};
-use ovis_core::{Instance, Scene};
+use ovis_runtime::{load_runtime, Mesh, Position, VertexColor, VertexPosition};
+use ovis_core::{DepthBufferConfig, Instance, Scene, SceneConfig};
 use pollster::block_on;
 use ovis_core::winit::window::WindowBuilder;
 
@@ -10,9 +10,25 @@ async fn run() {
 
     load_runtime();
 
-    let instance = Instance::new().await;
+    let instance = match Instance::new().await {
+        Ok(instance) => instance,
+        Err(error) => {
+            eprintln!("{error}");
+            return;
+        }
+    };
 
-    let mut scene = Scene::new(&instance).await;
+    let config = SceneConfig {
+        depth_buffer: Some(DepthBufferConfig::default()),
+        ..Default::default()
+    };
+    let mut scene = match Scene::with_config(&instance, config).await {
+        Ok(scene) => scene,
+        Err(error) => {
+            eprintln!("{error}");
+            return;
+        }
+    };
     let _window = instance
         .build_window(&mut scene, WindowBuilder::new().with_title("Example"))
         .unwrap();
@@ -35,6 +51,31 @@ async fn run() {
         }
     }
 
+    {
+        let entity_id = scene.entities().write().unwrap().reserve();
+        let state = scene.state();
+        state.slice_storage_mut::<VertexPosition>().unwrap().insert_slice(
+            entity_id,
+            [
+                VertexPosition { x: 0.0, y: 0.5, z: 0.0 },
+                VertexPosition { x: -0.5, y: -0.5, z: 0.0 },
+                VertexPosition { x: 0.5, y: -0.5, z: 0.0 },
+            ],
+        );
+        state.slice_storage_mut::<VertexColor>().unwrap().insert_slice(
+            entity_id,
+            [
+                VertexColor { r: 1.0, g: 0.0, b: 0.0, a: 1.0 },
+                VertexColor { r: 0.0, g: 1.0, b: 0.0, a: 1.0 },
+                VertexColor { r: 0.0, g: 0.0, b: 1.0, a: 1.0 },
+            ],
+        );
+        state
+            .resource_storage_mut::<Mesh>()
+            .unwrap()
+            .insert(entity_id, Mesh { vertices: entity_id });
+    }
+
     instance.run([scene]);
 }
 
@@ -1,49 +1,153 @@
 use ovis_core::{
-    add_job_dependency, register_job, wgpu, EntityId, Error, JobId, JobKind, Resource,
-    ResourceAccess, SceneState, SystemResources,
+    add_job_dependency, register_job, register_shader, set_job_executed_per_viewport,
+    set_job_shader, wgpu, EntityId, Error, IdMappedResourceStorage, JobKind, Resource,
+    ResourceAccess, SceneState, ShaderSource, SourceLocation, SystemResources,
+    VersionedIndexId, ViewportComponentStorage, ViewportId,
 };
+use bytemuck::{Pod, Zeroable};
 use ovis_macros::resource;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Once;
+
+mod sprite;
+pub use sprite::*;
+
+mod mesh;
+pub use mesh::*;
 
 pub type Vec3 = glam::Vec3A;
 pub type Quat = glam::Quat;
 pub type Affine3A = glam::Affine3A;
 pub type Mat4 = glam::Mat4;
 
+// Affine3A contains padding, so the transforms are not plain old data and are only available on
+// the CPU.
 #[resource(EntityComponent)]
 pub struct LocalToParent(Affine3A);
 
-impl std::ops::Deref for LocalToParent {
-    type Target = Affine3A;
-
-    fn deref(&self) -> &Self::Target {
-        return &self.0;
-    }
-}
-
 #[resource(EntityComponent)]
 pub struct LocalToWorld(Affine3A);
 
+// The entity the transform of an entity is relative to. Entities without a parent, or whose
+// parent has no transform, are placed relative to the world origin.
 #[resource(EntityComponent)]
-pub type WorldToCamera = Affine3A;
+#[derive(Clone, Copy, Deserialize, Pod, Serialize, Zeroable)]
+#[repr(C)]
+pub struct Parent(
+    #[serde(deserialize_with = "ovis_core::deserialize_entity_reference")] pub EntityId,
+);
 
 #[resource(EntityComponent)]
+#[derive(Default, Deserialize)]
+pub type WorldToCamera = Affine3A;
+
+// The projection of the active camera for a viewport, i.e., with the aspect ratio of the viewport.
+#[resource(ViewportComponent)]
+#[derive(Clone, Copy, Default, Deserialize, Pod, Zeroable)]
 pub type CameraToClip = Mat4;
 
 #[resource(EntityComponent)]
+#[derive(Clone, Copy)]
 pub struct Transform {
     pub translation: Vec3,
     pub rotation: Quat,
     pub scaling: Vec3,
 }
 
+// The default transform leaves an entity where it is, i.e., its scaling is one and not zero.
+impl Default for Transform {
+    fn default() -> Self {
+        return Self::identity();
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        return Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scaling: Vec3::ONE,
+        };
+    }
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        return Self {
+            translation,
+            ..Self::identity()
+        };
+    }
+
+    pub fn from_rotation(rotation: Quat) -> Self {
+        return Self {
+            rotation,
+            ..Self::identity()
+        };
+    }
+
+    // Blends from this transform to the next one, the rotation is interpolated spherically.
+    pub fn interpolate(&self, next: &Transform, alpha: f32) -> Transform {
+        return Transform {
+            translation: self.translation.lerp(next.translation, alpha),
+            rotation: self.rotation.slerp(next.rotation, alpha),
+            scaling: self.scaling.lerp(next.scaling, alpha),
+        };
+    }
+}
+
+// The transform of an entity before the last fixed update step. Fixed update jobs that move an
+// entity copy its transform here before they change it. Render jobs then place the entity at
+// `previous.interpolate(current, sr.interpolation_alpha())`, so it moves smoothly even if the
+// scene is rendered more often than it is updated.
 #[resource(EntityComponent)]
+pub struct PreviousTransform(pub Transform);
+
+// The vertical field of view is given in radians.
+#[resource(EntityComponent)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
 pub struct Camera {
     pub fov: f32,
     pub near: f32,
     pub far: f32,
 }
 
-pub type ActiveCamera = EntityId;
+// The camera entity the scene is rendered with, see `set_active_camera`. Scenes without one are
+// rendered with their first camera.
+#[resource(SceneComponent)]
+#[derive(Clone, Copy, Deserialize, Pod, Serialize, Zeroable)]
+#[repr(C)]
+pub struct ActiveCamera(pub EntityId);
+
+// The projection and the view transform of the active camera combined for a viewport, i.e., the
+// transform shaders place entities with. It is not set while the scene has no camera.
+#[resource(ViewportComponent)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+pub type WorldToClip = Mat4;
+
+// The mesh and sprite shaders get the index of the viewport they are rendered for in the upper 8
+// bits of the vertex index, so they can look up its `WorldToClip`. The lower 24 bits store the
+// index of the vertex, i.e., the draws start at the returned vertex.
+pub(crate) fn viewport_vertex_base(viewport_id: ViewportId) -> Result<u32, Error> {
+    let viewport_index = viewport_id.index();
+    if viewport_index >= 1 << 8 {
+        return Err(Error::new(
+            format!("the viewport index {viewport_index} does not fit into a vertex index"),
+            SourceLocation::here(),
+        ));
+    }
+    return Ok((viewport_index as u32) << 24);
+}
+
+// Renders the scene with the camera of the entity from the next frame on. Must not be called by
+// jobs that do not write `ActiveCamera`.
+pub fn set_active_camera(s: &SceneState, camera_entity_id: EntityId) {
+    s.set_scene_component(ActiveCamera(camera_entity_id));
+}
+
+pub fn active_camera(s: &SceneState) -> Option<EntityId> {
+    return s.scene_component::<ActiveCamera>().map(|active_camera| active_camera.0);
+}
 
 // #[job]
 fn calculate_local_to_parent(transform: &Transform) -> LocalToParent {
@@ -54,22 +158,204 @@ fn calculate_local_to_parent(transform: &Transform) -> LocalToParent {
     ));
 }
 
+// The parent transform is applied after the local one, i.e., it is on the left. Entities without
+// a parent are placed relative to the world origin.
 fn calculate_local_to_world(
     local_to_parent: &LocalToParent,
-    parent_local_to_world: &LocalToWorld,
+    parent_local_to_world: Option<&LocalToWorld>,
 ) -> LocalToWorld {
-    LocalToWorld(**local_to_parent)
-    // parent_local_to_world.0
-    // local_to_parent.
-    // return LocalToWorld(local_to_parent.0 * parent_local_to_world.0);
+    return match parent_local_to_world {
+        Some(parent_local_to_world) => LocalToWorld(**parent_local_to_world * **local_to_parent),
+        None => LocalToWorld(**local_to_parent),
+    };
+}
+
+pub fn update_local_to_parent(_sr: &SystemResources, s: &SceneState) -> Result<(), Error> {
+    let transforms = s.resource_storage_ref::<Transform>().unwrap();
+    let mut local_to_parents = s.resource_storage_mut::<LocalToParent>().unwrap();
+    for (entity_id, transform) in transforms.iter() {
+        local_to_parents.insert(entity_id, calculate_local_to_parent(transform));
+    }
+
+    Ok(())
+}
+
+// Calculates the local-to-world transforms of all entities with a local-to-parent transform. The
+// transform of a parent is calculated before the ones of its children. If the parents of the
+// entities form a cycle, the entity the cycle is entered at is treated as having no parent.
+fn calculate_local_to_worlds(
+    local_to_parents: &IdMappedResourceStorage<EntityId, LocalToParent>,
+    parents: &IdMappedResourceStorage<EntityId, Parent>,
+) -> HashMap<EntityId, Affine3A> {
+    let mut local_to_worlds = HashMap::new();
+    let mut chain = Vec::new();
+
+    for (entity_id, _) in local_to_parents.iter() {
+        // Walk up the hierarchy until an entity whose transform is already known or a root is
+        // reached, then calculate the transforms down the chain.
+        let mut parent_local_to_world = None;
+        let mut next = Some(entity_id);
+        while let Some(id) = next {
+            if let Some(&local_to_world) = local_to_worlds.get(&id) {
+                parent_local_to_world = Some(LocalToWorld(local_to_world));
+                break;
+            }
+            // A parent that was removed may have its index reused by another entity.
+            if local_to_parents.stored_id(id) != Some(id) || chain.contains(&id) {
+                break;
+            }
+            chain.push(id);
+            next = parents.get(id).map(|parent| parent.0);
+        }
+
+        for id in chain.drain(..).rev() {
+            let local_to_world = calculate_local_to_world(
+                local_to_parents.get(id).unwrap(),
+                parent_local_to_world.as_ref(),
+            );
+            local_to_worlds.insert(id, local_to_world.0);
+            parent_local_to_world = Some(local_to_world);
+        }
+    }
+
+    return local_to_worlds;
+}
+
+pub fn update_local_to_world(_sr: &SystemResources, s: &SceneState) -> Result<(), Error> {
+    let local_to_worlds = calculate_local_to_worlds(
+        &s.resource_storage_ref::<LocalToParent>().unwrap(),
+        &s.resource_storage_ref::<Parent>().unwrap(),
+    );
+    let mut local_to_world_storage = s.resource_storage_mut::<LocalToWorld>().unwrap();
+    for (entity_id, local_to_world) in local_to_worlds {
+        local_to_world_storage.insert(entity_id, LocalToWorld(local_to_world));
+    }
+
+    Ok(())
+}
+
+// Returns None for viewports without an area as their aspect ratio is undefined.
+fn calculate_camera_to_clip(camera: &Camera, width: u32, height: u32) -> Option<CameraToClip> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let aspect_ratio = width as f32 / height as f32;
+    return Some(Mat4::perspective_lh(camera.fov, aspect_ratio, camera.near, camera.far).into());
+}
+
+fn calculate_world_to_camera(camera_local_to_world: &LocalToWorld) -> WorldToCamera {
+    return camera_local_to_world.inverse().into();
+}
+
+// Returns the camera the scene is rendered with. If the active camera is not set or its entity no
+// longer has a camera, e.g., because it was despawned, the first camera is used instead.
+fn select_camera(
+    active_camera: Option<EntityId>,
+    cameras: &IdMappedResourceStorage<EntityId, Camera>,
+) -> Option<EntityId> {
+    if let Some(active_camera) = active_camera {
+        if cameras.get(active_camera).is_some() {
+            return Some(active_camera);
+        }
+    }
+    return cameras.iter().next().map(|(entity_id, _)| entity_id);
+}
+
+// Calculates the view transform of all cameras and the projection and world-to-clip transform of
+// the active one for every viewport. The projections use the aspect ratio of their viewport.
+pub fn update_cameras(_sr: &SystemResources, s: &SceneState) -> Result<(), Error> {
+    let viewport_sizes = (&*s.viewports().read().unwrap())
+        .into_iter()
+        .map(|(viewport_id, viewport)| {
+            return (viewport_id, viewport.size().width, viewport.size().height);
+        })
+        .collect::<Vec<_>>();
+
+    let cameras = s.resource_storage_ref::<Camera>().unwrap();
+    let local_to_worlds = s.resource_storage_ref::<LocalToWorld>().unwrap();
+    let mut world_to_cameras = s.resource_storage_mut::<WorldToCamera>().unwrap();
+    for (entity_id, _) in cameras.iter() {
+        if let Some(local_to_world) = local_to_worlds.get(entity_id) {
+            world_to_cameras.insert(entity_id, calculate_world_to_camera(local_to_world));
+        }
+    }
+
+    let active_camera = active_camera(s);
+    let camera = select_camera(active_camera, &cameras);
+    match camera {
+        Some(camera) if active_camera != Some(camera) => set_active_camera(s, camera),
+        None if active_camera.is_some() => s.reset_scene_component::<ActiveCamera>(),
+        _ => {}
+    }
+    let camera = camera.and_then(|camera| {
+        return Some((cameras.get(camera)?, world_to_cameras.get(camera)?));
+    });
+
+    update_viewport_projections(
+        camera,
+        &viewport_sizes,
+        &mut s.resource_storage_mut::<CameraToClip>().unwrap(),
+        &mut s.resource_storage_mut::<WorldToClip>().unwrap(),
+    );
+
+    Ok(())
+}
+
+// Sets the projection and world-to-clip transform of the camera for the viewports, which are
+// given with their width and height. Viewports without an area keep their previous projection,
+// without a camera the transforms of all viewports are removed.
+fn update_viewport_projections(
+    camera: Option<(&Camera, &WorldToCamera)>,
+    viewport_sizes: &[(ViewportId, u32, u32)],
+    camera_to_clips: &mut ViewportComponentStorage<CameraToClip>,
+    world_to_clips: &mut ViewportComponentStorage<WorldToClip>,
+) {
+    for &(viewport_id, width, height) in viewport_sizes {
+        let Some((camera, world_to_camera)) = camera else {
+            camera_to_clips.remove(viewport_id);
+            world_to_clips.remove(viewport_id);
+            continue;
+        };
+        if let Some(camera_to_clip) = calculate_camera_to_clip(camera, width, height) {
+            camera_to_clips.insert(viewport_id, camera_to_clip);
+        }
+        let Some(camera_to_clip) = camera_to_clips.get(viewport_id) else {
+            continue;
+        };
+        let world_to_clip = WorldToClip::from(**camera_to_clip * Mat4::from(**world_to_camera));
+        world_to_clips.insert(viewport_id, world_to_clip);
+    }
 }
 
 #[resource(EntityComponent)]
+#[derive(Clone, Copy, Deserialize, Pod, Serialize, Zeroable)]
+#[repr(C)]
 pub struct Position {
     pub x: f32,
     pub y: f32,
 }
 
+// The vertices of a mesh. Every entity stores a slice of them.
+#[resource(EntityComponent, layout = slice)]
+#[derive(Clone, Copy, Deserialize, Pod, Serialize, Zeroable)]
+#[repr(C)]
+pub struct VertexPosition {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+// The colors of the vertices of a mesh, see `VertexPosition`.
+#[resource(EntityComponent, layout = slice)]
+#[derive(Clone, Copy, Deserialize, Pod, Serialize, Zeroable)]
+#[repr(C)]
+pub struct VertexColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
 // pub fn foo(x: &mut Mat4) {
 // x = Mat4::perspective_lh(1.0, 1.0, 1.0, 1.0).into();
 // x.perspective_lh();
@@ -78,94 +364,497 @@ pub struct Position {
 // ViewToClip::perspective_lh(1.0, 1.0, 1.0, 1.0);
 // }
 
-static mut CLEAR_SURFACE_ID: JobId = JobId::from_index_and_version(0, 0);
-pub fn clear_surface(sr: &SystemResources, _s: &SceneState) -> Result<(), Error> {
+// The color a viewport is cleared to at the beginning of every frame.
+#[resource(ViewportComponent)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct ClearColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+// Viewports without a clear color are cleared to this one.
+const DEFAULT_CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+
+fn viewport_clear_color(s: &SceneState, viewport_id: ViewportId) -> wgpu::Color {
+    return match s.viewport_component::<ClearColor>(viewport_id) {
+        Some(clear_color) => wgpu::Color {
+            r: clear_color.r as f64,
+            g: clear_color.g as f64,
+            b: clear_color.b as f64,
+            a: clear_color.a as f64,
+        },
+        None => DEFAULT_CLEAR_COLOR,
+    };
+}
+
+pub fn clear_surface(sr: &SystemResources, s: &SceneState) -> Result<(), Error> {
     let viewport = sr.viewport().unwrap();
+    let clear_color = viewport_clear_color(s, sr.viewport_id().unwrap());
 
-    let gpu = viewport.gpu();
-    let mut encoder = gpu
-        .device()
-        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("") });
-    let color_attachment = viewport
-        .texture_view()
-        .map(|view| wgpu::RenderPassColorAttachment {
-            view,
-            resolve_target: None,
-            ops: wgpu::Operations {
-                load: wgpu::LoadOp::Clear(wgpu::Color {
-                    r: 0.1,
-                    g: 0.2,
-                    b: 0.3,
-                    a: 1.0,
-                }),
-                store: true,
-            },
-        });
+    let color_attachment = viewport.color_attachment(wgpu::LoadOp::Clear(clear_color));
 
-    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+    // Clears the depth buffer of the viewport as well, the following passes load it.
+    sr.encoder().begin_render_pass(&wgpu::RenderPassDescriptor {
         label: Some("ClearSurface"),
         color_attachments: &[color_attachment],
-        depth_stencil_attachment: None,
+        depth_stencil_attachment: viewport.depth_stencil_attachment(true),
     });
-    gpu.queue().submit(std::iter::once(encoder.finish()));
 
     Ok(())
 }
 
-static mut DRAW_TRIANGLES_ID: JobId = JobId::from_index_and_version(0, 0);
-pub fn draw_triangles(sr: &SystemResources, s: &SceneState) -> Result<(), Error> {
-    let viewport = sr.viewport().unwrap();
+// Registers the resources and jobs of the runtime. Only the first call registers them, so the ids,
+// and with them the bindings of the shaders, are the same no matter how often it is called.
+pub fn load_runtime() {
+    static LOADED: Once = Once::new();
+    LOADED.call_once(|| {
+        Transform::register();
+        PreviousTransform::register();
+        LocalToParent::register();
+        LocalToWorld::register();
+        Parent::register();
+        let update_local_to_parent_id = register_job(
+            JobKind::Update,
+            update_local_to_parent,
+            &[
+                ResourceAccess::Read(Transform::id()),
+                ResourceAccess::Write(LocalToParent::id()),
+            ],
+        );
+        set_job_executed_per_viewport(update_local_to_parent_id, false);
+        let update_local_to_world_id = register_job(
+            JobKind::Update,
+            update_local_to_world,
+            &[
+                ResourceAccess::Read(LocalToParent::id()),
+                ResourceAccess::Read(Parent::id()),
+                ResourceAccess::Write(LocalToWorld::id()),
+            ],
+        );
+        set_job_executed_per_viewport(update_local_to_world_id, false);
+        add_job_dependency(update_local_to_world_id, update_local_to_parent_id);
 
-    let gpu = viewport.gpu();
-    let mut encoder = gpu
-        .device()
-        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("") });
-
-    let color_attachment = viewport
-        .texture_view()
-        .map(|view| wgpu::RenderPassColorAttachment {
-            view,
-            resolve_target: None,
-            ops: wgpu::Operations {
-                load: wgpu::LoadOp::Load,
-                store: true,
-            },
-        });
-    {
-        let position_storage = s.resource_storage_mut::<Position>().unwrap(); // TODO: mut not necessary here
-        position_storage.update_gpu_buffers();
-
-        // for (id, p) in position_storage.iter() {
-        //     println!("{}: ({}, {})", id, p.x, p.y);
-        // }
-
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("DrawTriangles"),
-            color_attachments: &[color_attachment],
-            depth_stencil_attachment: None,
+        Camera::register();
+        CameraToClip::register();
+        WorldToCamera::register();
+        ActiveCamera::register();
+        WorldToClip::register();
+        let update_cameras_id = register_job(
+            JobKind::Update,
+            update_cameras,
+            &[
+                ResourceAccess::Read(Camera::id()),
+                ResourceAccess::Read(LocalToWorld::id()),
+                ResourceAccess::Write(CameraToClip::id()),
+                ResourceAccess::Write(WorldToCamera::id()),
+                ResourceAccess::Write(ActiveCamera::id()),
+                ResourceAccess::Write(WorldToClip::id()),
+            ],
+        );
+        set_job_executed_per_viewport(update_cameras_id, false);
+        add_job_dependency(update_cameras_id, update_local_to_world_id);
+
+        Position::register();
+        ClearColor::register();
+        let clear_surface_id = register_job(
+            JobKind::Update,
+            clear_surface,
+            &[ResourceAccess::Read(ClearColor::id())],
+        );
+
+        Mesh::register();
+        VertexPosition::register();
+        VertexColor::register();
+        let draw_meshes_id = register_job(
+            JobKind::Update,
+            draw_meshes,
+            &[
+                ResourceAccess::Read(Mesh::id()),
+                ResourceAccess::Read(VertexPosition::id()),
+                ResourceAccess::Read(VertexColor::id()),
+                ResourceAccess::Read(WorldToClip::id()),
+            ],
+        );
+        set_job_shader(draw_meshes_id, register_shader(ShaderSource::wgsl(MESH_SHADER)));
+        add_job_dependency(draw_meshes_id, clear_surface_id);
+        add_job_dependency(draw_meshes_id, update_cameras_id);
+
+        Sprite::register();
+        SpriteTexture::register();
+        let draw_sprites_id = register_job(
+            JobKind::Update,
+            draw_sprites,
+            &[
+                ResourceAccess::Read(Position::id()),
+                ResourceAccess::Read(Sprite::id()),
+                ResourceAccess::Read(SpriteTexture::id()),
+                ResourceAccess::Read(WorldToClip::id()),
+            ],
+        );
+        // Sprites are drawn without depth test, so they are drawn on top of the meshes.
+        add_job_dependency(draw_sprites_id, draw_meshes_id);
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ovis_core::{DeltaTime, DrawCommand, Scheduler};
+    use std::sync::Arc;
+
+    #[test]
+    fn children_are_placed_relative_to_their_parent() {
+        let parent = calculate_local_to_world(
+            &calculate_local_to_parent(&Transform {
+                translation: Vec3::new(10.0, 0.0, 0.0),
+                rotation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+                scaling: Vec3::ONE,
+            }),
+            None,
+        );
+        assert!(parent.translation.abs_diff_eq(Vec3::new(10.0, 0.0, 0.0), 1e-5));
+
+        let child = calculate_local_to_world(
+            &calculate_local_to_parent(&Transform {
+                translation: Vec3::new(1.0, 0.0, 0.0),
+                rotation: Quat::IDENTITY,
+                scaling: Vec3::ONE,
+            }),
+            Some(&parent),
+        );
+
+        // The offset of the child is rotated by the parent before the parent is translated.
+        assert!(child.translation.abs_diff_eq(Vec3::new(10.0, 1.0, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn transforms_are_interpolated_between_fixed_steps() {
+        let previous = Transform {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scaling: Vec3::ONE,
+        };
+        let current = Transform {
+            translation: Vec3::new(2.0, 0.0, 0.0),
+            rotation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            scaling: Vec3::splat(3.0),
+        };
+
+        let halfway = previous.interpolate(&current, 0.5);
+        assert!(halfway.translation.abs_diff_eq(Vec3::new(1.0, 0.0, 0.0), 1e-5));
+        assert!(halfway
+            .rotation
+            .abs_diff_eq(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4), 1e-5));
+        assert!(halfway.scaling.abs_diff_eq(Vec3::splat(2.0), 1e-5));
+        assert!(previous
+            .interpolate(&current, 0.0)
+            .translation
+            .abs_diff_eq(previous.translation, 1e-5));
+    }
+
+    #[test]
+    fn default_transform_is_the_identity() {
+        let local_to_parent = calculate_local_to_parent(&Transform::default());
+        assert_eq!(*local_to_parent, Affine3A::IDENTITY);
+
+        let translation = Vec3::new(1.0, 2.0, 3.0);
+        assert_eq!(
+            *calculate_local_to_parent(&Transform::from_translation(translation)),
+            Affine3A::from_translation(translation.into())
+        );
+        let rotation = Quat::from_rotation_z(1.0);
+        assert!(calculate_local_to_parent(&Transform::from_rotation(rotation))
+            .abs_diff_eq(Affine3A::from_quat(rotation), 1e-6));
+    }
+
+    #[test]
+    fn camera_projection_uses_the_aspect_ratio_of_the_viewport() {
+        let camera = Camera {
+            fov: std::f32::consts::FRAC_PI_2,
+            near: 0.1,
+            far: 100.0,
+        };
+        let camera_to_clip = calculate_camera_to_clip(&camera, 1600, 900).unwrap();
+        assert_eq!(*camera_to_clip, Mat4::perspective_lh(camera.fov, 16.0 / 9.0, 0.1, 100.0));
+        assert!(calculate_camera_to_clip(&camera, 1600, 0).is_none());
+
+        let local_to_world = LocalToWorld(Affine3A::from_rotation_translation(
+            Quat::from_rotation_y(1.0),
+            glam::Vec3::new(1.0, 2.0, 3.0),
+        ));
+        let world_to_camera = calculate_world_to_camera(&local_to_world);
+        assert!((*world_to_camera * *local_to_world).abs_diff_eq(Affine3A::IDENTITY, 1e-5));
+    }
+
+    #[test]
+    fn the_active_camera_can_be_switched_between_frames() {
+        load_runtime();
+        let state = Arc::new(SceneState::for_gpus(&[], 0).unwrap());
+        let [first, second] = {
+            let mut entities = state.entities().write().unwrap();
+            [entities.reserve(), entities.reserve()]
+        };
+        for (entity_id, x) in [(first, 1.0), (second, 2.0)] {
+            let camera = Camera { fov: 1.0, near: 0.1, far: 100.0 };
+            state.resource_storage_mut::<Camera>().unwrap().insert(entity_id, camera);
+            let transform = Transform::from_translation(Vec3::new(x, 0.0, 0.0));
+            state.resource_storage_mut::<Transform>().unwrap().insert(entity_id, transform);
+        }
+        // Without viewports, only the view transforms are calculated.
+        let camera_x = || {
+            let world_to_cameras = state.resource_storage_ref::<WorldToCamera>().unwrap();
+            return -world_to_cameras.get(active_camera(&state).unwrap()).unwrap().translation.x;
+        };
+
+        // Without an active camera, the first one is used.
+        let scheduler = Scheduler::headless(JobKind::Update, state.clone(), 1).unwrap();
+        scheduler.run_jobs(0.0, 0.1).unwrap();
+        assert_eq!(active_camera(&state), Some(first));
+        assert_eq!(camera_x(), 1.0);
+
+        set_active_camera(&state, second);
+        scheduler.run_jobs(0.1, 0.1).unwrap();
+        assert_eq!(active_camera(&state), Some(second));
+        assert_eq!(camera_x(), 2.0);
+
+        // A stale active camera falls back to the first camera.
+        state.resource_storage_mut::<Camera>().unwrap().remove(second);
+        scheduler.run_jobs(0.2, 0.1).unwrap();
+        assert_eq!(active_camera(&state), Some(first));
+        assert_eq!(camera_x(), 1.0);
+
+        state.resource_storage_mut::<Camera>().unwrap().remove(first);
+        scheduler.run_jobs(0.3, 0.1).unwrap();
+        assert_eq!(active_camera(&state), None);
+    }
+
+    #[test]
+    fn every_viewport_is_projected_with_its_own_aspect_ratio() {
+        CameraToClip::register();
+        WorldToClip::register();
+        let mut camera_to_clips = ViewportComponentStorage::new(&[], CameraToClip::id());
+        let mut world_to_clips = ViewportComponentStorage::new(&[], WorldToClip::id());
+        let camera = Camera { fov: 1.0, near: 0.1, far: 100.0 };
+        let world_to_camera = WorldToCamera::from(Affine3A::from_translation(Vec3::Z.into()));
+        let [wide, tall, empty] = [0, 1, 2].map(ViewportId::from_index);
+        let sizes = [(wide, 1600, 900), (tall, 900, 1600), (empty, 0, 0)];
+
+        update_viewport_projections(
+            Some((&camera, &world_to_camera)),
+            &sizes,
+            &mut camera_to_clips,
+            &mut world_to_clips,
+        );
+        for (viewport_id, aspect_ratio) in [(wide, 16.0 / 9.0), (tall, 9.0 / 16.0)] {
+            let projection = Mat4::perspective_lh(1.0, aspect_ratio, 0.1, 100.0);
+            assert_eq!(**camera_to_clips.get(viewport_id).unwrap(), projection);
+            let world_to_clip = projection * Mat4::from(*world_to_camera);
+            assert_eq!(**world_to_clips.get(viewport_id).unwrap(), world_to_clip);
+        }
+        assert!(camera_to_clips.get(empty).is_none());
+        assert!(world_to_clips.get(empty).is_none());
+
+        update_viewport_projections(None, &sizes, &mut camera_to_clips, &mut world_to_clips);
+        assert_eq!(world_to_clips.iter().count(), 0);
+    }
+
+    #[test]
+    fn type_resources_are_newtypes_of_the_aliased_type() {
+        assert_eq!(*CameraToClip::default(), Mat4::IDENTITY);
+
+        // The newtype is deserialized like the aliased type.
+        let world_to_camera = Affine3A::from_translation(glam::Vec3::new(1.0, 2.0, 3.0));
+        let value = ovis_core::serde_json::to_value(world_to_camera).unwrap();
+        let mut deserializer = <dyn ovis_core::erased_serde::Deserializer>::erase(value);
+        let mut deserialized = WorldToCamera::from_serialized(&mut deserializer).unwrap();
+        assert_eq!(*deserialized, world_to_camera);
+
+        *deserialized = Affine3A::IDENTITY;
+        assert_eq!(*deserialized, *WorldToCamera::from(Affine3A::IDENTITY));
+    }
+
+    #[resource(EntityComponent)]
+    struct Distance(f32);
+
+    #[resource(EntityComponent)]
+    struct Speed(f32);
+
+    #[ovis_macros::job]
+    fn travel(distance: &mut Distance, speed: &Speed, delta_time: DeltaTime) {
+        **distance += **speed * delta_time.0;
+    }
+
+    #[test]
+    fn job_macro_calls_function_for_entities_with_all_components() {
+        Distance::register();
+        Speed::register();
+        let travel_job_id = register_travel_job();
+        assert_eq!(
+            ovis_core::jobs().get(travel_job_id).unwrap().resource_access(),
+            [
+                ResourceAccess::Write(Distance::id()),
+                ResourceAccess::Read(Speed::id()),
+            ]
+        );
+
+        let state = Arc::new(SceneState::for_gpus(&[], 0).unwrap());
+        let [moving, resting] = {
+            let mut entities = state.entities().write().unwrap();
+            [entities.reserve(), entities.reserve()]
+        };
+        let mut distances = state.resource_storage_mut::<Distance>().unwrap();
+        distances.insert(moving, Distance(1.0));
+        distances.insert(resting, Distance(1.0));
+        drop(distances);
+        state.resource_storage_mut::<Speed>().unwrap().insert(moving, Speed(4.0));
+
+        let scheduler = Scheduler::headless(JobKind::Update, state.clone(), 1).unwrap();
+        scheduler.run_jobs(0.0, 0.5).unwrap();
+
+        let distances = state.resource_storage_ref::<Distance>().unwrap();
+        assert_eq!(distances.get(moving).unwrap().0, 3.0);
+        assert_eq!(distances.get(resting).unwrap().0, 1.0);
+    }
+
+    #[resource(EntityComponent)]
+    struct Concurrent1(u32);
+
+    #[resource(EntityComponent)]
+    struct Concurrent2(u32);
+
+    #[test]
+    fn concurrent_registration_assigns_one_id_per_resource() {
+        let ids = std::thread::scope(|scope| {
+            let threads = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        Concurrent1::register();
+                        Concurrent2::register();
+                        return (Concurrent1::id(), Concurrent2::id());
+                    })
+                })
+                .collect::<Vec<_>>();
+            return threads.into_iter().map(|thread| thread.join().unwrap()).collect::<Vec<_>>();
         });
-        // render_pass.set_push_constants
-        render_pass.set_pipeline(sr.pipeline().unwrap());
-        render_pass.set_bind_group(0, viewport.gpu().system_bind_group(), &[]);
-        render_pass.set_bind_group(1, s.resource_bind_group(viewport.gpu().index()), &[]);
 
-        render_pass.draw(0..3, 1..2);
+        let (first, second) = ids[0];
+        assert_ne!(first, second);
+        assert!(ids.iter().all(|&ids| ids == (first, second)));
+        assert_eq!(ovis_core::find_resource("Concurrent1"), Some(first));
+        assert_eq!(ovis_core::find_resource("Concurrent2"), Some(second));
+
+        // Registering again keeps the ids.
+        Concurrent1::register();
+        assert_eq!(Concurrent1::id(), first);
     }
-    gpu.queue().submit(std::iter::once(encoder.finish()));
 
-    Ok(())
-}
+    #[test]
+    fn viewports_are_cleared_to_their_own_color() {
+        ClearColor::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let [red_viewport, green_viewport, default_viewport] =
+            [0, 1, 2].map(ViewportId::from_index);
+        {
+            let mut storage = state.resource_storage(ClearColor::id()).unwrap().write().unwrap();
+            let clear_colors = storage.as_viewport_component_mut::<ClearColor>().unwrap();
+            clear_colors.insert(red_viewport, ClearColor { r: 1.0, g: 0.0, b: 0.0, a: 1.0 });
+            clear_colors.insert(green_viewport, ClearColor { r: 0.0, g: 1.0, b: 0.0, a: 0.5 });
+        }
 
-pub fn load_runtime() {
-    unsafe {
-        Position::register();
-        // POSITION_ID = register_entity_component::<Position>("ovis::runtime::Position");
-        CLEAR_SURFACE_ID = register_job(JobKind::Update, clear_surface, &[]);
-        DRAW_TRIANGLES_ID = register_job(
-            JobKind::Update,
-            draw_triangles,
-            &[ResourceAccess::Read(POSITION_ID)],
+        assert_eq!(
+            viewport_clear_color(&state, red_viewport),
+            wgpu::Color { r: 1.0, g: 0.0, b: 0.0, a: 1.0 }
+        );
+        assert_eq!(
+            viewport_clear_color(&state, green_viewport),
+            wgpu::Color { r: 0.0, g: 1.0, b: 0.0, a: 0.5 }
+        );
+        assert_eq!(
+            viewport_clear_color(&state, default_viewport),
+            DEFAULT_CLEAR_COLOR
+        );
+    }
+
+    #[test]
+    fn instance_count_tracks_the_entities_with_the_component() {
+        Transform::register();
+        Speed::register();
+        ClearColor::register();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let instance_count = || {
+            let draw = DrawCommand::new(0..6).with_instance_per_component::<Transform>(&state);
+            return draw.instance_count();
+        };
+        assert_eq!(instance_count(), 0);
+
+        let spawned: Vec<EntityId> = (0..3)
+            .map(|_| {
+                let entity_id = state.entities().write().unwrap().reserve();
+                let mut transforms = state.resource_storage_mut::<Transform>().unwrap();
+                transforms.insert(entity_id, Transform::default());
+                return entity_id;
+            })
+            .collect();
+        // Entities without the component are not drawn.
+        let without_transform = state.entities().write().unwrap().reserve();
+        state.resource_storage_mut::<Speed>().unwrap().insert(without_transform, Speed(1.0));
+        assert_eq!(instance_count(), 3);
+
+        // Removing a component from the middle leaves an empty slot that the shader skips, the
+        // instances still cover the last entity.
+        state.resource_storage_mut::<Transform>().unwrap().remove(spawned[1]);
+        assert_eq!(state.component_count(Transform::id()), 2);
+        assert_eq!(instance_count(), 3);
+        // The instance index is the entity index, so the entities before the first one with the
+        // component are skipped as well.
+        assert_eq!(
+            DrawCommand::new(0..6).with_instance_per_component::<Speed>(&state).instances(),
+            0..4
         );
-        add_job_dependency(DRAW_TRIANGLES_ID, CLEAR_SURFACE_ID);
+        // Resources that are not entity components have no instances.
+        assert_eq!(state.component_count(ClearColor::id()), 0);
+    }
+
+    fn translation(x: f32) -> LocalToParent {
+        return LocalToParent(Affine3A::from_translation(glam::Vec3::new(x, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn transforms_are_propagated_down_the_hierarchy() {
+        LocalToParent::register();
+        Parent::register();
+        let mut local_to_parents =
+            IdMappedResourceStorage::<EntityId, LocalToParent>::new(&[], LocalToParent::id());
+        let mut parents = IdMappedResourceStorage::<EntityId, Parent>::new(&[], Parent::id());
+
+        // The children are inserted before their parents, so they are visited first.
+        let [grandchild, child, root, orphan] = [0, 1, 2, 3].map(EntityId::from_index);
+        local_to_parents.insert(grandchild, translation(100.0));
+        parents.insert(grandchild, Parent(child));
+        local_to_parents.insert(child, translation(10.0));
+        parents.insert(child, Parent(root));
+        local_to_parents.insert(root, translation(1.0));
+        local_to_parents.insert(orphan, translation(1000.0));
+        parents.insert(orphan, Parent(EntityId::from_index(42)));
+        // The parent was removed and its index is used by the root now.
+        let stale_child = EntityId::from_index(4);
+        local_to_parents.insert(stale_child, translation(10000.0));
+        parents.insert(stale_child, Parent(EntityId::from_index_and_version(2, 1)));
+
+        let local_to_worlds = calculate_local_to_worlds(&local_to_parents, &parents);
+        let world_x = |entity_id: EntityId| local_to_worlds[&entity_id].translation.x;
+        assert_eq!(world_x(root), 1.0);
+        assert_eq!(world_x(child), 11.0);
+        assert_eq!(world_x(grandchild), 111.0);
+        assert_eq!(world_x(orphan), 1000.0);
+        assert_eq!(world_x(stale_child), 10000.0);
     }
 }
@@ -0,0 +1,242 @@
+use crate::{viewport_vertex_base, VertexColor, VertexPosition, WorldToClip};
+use bytemuck::{Pod, Zeroable};
+use ovis_core::{
+    wgpu, DrawCommand, EntityId, Error, SceneState, SystemResources, VersionedIndexId,
+};
+use ovis_macros::resource;
+
+// Draws the vertices stored at the entity `vertices` as a triangle list. The vertex count is the
+// length of its `VertexPosition` slice, the `VertexColor` slice is optional and vertices without
+// a color are white. Several entities can refer to the same vertices. The positions are given in
+// world space and transformed with the `WorldToClip` of the viewport, viewports without a camera
+// draw them in clip space. Triangles are front facing if their vertices are in counter-clockwise
+// order.
+#[resource(EntityComponent)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct Mesh {
+    pub vertices: EntityId,
+}
+
+// Entity ids are uploaded as is, so the lower 24 bits store the index of the entity. The slices
+// are located via the block tables of the slice storages, see `IdMappedResourceSliceStorage`. The
+// vertex index stores the index of the viewport as well, see `viewport_vertex_base`.
+pub(crate) const MESH_SHADER: &str = r#"
+struct VertexPosition {
+    x: f32,
+    y: f32,
+    z: f32,
+};
+
+struct VertexColor {
+    r: f32,
+    g: f32,
+    b: f32,
+    a: f32,
+};
+
+struct SliceBlock {
+    offset: u32,
+    size: u32,
+};
+
+@resource(Mesh, 0)
+var<storage, read> meshes: array<u32>;
+@resource(Mesh, 1)
+var<storage, read> meshes_index: array<u32>;
+
+@resource(VertexPosition, 0)
+var<storage, read> vertex_positions: array<VertexPosition>;
+@resource(VertexPosition, 1)
+var<storage, read> vertex_position_blocks: array<SliceBlock>;
+
+@resource(VertexColor, 0)
+var<storage, read> vertex_colors: array<VertexColor>;
+@resource(VertexColor, 1)
+var<storage, read> vertex_color_blocks: array<SliceBlock>;
+
+@resource(WorldToClip, 0)
+var<storage, read> world_to_clips: array<mat4x4<f32>>;
+@resource(WorldToClip, 1)
+var<storage, read> world_to_clips_index: array<u32>;
+
+fn world_to_clip(viewport_index: u32) -> mat4x4<f32> {
+    if viewport_index < arrayLength(&world_to_clips_index) {
+        let index = world_to_clips_index[viewport_index];
+        if (index >> 24u) != 0u {
+            return world_to_clips[index & 0xffffffu];
+        }
+    }
+    return mat4x4<f32>(
+        vec4<f32>(1.0, 0.0, 0.0, 0.0),
+        vec4<f32>(0.0, 1.0, 0.0, 0.0),
+        vec4<f32>(0.0, 0.0, 1.0, 0.0),
+        vec4<f32>(0.0, 0.0, 0.0, 1.0),
+    );
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+// Each instance corresponds to the entity with the same index. Only entities with a mesh are
+// drawn and only as many vertices as the mesh has positions.
+@vertex
+fn vs_main(
+    @builtin(vertex_index) viewport_vertex_index: u32,
+    @builtin(instance_index) entity_index: u32,
+) -> VertexOutput {
+    let vertex_index = viewport_vertex_index & 0xffffffu;
+    let vertices = meshes[meshes_index[entity_index] & 0xffffffu] & 0xffffffu;
+    let position = vertex_positions[vertex_position_blocks[vertices].offset + vertex_index];
+
+    var out: VertexOutput;
+    out.clip_position = world_to_clip(viewport_vertex_index >> 24u)
+        * vec4<f32>(position.x, position.y, position.z, 1.0);
+    out.color = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    if vertices < arrayLength(&vertex_color_blocks) {
+        let colors = vertex_color_blocks[vertices];
+        if vertex_index < colors.size {
+            let color = vertex_colors[colors.offset + vertex_index];
+            out.color = vec4<f32>(color.r, color.g, color.b, color.a);
+        }
+    }
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
+"#;
+
+// Returns the index of every entity with a mesh and the number of its vertices. Meshes whose
+// vertices have no positions are skipped, as are meshes with more vertices than the lower 24 bits
+// of the vertex index can address, see `viewport_vertex_base`.
+pub fn mesh_draws(s: &SceneState) -> Vec<(u32, u32)> {
+    let meshes = s.resource_storage_ref::<Mesh>().unwrap();
+    let vertex_positions = s.slice_storage::<VertexPosition>().unwrap();
+    return meshes
+        .iter()
+        .filter_map(|(entity_id, mesh)| {
+            let vertex_count = vertex_positions.get(mesh.vertices)?.len();
+            if vertex_count == 0 || vertex_count > 1 << 24 {
+                return None;
+            }
+            return Some((
+                entity_id.index().try_into().unwrap(),
+                vertex_count.try_into().unwrap(),
+            ));
+        })
+        .collect();
+}
+
+pub fn draw_meshes(sr: &SystemResources, s: &SceneState) -> Result<(), Error> {
+    let viewport = sr.viewport().unwrap();
+    let Some(color_attachment) = viewport.color_attachment(wgpu::LoadOp::Load) else {
+        return Ok(());
+    };
+
+    s.resource_storage_ref::<Mesh>().unwrap().update_gpu_buffers();
+    s.slice_storage::<VertexPosition>()
+        .unwrap()
+        .update_gpu_buffers();
+    s.slice_storage::<VertexColor>().unwrap().update_gpu_buffers();
+    s.resource_storage_ref::<WorldToClip>()
+        .unwrap()
+        .update_gpu_buffers();
+    s.update_resource_bindings();
+    let resource_bind_groups = s.resource_bind_groups(viewport.gpu().index());
+    let draws = mesh_draws(s);
+    let vertex_base = viewport_vertex_base(sr.viewport_id().unwrap())?;
+
+    let mut encoder = sr.encoder();
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("DrawMeshes"),
+        color_attachments: &[Some(color_attachment)],
+        depth_stencil_attachment: viewport.depth_stencil_attachment(false),
+    });
+    render_pass.set_pipeline(sr.pipeline().unwrap());
+    render_pass.set_bind_group(0, viewport.gpu().system_bind_group(), &[]);
+    for (index, group) in resource_bind_groups.iter().enumerate() {
+        render_pass.set_bind_group(1 + index as u32, group, &[]);
+    }
+    for (entity_index, vertex_count) in draws {
+        DrawCommand::new(vertex_base..vertex_base + vertex_count)
+            .with_instances(entity_index..entity_index + 1)
+            .record(&mut render_pass);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ovis_core::{resource_binding, Resource, ResourceBinding};
+
+    fn register_mesh_resources() {
+        Mesh::register();
+        VertexPosition::register();
+        VertexColor::register();
+        WorldToClip::register();
+    }
+
+    fn vertex(x: f32, y: f32) -> VertexPosition {
+        return VertexPosition { x, y, z: 0.0 };
+    }
+
+    #[test]
+    fn meshes_draw_all_vertices_of_the_referenced_entity() {
+        register_mesh_resources();
+        let state = SceneState::for_gpus(&[], 0).unwrap();
+        let [triangle, quad, instance, without_vertices, without_mesh] = {
+            let mut entities = state.entities().write().unwrap();
+            [0; 5].map(|_| entities.reserve())
+        };
+
+        {
+            let mut vertex_positions = state.slice_storage_mut::<VertexPosition>().unwrap();
+            let triangle_vertices = [vertex(0.0, 0.5), vertex(-0.5, -0.5), vertex(0.5, -0.5)];
+            vertex_positions.insert_slice(triangle, triangle_vertices);
+            vertex_positions.insert_slice(quad, [vertex(0.0, 0.0); 6]);
+            vertex_positions.insert_slice(without_mesh, [vertex(0.0, 0.0); 3]);
+        }
+        {
+            let mut meshes = state.resource_storage_mut::<Mesh>().unwrap();
+            meshes.insert(triangle, Mesh { vertices: triangle });
+            meshes.insert(quad, Mesh { vertices: quad });
+            // Instances share the vertices of another entity.
+            meshes.insert(instance, Mesh { vertices: quad });
+            meshes.insert(without_vertices, Mesh { vertices: without_vertices });
+        }
+
+        let mut draws = mesh_draws(&state);
+        draws.sort();
+        assert_eq!(
+            draws,
+            [
+                (triangle.index() as u32, 3),
+                (quad.index() as u32, 6),
+                (instance.index() as u32, 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn mesh_shader_uses_the_bindings_of_the_resources() {
+        register_mesh_resources();
+        let s = SceneState::for_gpus(&[], 0).unwrap();
+        let shader = s.resolve_resource_bindings(MESH_SHADER).unwrap();
+        assert!(!shader.contains("@resource"));
+        let resource_ids = [Mesh::id(), VertexPosition::id(), VertexColor::id(), WorldToClip::id()];
+        for resource_id in resource_ids {
+            let ResourceBinding { group, binding } = s.resource_binding(resource_id).unwrap();
+            // Scenes allocate the same bindings for the resources as the registered ones.
+            assert_eq!(resource_binding(resource_id, u32::MAX), s.resource_binding(resource_id));
+            assert!(shader.contains(&format!("@group({group}) @binding({binding})")));
+            assert!(shader.contains(&format!("@group({group}) @binding({})", binding + 1)));
+        }
+    }
+}
@@ -0,0 +1,492 @@
+use crate::{viewport_vertex_base, Position, WorldToClip};
+use bytemuck::{Pod, Zeroable};
+use ovis_core::{
+    wgpu, DrawCommand, Error, Gpu, SceneState, SystemResources, Texture, ViewportId,
+};
+use ovis_macros::resource;
+use std::sync::Arc;
+
+// Refers to a texture of the scene. Texture resources store a single texture per scene, see
+// `TextureStorage`, so there is no set of textures the handle could select from yet. Until then,
+// all sprites sample the `SpriteTexture` of the scene.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Pod, Zeroable)]
+pub struct TextureHandle(pub u32);
+
+// The texture the sprites are drawn with, e.g., an atlas that contains the images of all sprites.
+// Until it is set, sprites are transparent.
+#[resource(Texture)]
+pub type SpriteTexture = Texture;
+
+// A textured quad centered at the 2D position of the entity. The region of the texture that is
+// drawn is given by `uv_rect` as [min u, min v, max u, max v]. The quad lies in the plane z = 0
+// of the world and is transformed with the `WorldToClip` of the viewport like meshes are.
+#[resource(EntityComponent)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct Sprite {
+    pub texture: TextureHandle,
+    pub uv_rect: [f32; 4],
+    pub size: [f32; 2],
+}
+
+// The layout of the sprite storage has to match the Rust struct, so arrays are used instead of
+// vectors which would require a 16 byte alignment.
+const SPRITE_SHADER: &str = r#"
+struct Sprite {
+    texture: u32,
+    uv_rect: array<f32, 4>,
+    size: array<f32, 2>,
+};
+
+@resource(Position, 0)
+var<storage, read> positions: array<vec2<f32>>;
+@resource(Position, 1)
+var<storage, read> positions_index: array<u32>;
+
+@resource(Sprite, 0)
+var<storage, read> sprites: array<Sprite>;
+@resource(Sprite, 1)
+var<storage, read> sprites_index: array<u32>;
+
+@resource(SpriteTexture, 0)
+var sprite_texture: texture_2d<f32>;
+@resource(SpriteTexture, 1)
+var sprite_sampler: sampler;
+
+@resource(WorldToClip, 0)
+var<storage, read> world_to_clips: array<mat4x4<f32>>;
+@resource(WorldToClip, 1)
+var<storage, read> world_to_clips_index: array<u32>;
+
+fn world_to_clip(viewport_index: u32) -> mat4x4<f32> {
+    if viewport_index < arrayLength(&world_to_clips_index) {
+        let index = world_to_clips_index[viewport_index];
+        if (index >> 24u) != 0u {
+            return world_to_clips[index & 0xffffffu];
+        }
+    }
+    return mat4x4<f32>(
+        vec4<f32>(1.0, 0.0, 0.0, 0.0),
+        vec4<f32>(0.0, 1.0, 0.0, 0.0),
+        vec4<f32>(0.0, 0.0, 1.0, 0.0),
+        vec4<f32>(0.0, 0.0, 0.0, 1.0),
+    );
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// Each instance corresponds to the entity with the same index, the upper 8 bits of the vertex
+// index store the index of the viewport. Entities without a sprite or position produce degenerate
+// triangles.
+@vertex
+fn vs_main(
+    @builtin(vertex_index) viewport_vertex_index: u32,
+    @builtin(instance_index) entity_index: u32,
+) -> VertexOutput {
+    let vertex_index = viewport_vertex_index & 0xffffffu;
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    out.uv = vec2<f32>(0.0, 0.0);
+
+    if entity_index >= arrayLength(&sprites_index) || entity_index >= arrayLength(&positions_index) {
+        return out;
+    }
+    let sprite_index = sprites_index[entity_index];
+    let position_index = positions_index[entity_index];
+    if (sprite_index >> 24u) == 0u || (position_index >> 24u) == 0u {
+        return out;
+    }
+
+    let sprite = sprites[sprite_index & 0xffffffu];
+    let position = positions[position_index & 0xffffffu];
+
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+    let size = vec2<f32>(sprite.size[0], sprite.size[1]);
+
+    out.clip_position = world_to_clip(viewport_vertex_index >> 24u)
+        * vec4<f32>(position + (corner - 0.5) * size, 0.0, 1.0);
+    out.uv = vec2<f32>(
+        mix(sprite.uv_rect[0], sprite.uv_rect[2], corner.x),
+        mix(sprite.uv_rect[3], sprite.uv_rect[1], corner.y),
+    );
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(sprite_texture, sprite_sampler, in.uv);
+}
+"#;
+
+// The sprite pipelines are created on first use for every GPU, target format and sample count.
+// They are cached by the scene, as they are created with its resource bind group layouts.
+fn sprite_pipeline(
+    gpu: &Gpu,
+    s: &SceneState,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> Result<Arc<wgpu::RenderPipeline>, Error> {
+    let key = ("Sprite Pipeline", format, sample_count);
+    return s.render_pipeline(gpu.index(), key, || {
+        let source = s.resolve_resource_bindings(SPRITE_SHADER)?;
+        let shader_module = gpu
+            .device()
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Sprite Shader"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+
+        let bind_group_layouts = std::iter::once(gpu.system_bind_group_layout())
+            .chain(s.resource_bind_group_layouts(gpu.index()))
+            .collect::<Vec<_>>();
+        let layout = gpu
+            .device()
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Sprite Pipeline Layout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+
+        return Ok(gpu
+            .device()
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Sprite Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
+                multiview: None,
+            }));
+    });
+}
+
+// Records the commands for drawing the sprites of all entities with a position into the color
+// attachment, which has the given format and sample count, see `Viewport::color_attachment`. The
+// sprites are placed with the `WorldToClip` of the viewport and sample the `SpriteTexture`.
+pub fn encode_draw_sprites(
+    gpu: &Gpu,
+    s: &SceneState,
+    viewport_id: ViewportId,
+    color_attachment: wgpu::RenderPassColorAttachment<'_>,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    encoder: &mut wgpu::CommandEncoder,
+) -> Result<(), Error> {
+    s.resource_storage_ref::<Position>()
+        .unwrap()
+        .update_gpu_buffers();
+    s.resource_storage_ref::<Sprite>()
+        .unwrap()
+        .update_gpu_buffers();
+    s.resource_storage_ref::<WorldToClip>()
+        .unwrap()
+        .update_gpu_buffers();
+    s.texture_storage::<SpriteTexture>()
+        .unwrap()
+        .update_gpu_buffers()?;
+    // One instance per entity index the sprites are looked up with, the shader skips the entities
+    // without a sprite.
+    let vertex_base = viewport_vertex_base(viewport_id)?;
+    let draw =
+        DrawCommand::new(vertex_base..vertex_base + 6).with_instance_per_component::<Sprite>(s);
+
+    s.update_resource_bindings();
+
+    let pipeline = sprite_pipeline(gpu, s, format, sample_count)?;
+    let resource_bind_groups = s.resource_bind_groups(gpu.index());
+
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("DrawSprites"),
+        color_attachments: &[Some(color_attachment)],
+        depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(&pipeline);
+    render_pass.set_bind_group(0, gpu.system_bind_group(), &[]);
+    for (index, group) in resource_bind_groups.iter().enumerate() {
+        render_pass.set_bind_group(1 + index as u32, group, &[]);
+    }
+    draw.record(&mut render_pass);
+
+    return Ok(());
+}
+
+pub fn draw_sprites(sr: &SystemResources, s: &SceneState) -> Result<(), Error> {
+    let viewport = sr.viewport().unwrap();
+    let Some(color_attachment) = viewport.color_attachment(wgpu::LoadOp::Load) else {
+        return Ok(());
+    };
+
+    return encode_draw_sprites(
+        viewport.gpu(),
+        s,
+        sr.viewport_id().unwrap(),
+        color_attachment,
+        viewport.format(),
+        viewport.sample_count(),
+        &mut sr.encoder(),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Mat4;
+    use ovis_core::{EntityId, Resource, VersionedIndexId};
+
+    const TARGET_SIZE: u32 = 64;
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+
+    // Creates a scene with only the resources the sprites are drawn with, all resources of the
+    // runtime exceed the storage buffer limit of software adapters. The sprite texture is a row of
+    // four texels.
+    fn sprite_scene(gpu: &Arc<Gpu>, texels: [[u8; 4]; 4]) -> SceneState {
+        Position::register();
+        Sprite::register();
+        SpriteTexture::register();
+        WorldToClip::register();
+        let s = SceneState::for_gpus(std::slice::from_ref(gpu), 0).unwrap();
+        let texture = Texture::from_rgba8(4, 1, texels.concat()).unwrap();
+        s.set_texture(SpriteTexture::from(texture));
+        return s;
+    }
+
+    fn spawn_sprite(s: &SceneState, position: Position) -> EntityId {
+        return spawn_sprite_with_uv_rect(s, position, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    fn spawn_sprite_with_uv_rect(
+        s: &SceneState,
+        position: Position,
+        uv_rect: [f32; 4],
+    ) -> EntityId {
+        let entity_id = s.entities().write().unwrap().reserve();
+        s.resource_storage_mut::<Position>()
+            .unwrap()
+            .insert(entity_id, position);
+        s.resource_storage_mut::<Sprite>().unwrap().insert(
+            entity_id,
+            Sprite {
+                texture: TextureHandle(0),
+                uv_rect,
+                size: [0.5, 0.5],
+            },
+        );
+        return entity_id;
+    }
+
+    // Renders the sprites of the scene for the viewport into a black texture and returns its RGBA
+    // pixels.
+    fn render(gpu: &Gpu, s: &SceneState, viewport_id: ViewportId) -> Vec<u8> {
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let texture = gpu.device().create_texture(&wgpu::TextureDescriptor {
+            label: Some("Sprite Test Target"),
+            size: wgpu::Extent3d {
+                width: TARGET_SIZE,
+                height: TARGET_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let readback_buffer = gpu.device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Test Readback"),
+            size: (TARGET_SIZE * TARGET_SIZE * 4).into(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = gpu
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Clear"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        let color_attachment = wgpu::RenderPassColorAttachment {
+            view: &view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: true,
+            },
+        };
+        encode_draw_sprites(gpu, s, viewport_id, color_attachment, format, 1, &mut encoder)
+            .unwrap();
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(TARGET_SIZE * 4),
+                    rows_per_image: None,
+                },
+            },
+            texture.size(),
+        );
+        gpu.queue().submit(std::iter::once(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        gpu.device().poll(wgpu::Maintain::Wait);
+        let pixels = slice.get_mapped_range().to_vec();
+        return pixels;
+    }
+
+    fn pixel(pixels: &[u8], x: u32, y: u32) -> [u8; 4] {
+        let offset = ((y * TARGET_SIZE + x) * 4) as usize;
+        return pixels[offset..offset + 4].try_into().unwrap();
+    }
+
+    #[test]
+    fn sprites_are_drawn_at_their_positions() {
+        let Some(gpu) = ovis_core::test_gpu() else {
+            return;
+        };
+        let s = sprite_scene(&gpu, [WHITE; 4]);
+
+        // The sprites cover the upper left and lower right quarter of the target centered at
+        // pixels (16, 16) and (48, 48) respectively.
+        spawn_sprite(&s, Position { x: -0.5, y: 0.5 });
+        spawn_sprite(&s, Position { x: 0.5, y: -0.5 });
+        let pixels = render(&gpu, &s, ViewportId::from_index(0));
+
+        assert_eq!(pixel(&pixels, 16, 16), WHITE);
+        assert_eq!(pixel(&pixels, 48, 48), WHITE);
+        assert_eq!(pixel(&pixels, 48, 16), BLACK);
+        assert_eq!(pixel(&pixels, 16, 48), BLACK);
+        assert_eq!(pixel(&pixels, 32, 32), BLACK);
+        assert_eq!(pixel(&pixels, 4, 4), BLACK);
+    }
+
+    #[test]
+    fn entities_whose_sprite_was_removed_are_skipped() {
+        let Some(gpu) = ovis_core::test_gpu() else {
+            return;
+        };
+        let s = sprite_scene(&gpu, [WHITE; 4]);
+        spawn_sprite(&s, Position { x: -0.5, y: 0.5 });
+        let middle = spawn_sprite(&s, Position { x: 0.0, y: 0.0 });
+        spawn_sprite(&s, Position { x: 0.5, y: -0.5 });
+
+        // The slot of the middle entity stays in the reverse array, so the last sprite is still
+        // drawn, but the empty slot is not.
+        s.resource_storage_mut::<Sprite>().unwrap().remove(middle);
+        assert_eq!(s.component_slot_bound(Sprite::id()), 3);
+        let pixels = render(&gpu, &s, ViewportId::from_index(0));
+
+        assert_eq!(pixel(&pixels, 16, 16), WHITE);
+        assert_eq!(pixel(&pixels, 48, 48), WHITE);
+        assert_eq!(pixel(&pixels, 32, 32), BLACK);
+    }
+
+    #[test]
+    fn sprites_are_transformed_with_the_world_to_clip_of_the_viewport() {
+        let Some(gpu) = ovis_core::test_gpu() else {
+            return;
+        };
+        let s = sprite_scene(&gpu, [WHITE; 4]);
+        spawn_sprite(&s, Position { x: 0.0, y: 0.0 });
+
+        // Only the second viewport has a camera, which moves the sprite to the upper left quarter
+        // of the target.
+        let [without_camera, with_camera] = [0, 1].map(ViewportId::from_index);
+        let translation = Mat4::from_translation(glam::Vec3::new(-0.5, 0.5, 0.0));
+        s.resource_storage_mut::<WorldToClip>()
+            .unwrap()
+            .insert(with_camera, WorldToClip::from(translation));
+
+        let pixels = render(&gpu, &s, without_camera);
+        assert_eq!(pixel(&pixels, 32, 32), WHITE);
+        assert_eq!(pixel(&pixels, 16, 16), BLACK);
+        let pixels = render(&gpu, &s, with_camera);
+        assert_eq!(pixel(&pixels, 16, 16), WHITE);
+        assert_eq!(pixel(&pixels, 32, 32), BLACK);
+    }
+
+    #[test]
+    fn sprites_show_the_region_of_the_texture_given_by_their_uv_rect() {
+        let Some(gpu) = ovis_core::test_gpu() else {
+            return;
+        };
+        // The left half of the texture is red, the right half green. The centers of the sprites
+        // are far enough from the middle of the texture that filtering does not blend the halves.
+        let s = sprite_scene(&gpu, [RED, RED, GREEN, GREEN]);
+        spawn_sprite_with_uv_rect(&s, Position { x: -0.5, y: 0.5 }, [0.0, 0.0, 0.5, 1.0]);
+        spawn_sprite_with_uv_rect(&s, Position { x: 0.5, y: -0.5 }, [0.5, 0.0, 1.0, 1.0]);
+        let pixels = render(&gpu, &s, ViewportId::from_index(0));
+
+        assert_eq!(pixel(&pixels, 16, 16), RED);
+        assert_eq!(pixel(&pixels, 48, 48), GREEN);
+        assert_eq!(pixel(&pixels, 32, 32), BLACK);
+    }
+
+    #[test]
+    fn sprite_pipelines_are_cached_per_scene() {
+        let Some(gpu) = ovis_core::test_gpu() else {
+            return;
+        };
+        Position::register();
+        Sprite::register();
+        SpriteTexture::register();
+        WorldToClip::register();
+        let first = SceneState::for_gpus(std::slice::from_ref(&gpu), 0).unwrap();
+        let second = SceneState::for_gpus(std::slice::from_ref(&gpu), 0).unwrap();
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+
+        let pipeline = sprite_pipeline(&gpu, &first, format, 1).unwrap();
+        assert!(Arc::ptr_eq(&pipeline, &sprite_pipeline(&gpu, &first, format, 1).unwrap()));
+        assert!(!Arc::ptr_eq(&pipeline, &sprite_pipeline(&gpu, &second, format, 1).unwrap()));
+        assert!(!Arc::ptr_eq(&pipeline, &sprite_pipeline(&gpu, &first, format, 4).unwrap()));
+    }
+}